@@ -0,0 +1,32 @@
+fn main() {
+    // Regenerate the C header for the `ffi` feature; it is checked in so
+    // that C users don't need cbindgen.
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let header = "\
+// C API of the ray tracer. Generated from src/ffi.rs by build.rs; do not edit.
+//
+// Functions returning int32_t return -1 on error, and render_last_error()
+// then describes it. Scene pointers must come from render_scene_new() and
+// stay valid until passed to render_scene_free(). A scene may be used from
+// any thread, but by one thread at a time.";
+        let config = cbindgen::Config {
+            usize_is_size_t: true,
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .with_language(cbindgen::Language::C)
+            .with_header(header)
+            .with_include_guard("RENDER_H")
+            .with_cpp_compat(true)
+            .with_no_includes()
+            .with_sys_include("stdint.h")
+            .with_sys_include("stddef.h")
+            .generate()
+            .expect("Unable to generate the C header")
+            .write_to_file("include/render.h");
+    }
+}
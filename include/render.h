@@ -0,0 +1,99 @@
+// C API of the ray tracer. Generated from src/ffi.rs by build.rs; do not edit.
+//
+// Functions returning int32_t return -1 on error, and render_last_error()
+// then describes it. Scene pointers must come from render_scene_new() and
+// stay valid until passed to render_scene_free(). A scene may be used from
+// any thread, but by one thread at a time.
+
+#ifndef RENDER_H
+#define RENDER_H
+
+#include <stdint.h>
+#include <stddef.h>
+
+/**
+ * A scene under construction together with the camera that renders it.
+ */
+typedef struct RenderScene RenderScene;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last error on this thread, or null if there was none.
+ * The string stays valid until the next failing call on the same thread.
+ */
+const char *render_last_error(void);
+
+/**
+ * An empty scene with the default camera and background. Free it with
+ * `render_scene_free`.
+ */
+struct RenderScene *render_scene_new(void);
+
+void render_scene_free(struct RenderScene *scene);
+
+/**
+ * Adds a diffuse material and returns its id, or -1 on error.
+ */
+int32_t render_scene_add_lambertian(struct RenderScene *scene, double r, double g, double b);
+
+/**
+ * Adds a reflective material and returns its id, or -1 on error. `fuzz`
+ * blurs the reflection, from 0 (mirror) to 1.
+ */
+int32_t render_scene_add_metal(struct RenderScene *scene,
+                               double r,
+                               double g,
+                               double b,
+                               double fuzz);
+
+/**
+ * Adds a glass-like material with the given index of refraction and returns
+ * its id, or -1 on error.
+ */
+int32_t render_scene_add_dielectric(struct RenderScene *scene, double ir);
+
+/**
+ * Adds a material that gives off light of the given color and returns its
+ * id, or -1 on error. Components above 1 make brighter lights.
+ */
+int32_t render_scene_add_diffuse_light(struct RenderScene *scene, double r, double g, double b);
+
+/**
+ * Adds a sphere made of a material returned by one of the
+ * `render_scene_add_*` material functions. Returns 0, or -1 on error.
+ */
+int32_t render_scene_add_sphere(struct RenderScene *scene,
+                                double x,
+                                double y,
+                                double z,
+                                double radius,
+                                int32_t material);
+
+/**
+ * Sets a camera option by name, with the keys of a script's `set_camera`
+ * and vectors written as "x,y,z", e.g. "look_from" = "13,2,3". Returns 0,
+ * or -1 on error.
+ */
+int32_t render_scene_set_camera(struct RenderScene *scene, const char *key, const char *value);
+
+/**
+ * Stores the size of the image `render_scene_render` will produce.
+ * Returns 0, or -1 on error.
+ */
+int32_t render_scene_image_size(struct RenderScene *scene, int32_t *width, int32_t *height);
+
+/**
+ * Renders the scene into `pixels`, which must hold `len` bytes, at least
+ * width * height * 3. Pixels are written as gamma-corrected 8-bit RGB,
+ * row by row from the top. Returns 0, or -1 on error.
+ */
+int32_t render_scene_render(struct RenderScene *scene, uint8_t *pixels, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RENDER_H */
@@ -0,0 +1,168 @@
+use crate::ray::{Point3, Ray};
+use crate::utility::Interval;
+use crate::vec3::Vec3;
+
+use std::ops;
+
+// Axis-aligned bounding box, one interval per axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+    pub const UNIVERSE: Aabb = Aabb {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+
+    pub fn from(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }.pad()
+    }
+
+    // Treat the two points a and b as extrema for the bounding box, so we
+    // don't require a particular minimum/maximum coordinate order.
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        let axis = |i: usize| Interval::from(a[i].min(b[i]), a[i].max(b[i]));
+        Self::from(axis(0), axis(1), axis(2))
+    }
+
+    // The smallest box enclosing both boxes.
+    pub fn union(a: &Aabb, b: &Aabb) -> Self {
+        Self {
+            x: Interval::union(&a.x, &b.x),
+            y: Interval::union(&a.y, &b.y),
+            z: Interval::union(&a.z, &b.z),
+        }
+    }
+
+    pub fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    // Index of the axis along which the box is largest.
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test: whether the ray passes through the box within `ray_t`.
+    pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
+        let origin = r.origin();
+        let direction = r.direction();
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / direction[axis];
+
+            let t0 = (ax.min - origin[axis]) * adinv;
+            let t1 = (ax.max - origin[axis]) * adinv;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            ray_t = Interval::intersect(&ray_t, &Interval::from(t0, t1));
+            if ray_t.size() <= 0.0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Adjust the box so that no side is narrower than some delta, padding if
+    // necessary. Flat primitives such as quads would otherwise have zero
+    // thickness along one axis and be missed by the slab test.
+    fn pad(self) -> Self {
+        const DELTA: f64 = 0.0001;
+        let pad = |i: Interval| {
+            if i.size() < DELTA {
+                i.expand(DELTA)
+            } else {
+                i
+            }
+        };
+        Self {
+            x: pad(self.x),
+            y: pad(self.y),
+            z: pad(self.z),
+        }
+    }
+}
+
+impl ops::Add<Vec3> for Aabb {
+    type Output = Self;
+    fn add(self, offset: Vec3) -> Self::Output {
+        Self {
+            x: self.x + offset.x(),
+            y: self.y + offset.y(),
+            z: self.z + offset.z(),
+        }
+    }
+}
+
+impl ops::Add<Aabb> for Vec3 {
+    type Output = Aabb;
+    fn add(self, bbox: Aabb) -> Self::Output {
+        bbox + self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn slab_hits() {
+        let bbox = Aabb::from_points(Point3::from(1.0, 1.0, 1.0), Point3::from(-1.0, -1.0, -1.0));
+        let t = || Interval::from(0.0, INFINITY);
+
+        let towards = Ray::from(Point3::from(0.0, 0.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        let away = Ray::from(Point3::from(0.0, 0.0, -5.0), Vec3::from(0.0, 0.0, -1.0));
+        let beside = Ray::from(Point3::from(2.0, 0.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        assert!(bbox.hit(&towards, t()));
+        assert!(!bbox.hit(&away, t()));
+        assert!(!bbox.hit(&beside, t()));
+        assert!(!bbox.hit(&towards, Interval::from(0.0, 3.0)));
+        assert!((bbox + Vec3::from(2.0, 0.0, 0.0)).hit(&beside, t()));
+    }
+
+    #[test]
+    fn union_and_padding() {
+        let a = Aabb::from_points(Point3::from(0.0, 0.0, 0.0), Point3::from(1.0, 1.0, 1.0));
+        let b = Aabb::from_points(Point3::from(2.0, -1.0, 0.0), Point3::from(3.0, 0.0, 0.0));
+        let both = Aabb::union(&a, &b);
+        assert_eq!((both.x.min, both.x.max), (0.0, 3.0));
+        assert_eq!((both.y.min, both.y.max), (-1.0, 1.0));
+        assert_eq!(both.longest_axis(), 0);
+
+        // The flat box gets a sliver of thickness along z.
+        assert!(b.z.size() > 0.0);
+        assert!(Aabb::union(&Aabb::EMPTY, &a).x.size() == 1.0);
+    }
+}
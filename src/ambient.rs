@@ -0,0 +1,69 @@
+use crate::color::Color;
+use crate::vec3::*;
+
+// A constant fill light added at every surface hit, scaled by the surface's
+// albedo. It is independent of the background and costs no extra rays, for
+// fast previews and stylized looks where full global illumination is more
+// than is needed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ambient {
+    Flat(Color),
+    // Blend from `ground` for normals facing down to `sky` facing up.
+    Hemisphere { sky: Color, ground: Color },
+}
+
+impl Ambient {
+    // Light arriving at a surface with the given unit normal.
+    pub fn value(&self, normal: &Vec3) -> Color {
+        match self {
+            Ambient::Flat(color) => *color,
+            Ambient::Hemisphere { sky, ground } => {
+                let a = 0.5 * (normal.y() + 1.0);
+                (1.0 - a) * *ground + a * *sky
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::ray::Point3;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn fills_in_without_a_background() {
+        let hemisphere = Ambient::Hemisphere {
+            sky: Color::gray(1.0),
+            ground: Color::gray(0.0),
+        };
+        approx::assert_relative_eq!(hemisphere.value(&Vec3::from(0.0, 1.0, 0.0)).r(), 1.0);
+        approx::assert_relative_eq!(hemisphere.value(&Vec3::from(1.0, 0.0, 0.0)).r(), 0.5);
+
+        // Under a black background, only the ambient term lights the sphere.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::new());
+        scene.ambient = Some(Ambient::Flat(Color::from(0.2, 0.4, 0.6)));
+        let camera = CameraBuilder {
+            image_width: Some(3),
+            look_from: Some(Point3::from(0.0, 0.0, 2.0)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let color = camera.sample_pixel_with(&scene, 1, 1, 4) / 4.0;
+        approx::assert_relative_eq!(color.r(), 0.1);
+        approx::assert_relative_eq!(color.b(), 0.3);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::progress;
+use crate::scene::Scene;
+use crate::temporal::Temporal;
+use crate::utility::Instant;
+
+use log::info;
+use std::fs;
+use std::path::PathBuf;
+
+pub const DEFAULT_PATTERN: &str = "frame_####.ppm";
+
+// Inclusive range of frame numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl FrameRange {
+    // Parses "START..END" or "START-END" (both inclusive), or a single frame.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid frame range '{}', expected START..END", s);
+        let (start, end) = s
+            .split_once("..")
+            .or_else(|| s.split_once('-'))
+            .unwrap_or((s, s));
+        let start: i64 = start.trim().parse().map_err(|_| invalid())?;
+        let end: i64 = end.trim().parse().map_err(|_| invalid())?;
+        if end < start {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start + 1) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Replaces the first run of '#' in `pattern` with the zero-padded frame
+// number, e.g. "frame_####.ppm" -> "frame_0012.ppm". Patterns without '#'
+// get the number appended before the extension.
+pub fn frame_path(pattern: &str, frame: i64) -> PathBuf {
+    match pattern.find('#') {
+        Some(start) => {
+            let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+            PathBuf::from(format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[start + width..],
+                width = width
+            ))
+        }
+        None => {
+            let path = PathBuf::from(pattern);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match path.extension() {
+                Some(ext) => format!("{}_{:04}.{}", stem, frame, ext.to_string_lossy()),
+                None => format!("{}_{:04}", stem, frame),
+            };
+            path.with_file_name(name)
+        }
+    }
+}
+
+// Renders every frame in `range` to files named after `pattern`. `load` builds
+// the world and camera for a frame number and time in seconds (frame / fps).
+// With a `temporal_blend` weight, each frame is blended with the reprojected
+// previous one (see `Temporal`).
+pub fn render_sequence<L>(
+    range: FrameRange,
+    fps: f64,
+    pattern: &str,
+    temporal_blend: Option<f64>,
+    load: L,
+) -> Result<(), String>
+where
+    L: Fn(i64, f64) -> Result<(Scene, CameraBuilder), String>,
+{
+    let mut temporal = temporal_blend.map(Temporal::from);
+    render_sequence_with(range, fps, load, |frame, camera, scene| {
+        let path = frame_path(pattern, frame);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        match &mut temporal {
+            Some(temporal) => temporal.render(camera, scene).write_image(&path),
+            None => camera
+                .render_to_path(scene, &path, &mut progress::log)
+                .map(|_| ()),
+        }
+        .map_err(|err| err.to_string())
+    })
+}
+
+// Like `render_sequence`, but hands each frame's camera and scene to `write`,
+// which renders it wherever the frames should go (e.g. a video encoder).
+// Each frame's scene is built anew, but the models and images it loads come
+// from the loaders' caches, hierarchies and all, after the first frame.
+pub fn render_sequence_with<L, W>(
+    range: FrameRange,
+    fps: f64,
+    load: L,
+    mut write: W,
+) -> Result<(), String>
+where
+    L: Fn(i64, f64) -> Result<(Scene, CameraBuilder), String>,
+    W: FnMut(i64, &Camera, &Scene) -> Result<(), String>,
+{
+    let time_start = Instant::now();
+    for frame in range.start..=range.end {
+        crate::stage_span!("frame", frame);
+        let (scene, settings) = load(frame, frame as f64 / fps)?;
+        let camera = settings
+            .build()
+            .map_err(|err| format!("frame {}: {}", frame, err))?;
+        write(frame, &camera, &scene)?;
+        info!(
+            "Frame {} ({}/{}) done",
+            frame,
+            frame - range.start + 1,
+            range.len()
+        );
+    }
+    info!("Sequence done in {:?}.", time_start.elapsed());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_ranges() {
+        assert_eq!(
+            FrameRange::parse("1..48").unwrap(),
+            FrameRange { start: 1, end: 48 }
+        );
+        assert_eq!(FrameRange::parse("3-5").unwrap().len(), 3);
+        assert_eq!(FrameRange::parse("7").unwrap().len(), 1);
+        assert!(FrameRange::parse("5..1").is_err());
+    }
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!(
+            frame_path("out/frame_####.ppm", 12),
+            PathBuf::from("out/frame_0012.ppm")
+        );
+        assert_eq!(frame_path("f_##.ppm", 123), PathBuf::from("f_123.ppm"));
+        assert_eq!(
+            frame_path("turntable.ppm", 3),
+            PathBuf::from("turntable_0003.ppm")
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::camera::Camera;
+use crate::color::{Color, Tonemap};
+use crate::error::Error;
+use crate::framebuffer::{FrameBuffer, ImageFormat};
+use crate::ray::Point3;
+use crate::scene::Scene;
+use crate::utility::{Interval, INFINITY};
+use crate::vec3::*;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// What the ray through a pixel's center hits first.
+#[derive(Clone, Copy, Debug)]
+pub struct Surface {
+    pub position: Point3,
+    pub normal: Vec3, // Facing the camera
+    pub albedo: Color,
+    pub depth: f64,            // Distance from the camera
+    pub object: Option<usize>, // Index in the scene's world; None on clip plane caps
+}
+
+// One attribute of the surfaces, written as an image of its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pass {
+    Depth,
+    Normal,
+    Albedo,
+    Object,
+}
+
+impl FromStr for Pass {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Pass::Depth),
+            "normal" => Ok(Pass::Normal),
+            "albedo" => Ok(Pass::Albedo),
+            "object" => Ok(Pass::Object),
+            _ => Err(format!(
+                "unknown pass '{}', expected depth, normal, albedo or object",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Pass::Depth => "depth",
+            Pass::Normal => "normal",
+            Pass::Albedo => "albedo",
+            Pass::Object => "object",
+        })
+    }
+}
+
+// Arbitrary output variables: noise-free per-pixel surface attributes of
+// the first hit, used to guide filtering and reprojection. Pixels that see
+// the background have no surface.
+pub struct Aovs {
+    width: i32,
+    height: i32,
+    surfaces: Vec<Option<Surface>>,
+}
+
+impl Aovs {
+    // Buffers for `width` x `height` pixels from surfaces in row-major order.
+    pub fn from(width: i32, height: i32, surfaces: Vec<Option<Surface>>) -> Self {
+        assert_eq!(surfaces.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            surfaces,
+        }
+    }
+
+    // Traces one ray through the center of every pixel of an initialized camera.
+    pub fn render(camera: &Camera, scene: &Scene) -> Self {
+        crate::stage_span!("aovs");
+        let width = camera.image_width();
+        let height = camera.image_height();
+        let overridden = camera.mode().material();
+        let mut surfaces = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let r = camera.primary_ray(i, j);
+                let hit = scene.hit_object(&r, Interval::from(0.001, INFINITY));
+                surfaces.push(hit.map(|(object, rec)| Surface {
+                    position: rec.p,
+                    normal: rec.normal,
+                    albedo: overridden.as_ref().unwrap_or(rec.mat).albedo(&rec),
+                    depth: distance(&r.origin(), &rec.p),
+                    object,
+                }));
+            }
+        }
+        Self::from(width, height, surfaces)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn get(&self, i: i32, j: i32) -> Option<&Surface> {
+        if i < 0 || j < 0 || i >= self.width || j >= self.height {
+            return None;
+        }
+        self.surfaces[(j * self.width + i) as usize].as_ref()
+    }
+
+    // One pass as an image. With `raw`, pixels hold the values themselves:
+    // the distance, the normal's components from -1 to 1, the albedo and the
+    // object's index plus one, 0 where nothing was hit. Otherwise they are
+    // brought into the range 8-bit images keep: the distance over the
+    // farthest one, the normal halfway to white and a color per object. The
+    // albedo is encoded as sRGB, the others as they are.
+    pub fn to_buffer(&self, pass: Pass, raw: bool) -> FrameBuffer {
+        let farthest = self
+            .surfaces
+            .iter()
+            .flatten()
+            .map(|surface| surface.depth)
+            .fold(0.0, f64::max);
+        let mut buffer = FrameBuffer::new(self.width, self.height);
+        buffer.set_tonemap(match pass {
+            Pass::Albedo => Tonemap::Srgb,
+            _ => Tonemap::Linear,
+        });
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let Some(surface) = self.get(i, j) else {
+                    buffer.add_samples(i, j, Color::new(), 1);
+                    continue;
+                };
+                let value = match (pass, raw) {
+                    (Pass::Depth, true) => Color::gray(surface.depth),
+                    (Pass::Depth, false) => Color::gray(surface.depth / farthest),
+                    (Pass::Normal, true) => surface.normal.into(),
+                    (Pass::Normal, false) => {
+                        (0.5 * (surface.normal + Vec3::from(1.0, 1.0, 1.0))).into()
+                    }
+                    (Pass::Albedo, _) => surface.albedo,
+                    (Pass::Object, true) => {
+                        Color::gray(surface.object.map_or(0.0, |index| (index + 1) as f64))
+                    }
+                    // Successive objects a golden angle apart in hue.
+                    (Pass::Object, false) => surface.object.map_or(Color::gray(1.0), |index| {
+                        Color::from_hsv((index as f64 * 0.618034).fract(), 0.65, 0.9)
+                    }),
+                };
+                buffer.add_samples(i, j, value, 1);
+            }
+        }
+        buffer
+    }
+
+    // Writes a pass to `path` in the format its extension names, raw in
+    // OpenEXR files and brought into range in the others.
+    pub fn write(&self, pass: Pass, path: &Path) -> Result<(), Error> {
+        let raw = ImageFormat::from_path(path) == ImageFormat::Exr;
+        self.to_buffer(pass, raw).write_image(path)
+    }
+
+    // Screen-space motion of every pixel's surface point since it was seen by
+    // `previous`, in pixels (current position minus previous position).
+    // Geometry is assumed static between the two cameras, so only camera
+    // motion is captured.
+    pub fn motion_vectors(&self, previous: &Camera) -> Vec<Option<(f64, f64)>> {
+        let mut motion = Vec::with_capacity(self.surfaces.len());
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let projected = self
+                    .get(i, j)
+                    .and_then(|surface| previous.project(surface.position));
+                motion.push(projected.map(|(x, y)| (i as f64 - x, j as f64 - y)));
+            }
+        }
+        motion
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn motion_follows_the_camera() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let scene = Scene::from(world);
+        let camera = CameraBuilder {
+            image_width: Some(9),
+            look_from: Some(Point3::from(0.0, 0.0, 4.0)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let aovs = Aovs::render(&camera, &scene);
+
+        let center = aovs.get(4, 4).unwrap();
+        approx::assert_relative_eq!(center.depth, 3.0);
+        approx::assert_relative_eq!(center.albedo.r(), 0.5);
+        assert_eq!(center.object, Some(0));
+        assert!(aovs.get(0, 0).is_none());
+        let depth = aovs.to_buffer(Pass::Depth, true);
+        approx::assert_relative_eq!(depth.pixel_sum(4, 4).g(), 3.0);
+        let normal = aovs.to_buffer(Pass::Normal, false);
+        approx::assert_relative_eq!(normal.pixel_sum(4, 4).b(), 1.0);
+        assert_eq!(normal.to_rgb8()[..3], [0, 0, 0]);
+
+        // Without camera motion, everything stays put.
+        let (dx, dy) = aovs.motion_vectors(&camera)[4 * 9 + 4].unwrap();
+        assert!(dx.abs() < 1e-9 && dy.abs() < 1e-9);
+
+        // Sliding the camera right moves the image left.
+        let previous = CameraBuilder {
+            look_from: Some(Point3::from(-0.5, 0.0, 4.0)),
+            look_at: Some(Point3::from(-0.5, 0.0, 0.0)),
+            ..camera.to_builder()
+        }
+        .build()
+        .unwrap();
+        let (dx, dy) = aovs.motion_vectors(&previous)[4 * 9 + 4].unwrap();
+        assert!(dx < -0.5 && dy.abs() < 1e-9, "motion ({}, {})", dx, dy);
+    }
+}
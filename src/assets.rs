@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// Files read while building a scene. Loaders note each model, image and
+// buffer they open, so watch mode can re-render when an asset changes and
+// not only when the scene does.
+thread_local! {
+    static OPENED: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+// Runs `build`, returning what it built and the asset files opened on this
+// thread meanwhile, each once.
+pub fn recording<T>(build: impl FnOnce() -> T) -> (T, Vec<PathBuf>) {
+    let outer = OPENED.with(|opened| opened.borrow_mut().replace(Vec::new()));
+    let built = build();
+    let paths = OPENED
+        .with(|opened| std::mem::replace(&mut *opened.borrow_mut(), outer))
+        .unwrap_or_default();
+    // Files opened within a nested recording were opened within this one too.
+    for path in &paths {
+        opened(path);
+    }
+    (built, paths)
+}
+
+// Notes that `path` was opened, if a `recording` is in progress.
+pub fn opened(path: &Path) {
+    OPENED.with(|opened| {
+        if let Some(paths) = opened.borrow_mut().as_mut() {
+            if !paths.iter().any(|p| p == path) {
+                paths.push(path.to_path_buf());
+            }
+        }
+    });
+}
+
+// Decoded assets by path, kept while their file is unchanged, so rebuilding
+// a scene after an edit or for the next frame reuses whatever didn't change.
+// Each file has one entry, replaced when the file's modification time moves.
+pub struct Cache<T> {
+    entries: Mutex<BTreeMap<PathBuf, (SystemTime, Arc<T>)>>,
+}
+
+impl<T> Cache<T> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // The asset at `path`, loaded by `load` unless the cached one is still
+    // current. Files whose modification time can't be read aren't cached.
+    pub fn get<E>(
+        &self,
+        path: &Path,
+        load: impl FnOnce(&Path) -> Result<T, E>,
+    ) -> Result<Arc<T>, E> {
+        opened(path);
+        let Ok(stamp) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return load(path).map(Arc::new);
+        };
+        let cached = self.entries.lock().unwrap().get(path).cloned();
+        if let Some((modified, asset)) = cached {
+            if modified == stamp {
+                return Ok(asset);
+            }
+        }
+        let asset = Arc::new(load(path)?);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (stamp, asset.clone()));
+        Ok(asset)
+    }
+}
+
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_and_caches_assets() {
+        let path = std::env::temp_dir().join(format!("asset-{}.txt", std::process::id()));
+        fs::write(&path, "one").unwrap();
+        let cache = Cache::new();
+        let mut loads = 0;
+        let mut load = |path: &Path| {
+            loads += 1;
+            fs::read_to_string(path)
+        };
+
+        let (first, paths) = recording(|| cache.get(&path, &mut load).unwrap());
+        assert_eq!(paths, vec![path.clone()]);
+        let (second, _) = recording(|| cache.get(&path, &mut load).unwrap());
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let (inner, outer) = recording(|| recording(|| opened(&path)).1);
+        assert_eq!((&inner, &outer), (&vec![path.clone()], &vec![path.clone()]));
+
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::write(&path, "two").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(*cache.get(&path, &mut load).unwrap(), "two");
+        assert_eq!(loads, 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
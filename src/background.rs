@@ -0,0 +1,150 @@
+use crate::color::Color;
+use crate::expr::{Expr, ExprInputs};
+use crate::ray::Ray;
+use crate::texture::{ImageTexture, Texture};
+use crate::utility::PI;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+// What a ray sees when it leaves the scene without hitting anything.
+#[derive(Clone)]
+pub enum Background {
+    Solid(Color),
+    // Blend from `bottom` (looking straight down) to `top` (straight up).
+    Gradient {
+        top: Color,
+        bottom: Color,
+    },
+    Sky(Sky),
+    // An expression evaluated over the ray direction: `p` and `normal` are
+    // the unit direction, and `u`, `v` its equirectangular coordinates.
+    Environment(Arc<Expr>),
+    // An equirectangular image around the scene, normally an HDR capture of
+    // a real place, which lights the scene as it was lit. `strength` scales
+    // it, as few images are captured at the brightness a scene needs.
+    Image {
+        image: Arc<ImageTexture>,
+        strength: f64,
+    },
+}
+
+impl Default for Background {
+    // The blue-white sky from the book.
+    fn default() -> Self {
+        Background::Gradient {
+            top: Color::from(0.5, 0.7, 1.0),
+            bottom: Color::from(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Background {
+    pub fn value(&self, r: &Ray) -> Color {
+        let unit_direction = unit_vector(r.direction());
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { top, bottom } => {
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * *bottom + a * *top
+            }
+            Background::Sky(sky) => sky.value(&unit_direction),
+            Background::Environment(expr) => {
+                let (u, v) = direction_uv(&unit_direction);
+                expr.eval(&ExprInputs {
+                    u,
+                    v,
+                    p: unit_direction,
+                    normal: unit_direction,
+                })
+                .into()
+            }
+            Background::Image { image, strength } => {
+                let (u, v) = direction_uv(&unit_direction);
+                *strength * image.value(u, v, &unit_direction)
+            }
+        }
+    }
+}
+
+// Equirectangular coordinates of a unit direction, matching the sphere's
+// texture mapping: u goes around the Y axis from X=-1, v from Y=-1 to Y=+1.
+fn direction_uv(d: &Vec3) -> (f64, f64) {
+    let theta = f64::acos(-d.y());
+    let phi = f64::atan2(-d.z(), d.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// A simple procedural daylight sky: a zenith-to-horizon blend above a flat
+// ground color, with a sun disk and glow around `sun_direction`.
+#[derive(Clone)]
+pub struct Sky {
+    pub sun_direction: Vec3,
+    pub sun_color: Color,
+    pub zenith: Color,
+    pub horizon: Color,
+    pub ground: Color,
+}
+
+impl Sky {
+    pub fn from(sun_direction: Vec3) -> Self {
+        Self {
+            sun_direction: unit_vector(sun_direction),
+            sun_color: Color::from(20.0, 18.0, 15.0),
+            zenith: Color::from(0.25, 0.45, 0.9),
+            horizon: Color::from(0.85, 0.9, 1.0),
+            ground: Color::from(0.35, 0.32, 0.3),
+        }
+    }
+
+    fn value(&self, d: &Vec3) -> Color {
+        if d.y() < 0.0 {
+            // Fade the horizon haze into the ground just below the horizon.
+            let a = (-d.y() * 10.0).min(1.0);
+            return (1.0 - a) * self.horizon + a * self.ground;
+        }
+
+        let a = d.y().sqrt();
+        let sky = (1.0 - a) * self.horizon + a * self.zenith;
+
+        // The sun subtends about half a degree.
+        let cos_angle = dot(d, &self.sun_direction);
+        if cos_angle > 0.99996 {
+            return self.sun_color;
+        }
+        let glow = cos_angle.max(0.0).powi(64) * 0.05;
+        sky + glow * self.sun_color
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ray::Point3;
+
+    #[test]
+    fn backgrounds() {
+        let up = Ray::from(Point3::new(), Vec3::from(0.0, 2.0, 0.0));
+        let down = Ray::from(Point3::new(), Vec3::from(0.0, -1.0, 0.0));
+
+        let black = Background::Solid(Color::new());
+        assert_eq!(black.value(&up), Color::new());
+
+        let sky = Background::default();
+        assert_eq!(sky.value(&up), Color::from(0.5, 0.7, 1.0));
+        assert_eq!(sky.value(&down), Color::from(1.0, 1.0, 1.0));
+
+        let env = Background::Environment(Arc::new(Expr::parse("vec3(u, v, 0.0)").unwrap()));
+        assert_eq!(env.value(&up).g(), 1.0);
+        assert_eq!(env.value(&down).g(), 0.0);
+
+        // Bright above, dark below, at twice the image's brightness.
+        let image = ImageTexture::from(1, 2, vec![Color::gray(3.0), Color::gray(0.1)]).unwrap();
+        let hdri = Background::Image {
+            image: Arc::new(image),
+            strength: 2.0,
+        };
+        assert_eq!(hdri.value(&up), Color::gray(6.0));
+        assert_eq!(hdri.value(&down), Color::gray(0.2));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::hittable::Hittable;
+use crate::pdf::MisHeuristic;
+use crate::progress::{self, Tile, Tracker};
+use crate::ray::Ray;
+use crate::sampler::{PixelSampler, Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::utility::Interval;
+use crate::vec3::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+// What a bake stores per texel. Texels are sampled over the surface patch
+// the object's UV layout maps them to, with rays sent from the surface into
+// the hemisphere around the normal, cosine-weighted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BakeKind {
+    // Light arriving at the surface from the scene, including bounces,
+    // divided by pi: the brightness a white diffuse surface there would have.
+    #[default]
+    Irradiance,
+    // The fraction of the hemisphere left open within the occlusion
+    // distance, 1 on open surfaces and 0 in closed corners.
+    Occlusion,
+}
+
+impl FromStr for BakeKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "irradiance" => Ok(BakeKind::Irradiance),
+            "ao" => Ok(BakeKind::Occlusion),
+            _ => Err(format!("unknown bake '{}', expected irradiance or ao", s)),
+        }
+    }
+}
+
+impl fmt::Display for BakeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BakeKind::Irradiance => "irradiance",
+            BakeKind::Occlusion => "ao",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bake {
+    pub kind: BakeKind,
+    pub width: i32,
+    pub height: i32,
+    pub samples: i32,   // Rays per texel
+    pub max_depth: i32, // Bounces after the first, for irradiance
+    pub distance: f64,  // Occluders further than this don't count, for ao
+    pub sampler: SamplerKind,
+    pub mis: MisHeuristic,
+}
+
+impl Default for Bake {
+    fn default() -> Self {
+        Self {
+            kind: BakeKind::Irradiance,
+            width: 256,
+            height: 256,
+            samples: 64,
+            max_depth: 10,
+            distance: 1.0,
+            sampler: SamplerKind::Sobol,
+            mis: MisHeuristic::default(),
+        }
+    }
+}
+
+impl Bake {
+    // Bakes the `object`th object of the scene (in order of `add`) into a
+    // texture, row by row from the top, which is v = 1 as in image textures.
+    pub fn render(&self, scene: &Scene, object: usize) -> Result<FrameBuffer, String> {
+        let target = scene
+            .world
+            .get(object)
+            .ok_or_else(|| format!("the scene has no object {}", object))?;
+        target
+            .surface_at(0.5, 0.5)
+            .ok_or_else(|| format!("object {} has no UV layout to bake into", object))?;
+        crate::stage_span!("bake", self.width, self.height, self.samples);
+
+        let mut on_progress = progress::log;
+        let mut progress = Tracker::start(self.width, self.height, self.samples, &mut on_progress);
+        let mut buffer = FrameBuffer::new(self.width, self.height);
+        for j in 0..self.height {
+            let tile = Tile::row(j, self.width);
+            progress.tile_started(tile);
+            for i in 0..self.width {
+                let mut sum = Color::new();
+                for s in 0..self.samples {
+                    let mut sampler = PixelSampler::start(
+                        self.sampler,
+                        i,
+                        j,
+                        s as u64,
+                        self.samples as u64,
+                        None,
+                    );
+                    sum += self.sample(scene, target, i, j, &mut sampler);
+                }
+                buffer.add_samples(i, j, sum, self.samples as u32);
+            }
+            progress.tile_finished(tile, tile.pixels() * self.samples as u64);
+        }
+        progress.finish();
+        for _ in 0..self.samples {
+            buffer.complete_pass();
+        }
+        Ok(buffer)
+    }
+
+    fn sample(
+        &self,
+        scene: &Scene,
+        target: &dyn Hittable,
+        i: i32,
+        j: i32,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let (du, dv) = sampler.get_2d();
+        let u = (i as f64 + du) / self.width as f64;
+        let v = 1.0 - (j as f64 + dv) / self.height as f64;
+        let Some((p, normal)) = target.surface_at(u, v) else {
+            return Color::new();
+        };
+        let direction = normal + sample_unit_vector(sampler.get_2d());
+        let direction = if direction.near_zero() {
+            normal
+        } else {
+            direction
+        };
+        let r = Ray::from(p, direction);
+        match self.kind {
+            BakeKind::Occlusion => {
+                let reach = self.distance / direction.length();
+                let open = !scene.world.is_occluded(&r, Interval::from(0.001, reach));
+                Color::gray(if open { 1.0 } else { 0.0 })
+            }
+            // With cosine-weighted directions, the radiance itself is the
+            // estimate of irradiance over pi.
+            BakeKind::Irradiance => {
+                Camera::ray_color(&r, self.max_depth + 1, 1, None, self.mis, scene, sampler)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::ray::Point3;
+    use crate::sphere::Sphere;
+
+    fn sphere(center: Point3, radius: f64) -> Box<dyn Hittable> {
+        Box::new(Sphere::from(
+            center,
+            radius,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        ))
+    }
+
+    #[test]
+    fn bakes_a_sphere_resting_on_the_ground() {
+        let mut world = HittableList::default();
+        world.add(sphere(Point3::new(), 1.0));
+        world.add(sphere(Point3::from(0.0, -1001.0, 0.0), 1000.0));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        let bake = Bake {
+            width: 8,
+            height: 8,
+            samples: 32,
+            ..Bake::default()
+        };
+
+        // Under a uniform white sky, the top of the sphere (the first row)
+        // sees mostly sky; the bottom row faces the ground.
+        let irradiance = bake.render(&scene, 0).unwrap();
+        let top = irradiance.pixel_sum(3, 0).g() / 32.0;
+        let bottom = irradiance.pixel_sum(3, 7).g() / 32.0;
+        assert!(top > 0.9, "top {}", top);
+        assert!(bottom < 0.75, "bottom {}", bottom);
+
+        let occlusion = Bake {
+            kind: BakeKind::Occlusion,
+            ..bake
+        }
+        .render(&scene, 0)
+        .unwrap();
+        approx::assert_relative_eq!(occlusion.pixel_sum(3, 0).g() / 32.0, 1.0);
+        assert!(occlusion.pixel_sum(3, 7).g() / 32.0 < 0.5);
+
+        assert!(bake.render(&scene, 2).is_err());
+    }
+}
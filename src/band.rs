@@ -0,0 +1,184 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::material::Scatterable;
+use crate::progress::{self, Tile, Tracker};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::utility::{Interval, INFINITY};
+
+use log::info;
+use std::io::{self, Write};
+
+// Single-band rendering: instead of RGB, every path carries one scalar such as
+// thermal radiance. Surfaces emit their band emission and pass on a fraction
+// of what their scattered ray sees; the result is shown through a colormap.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Grayscale,
+    Ironbow,
+    Inferno,
+    Turbo,
+}
+
+impl Colormap {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "gray" | "grayscale" => Ok(Colormap::Grayscale),
+            "ironbow" => Ok(Colormap::Ironbow),
+            "inferno" => Ok(Colormap::Inferno),
+            "turbo" => Ok(Colormap::Turbo),
+            _ => Err(format!(
+                "unknown colormap '{}', expected gray, ironbow, inferno or turbo",
+                name
+            )),
+        }
+    }
+
+    // Maps t in [0,1] to a display color by interpolating between control points.
+    pub fn map(&self, t: f64) -> Color {
+        let stops: &[(f64, f64, f64)] = match self {
+            Colormap::Grayscale => &[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)],
+            Colormap::Ironbow => &[
+                (0.0, 0.0, 0.0),
+                (0.19, 0.0, 0.55),
+                (0.8, 0.1, 0.4),
+                (1.0, 0.55, 0.0),
+                (1.0, 1.0, 0.85),
+            ],
+            Colormap::Inferno => &[
+                (0.001, 0.0, 0.014),
+                (0.341, 0.062, 0.429),
+                (0.735, 0.216, 0.33),
+                (0.978, 0.557, 0.035),
+                (0.988, 1.0, 0.645),
+            ],
+            Colormap::Turbo => &[
+                (0.19, 0.07, 0.23),
+                (0.27, 0.47, 0.96),
+                (0.11, 0.87, 0.73),
+                (0.64, 0.99, 0.24),
+                (0.98, 0.73, 0.21),
+                (0.9, 0.29, 0.05),
+                (0.48, 0.02, 0.01),
+            ],
+        };
+
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (x as usize).min(stops.len() - 2);
+        let a = x - i as f64;
+        let (c0, c1) = (stops[i], stops[i + 1]);
+        Color::from(
+            c0.0 + a * (c1.0 - c0.0),
+            c0.1 + a * (c1.1 - c0.1),
+            c0.2 + a * (c1.2 - c0.2),
+        )
+    }
+}
+
+// A rendered scalar image, one averaged value per pixel.
+pub struct BandImage {
+    pub width: i32,
+    pub height: i32,
+    pub values: Vec<f64>,
+}
+
+impl BandImage {
+    // The smallest and largest pixel values.
+    pub fn range(&self) -> (f64, f64) {
+        self.values
+            .iter()
+            .fold((INFINITY, -INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)))
+    }
+
+    // Writes the image mapped through `colormap`, with `range` (or the image's
+    // own range) stretched across the whole map.
+    pub fn write_ppm(
+        &self,
+        out: &mut impl Write,
+        colormap: Colormap,
+        range: Option<(f64, f64)>,
+    ) -> io::Result<()> {
+        let (lo, hi) = range.unwrap_or_else(|| self.range());
+        let span = if hi > lo { hi - lo } else { 1.0 };
+        info!("Band range {} to {}", lo, hi);
+
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for &value in &self.values {
+            let c = colormap.map((value - lo) / span);
+            let byte = |x: f64| (256.0 * x.clamp(0.0, 0.999)) as u8;
+            writeln!(out, "{} {} {}", byte(c.r()), byte(c.g()), byte(c.b()))?;
+        }
+        Ok(())
+    }
+}
+
+// Renders the band image. Rays that escape the scene see `sky`.
+pub fn render_band(camera: &Camera, scene: &Scene, sky: f64) -> BandImage {
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let samples = camera.samples_per_pixel();
+    let depth = camera.max_depth();
+
+    let mut on_progress = progress::log;
+    let mut progress = Tracker::start(width, height, samples, &mut on_progress);
+    let mut values = Vec::with_capacity((width * height) as usize);
+    for j in 0..height {
+        let tile = Tile::row(j, width);
+        progress.tile_started(tile);
+        for i in 0..width {
+            let sum: f64 = (0..samples)
+                .map(|s| {
+                    let mut sampler = camera.pixel_sampler(i, j, s as u64);
+                    let r = camera.get_ray(i, j, &mut sampler);
+                    band_value(&r, depth, scene, sky, &mut sampler)
+                })
+                .sum();
+            values.push(sum / samples as f64);
+        }
+        progress.tile_finished(tile, tile.pixels() * samples as u64);
+    }
+    progress.finish();
+    BandImage {
+        width,
+        height,
+        values,
+    }
+}
+
+fn band_value(r: &Ray, depth: i32, scene: &Scene, sky: f64, sampler: &mut dyn Sampler) -> f64 {
+    if depth <= 0 {
+        return 0.0;
+    }
+    let Some(rec) = scene.hit(r, Interval::from(0.001, INFINITY)) else {
+        return sky;
+    };
+
+    let band = rec.mat.band();
+    let emission = band.map_or(0.0, |b| b.emission());
+    match rec.mat.scatter(r, &rec, sampler) {
+        Some((scattered, attenuation)) => {
+            let reflectance = band
+                .and_then(|b| b.reflectance())
+                .unwrap_or(attenuation.average());
+            let scattered = scattered.with_eye(r.eye());
+            emission + reflectance * band_value(&scattered, depth - 1, scene, sky, sampler)
+        }
+        None => emission,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn colormap_endpoints() {
+        assert_eq!(Colormap::Grayscale.map(0.5), Color::from(0.5, 0.5, 0.5));
+        assert_eq!(Colormap::Ironbow.map(0.0), Color::from(0.0, 0.0, 0.0));
+        assert_eq!(Colormap::Ironbow.map(2.0), Color::from(1.0, 1.0, 0.85));
+        approx::assert_relative_eq!(Colormap::Turbo.map(1.0).r(), 0.48);
+        assert!(Colormap::parse("viridis").is_err());
+    }
+}
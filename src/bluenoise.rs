@@ -0,0 +1,161 @@
+use crate::sampler::hash;
+
+use std::sync::OnceLock;
+
+// Side of the tiled mask, in pixels.
+pub const SIZE: usize = 64;
+// Width of the Gaussian used to measure clustering, in pixels.
+const SIGMA: f64 = 1.5;
+// Fraction of the pixels set in the initial binary pattern.
+const INITIAL_FILL: f64 = 0.1;
+
+// A tileable blue-noise dither mask: every value from 0 to 1 appears once per
+// tile, and neighbouring pixels get values as different as possible, so the
+// error of any thresholding (or of any sample offset by the mask) has no
+// low-frequency content.
+pub struct BlueNoise {
+    ranks: Vec<u16>,
+}
+
+impl BlueNoise {
+    // Builds the mask with Ulichney's void-and-cluster method, seeded with a
+    // fixed hash so that every run (and every machine) gets the same mask.
+    pub fn generate() -> Self {
+        let n = SIZE * SIZE;
+        let kernel = kernel();
+        let mut pattern = vec![false; n];
+        let mut energy = vec![0.0; n];
+        let toggle = |pattern: &mut [bool], energy: &mut [f64], p: usize| {
+            let sign = if pattern[p] { -1.0 } else { 1.0 };
+            pattern[p] = !pattern[p];
+            let (px, py) = (p % SIZE, p / SIZE);
+            for (q, e) in energy.iter_mut().enumerate() {
+                let dx = (q % SIZE + SIZE - px) % SIZE;
+                let dy = (q / SIZE + SIZE - py) % SIZE;
+                *e += sign * kernel[dy * SIZE + dx];
+            }
+        };
+
+        // Random initial pattern.
+        let mut ones = 0;
+        let mut k = 0;
+        while (ones as f64) < INITIAL_FILL * n as f64 {
+            let p = (hash(&[k]) % n as u64) as usize;
+            k += 1;
+            if !pattern[p] {
+                toggle(&mut pattern, &mut energy, p);
+                ones += 1;
+            }
+        }
+
+        // Move points from the tightest cluster to the largest void until the
+        // two coincide, which spreads the initial pattern evenly.
+        loop {
+            let cluster = tightest_cluster(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, cluster);
+            let void = largest_void(&pattern, &energy);
+            if void == cluster {
+                toggle(&mut pattern, &mut energy, cluster);
+                break;
+            }
+            toggle(&mut pattern, &mut energy, void);
+        }
+        let prototype = (pattern.clone(), energy.clone());
+
+        // Rank the initial points by removing clusters first...
+        let mut ranks = vec![0; n];
+        for rank in (0..ones).rev() {
+            let cluster = tightest_cluster(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, cluster);
+            ranks[cluster] = rank as u16;
+        }
+
+        // ...then fill the remaining pixels void by void.
+        let (mut pattern, mut energy) = prototype;
+        for rank in ones..n {
+            let void = largest_void(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, void);
+            ranks[void] = rank as u16;
+        }
+
+        Self { ranks }
+    }
+
+    // Mask value in [0, 1) at pixel (i, j), tiling in both directions.
+    pub fn value(&self, i: i32, j: i32) -> f64 {
+        let x = i.rem_euclid(SIZE as i32) as usize;
+        let y = j.rem_euclid(SIZE as i32) as usize;
+        (self.ranks[y * SIZE + x] as f64 + 0.5) / (SIZE * SIZE) as f64
+    }
+}
+
+// The shared mask, generated on first use.
+pub fn mask() -> &'static BlueNoise {
+    static MASK: OnceLock<BlueNoise> = OnceLock::new();
+    MASK.get_or_init(BlueNoise::generate)
+}
+
+// exp(-r^2 / 2 sigma^2) for every toroidal offset within the tile.
+fn kernel() -> Vec<f64> {
+    let mut kernel = vec![0.0; SIZE * SIZE];
+    for (p, k) in kernel.iter_mut().enumerate() {
+        let dx = (p % SIZE).min(SIZE - p % SIZE) as f64;
+        let dy = (p / SIZE).min(SIZE - p / SIZE) as f64;
+        *k = (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+    }
+    kernel
+}
+
+fn tightest_cluster(pattern: &[bool], energy: &[f64]) -> usize {
+    (0..pattern.len())
+        .filter(|&p| pattern[p])
+        .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        .unwrap()
+}
+
+fn largest_void(pattern: &[bool], energy: &[f64]) -> usize {
+    (0..pattern.len())
+        .filter(|&p| !pattern[p])
+        .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mask_is_blue() {
+        let mask = mask();
+
+        // Every rank appears exactly once.
+        let mut seen = vec![false; SIZE * SIZE];
+        for &rank in &mask.ranks {
+            assert!(!seen[rank as usize]);
+            seen[rank as usize] = true;
+        }
+
+        // Averages over 4x4 blocks are much closer to 1/2 than for white
+        // noise, whose block means have a variance of 1/192.
+        let mut variance = 0.0;
+        for by in 0..SIZE / 4 {
+            for bx in 0..SIZE / 4 {
+                let mut mean = 0.0;
+                for y in 0..4 {
+                    for x in 0..4 {
+                        mean += mask.value((bx * 4 + x) as i32, (by * 4 + y) as i32) / 16.0;
+                    }
+                }
+                variance += (mean - 0.5).powi(2);
+            }
+        }
+        variance /= (SIZE * SIZE / 16) as f64;
+        assert!(variance < 1.0 / 192.0 / 4.0, "block variance {}", variance);
+
+        // The mask tiles.
+        assert_eq!(
+            mask.value(-1, 3),
+            mask.value(SIZE as i32 - 1, 3 + SIZE as i32)
+        );
+    }
+}
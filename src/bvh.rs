@@ -0,0 +1,255 @@
+use crate::aabb::Aabb;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::stats;
+use crate::utility::Interval;
+
+// A bounding volume hierarchy over a set of items, such as the objects of a
+// list or the triangles of a mesh, referred to by index. Rays visit only the
+// items in leaves whose boxes they pass through, nearer children first, so
+// tracing cost grows with the log of the item count instead of linearly.
+//
+// Nodes are stored depth first in one array. Leaves cover `count` items of
+// `order` from `first`; interior nodes have `count` 0, their left child right
+// after them and their right child at `first`.
+#[derive(Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    order: Vec<u32>, // Item indices, reordered so every leaf covers a contiguous run
+}
+
+#[derive(Clone)]
+struct Node {
+    bbox: Aabb,
+    mask: u32, // Union of the trace sets of the items below
+    first: u32,
+    count: u32,
+    axis: u32, // Split axis of interior nodes
+}
+
+impl Bvh {
+    // Builds the hierarchy over `len` items with the given boxes and trace
+    // sets, splitting at the median centroid along the widest axis until at
+    // most `leaf_size` items remain. `bounds` is called a few times per item
+    // and level, so it can compute boxes on the fly rather than from memory.
+    pub fn build(
+        len: usize,
+        leaf_size: usize,
+        bounds: impl Fn(usize) -> Aabb,
+        mask: impl Fn(usize) -> u32,
+    ) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            order: (0..len as u32).collect(),
+        };
+        if len == 0 {
+            return bvh;
+        }
+        crate::stage_span!("bvh_build", items = len);
+        // Centroids in single precision, which only decide the split.
+        let centroids: Vec<[f32; 3]> = (0..len)
+            .map(|i| {
+                let bbox = bounds(i);
+                [0, 1, 2].map(|axis| {
+                    let interval = bbox.axis_interval(axis);
+                    let mid = (interval.min + interval.max) / 2.0;
+                    // Unbounded objects have no useful centre.
+                    if mid.is_finite() {
+                        mid as f32
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect();
+        let mut order = std::mem::take(&mut bvh.order);
+        let builder = Builder {
+            leaf_size: leaf_size.max(1),
+            bounds: &bounds,
+            mask: &mask,
+            centroids: &centroids,
+        };
+        builder.build(&mut bvh.nodes, &mut order, 0);
+        bvh.order = order;
+        bvh
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |root| root.bbox)
+    }
+
+    // The nodes depth first as (box, first, count), in the layout described
+    // above, for copying the hierarchy to the GPU.
+    pub fn nodes(&self) -> impl Iterator<Item = (Aabb, u32, u32)> + '_ {
+        self.nodes
+            .iter()
+            .map(|node| (node.bbox, node.first, node.count))
+    }
+
+    // The item indices leaves refer to, in leaf order.
+    pub fn order(&self) -> &[u32] {
+        &self.order
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Levels from the root to the deepest leaf, counting a lone leaf as one.
+    pub fn depth(&self) -> usize {
+        if self.nodes.is_empty() {
+            return 0;
+        }
+        let mut deepest = 0;
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            deepest = deepest.max(depth);
+            if node.count == 0 {
+                stack.push((index + 1, depth + 1));
+                stack.push((node.first as usize, depth + 1));
+            }
+        }
+        deepest
+    }
+
+    // Bytes held by the hierarchy.
+    pub fn memory(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + self.order.capacity() * std::mem::size_of::<u32>()
+    }
+
+    // Calls `visit` with every item in a leaf the ray passes through within
+    // `ray_t`, skipping subtrees outside the ray's trace sets; `visit` returns
+    // a new upper bound for the interval, or None to stop.
+    pub fn traverse(
+        &self,
+        r: &Ray,
+        mut ray_t: Interval,
+        mut visit: impl FnMut(usize, Interval) -> Option<f64>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let direction = r.direction();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            stats::count_bvh_node_visit();
+            if node.mask & r.mask() == 0 || !node.bbox.hit(r, ray_t) {
+                continue;
+            }
+            if node.count == 0 {
+                let (left, right) = (index + 1, node.first as usize);
+                // Push the far child first so the near one is visited first
+                // and can shorten the interval for it.
+                if direction[node.axis as usize] < 0.0 {
+                    stack.push(left);
+                    stack.push(right);
+                } else {
+                    stack.push(right);
+                    stack.push(left);
+                }
+                continue;
+            }
+            let first = node.first as usize;
+            for &item in &self.order[first..first + node.count as usize] {
+                match visit(item as usize, ray_t) {
+                    Some(max) => ray_t.max = max,
+                    None => return,
+                }
+            }
+        }
+    }
+}
+
+struct Builder<'a, B, M> {
+    leaf_size: usize,
+    bounds: &'a B,
+    mask: &'a M,
+    centroids: &'a [[f32; 3]],
+}
+
+impl<B: Fn(usize) -> Aabb, M: Fn(usize) -> u32> Builder<'_, B, M> {
+    // Builds the subtree over `order`, whose first entry is at `offset` in
+    // the whole order.
+    fn build(&self, nodes: &mut Vec<Node>, order: &mut [u32], offset: usize) {
+        let (bbox, mask) = order.iter().fold((Aabb::EMPTY, 0), |(bbox, mask), &i| {
+            let i = i as usize;
+            (Aabb::union(&bbox, &(self.bounds)(i)), mask | (self.mask)(i))
+        });
+        let node = nodes.len();
+        nodes.push(Node {
+            bbox,
+            mask,
+            first: offset as u32,
+            count: order.len() as u32,
+            axis: 0,
+        });
+        if order.len() <= self.leaf_size {
+            return;
+        }
+
+        let spread = order.iter().fold(Aabb::EMPTY, |spread, &i| {
+            let p = self.centroids[i as usize].map(|c| c as f64);
+            let point = Point3::from(p[0], p[1], p[2]);
+            Aabb::union(&spread, &Aabb::from_points(point, point))
+        });
+        let axis = spread.longest_axis();
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |&a, &b| {
+            self.centroids[a as usize][axis].total_cmp(&self.centroids[b as usize][axis])
+        });
+        let (left, right) = order.split_at_mut(mid);
+        nodes[node].count = 0;
+        nodes[node].axis = axis as u32;
+        self.build(nodes, left, offset);
+        nodes[node].first = nodes.len() as u32;
+        self.build(nodes, right, offset + mid);
+    }
+}
+
+// For items that are in every trace set, such as triangles.
+pub fn all_sets(_: usize) -> u32 {
+    ALL_SETS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::INFINITY;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn visits_only_leaves_along_the_ray() {
+        // Unit boxes at x = 0, 2, 4, ... in trace set 1, except every
+        // fourth one, which is in set 2.
+        let bounds = |i: usize| {
+            let x = 2.0 * i as f64;
+            Aabb::from_points(Point3::from(x, 0.0, 0.0), Point3::from(x + 1.0, 1.0, 1.0))
+        };
+        let mask = |i: usize| if i.is_multiple_of(4) { 2 } else { 1 };
+        let bvh = Bvh::build(100, 2, bounds, mask);
+        assert!(bvh.depth() >= 6);
+        approx::assert_relative_eq!(bvh.bounding_box().x.max, 199.0);
+
+        let mut visited = Vec::new();
+        let across = Ray::from(Point3::from(40.5, 0.5, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        bvh.traverse(&across, Interval::from(0.0, INFINITY), |i, ray_t| {
+            visited.push(i);
+            Some(ray_t.max)
+        });
+        assert!(visited.contains(&20) && visited.len() <= 2);
+
+        // Along the row, set 2 rays skip the subtrees without set 2 boxes
+        // and start with the nearest.
+        let along = Ray::from(Point3::from(-5.0, 0.5, 0.5), Vec3::from(1.0, 0.0, 0.0)).with_mask(2);
+        let mut visited = Vec::new();
+        bvh.traverse(&along, Interval::from(0.0, INFINITY), |i, ray_t| {
+            visited.push(i);
+            Some(ray_t.max)
+        });
+        assert_eq!(visited[0], 0);
+        assert!((0..100).step_by(4).all(|i| visited.contains(&i)));
+        assert!(visited.len() <= 50);
+    }
+}
@@ -1,19 +1,84 @@
-use crate::color::{write_color, Color};
-use crate::hittable::Hittable;
-use crate::material::Scatterable;
+use crate::color::{write_color, Color, Tonemap, INVALID_SAMPLE};
+use crate::control::RenderControl;
+use crate::error::Error;
+use crate::framebuffer::{FrameBuffer, ImageFormat};
+use crate::hittable::HitRecord;
+use crate::material::{Material, Scatterable};
+use crate::mode::RenderMode;
+use crate::pdf::{HittablePdf, MisHeuristic, Pdf};
+use crate::progress::{self, ProgressEvent, Tile, Tracker};
 use crate::ray::{Point3, Ray};
+use crate::sampler::{hash, PixelSampler, Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::stats::{self, RenderStats};
+use crate::tiles::{self, TileEvent};
 use crate::utility::*;
 use crate::vec3::*;
 
-use log::info;
-use std::time::Instant;
+use log::{info, warn};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-#[derive(Default)]
-pub struct Camera {
+// Stopping rule for `Camera::render_until_clean`: stop once the image-wide
+// noise (see `FrameBuffer::noise`) at `percentile` is below `threshold`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseTarget {
+    pub threshold: f64,
+    pub percentile: f64,
+    // Passes to render before trusting the estimate.
+    pub min_passes: i32,
+}
+
+impl NoiseTarget {
+    pub fn from(threshold: f64) -> Self {
+        Self {
+            threshold,
+            percentile: 0.95,
+            min_passes: 8,
+        }
+    }
+}
+
+// Stopping rule for `Camera::render_adaptive`: every pixel takes samples until
+// the 95% confidence interval of its brightness is within `threshold` of it,
+// relative to the brightness, but at least `min_samples` and at most
+// `samples_per_pixel`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveTarget {
+    pub threshold: f64,
+    pub min_samples: i32,
+}
+
+impl AdaptiveTarget {
+    pub fn from(threshold: f64) -> Self {
+        Self {
+            threshold,
+            min_samples: 16,
+        }
+    }
+
+    // Whether pixel (i, j) of `buffer` needs more samples.
+    fn needs_samples(&self, buffer: &FrameBuffer, i: i32, j: i32) -> bool {
+        let samples = buffer.sample_count(i, j) as i32;
+        samples < self.min_samples
+            || (!buffer.is_invalid(i, j) && 1.96 * buffer.pixel_noise(i, j) > self.threshold)
+    }
+}
+
+// The settings a `Camera` is built from. Unset options take their defaults in
+// `build`, so scenes, scripts and the command line can each set some and
+// leave the rest.
+#[derive(Clone, Debug, Default)]
+pub struct CameraBuilder {
     pub aspect_ratio: Option<f64>,      // Ratio of image width over height
     pub image_width: Option<i32>,       // Rendered image width in pixel count
     pub samples_per_pixel: Option<i32>, // Count of random samples for each pixel
     pub max_depth: Option<i32>,         // Maximum number of ray bounces into scene
+    pub branches: Option<i32>,          // Scattered rays traced from each camera ray's first hit
+    pub mis: Option<MisHeuristic>, // Weighing of light found by sampling lights and by scattering
 
     pub vfov: Option<f64>,         // Vertical view angle (field of view)
     pub look_from: Option<Point3>, // Point camera is looking from
@@ -23,73 +88,314 @@ pub struct Camera {
     pub defocus_angle: Option<f64>, // Variation angle of rays through each pixel
     pub focus_dist: Option<f64>,    // Distance from camera lookfrom point to plane of perfect focus
 
-    image_height: i32,
-    center: Point3,
-    pixel00_loc: Point3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-    defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3,
+    pub shutter_open: Option<f64>, // Ray time at which the shutter opens
+    pub shutter_close: Option<f64>, // Ray time at which it closes; motion blur needs it later than opening
+
+    pub sampler: Option<SamplerKind>, // Source of the pixel and lens sample positions
+    pub seed: Option<u64>,            // Seed of the random samples, for renders that repeat exactly
+    pub mode: Option<RenderMode>,     // Material override for clay and wireframe renders
+    pub tonemap: Option<Tonemap>,     // Curve taking radiance to the values of image pixels
+    pub debug_nan: Option<bool>, // Check every sample for NaN or infinite values and log their source
+
+    pub tile_size: Option<i32>, // Edge in pixels of the square tiles shared out between threads
+    pub threads: Option<i32>,   // Threads rendering tiles; one per core if unset
+    pub time_limit: Option<f64>, // Seconds after which to stop adding passes and keep those done
 }
 
-impl Camera {
-    fn initialize(&mut self) {
-        if self.aspect_ratio.is_none() {
-            self.aspect_ratio = Some(1.0);
+impl CameraBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Sets a camera option from its textual form, as used by query strings and
+    // the distributed rendering protocol. Vectors are written as "x,y,z".
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn number<T: std::str::FromStr>(key: &str, value: &str) -> Result<Option<T>, String> {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid value for '{}': {}", key, value))
         }
-        if self.image_width.is_none() {
-            self.image_width = Some(100);
+
+        fn vector(key: &str, value: &str) -> Result<Option<Vec3>, String> {
+            let parts: Result<Vec<f64>, _> = value.split(',').map(|p| p.trim().parse()).collect();
+            match parts {
+                Ok(p) if p.len() == 3 => Ok(Some(Vec3::from(p[0], p[1], p[2]))),
+                _ => Err(format!("invalid vector for '{}': {}", key, value)),
+            }
         }
-        if self.samples_per_pixel.is_none() {
-            self.samples_per_pixel = Some(10);
+
+        match key {
+            "width" => self.image_width = number(key, value)?,
+            "aspect_ratio" => self.aspect_ratio = number(key, value)?,
+            "samples" => self.samples_per_pixel = number(key, value)?,
+            "max_depth" => self.max_depth = number(key, value)?,
+            "branches" => self.branches = number(key, value)?,
+            "mis" => self.mis = Some(value.parse()?),
+            "vfov" => self.vfov = number(key, value)?,
+            "look_from" => self.look_from = vector(key, value)?,
+            "look_at" => self.look_at = vector(key, value)?,
+            "vup" => self.vup = vector(key, value)?,
+            "defocus_angle" => self.defocus_angle = number(key, value)?,
+            "focus_dist" => self.focus_dist = number(key, value)?,
+            "shutter_open" => self.shutter_open = number(key, value)?,
+            "shutter_close" => self.shutter_close = number(key, value)?,
+            "sampler" => self.sampler = Some(value.parse()?),
+            "seed" => self.seed = number(key, value)?,
+            "mode" => self.mode = Some(value.parse()?),
+            "tonemap" => self.tonemap = Some(value.parse()?),
+            "debug_nan" => self.debug_nan = number(key, value)?,
+            "tile_size" => self.tile_size = number(key, value)?,
+            "threads" => self.threads = number(key, value)?,
+            "time_limit" => self.time_limit = number(key, value)?,
+            _ => return Err(format!("unknown camera setting '{}'", key)),
         }
-        if self.max_depth.is_none() {
-            self.max_depth = Some(10);
+        Ok(())
+    }
+
+    // The options that have been set, in the form accepted by `set`.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let vector = |v: Vec3| format!("{},{},{}", v.x(), v.y(), v.z());
+        let mut settings = Vec::new();
+        let mut push = |key: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                settings.push((key, value));
+            }
+        };
+
+        push("width", self.image_width.map(|x| x.to_string()));
+        push("aspect_ratio", self.aspect_ratio.map(|x| x.to_string()));
+        push("samples", self.samples_per_pixel.map(|x| x.to_string()));
+        push("max_depth", self.max_depth.map(|x| x.to_string()));
+        push("branches", self.branches.map(|x| x.to_string()));
+        push("mis", self.mis.map(|x| x.to_string()));
+        push("vfov", self.vfov.map(|x| x.to_string()));
+        push("look_from", self.look_from.map(vector));
+        push("look_at", self.look_at.map(vector));
+        push("vup", self.vup.map(vector));
+        push("defocus_angle", self.defocus_angle.map(|x| x.to_string()));
+        push("focus_dist", self.focus_dist.map(|x| x.to_string()));
+        push("shutter_open", self.shutter_open.map(|x| x.to_string()));
+        push("shutter_close", self.shutter_close.map(|x| x.to_string()));
+        push("sampler", self.sampler.map(|x| x.to_string()));
+        push("seed", self.seed.map(|x| x.to_string()));
+        push("mode", self.mode.map(|x| x.to_string()));
+        push("tonemap", self.tonemap.map(|x| x.to_string()));
+        push("debug_nan", self.debug_nan.map(|x| x.to_string()));
+        push("tile_size", self.tile_size.map(|x| x.to_string()));
+        push("threads", self.threads.map(|x| x.to_string()));
+        push("time_limit", self.time_limit.map(|x| x.to_string()));
+        settings
+    }
+
+    // The camera these settings describe, with defaults for those unset, or
+    // what is wrong with them.
+    pub fn build(&self) -> Result<Camera, String> {
+        let aspect_ratio = self.aspect_ratio.unwrap_or(1.0);
+        let image_width = self.image_width.unwrap_or(100);
+        let samples_per_pixel = self.samples_per_pixel.unwrap_or(10);
+        let max_depth = self.max_depth.unwrap_or(10);
+        let branches = self.branches.unwrap_or(1);
+        let vfov = self.vfov.unwrap_or(90.0);
+        let look_from = self.look_from.unwrap_or(Point3::from(0.0, 0.0, -1.0));
+        let look_at = self.look_at.unwrap_or(Point3::from(0.0, 0.0, 0.0));
+        let vup = self.vup.unwrap_or(Vec3::from(0.0, 1.0, 0.0));
+        let defocus_angle = self.defocus_angle.unwrap_or(0.0);
+        let focus_dist = self.focus_dist.unwrap_or(10.0);
+        let shutter_open = self.shutter_open.unwrap_or(0.0);
+        let shutter_close = self.shutter_close.unwrap_or(shutter_open);
+        let tile_size = self.tile_size.unwrap_or(32);
+
+        let positive = |name: &str, value: f64| {
+            if value > 0.0 && value.is_finite() {
+                Ok(())
+            } else {
+                Err(format!("{} must be positive, not {}", name, value))
+            }
+        };
+        positive("width", image_width as f64)?;
+        positive("aspect_ratio", aspect_ratio)?;
+        positive("samples", samples_per_pixel as f64)?;
+        positive("branches", branches as f64)?;
+        positive("focus_dist", focus_dist)?;
+        positive("tile_size", tile_size as f64)?;
+        if let Some(threads) = self.threads {
+            positive("threads", threads as f64)?;
         }
-        if self.vfov.is_none() {
-            self.vfov = Some(90.0);
+        if max_depth < 0 {
+            return Err(format!("max_depth can't be negative, not {}", max_depth));
         }
-        if self.look_from.is_none() {
-            self.look_from = Some(Point3::from(0.0, 0.0, -1.0));
+        if vfov.is_nan() || vfov <= 0.0 || vfov >= 180.0 {
+            return Err(format!(
+                "vfov must be between 0 and 180 degrees, not {}",
+                vfov
+            ));
         }
-        if self.look_at.is_none() {
-            self.look_at = Some(Point3::from(0.0, 0.0, 0.0));
+        if !(0.0..180.0).contains(&defocus_angle) {
+            return Err(format!(
+                "defocus_angle must be from 0 to 180 degrees, not {}",
+                defocus_angle
+            ));
         }
-        if self.vup.is_none() {
-            self.vup = Some(Point3::from(0.0, 1.0, 0.0));
+        if shutter_close < shutter_open {
+            return Err(format!(
+                "shutter_close ({}) is before shutter_open ({})",
+                shutter_close, shutter_open
+            ));
         }
-        if self.defocus_angle.is_none() {
-            self.defocus_angle = Some(0.0);
+        if let Some(seconds) = self.time_limit.filter(|s| s.is_nan() || *s < 0.0) {
+            return Err(format!("time_limit can't be negative, not {}", seconds));
         }
-        if self.focus_dist.is_none() {
-            self.focus_dist = Some(10.0);
+        let forward = look_at - look_from;
+        if forward.length_squared() == 0.0 {
+            return Err(format!("look_from and look_at are both {:?}", look_from));
+        }
+        if cross(&vup, &forward).length_squared()
+            <= 1e-12 * vup.length_squared() * forward.length_squared()
+        {
+            return Err(format!(
+                "vup {:?} is parallel to the view direction {:?}",
+                vup, forward
+            ));
         }
 
-        self.image_height = (self.image_width.unwrap() as f64 / self.aspect_ratio.unwrap()) as i32;
-        self.image_height = if self.image_height > 1 {
-            self.image_height
-        } else {
-            1
+        let mut camera = Camera {
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            max_depth,
+            branches,
+            mis: self.mis.unwrap_or_default(),
+            vfov,
+            look_from,
+            look_at,
+            vup,
+            defocus_angle,
+            focus_dist,
+            shutter_open,
+            shutter_close,
+            sampler: self.sampler.unwrap_or(SamplerKind::Random),
+            seed: self.seed,
+            mode: self.mode.unwrap_or(RenderMode::Shaded),
+            tonemap: self.tonemap.unwrap_or(Tonemap::Gamma),
+            debug_nan: self.debug_nan.unwrap_or(false),
+            tile_size,
+            threads: self.threads,
+            time_limit: self.time_limit,
+            image_height: 1,
+            center: look_from,
+            pixel00_loc: Point3::new(),
+            pixel_delta_u: Vec3::new(),
+            pixel_delta_v: Vec3::new(),
+            u: Vec3::new(),
+            v: Vec3::new(),
+            w: Vec3::new(),
+            defocus_disk_u: Vec3::new(),
+            defocus_disk_v: Vec3::new(),
         };
+        camera.initialize();
+        Ok(camera)
+    }
+}
+
+// A camera ready to render, built from a `CameraBuilder`. Its settings don't
+// change; `builder` gives them back to make a camera that differs.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    aspect_ratio: f64,
+    image_width: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+    branches: i32,
+    mis: MisHeuristic,
+
+    vfov: f64,
+    look_from: Point3,
+    look_at: Point3,
+    vup: Vec3,
+
+    defocus_angle: f64,
+    focus_dist: f64,
+
+    shutter_open: f64,
+    shutter_close: f64,
+
+    sampler: SamplerKind,
+    seed: Option<u64>,
+    mode: RenderMode,
+    tonemap: Tonemap,
+    debug_nan: bool,
+
+    tile_size: i32,
+    threads: Option<i32>,
+    time_limit: Option<f64>,
 
-        self.center = self.look_from.unwrap();
+    image_height: i32,
+    center: Point3,
+    pixel00_loc: Point3,
+    pixel_delta_u: Vec3,
+    pixel_delta_v: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        CameraBuilder::new()
+            .build()
+            .expect("the default settings are valid")
+    }
+}
+
+impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::new()
+    }
+
+    // The camera's settings, every one set, to build a changed camera from.
+    pub fn to_builder(&self) -> CameraBuilder {
+        CameraBuilder {
+            aspect_ratio: Some(self.aspect_ratio),
+            image_width: Some(self.image_width),
+            samples_per_pixel: Some(self.samples_per_pixel),
+            max_depth: Some(self.max_depth),
+            branches: Some(self.branches),
+            mis: Some(self.mis),
+            vfov: Some(self.vfov),
+            look_from: Some(self.look_from),
+            look_at: Some(self.look_at),
+            vup: Some(self.vup),
+            defocus_angle: Some(self.defocus_angle),
+            focus_dist: Some(self.focus_dist),
+            shutter_open: Some(self.shutter_open),
+            shutter_close: Some(self.shutter_close),
+            sampler: Some(self.sampler),
+            seed: self.seed,
+            mode: Some(self.mode),
+            tonemap: Some(self.tonemap),
+            debug_nan: Some(self.debug_nan),
+            tile_size: Some(self.tile_size),
+            threads: self.threads,
+            time_limit: self.time_limit,
+        }
+    }
+
+    fn initialize(&mut self) {
+        self.image_height = ((self.image_width as f64 / self.aspect_ratio) as i32).max(1);
+        self.center = self.look_from;
 
         // Determine viewport dimensions.
-        let theta = degrees_to_radians(
-            self.vfov
-                .expect("Camera is missing member vfov: vertical field of view"),
-        );
+        let theta = degrees_to_radians(self.vfov);
         let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist.unwrap();
-        let viewport_width =
-            viewport_height * (self.image_width.unwrap() as f64 / self.image_height as f64);
+        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame
-        self.w = unit_vector(self.look_from.unwrap() - self.look_at.unwrap());
-        self.u = unit_vector(cross(&self.vup.unwrap(), &self.w));
+        self.w = unit_vector(self.look_from - self.look_at);
+        self.u = unit_vector(cross(&self.vup, &self.w));
         self.v = cross(&self.w, &self.u);
 
         info!("u: {:?}, v: {:?}, w: {:?}", self.u, self.v, self.w);
@@ -99,95 +405,1587 @@ impl Camera {
         let viewport_v = viewport_height * -self.v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        self.pixel_delta_u = viewport_u / self.image_width.unwrap() as f64;
+        self.pixel_delta_u = viewport_u / self.image_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
         // Calculate the location of the upper left pixel.
         let viewport_upper_left =
-            self.center - self.focus_dist.unwrap() * self.w - viewport_u / 2.0 - viewport_v / 2.0;
+            self.center - self.focus_dist * self.w - viewport_u / 2.0 - viewport_v / 2.0;
         info!("upper left {:?}", viewport_upper_left);
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius =
-            self.focus_dist.unwrap() * degrees_to_radians(self.defocus_angle.unwrap() / 2.0).tan();
+        let defocus_radius = self.focus_dist * degrees_to_radians(self.defocus_angle / 2.0).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
+    pub fn image_width(&self) -> i32 {
+        self.image_width
+    }
+
+    pub fn samples_per_pixel(&self) -> i32 {
+        self.samples_per_pixel
+    }
+
+    pub fn max_depth(&self) -> i32 {
+        self.max_depth
+    }
+
+    pub fn vfov(&self) -> f64 {
+        self.vfov
+    }
+
+    pub fn look_from(&self) -> Point3 {
+        self.look_from
+    }
+
+    pub fn look_at(&self) -> Point3 {
+        self.look_at
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    pub fn time_limit(&self) -> Option<f64> {
+        self.time_limit
+    }
+
+    pub fn image_height(&self) -> i32 {
+        self.image_height
+    }
+
+    // An empty buffer the size of the image, encoded with the camera's
+    // tonemap.
+    pub fn frame_buffer(&self) -> FrameBuffer {
+        let mut buffer = FrameBuffer::new(self.image_width, self.image_height);
+        buffer.set_tonemap(self.tonemap);
+        buffer
+    }
+
+    // The sample values for sample `s` of pixel (i, j).
+    pub(crate) fn pixel_sampler(&self, i: i32, j: i32, s: u64) -> PixelSampler {
+        let samples = self.samples_per_pixel as u64;
+        PixelSampler::start(self.sampler, i, j, s, samples, self.seed)
+    }
+
+    // Jittered ray through pixel (i, j), with defocus blur, at a random time
+    // while the shutter is open. An instant shutter draws no time sample, so
+    // still renders don't spend a sample dimension on it.
+    pub(crate) fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> Ray {
         let pixel_center =
             self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_sample = pixel_center + self.pixel_sample_square(sampler.get_2d());
 
-        let ray_origin = if self.defocus_angle.unwrap() <= 0.0 {
+        let lens = sampler.get_2d();
+        let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens)
         };
         let ray_direction = pixel_sample - ray_origin;
 
+        let (open, close) = (self.shutter_open, self.shutter_close);
+        let time = if close > open {
+            open + sampler.get_1d() * (close - open)
+        } else {
+            open
+        };
+
         Ray::from(ray_origin, ray_direction)
+            .with_time(time)
+            .with_key(ray_key(sampler))
+            .with_eye(self.center)
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random();
-        let py = -0.5 + random();
+    // Ray from the camera center through the middle of pixel (i, j), without
+    // jitter or defocus blur. Used for picking.
+    pub fn primary_ray(&self, i: i32, j: i32) -> Ray {
+        let pixel_center =
+            self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
+        Ray::from(self.center, pixel_center - self.center).with_time(self.shutter_open)
+    }
+
+    // Continuous pixel coordinates (pixel centers at whole numbers) at which
+    // `p` appears, or None if it is behind the camera. The inverse of
+    // `primary_ray`.
+    pub fn project(&self, p: Point3) -> Option<(f64, f64)> {
+        let d = p - self.center;
+        let along = dot(&d, &self.w);
+        if along >= 0.0 {
+            return None;
+        }
+        let on_viewport = self.center + d * (-self.focus_dist / along);
+        let offset = on_viewport - self.pixel00_loc;
+        Some((
+            dot(&offset, &self.pixel_delta_u) / self.pixel_delta_u.length_squared(),
+            dot(&offset, &self.pixel_delta_v) / self.pixel_delta_v.length_squared(),
+        ))
+    }
+
+    fn pixel_sample_square(&self, (u, v): (f64, f64)) -> Vec3 {
+        let px = -0.5 + u;
+        let py = -0.5 + v;
 
         px * self.pixel_delta_u + py * self.pixel_delta_v
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, (u, v): (f64, f64)) -> Point3 {
+        let p = concentric_disk(u, v);
         self.center + p[0] * self.defocus_disk_u + p[1] * self.defocus_disk_v
     }
 
-    fn ray_color(r: &Ray, depth: i32, world: &dyn Hittable) -> Color {
-        // When exceeds the ray bounce limit, no more light is gathered
-        if depth <= 0 {
+    // Radiance along `r`. At its first hit, `branches` scattered rays are
+    // traced and averaged; deeper hits trace one each, so branching at the
+    // first hit spends more of a sample's rays on its first bounce, where they
+    // matter most, without the cost growing exponentially with depth. Hits add
+    // what their material emits; surfaces scatter with `overridden` instead of
+    // their own material, if given, but lights still shine and volumes keep
+    // scattering as they did. Light found both ways is weighed by `heuristic`.
+    pub(crate) fn ray_color(
+        r: &Ray,
+        depth: i32,
+        branches: i32,
+        overridden: Option<&Material>,
+        heuristic: MisHeuristic,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let tracing = Tracing {
+            overridden,
+            scene,
+            heuristic,
+            checked: false,
+        };
+        Self::trace(r, None, depth, branches, &tracing, sampler)
+            .unwrap_or_else(|_| unreachable!("only checked paths fail"))
+    }
+
+    // The ray `mat` scatters at the hit and the attenuation along it, with
+    // the density it was drawn with if the material is diffuse and the scene
+    // has lights to sample, for `trace` to weigh the light it finds against
+    // `sample_lights`.
+    fn scatter(
+        r: &Ray,
+        rec: &HitRecord,
+        mat: &Material,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color, Option<f64>)> {
+        let diffuse = if scene.lights.is_empty() {
+            None
+        } else {
+            mat.scatter_pdf(r, rec)
+        };
+        let Some((attenuation, mat_pdf)) = diffuse else {
+            let (scattered, attenuation) = mat.scatter(r, rec, sampler)?;
+            let scattered = scattered.with_key(ray_key(sampler)).with_eye(r.eye());
+            return Some((scattered, attenuation, None));
+        };
+        let direction = mat_pdf.generate(sampler);
+        let scattering = mat_pdf.value(&direction);
+        if scattering <= 0.0 {
+            return None;
+        }
+        let scattered = Ray::from(rec.p, direction)
+            .with_mask(rec.trace_set)
+            .with_time(r.time())
+            .with_key(ray_key(sampler))
+            .with_eye(r.eye());
+        Some((scattered, attenuation, Some(scattering)))
+    }
+
+    // Next event estimation: light reaching the hit from a point drawn on
+    // the scene's lights, scattered back along the ray by `mat`. A shadow ray
+    // towards the point finds what emits there, if anything. Each light is
+    // also found by the rays `scatter` draws from the material, so both
+    // estimates are weighed by the camera's heuristic, by how likely each
+    // strategy was to draw the direction: the light sampling wins on small
+    // lights, the material's on large ones and on glossy highlights. On the
+    // `last` bounce, whose scattered ray is never traced, it takes all of it.
+    fn sample_lights(
+        r: &Ray,
+        rec: &HitRecord,
+        mat: &Material,
+        tracing: &Tracing,
+        sampler: &mut dyn Sampler,
+        last: bool,
+    ) -> Color {
+        let scene = tracing.scene;
+        if scene.lights.is_empty() {
             return Color::new();
         }
+        let Some((attenuation, mat_pdf)) = mat.scatter_pdf(r, rec) else {
+            return Color::new();
+        };
+        let lights = HittablePdf::from(&scene.lights, rec.p);
+        let direction = lights.generate(sampler);
+        let (light_pdf, scattering) = (lights.value(&direction), mat_pdf.value(&direction));
+        if light_pdf <= 0.0 || scattering <= 0.0 {
+            return Color::new();
+        }
+        stats::count_shadow_ray();
+        let shadow = Ray::from(rec.p, direction)
+            .with_mask(rec.trace_set)
+            .with_time(r.time())
+            .with_key(ray_key(sampler))
+            .with_eye(r.eye());
+        let Some(hit) = scene.hit(&shadow, Interval::from(0.001, INFINITY)) else {
+            return Color::new();
+        };
+        let weight = if last {
+            1.0
+        } else {
+            tracing.heuristic.weight(light_pdf, scattering)
+        };
+        attenuation * hit.mat.emitted(&hit) * (scattering / light_pdf * weight)
+    }
 
-        if let Some(rec) = world.hit(
-            r,
-            Interval {
-                min: 0.001,
-                max: INFINITY,
-            },
-        ) {
-            if let Some((scattered, attenuation)) = rec.mat.scatter(r, &rec) {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
-            }
+    // Light from the scene's punctual lights that `mat` scatters back along
+    // the ray, each checked for a clear line to the hit with a shadow ray.
+    // Only materials with a diffuse part see them: with no area, they never
+    // show up in a mirror or through glass.
+    fn direct_light(
+        r: &Ray,
+        rec: &HitRecord,
+        mat: &Material,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if scene.punctual_lights.is_empty() {
             return Color::new();
         }
+        let Some((attenuation, pdf)) = mat.scatter_pdf(r, rec) else {
+            return Color::new();
+        };
+        let mut light = Color::new();
+        for sample in scene.punctual_lights.iter().filter_map(|l| l.sample(rec.p)) {
+            let scattering = pdf.value(&sample.direction);
+            if scattering <= 0.0 {
+                continue;
+            }
+            stats::count_shadow_ray();
+            let shadow = Ray::from(rec.p, sample.direction)
+                .with_mask(rec.trace_set)
+                .with_time(r.time())
+                .with_key(ray_key(sampler))
+                .with_eye(r.eye());
+            if !scene.is_occluded(&shadow, Interval::from(0.001, sample.distance - 0.001)) {
+                light += attenuation * sample.irradiance * scattering;
+            }
+        }
+        light
+    }
 
-        let unit_direction = unit_vector(r.direction());
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::from(1.0, 1.0, 1.0) + a * Color::from(0.5, 0.7, 1.0)
+    // The material a hit scatters with: its own, or the render mode's, which
+    // applies to surfaces but not to volumes.
+    fn shading_material<'a>(rec: &HitRecord<'a>, overridden: Option<&'a Material>) -> &'a Material {
+        match overridden {
+            Some(mat) if !rec.mat.is_volumetric() => mat,
+            _ => rec.mat,
+        }
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
-        self.initialize();
+    // The path behind `ray_color`, followed bounce by bounce rather than by
+    // recursion, so deep paths don't grow the stack: each hit adds what it
+    // emits and the light `sample_lights` finds from it, weighed by the
+    // attenuation gathered on the way to it. Only the
+    // branches at the first hit recurse, one level deep. With `checked` set,
+    // the first non-finite value fails the path.
+    fn trace(
+        r: &Ray,
+        scattering: Option<f64>,
+        depth: i32,
+        branches: i32,
+        tracing: &Tracing,
+        sampler: &mut dyn Sampler,
+    ) -> Result<Color, String> {
+        let Tracing {
+            overridden,
+            scene,
+            heuristic,
+            checked,
+        } = *tracing;
+        let mut radiance = Color::new();
+        let mut throughput = Color::gray(1.0);
+        let mut next: Option<Ray> = None;
+        // The density the ray was scattered with, if `sample_lights` also
+        // looked for the light it may find.
+        let mut scattering = scattering;
+        // When exceeds the ray bounce limit, no more light is gathered
+        for bounce in 0..depth {
+            let ray = next.as_ref().unwrap_or(r);
+            stats::count_ray();
+            let Some(rec) = scene.hit(ray, Interval::from(0.001, INFINITY)) else {
+                let color = scene.background.value(ray);
+                if checked && !color.is_finite() {
+                    return Err(format!(
+                        "background returned {:?} for direction {:?}",
+                        color,
+                        ray.direction()
+                    ));
+                }
+                radiance += throughput * color;
+                break;
+            };
+            let mat = Self::shading_material(&rec, overridden);
+            let mut emitted = rec.mat.emitted(&rec);
+            if let Some(scattering) = scattering {
+                let lights = HittablePdf::from(&scene.lights, ray.origin());
+                let light_pdf = lights.value(&ray.direction());
+                emitted *= heuristic.weight(scattering, light_pdf);
+            }
+            let last = bounce == depth - 1;
+            emitted += Self::sample_lights(ray, &rec, mat, tracing, sampler, last);
+            if let Some(ambient) = &scene.ambient {
+                emitted += mat.albedo(&rec) * ambient.value(&rec.normal);
+            }
+            emitted += Self::direct_light(ray, &rec, mat, scene, sampler);
+            radiance += throughput * emitted;
 
-        // Render
+            let branching = bounce == 0 && branches > 1;
+            if branching {
+                let mut gathered = Color::new();
+                for _ in 0..branches {
+                    let Some((scattered, attenuation, scattering)) =
+                        Self::scatter_checked(ray, &rec, mat, scene, sampler, checked)?
+                    else {
+                        continue;
+                    };
+                    gathered += attenuation
+                        * Self::trace(&scattered, scattering, depth - 1, 1, tracing, sampler)?;
+                }
+                radiance += gathered / branches as f64;
+            }
+            if checked && !radiance.is_finite() {
+                return Err(format!(
+                    "radiance overflowed at {:?} on a {} material",
+                    rec.p,
+                    mat.name()
+                ));
+            }
+            if branching {
+                break;
+            }
+            let Some((scattered, attenuation, pdf)) =
+                Self::scatter_checked(ray, &rec, mat, scene, sampler, checked)?
+            else {
+                break;
+            };
+            throughput *= attenuation;
+            next = Some(scattered);
+            scattering = pdf;
+        }
+        Ok(radiance)
+    }
+
+    // `scatter`, failing with `checked` set if the ray or attenuation isn't
+    // finite.
+    fn scatter_checked(
+        r: &Ray,
+        rec: &HitRecord,
+        mat: &Material,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        checked: bool,
+    ) -> Result<Option<(Ray, Color, Option<f64>)>, String> {
+        let scattered = Self::scatter(r, rec, mat, scene, sampler);
+        if let Some((ray, attenuation, _)) = scattered.as_ref().filter(|_| checked) {
+            if !attenuation.is_finite() || !ray.direction().is_finite() {
+                return Err(format!(
+                    "{} material at {:?} scattered towards {:?} with attenuation {:?}",
+                    mat.name(),
+                    rec.p,
+                    ray.direction(),
+                    attenuation
+                ));
+            }
+        }
+        Ok(scattered)
+    }
+
+    // Sample `s` of pixel (i, j), or None if it was NaN or infinite. The
+    // source of the bad value is only tracked down with `debug_nan` set.
+    fn sample(&self, scene: &Scene, i: i32, j: i32, s: i32) -> Option<Color> {
+        stats::count_path();
+        let mut sampler = self.pixel_sampler(i, j, s as u64);
+        let r = self.get_ray(i, j, &mut sampler);
+        let depth = self.max_depth;
+        let branches = self.branches;
+        let overridden = self.mode.material();
+        let tracing = Tracing {
+            overridden: overridden.as_ref(),
+            scene,
+            heuristic: self.mis,
+            checked: self.debug_nan,
+        };
+        match Self::trace(&r, None, depth, branches, &tracing, &mut sampler) {
+            Ok(color) if !self.debug_nan => color.is_finite().then_some(color),
+            Ok(color) => Some(color),
+            Err(err) => {
+                warn!("invalid sample {} at pixel ({}, {}): {}", s, i, j, err);
+                None
+            }
+        }
+    }
+
+    // Sum of `samples_per_pixel` samples for pixel (i, j).
+    pub fn sample_pixel(&self, scene: &Scene, i: i32, j: i32) -> Color {
+        self.sample_pixel_with(scene, i, j, self.samples_per_pixel)
+    }
+
+    // Sum of `samples` samples for pixel (i, j). If any sample is NaN or
+    // infinite, the whole pixel becomes the `INVALID_SAMPLE` marker.
+    pub fn sample_pixel_with(&self, scene: &Scene, i: i32, j: i32, samples: i32) -> Color {
+        self.sample_pixel_from(scene, i, j, 0, samples)
+    }
+
+    // Sum of samples `first..first + samples` for pixel (i, j). Pieces of a
+    // pixel rendered separately (e.g. shards) must use disjoint ranges so that
+    // quasi-random samplers do not repeat the same sample positions.
+    pub fn sample_pixel_from(
+        &self,
+        scene: &Scene,
+        i: i32,
+        j: i32,
+        first: i32,
+        samples: i32,
+    ) -> Color {
+        let mut pixel_color = Color::new();
+        for s in first..first + samples {
+            match self.sample(scene, i, j, s) {
+                Some(color) => pixel_color += color,
+                None => {
+                    warn!("pixel ({}, {}) has a non-finite sample", i, j);
+                    return INVALID_SAMPLE * samples as f64;
+                }
+            }
+        }
+        pixel_color
+    }
+
+    // Adds one sample per pixel to the accumulation buffer. The camera must have
+    // been initialized, and the buffer sized to match the image.
+    pub fn render_pass(&self, scene: &Scene, buffer: &mut FrameBuffer) {
+        self.render_pass_controlled(scene, buffer, &RenderControl::new());
+    }
+
+    // Like `render_pass`, checking `control` before every scanline. Returns
+    // false if the pass was cancelled part way; the rows done keep their
+    // samples, but the pass is not counted.
+    pub fn render_pass_controlled(
+        &self,
+        scene: &Scene,
+        buffer: &mut FrameBuffer,
+        control: &RenderControl,
+    ) -> bool {
+        let pass = buffer.samples();
+        crate::stage_span!("render_pass", pass);
+        for j in 0..self.image_height {
+            if !control.checkpoint() {
+                return false;
+            }
+            for i in 0..self.image_width {
+                match self.sample(scene, i, j, pass) {
+                    Some(color) => buffer.add_sample(i, j, color),
+                    None => buffer.mark_invalid(i, j),
+                }
+            }
+        }
+        buffer.complete_pass();
+        true
+    }
+
+    // Adds progressive passes until the image meets `target`, giving up at
+    // `samples_per_pixel` passes.
+    pub fn render_until_clean(
+        &self,
+        scene: &Scene,
+        target: &NoiseTarget,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> FrameBuffer {
+        let width = self.image_width;
+        let max_passes = self.samples_per_pixel;
+        let mut buffer = self.frame_buffer();
+        let mut progress = Tracker::start(width, self.image_height, max_passes, on_progress);
+        while buffer.samples() < max_passes {
+            if !self.render_pass_controlled(scene, &mut buffer, control) {
+                break;
+            }
+            let pass = buffer.samples();
+            progress.pass_complete(pass, max_passes, (width * self.image_height) as u64);
+            if pass >= target.min_passes {
+                let noise = buffer.noise(target.percentile);
+                info!("Pass {}: noise {:.4}", pass, noise);
+                if noise <= target.threshold {
+                    break;
+                }
+            }
+        }
+        progress.finish();
+        buffer
+    }
+
+    // Adds a sample to every pixel that doesn't yet meet `target`, pass after
+    // pass, until every pixel does or has `samples_per_pixel` samples. Smooth
+    // and dark regions such as the sky stop early while edges, soft shadows
+    // and caustics keep sampling. The buffer's sample counts say how many
+    // samples each pixel took.
+    pub fn render_adaptive(
+        &self,
+        scene: &Scene,
+        target: &AdaptiveTarget,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> FrameBuffer {
+        let width = self.image_width;
+        let max_samples = self.samples_per_pixel;
+        let mut buffer = self.frame_buffer();
+        let mut progress = Tracker::start(width, self.image_height, max_samples, on_progress);
+        let tiles = self.tiles();
+        for pass in 0..max_samples {
+            let active: Vec<bool> = (0..self.image_height)
+                .flat_map(|j| (0..width).map(move |i| (i, j)))
+                .map(|(i, j)| target.needs_samples(&buffer, i, j))
+                .collect();
+            let sampled = active.iter().filter(|&&active| active).count();
+            if sampled == 0 {
+                break;
+            }
+            let is_active = |i: i32, j: i32| active[(j * width + i) as usize];
+            let trace = |tile| {
+                control.checkpoint().then(|| {
+                    tile_pixels(tile)
+                        .map(|(i, j)| {
+                            if !is_active(i, j) {
+                                return Color::new();
+                            }
+                            let sample = self.sample(scene, i, j, pass);
+                            sample.unwrap_or(Color::gray(f64::NAN))
+                        })
+                        .collect()
+                })
+            };
+            let mut finished = 0;
+            tiles::render(&tiles, self.render_threads(), trace, &mut |event| {
+                if let TileEvent::Finished { tile, pixels, .. } = event {
+                    let pixels = tile_pixels(tile).zip(pixels);
+                    for ((i, j), color) in pixels.filter(|&((i, j), _)| is_active(i, j)) {
+                        if color.is_finite() {
+                            buffer.add_sample(i, j, color);
+                        } else {
+                            buffer.mark_invalid(i, j);
+                        }
+                    }
+                    finished += 1;
+                }
+            });
+            if finished < tiles.len() {
+                break;
+            }
+            buffer.complete_pass();
+            progress.pass_complete(pass + 1, max_samples, sampled as u64);
+            info!(
+                "Pass {}: sampled {:.1}% of pixels",
+                pass + 1,
+                100.0 * sampled as f64 / active.len() as f64
+            );
+        }
+        progress.finish();
+        buffer
+    }
+
+    // Renders one sample per pixel per pass, up to `samples_per_pixel`
+    // passes, each pass tile by tile on `threads` threads. After every
+    // `every` passes but the last, `on_pass` gets the image so far, e.g. to
+    // write it for a look before the render is done. A cancel stops after the
+    // last complete pass, so every pixel has the same number of samples, and
+    // so does running out of `time_limit` once the first pass is done.
+    pub fn render_progressive(
+        &self,
+        scene: &Scene,
+        every: i32,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+        on_pass: &mut dyn FnMut(&FrameBuffer),
+    ) -> FrameBuffer {
+        let buffer = self.frame_buffer();
+        self.resume_progressive(scene, buffer, every, control, on_progress, on_pass)
+            .expect("the buffer is sized for the camera")
+    }
+
+    // Like `render_progressive`, adding passes to `buffer`, e.g. one loaded
+    // from a checkpoint, until it has `samples_per_pixel`. Fails if the
+    // buffer isn't the size of the image.
+    pub fn resume_progressive(
+        &self,
+        scene: &Scene,
+        mut buffer: FrameBuffer,
+        every: i32,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+        on_pass: &mut dyn FnMut(&FrameBuffer),
+    ) -> Result<FrameBuffer, String> {
+        let width = self.image_width;
+        if (buffer.width(), buffer.height()) != (width, self.image_height) {
+            return Err(format!(
+                "cannot resume a {}x{} render at {}x{}",
+                buffer.width(),
+                buffer.height(),
+                width,
+                self.image_height
+            ));
+        }
+        buffer.set_tonemap(self.tonemap);
+        let max_passes = self.samples_per_pixel;
+        crate::stage_span!(
+            "render",
+            width,
+            height = self.image_height,
+            samples = max_passes
+        );
+        let remaining = (max_passes - buffer.samples()).max(0);
+        let mut progress = Tracker::start(width, self.image_height, remaining, on_progress);
+        let tiles = self.tiles();
+        let deadline = self
+            .time_limit
+            .map(|seconds| Instant::now() + Duration::from_secs_f64(seconds.max(0.0)));
+        while buffer.samples() < max_passes {
+            // The first pass always finishes, so there is an image to keep.
+            let deadline = deadline.filter(|_| buffer.samples() > 0);
+            if !self.render_pass_tiled(scene, &mut buffer, &tiles, control, deadline) {
+                break;
+            }
+            let pass = buffer.samples();
+            progress.pass_complete(pass, max_passes, (width * self.image_height) as u64);
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            if every > 0 && pass % every == 0 && pass < max_passes {
+                on_pass(&buffer);
+            }
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            info!(
+                "Stopped at the time limit after {} passes",
+                buffer.samples()
+            );
+        }
+        progress.finish();
+        Ok(buffer)
+    }
+
+    // Like `render_pass_controlled`, but shares the pass out as `tiles` over
+    // the render threads, and gives up once `deadline` has passed. A
+    // cancelled or unfinished pass adds nothing to the buffer.
+    fn render_pass_tiled(
+        &self,
+        scene: &Scene,
+        buffer: &mut FrameBuffer,
+        tiles: &[Tile],
+        control: &RenderControl,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let pass = buffer.samples();
+        crate::stage_span!("render_pass", pass);
+        let in_time = || deadline.is_none_or(|deadline| Instant::now() < deadline);
+        let trace = |tile| {
+            (control.checkpoint() && in_time()).then(|| {
+                tile_pixels(tile)
+                    .map(|(i, j)| {
+                        let sample = self.sample(scene, i, j, pass);
+                        sample.unwrap_or(Color::gray(f64::NAN))
+                    })
+                    .collect()
+            })
+        };
+        let mut finished = Vec::with_capacity(tiles.len());
+        tiles::render(tiles, self.render_threads(), trace, &mut |event| {
+            if let TileEvent::Finished { tile, pixels, .. } = event {
+                finished.push((tile, pixels));
+            }
+        });
+        if finished.len() < tiles.len() {
+            return false;
+        }
+        for (tile, pixels) in finished {
+            for ((i, j), color) in tile_pixels(tile).zip(pixels) {
+                if color.is_finite() {
+                    buffer.add_sample(i, j, color);
+                } else {
+                    buffer.mark_invalid(i, j);
+                }
+            }
+        }
+        buffer.complete_pass();
+        true
+    }
+
+    // Renders all samples of every pixel into an accumulation buffer, tile by
+    // tile on `threads` threads, checking `control` before every tile. After a
+    // cancel, the buffer holds the tiles finished so far and the others have
+    // no samples.
+    pub fn render_buffer(
+        &self,
+        scene: &Scene,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> FrameBuffer {
+        self.render_buffer_showing(scene, control, on_progress, &mut |_, _| {})
+    }
+
+    // Like `render_buffer`, also handing every finished tile and the summed
+    // samples of its pixels, row by row, to `on_tile`, e.g. to show the image
+    // as it comes in.
+    pub fn render_buffer_showing(
+        &self,
+        scene: &Scene,
+        control: &RenderControl,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+        on_tile: &mut dyn FnMut(Tile, &[Color]),
+    ) -> FrameBuffer {
+        let width = self.image_width;
+        let samples = self.samples_per_pixel;
+        crate::stage_span!("render", width, height = self.image_height, samples);
+
+        let mut buffer = self.frame_buffer();
+        let mut progress = Tracker::start(width, self.image_height, samples, on_progress);
+        let trace = |tile| control.checkpoint().then(|| self.trace_tile(scene, tile));
+        tiles::render(
+            &self.tiles(),
+            self.render_threads(),
+            trace,
+            &mut |event| match event {
+                TileEvent::Started(tile) => progress.tile_started(tile),
+                TileEvent::Finished {
+                    tile,
+                    pixels,
+                    elapsed,
+                } => {
+                    on_tile(tile, &pixels);
+                    for ((i, j), sum) in tile_pixels(tile).zip(pixels) {
+                        buffer.add_samples(i, j, sum, samples as u32);
+                    }
+                    progress.tile_finished_after(tile, elapsed, tile.pixels() * samples as u64);
+                }
+            },
+        );
+        progress.finish();
+        buffer
+    }
+
+    // The tiles of the image, in the order they are handed out.
+    fn tiles(&self) -> Vec<Tile> {
+        Tile::grid(self.image_width, self.image_height, self.tile_size)
+    }
+
+    fn render_threads(&self) -> usize {
+        match self.threads {
+            Some(threads) if threads > 0 => threads as usize,
+            _ => tiles::default_threads(),
+        }
+    }
+
+    // The summed samples of the tile's pixels, row by row.
+    fn trace_tile(&self, scene: &Scene, tile: Tile) -> Vec<Color> {
+        crate::stage_span!("trace_tile", x = tile.x0, y = tile.y0);
+        tile_pixels(tile)
+            .map(|(i, j)| self.sample_pixel(scene, i, j))
+            .collect()
+    }
+
+    // Renders all samples of every pixel on the GPU, reporting a pass after
+    // every batch of samples. Fails, for the caller to fall back to
+    // `render_buffer`, if there is no GPU or the scene holds anything the
+    // shader can't render: only spheres of untextured lambertian, metal,
+    // dielectric and light materials under solid or gradient backgrounds,
+    // in the shaded mode. Lights are found by BSDF sampling alone, and
+    // samples come from the GPU's own random numbers whatever the sampler.
+    #[cfg(feature = "gpu")]
+    pub fn render_gpu(
+        &self,
+        scene: &Scene,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> Result<FrameBuffer, String> {
+        if self.mode != RenderMode::Shaded {
+            return Err("the GPU renders only the shaded mode".to_string());
+        }
+        let view = crate::gpu::View {
+            width: self.image_width,
+            height: self.image_height,
+            samples: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            center: self.center,
+            pixel00: self.pixel00_loc,
+            delta_u: self.pixel_delta_u,
+            delta_v: self.pixel_delta_v,
+            defocus_u: self.defocus_disk_u,
+            defocus_v: self.defocus_disk_v,
+            shutter: (self.shutter_open, self.shutter_close),
+        };
+        let seed = self
+            .seed
+            .unwrap_or_else(|| (random() * u32::MAX as f64) as u64);
+        let mut buffer = crate::gpu::render(&view, scene, seed, on_progress)?;
+        buffer.set_tonemap(self.tonemap);
+        Ok(buffer)
+    }
+
+    #[cfg(not(feature = "gpu"))]
+    pub fn render_gpu(
+        &self,
+        _scene: &Scene,
+        _on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> Result<FrameBuffer, String> {
+        Err("rebuild with `--features gpu` to render on the GPU".to_string())
+    }
+
+    // Renders the whole image into a new buffer, for programs embedding the
+    // renderer; `FrameBuffer::to_rgb8` or `write_image` turn it into pixels.
+    pub fn render_to_buffer(&self, scene: &Scene) -> FrameBuffer {
+        self.render_buffer(scene, &RenderControl::new(), &mut |_| {})
+    }
+
+    // Renders the image as PPM to standard output.
+    pub fn render(&self, scene: &Scene) -> Result<(), Error> {
+        self.render_to(scene, &mut io::stdout().lock())
+    }
+
+    pub fn render_to(&self, scene: &Scene, out: &mut impl Write) -> Result<(), Error> {
+        self.render_with_progress(scene, out, &mut progress::log)
+            .map(|_| ())
+    }
+
+    // Like `render_to`, reporting every scanline as a tile to `on_progress`,
+    // and returning statistics on the render.
+    pub fn render_with_progress(
+        &self,
+        scene: &Scene,
+        out: &mut impl Write,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> Result<RenderStats, Error> {
+        Ok(self.render_ppm(scene, out, on_progress)?)
+    }
+
+    fn render_ppm(
+        &self,
+        scene: &Scene,
+        out: &mut impl Write,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> io::Result<RenderStats> {
+        if self.time_limit.is_some() {
+            let (buffer, mut stats) = self.render_timed(scene, on_progress);
+            let time_start = Instant::now();
+            buffer.write_ppm(&mut BufWriter::new(out))?;
+            stats.write += time_start.elapsed();
+            return Ok(stats);
+        }
+        let samples = self.samples_per_pixel;
+        let tonemap = self.tonemap;
+        let mut out = BufWriter::new(out);
+        writeln!(out, "P3\n{} {}\n255", self.image_width, self.image_height)?;
+        let mut stats = self.render_rows(scene, on_progress, |_, row| {
+            row.iter()
+                .try_for_each(|pixel_color| write_color(&mut out, *pixel_color, samples, tonemap))
+        })?;
         let time_start = Instant::now();
-        println!(
-            "P3\n{} {}\n255",
-            self.image_width.unwrap(),
-            self.image_height
+        out.flush()?;
+        stats.write += time_start.elapsed();
+        Ok(stats)
+    }
+
+    // Renders into the image file at `path`, in the format its extension
+    // names. PPM is streamed row by row; other formats are encoded once the
+    // whole image is done.
+    pub fn render_to_path(
+        &self,
+        scene: &Scene,
+        path: &Path,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> Result<RenderStats, Error> {
+        if ImageFormat::from_path(path) == ImageFormat::Ppm {
+            return File::create(path)
+                .and_then(|mut file| self.render_ppm(scene, &mut file, on_progress))
+                .map_err(|err| Error::Image {
+                    path: path.to_path_buf(),
+                    message: err.to_string(),
+                });
+        }
+        let (buffer, mut stats) = if self.time_limit.is_some() {
+            self.render_timed(scene, on_progress)
+        } else {
+            let samples = self.samples_per_pixel as u32;
+            let mut buffer = self.frame_buffer();
+            let stats = self.render_rows(scene, on_progress, |j, row| {
+                for (i, pixel_color) in row.iter().enumerate() {
+                    buffer.add_samples(i as i32, j, *pixel_color, samples);
+                }
+                Ok(())
+            })?;
+            (buffer, stats)
+        };
+        let time_start = Instant::now();
+        buffer.write_image(path)?;
+        stats.write += time_start.elapsed();
+        Ok(stats)
+    }
+
+    // Renders pass by pass until `time_limit` runs out, for the renders that
+    // otherwise trace every sample of a tile at once.
+    fn render_timed(
+        &self,
+        scene: &Scene,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+    ) -> (FrameBuffer, RenderStats) {
+        let counters = stats::counters();
+        let time_start = Instant::now();
+        let buffer =
+            self.render_progressive(scene, 0, &RenderControl::new(), on_progress, &mut |_| {});
+        let stats = RenderStats {
+            counters: stats::counters() - counters,
+            samples: (buffer.width() * buffer.height()) as u64 * buffer.samples() as u64,
+            trace: time_start.elapsed(),
+            peak_memory: stats::peak_memory(),
+            ..RenderStats::default()
+        };
+        (buffer, stats)
+    }
+
+    // Traces the image in tiles, handing each row of summed samples to
+    // `write_row` from the top down, as soon as the tiles across it are done.
+    fn render_rows(
+        &self,
+        scene: &Scene,
+        on_progress: &mut dyn FnMut(&ProgressEvent),
+        mut write_row: impl FnMut(i32, &[Color]) -> io::Result<()>,
+    ) -> io::Result<RenderStats> {
+        let width = self.image_width;
+        let (height, tile_size) = (self.image_height, self.tile_size);
+        let samples = self.samples_per_pixel;
+        let mut stats = RenderStats::default();
+        let counters = stats::counters();
+
+        crate::stage_span!("render", width, height, samples);
+        let mut progress = Tracker::start(width, height, samples, on_progress);
+        let mut image = vec![Color::new(); (width * height) as usize];
+        // Tiles still to come in each band of rows one tile high, and the
+        // first band not yet written.
+        let bands = (height + tile_size - 1) / tile_size;
+        let mut waiting = vec![(width + tile_size - 1) / tile_size; bands as usize];
+        let mut next_band = 0;
+        let mut result = Ok(());
+        let failed = AtomicBool::new(false);
+        let trace = |tile| (!failed.load(Ordering::Relaxed)).then(|| self.trace_tile(scene, tile));
+        let time_start = Instant::now();
+        tiles::render(&self.tiles(), self.render_threads(), trace, &mut |event| {
+            let (tile, pixels, elapsed) = match event {
+                TileEvent::Started(tile) => return progress.tile_started(tile),
+                TileEvent::Finished {
+                    tile,
+                    pixels,
+                    elapsed,
+                } => (tile, pixels, elapsed),
+            };
+            for ((i, j), sum) in tile_pixels(tile).zip(pixels) {
+                image[(j * width + i) as usize] = sum;
+            }
+            waiting[(tile.y0 / tile_size) as usize] -= 1;
+            while result.is_ok() && next_band < bands && waiting[next_band as usize] == 0 {
+                let write_start = Instant::now();
+                let rows = next_band * tile_size..((next_band + 1) * tile_size).min(height);
+                for j in rows {
+                    crate::stage_span!("write_row", j);
+                    let row = &image[(j * width) as usize..((j + 1) * width) as usize];
+                    if let Err(err) = write_row(j, row) {
+                        result = Err(err);
+                        failed.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+                stats.write += write_start.elapsed();
+                next_band += 1;
+            }
+            progress.tile_finished_after(tile, elapsed, tile.pixels() * samples as u64);
+        });
+        result?;
+        stats.trace = time_start.elapsed().saturating_sub(stats.write);
+        progress.finish();
+
+        stats.counters = stats::counters() - counters;
+        stats.samples = (width * self.image_height) as u64 * samples as u64;
+        stats.peak_memory = stats::peak_memory();
+        Ok(stats)
+    }
+}
+
+// What every bounce of a path is traced with: the render mode's material,
+// the scene, the weighing of light sampling against scattering, and whether
+// non-finite values fail the path.
+struct Tracing<'a> {
+    overridden: Option<&'a Material>,
+    scene: &'a Scene,
+    heuristic: MisHeuristic,
+    checked: bool,
+}
+
+// A key for the random choices along a ray, from the next sample value.
+fn ray_key(sampler: &mut dyn Sampler) -> u64 {
+    hash(&[sampler.get_1d().to_bits()])
+}
+
+// The pixels of a tile, row by row.
+fn tile_pixels(tile: Tile) -> impl Iterator<Item = (i32, i32)> {
+    (tile.y0..tile.y1).flat_map(move |j| (tile.x0..tile.x1).map(move |i| (i, j)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::hittable_list::HittableList;
+    use crate::light::Light;
+    use crate::material::{DiffuseLight, Lambertian, Material, Metal, Scatterable};
+    use crate::quad::Quad;
+    use crate::sampler::{RandomSampler, Sampler};
+    use crate::sphere::Sphere;
+    use std::sync::Arc;
+
+    struct Broken;
+
+    impl Scatterable for Broken {
+        fn scatter(
+            &self,
+            _r_in: &Ray,
+            rec: &HitRecord,
+            _sampler: &mut dyn Sampler,
+        ) -> Option<(Ray, Color)> {
+            Some((Ray::from(rec.p, rec.normal), Color::gray(f64::NAN)))
+        }
+    }
+
+    #[test]
+    fn builder_checks_settings() {
+        let camera = CameraBuilder::new().build().unwrap();
+        assert_eq!((camera.image_width(), camera.image_height()), (100, 100));
+        assert_eq!(camera.samples_per_pixel(), 10);
+
+        let fails = |key: &str, value: &str| {
+            let mut settings = CameraBuilder::new();
+            settings.set(key, value).unwrap();
+            settings.build().unwrap_err()
+        };
+        assert_eq!(fails("width", "0"), "width must be positive, not 0");
+        assert!(fails("vfov", "-30").contains("vfov"));
+        assert!(fails("vfov", "180").contains("vfov"));
+        assert!(fails("samples", "0").contains("samples"));
+        assert!(fails("look_at", "0,0,-1").contains("look_at"));
+        assert!(fails("vup", "0,0,3").contains("parallel"));
+        assert!(fails("shutter_close", "-1").contains("shutter_open"));
+
+        // Built cameras give back settings that build them again.
+        let mut settings = CameraBuilder::new();
+        settings.set("look_from", "1,2,3").unwrap();
+        settings.set("mode", "clay").unwrap();
+        settings.set("mis", "balance").unwrap();
+        let camera = settings.build().unwrap();
+        let again = camera.to_builder().build().unwrap();
+        assert_eq!(again.look_from(), Point3::from(1.0, 2.0, 3.0));
+        assert_eq!(again.mode(), RenderMode::Clay);
+        assert_eq!(again.to_builder().mis, Some(MisHeuristic::Balance));
+        assert!(settings.set("mis", "maximum").is_err());
+    }
+
+    #[test]
+    fn cancelled_render_keeps_finished_tiles() {
+        let camera = CameraBuilder {
+            image_width: Some(4),
+            samples_per_pixel: Some(1),
+            tile_size: Some(2),
+            threads: Some(1),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let scene = Scene::default();
+
+        let control = RenderControl::new();
+        let cancel = control.clone();
+        let buffer = camera.render_buffer(&scene, &control, &mut |event| {
+            if let ProgressEvent::TileFinished { tile, .. } = event {
+                if tile.x0 == 2 {
+                    cancel.cancel();
+                }
+            }
+        });
+        assert_eq!(buffer.sample_count(3, 1), 1);
+        assert_eq!(buffer.sample_count(0, 2), 0);
+
+        let mut buffer = FrameBuffer::new(4, 4);
+        assert!(!camera.render_pass_controlled(&scene, &mut buffer, &control));
+        assert_eq!(buffer.samples(), 0);
+    }
+
+    #[test]
+    fn stops_when_clean() {
+        let camera = CameraBuilder {
+            image_width: Some(4),
+            samples_per_pixel: Some(100),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        // Rays that only see a solid background have no noise at all.
+        let mut scene = Scene::default();
+        scene.background = Background::Solid(Color::gray(0.5));
+        let buffer = camera.render_until_clean(
+            &scene,
+            &NoiseTarget::from(0.01),
+            &RenderControl::new(),
+            &mut progress::log,
         );
-        for j in 0..self.image_height {
-            info!("Scanlines remaining {}", (self.image_height - j));
-            for i in 0..self.image_width.unwrap() {
-                let mut pixel_color = Color::new();
-                for _s in 0..self.samples_per_pixel.unwrap() {
-                    let r = self.get_ray(i, j);
-                    pixel_color += Self::ray_color(&r, self.max_depth.unwrap(), world);
+        assert_eq!(buffer.samples(), 8);
+    }
+
+    #[test]
+    fn progressive_passes_are_shown_as_they_go() {
+        let camera = CameraBuilder {
+            image_width: Some(6),
+            samples_per_pixel: Some(10),
+            tile_size: Some(4),
+            threads: Some(2),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut scene = Scene::default();
+        scene.background = Background::Solid(Color::gray(0.5));
+        let mut shown = Vec::new();
+        let buffer = camera.render_progressive(
+            &scene,
+            4,
+            &RenderControl::new(),
+            &mut progress::log,
+            &mut |buffer| shown.push((buffer.samples(), buffer.sample_count(5, 5))),
+        );
+        assert_eq!(shown, [(4, 4), (8, 8)]);
+        assert_eq!(buffer.samples(), 10);
+        assert_eq!(buffer.sample_count(0, 0), 10);
+        approx::assert_relative_eq!(buffer.to_linear()[0].g(), 0.5);
+
+        // Cancelled after the second pass, the render keeps two whole passes.
+        let control = RenderControl::new();
+        let cancel = control.clone();
+        let buffer = camera.render_progressive(
+            &scene,
+            0,
+            &control,
+            &mut |event| {
+                if let ProgressEvent::PassComplete { pass: 2, .. } = event {
+                    cancel.cancel();
+                }
+            },
+            &mut |_| {},
+        );
+        assert_eq!(buffer.samples(), 2);
+        assert_eq!(
+            (buffer.sample_count(0, 0), buffer.sample_count(5, 5)),
+            (2, 2)
+        );
+    }
+
+    #[test]
+    fn time_limit_keeps_whole_passes() {
+        let camera = CameraBuilder {
+            image_width: Some(6),
+            samples_per_pixel: Some(i32::MAX),
+            tile_size: Some(4),
+            time_limit: Some(0.05),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut scene = Scene::default();
+        scene.background = Background::Solid(Color::gray(0.5));
+        let buffer =
+            camera.render_progressive(&scene, 0, &RenderControl::new(), &mut |_| {}, &mut |_| {});
+        let passes = buffer.samples();
+        assert!((1..i32::MAX).contains(&passes));
+        assert_eq!(
+            (buffer.sample_count(0, 0), buffer.sample_count(5, 5)),
+            (passes as u32, passes as u32)
+        );
+        approx::assert_relative_eq!(buffer.to_linear()[0].g(), 0.5);
+
+        // Even with no time at all, there is one pass to show.
+        let camera = CameraBuilder {
+            time_limit: Some(0.0),
+            ..camera.to_builder()
+        }
+        .build()
+        .unwrap();
+        let mut out = Vec::new();
+        let stats = camera
+            .render_with_progress(&scene, &mut out, &mut |_| {})
+            .unwrap();
+        assert_eq!(stats.samples, 36);
+        assert!(out.starts_with(b"P3\n6 6\n255\n"));
+    }
+
+    #[test]
+    fn resumed_renders_match_uninterrupted_ones() {
+        let camera = CameraBuilder {
+            image_width: Some(6),
+            samples_per_pixel: Some(6),
+            threads: Some(2),
+            seed: Some(3),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, 0.0, 0.0),
+            0.5,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let scene = Scene::from(world);
+        let whole = camera.render_progressive(
+            &scene,
+            0,
+            &RenderControl::new(),
+            &mut progress::log,
+            &mut |_| {},
+        );
+
+        // Stopped after two passes and resumed from there.
+        let control = RenderControl::new();
+        let cancel = control.clone();
+        let stopped = camera.render_progressive(
+            &scene,
+            0,
+            &control,
+            &mut |event| {
+                if let ProgressEvent::PassComplete { pass: 2, .. } = event {
+                    cancel.cancel();
                 }
-                write_color(pixel_color, self.samples_per_pixel.unwrap());
+            },
+            &mut |_| {},
+        );
+        assert_eq!(stopped.samples(), 2);
+        let resumed = camera
+            .resume_progressive(
+                &scene,
+                stopped,
+                0,
+                &RenderControl::new(),
+                &mut progress::log,
+                &mut |_| {},
+            )
+            .unwrap();
+        assert_eq!(resumed.samples(), 6);
+        for (a, b) in resumed.to_linear().iter().zip(whole.to_linear()) {
+            approx::assert_relative_eq!(a.g(), b.g(), epsilon = 1e-12);
+        }
+
+        let camera = CameraBuilder {
+            image_width: Some(8),
+            ..camera.to_builder()
+        }
+        .build()
+        .unwrap();
+        let result = camera.resume_progressive(
+            &scene,
+            resumed,
+            0,
+            &RenderControl::new(),
+            &mut progress::log,
+            &mut |_| {},
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn adaptive_pixels_stop_on_their_own() {
+        // Rays off the sphere see either the white sky or the black ground
+        // below it, which takes samples to settle; the sky alone doesn't.
+        let lambertian = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(Point3::new(), 1.0, lambertian(0.5))));
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, -101.0, 0.0),
+            100.0,
+            lambertian(0.0),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        let camera = CameraBuilder {
+            image_width: Some(9),
+            aspect_ratio: Some(1.0),
+            samples_per_pixel: Some(64),
+            look_from: Some(Point3::from(0.0, 0.0, 4.0)),
+            threads: Some(2),
+            // Seeded, so every run takes the same samples; unseeded, the
+            // center pixel's first few now and then agree by chance.
+            seed: Some(2),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let target = AdaptiveTarget {
+            threshold: 0.05,
+            min_samples: 4,
+        };
+        let buffer =
+            camera.render_adaptive(&scene, &target, &RenderControl::new(), &mut progress::log);
+        assert_eq!(buffer.sample_count(0, 0), 4);
+        assert!(buffer.sample_count(4, 4) > 4);
+        assert!(buffer.samples() <= 64 && buffer.sample_count(4, 4) <= 64);
+    }
+
+    #[test]
+    fn branches_are_averaged() {
+        // Under a white sky, every ray scattered off a convex diffuse sphere
+        // escapes, so the pixel is exactly the albedo however many branch.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        for branches in [1, 4] {
+            let camera = CameraBuilder {
+                image_width: Some(3),
+                look_from: Some(Point3::from(0.0, 0.0, 2.0)),
+                branches: Some(branches),
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            let color = camera.sample_pixel_with(&scene, 1, 1, 2) / 2.0;
+            approx::assert_relative_eq!(color.g(), 0.5);
+        }
+    }
+
+    #[test]
+    fn lights_shine_in_every_mode() {
+        // A light seen against the dark shows its emission, and a diffuse
+        // sphere next to one is lit by it.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::DiffuseLight(DiffuseLight::from(Color::gray(3.0))),
+        )));
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, -3.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::new());
+        for mode in [RenderMode::Shaded, RenderMode::Clay] {
+            let camera = CameraBuilder {
+                image_width: Some(3),
+                look_from: Some(Point3::from(0.0, 0.0, 2.0)),
+                mode: Some(mode),
+                ..Default::default()
             }
+            .build()
+            .unwrap();
+            let color = camera.sample_pixel_with(&scene, 1, 1, 2) / 2.0;
+            approx::assert_relative_eq!(color.g(), 3.0);
+        }
+
+        // Towards the top of the diffuse sphere, facing the light.
+        let r = Ray::from(Point3::from(0.0, -1.0, 3.0), Vec3::from(0.0, -1.0, -3.0));
+        let mut sampler = RandomSampler;
+        let lit: f64 = (0..100)
+            .map(|_| {
+                Camera::ray_color(
+                    &r,
+                    4,
+                    1,
+                    None,
+                    MisHeuristic::default(),
+                    &scene,
+                    &mut sampler,
+                )
+                .g()
+            })
+            .sum();
+        assert!(lit > 0.0);
+    }
+
+    #[test]
+    fn sampling_lights_keeps_the_image() {
+        // A gray ground under a 2 by 2 panel light, seen from below it: the
+        // light found by sampling it as well as by scattering averages out
+        // to what scattering alone finds, whichever way the two are weighed,
+        // even with no bounce left to scatter towards the light.
+        let gray = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, -1000.0, 0.0),
+            1000.0,
+            gray,
+        )));
+        let panel: Arc<dyn Hittable> = Arc::new(Quad::from(
+            Point3::from(-1.0, 1.0, -1.0),
+            Vec3::from(2.0, 0.0, 0.0),
+            Vec3::from(0.0, 0.0, 2.0),
+            Material::DiffuseLight(DiffuseLight::from(Color::gray(4.0))),
+        ));
+        world.add(Box::new(panel.clone()));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::new());
+        let r = Ray::from(Point3::from(0.0, 0.5, 0.5), Vec3::from(0.0, -0.5, -0.5));
+        let mean = |scene: &Scene, heuristic: MisHeuristic, depth: i32| {
+            let tracing = Tracing {
+                overridden: None,
+                scene,
+                heuristic,
+                checked: false,
+            };
+            let n = 20000;
+            (0..n)
+                .map(|_| Camera::trace(&r, None, depth, 1, &tracing, &mut RandomSampler).unwrap())
+                .map(|color| color.g())
+                .sum::<f64>()
+                / n as f64
+        };
+        let scattered = mean(&scene, MisHeuristic::Balance, 3);
+        scene.lights.add(Box::new(panel));
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            for depth in [1, 3] {
+                let sampled = mean(&scene, heuristic, depth);
+                approx::assert_relative_eq!(sampled, scattered, max_relative = 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn punctual_lights_cast_shadows() {
+        // A point light above a gray ground, with a black ball between it and
+        // one spot on the ground; with no sky, only the light's direct
+        // contribution reaches the camera.
+        let gray = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let black = Material::Lambertian(Lambertian::from(Color::new()));
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, -1000.0, 0.0),
+            1000.0,
+            gray,
+        )));
+        world.add(Box::new(Sphere::from(
+            Point3::from(1.5, 2.0, 0.0),
+            0.5,
+            black,
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::new());
+        scene
+            .punctual_lights
+            .push(Light::point(Point3::from(0.0, 4.0, 0.0), Color::gray(16.0)));
+
+        let mut sampler = RandomSampler;
+        let lit = Ray::from(Point3::from(0.0, 1.0, 1.0), Vec3::from(0.0, -1.0, -1.0));
+        let color = Camera::ray_color(
+            &lit,
+            4,
+            1,
+            None,
+            MisHeuristic::default(),
+            &scene,
+            &mut sampler,
+        );
+        approx::assert_relative_eq!(color.g(), 0.5 / PI, epsilon = 1e-6);
+        let shadowed = Ray::from(Point3::from(3.0, 1.0, 1.0), Vec3::from(0.0, -1.0, -1.0));
+        let color = Camera::ray_color(
+            &shadowed,
+            4,
+            1,
+            None,
+            MisHeuristic::default(),
+            &scene,
+            &mut sampler,
+        );
+        assert_eq!(color.g(), 0.0);
+    }
+
+    #[test]
+    fn deep_paths_keep_to_the_stack() {
+        // Inside a perfect mirror, a path bounces until the depth runs out.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Metal(Metal::from(Color::gray(1.0), 0.0)),
+        )));
+        let scene = Scene::from(world);
+        let r = Ray::from(Point3::new(), Vec3::from(0.3, 0.4, 0.5));
+        let color = Camera::ray_color(
+            &r,
+            200_000,
+            1,
+            None,
+            MisHeuristic::default(),
+            &scene,
+            &mut RandomSampler,
+        );
+        assert_eq!(color.g(), 0.0);
+    }
+
+    #[test]
+    fn invalid_samples_are_marked() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Custom(Arc::new(Broken)),
+        )));
+        let scene = Scene::from(world);
+        // Wide enough that no jittered ray through the corner pixel reaches the
+        // sphere, while every ray through the center hits it.
+        let settings = CameraBuilder {
+            image_width: Some(5),
+            look_from: Some(Point3::from(0.0, 0.0, 2.0)),
+            ..Default::default()
+        };
+
+        for debug in [false, true] {
+            let camera = CameraBuilder {
+                debug_nan: Some(debug),
+                ..settings.clone()
+            }
+            .build()
+            .unwrap();
+            assert_eq!(
+                camera.sample_pixel_with(&scene, 2, 2, 4),
+                INVALID_SAMPLE * 4.0
+            );
+            assert!(camera.sample_pixel_with(&scene, 0, 0, 4).is_finite());
+
+            let mut buffer = FrameBuffer::new(5, 5);
+            camera.render_pass(&scene, &mut buffer);
+            assert!(buffer.is_invalid(2, 2));
+            assert!(!buffer.is_invalid(0, 0));
         }
-        let duration = time_start.elapsed();
-        info!("Done in {:?}.", duration);
     }
 }
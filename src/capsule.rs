@@ -0,0 +1,184 @@
+use crate::aabb::Aabb;
+use crate::cylinder::{roots, surface_hit, Axis, Nearest};
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray};
+use crate::utility::{Interval, PI};
+use crate::vec3::*;
+
+// All points within `radius` of the segment from `a` to `b`: a cylinder with
+// a half sphere on each end, such as a pill, a rounded strut or a limb. u runs
+// around the segment as a fraction of a turn and v over the surface from the
+// far end of the sphere around `a` to that of the sphere around `b`, in
+// proportion to the distance covered.
+#[derive(Clone)]
+pub struct Capsule {
+    axis: Axis,
+    radius: f64,
+    material: Material,
+}
+
+impl Capsule {
+    pub fn from(a: Point3, b: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            axis: Axis::from(a, b),
+            radius,
+            material,
+        }
+    }
+
+    fn end(&self) -> Point3 {
+        self.axis.base + self.axis.height * self.axis.w
+    }
+
+    // The nearest hit within `ray_t`, and how far along the segment it is.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, f64)> {
+        let (oz, op) = self.axis.split(r.origin() - self.axis.base);
+        let (dz, dp) = self.axis.split(r.direction());
+        let height = self.axis.height;
+        let radius_squared = self.radius * self.radius;
+        let mut nearest = Nearest::within(ray_t);
+
+        let c = op.length_squared() - radius_squared;
+        if let Some(roots) = roots(dp.length_squared(), dot(&op, &dp), c) {
+            for t in roots {
+                let z = oz + t * dz;
+                if (0.0..=height).contains(&z) {
+                    nearest.consider(t, z);
+                }
+            }
+        }
+        // Each sphere counts only beyond its end of the segment.
+        let direction = r.direction();
+        for (center, first) in [(self.axis.base, true), (self.end(), false)] {
+            let oc = r.origin() - center;
+            let c = oc.length_squared() - radius_squared;
+            let Some(roots) = roots(direction.length_squared(), dot(&oc, &direction), c) else {
+                continue;
+            };
+            for t in roots {
+                let z = oz + t * dz;
+                let outside = if first { z < 0.0 } else { z > height };
+                if outside {
+                    nearest.consider(t, z);
+                }
+            }
+        }
+        nearest.found
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, z) = self.intersect(r, &ray_t)?;
+        let height = self.axis.height;
+        let on_axis = self.axis.base + z.clamp(0.0, height) * self.axis.w;
+        let normal = (r.at(t) - on_axis) / self.radius;
+        let (_, across) = self.axis.split(normal);
+        let u = self.axis.angle(across);
+        let around = self.axis.around(u);
+
+        // Distances over the surface from the far end of the first sphere.
+        let quarter = PI / 2.0 * self.radius;
+        let covered = if z < 0.0 {
+            self.radius * (-z / self.radius).clamp(-1.0, 1.0).acos()
+        } else if z > height {
+            quarter + height + self.radius * ((z - height) / self.radius).clamp(-1.0, 1.0).asin()
+        } else {
+            quarter + z
+        };
+        let length = 2.0 * quarter + height;
+        let meridian = cross(&normal, &around);
+        let bend = if (0.0..=height).contains(&z) {
+            Vec3::new()
+        } else {
+            length / self.radius * meridian
+        };
+        Some(surface_hit(
+            r,
+            t,
+            normal,
+            (u, covered / length),
+            (
+                2.0 * PI * self.radius * across.length() * around,
+                length * meridian,
+            ),
+            (2.0 * PI * across.length() * around, bend),
+            &self.material,
+        ))
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let reach = Vec3::from(self.radius, self.radius, self.radius);
+        let end = self.end();
+        Aabb::union(
+            &Aabb::from_points(self.axis.base - reach, self.axis.base + reach),
+            &Aabb::from_points(end - reach, end + reach),
+        )
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        let (z, _) = self.axis.split(p - self.axis.base);
+        let on_axis = self.axis.base + z.clamp(0.0, self.axis.height) * self.axis.w;
+        (p - on_axis).length_squared() <= self.radius * self.radius
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("capsule", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn rounds_off_the_ends() {
+        // Radius 1 around the segment from the origin to (0, 2, 0).
+        let capsule = Capsule::from(
+            Point3::new(),
+            Point3::from(0.0, 2.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        let across = Ray::from(Point3::from(0.0, 1.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = capsule.hit(&across, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.0);
+        approx::assert_relative_eq!(rec.normal.z(), 1.0);
+        approx::assert_relative_eq!(rec.v, 0.5);
+
+        // Straight down onto the top end, then up into the bottom one.
+        let down = Ray::from(Point3::from(0.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        let rec = capsule.hit(&down, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 2.0);
+        approx::assert_relative_eq!(rec.normal.y(), 1.0);
+        approx::assert_relative_eq!(rec.v, 1.0);
+        let up = Ray::from(Point3::from(0.0, -5.0, 0.0), Vec3::from(0.0, 1.0, 0.0));
+        let rec = capsule.hit(&up, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.0);
+        approx::assert_relative_eq!(rec.v, 0.0);
+        assert!(rec.front_face);
+
+        // Off the rounded end, where a cylinder's corner would be, rays miss.
+        let corner = Ray::from(Point3::from(0.8, 3.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(capsule.hit(&corner, ray_t).is_none());
+        assert!(capsule.contains(Point3::from(0.0, 2.9, 0.0)));
+        assert!(!capsule.contains(Point3::from(0.8, 2.9, 0.0)));
+        approx::assert_relative_eq!(capsule.bounding_box().y.min, -1.0, epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::framebuffer::FrameBuffer;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Magic bytes at the start of a checkpoint. The little-endian u64 seed and
+// u32 count of completed passes follow, then the accumulation buffer as
+// `FrameBuffer::save` writes it.
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCHKPT1";
+
+// A progressive render saved part way, so it can be resumed after a crash or
+// an interruption. Camera samples, and scenes that scatter objects at random,
+// are drawn from the seed alone, so a render resumed with the same seed
+// carries on with exactly the samples the interrupted one would have taken.
+pub struct Checkpoint {
+    pub seed: u64,
+    pub buffer: FrameBuffer,
+}
+
+// Saves `buffer`, rendered with `seed`, to `path`. Writes a hidden file next
+// to `path` and moves it over `path`, so a crash while saving leaves the
+// previous checkpoint whole.
+pub fn save(seed: u64, buffer: &FrameBuffer, path: &Path) -> io::Result<()> {
+    crate::stage_span!("save_checkpoint");
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let partial = path.with_file_name(format!(".{}.partial", name));
+    let mut out = BufWriter::new(File::create(&partial)?);
+    out.write_all(CHECKPOINT_MAGIC)?;
+    out.write_all(&seed.to_le_bytes())?;
+    out.write_all(&(buffer.samples() as u32).to_le_bytes())?;
+    buffer.write_accumulation(&mut out)?;
+    out.into_inner()?.sync_all()?;
+    std::fs::rename(&partial, path)
+}
+
+pub fn load(path: &Path) -> io::Result<Checkpoint> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != CHECKPOINT_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a render checkpoint", path.display()),
+        ));
+    }
+    let mut seed = [0; 8];
+    input.read_exact(&mut seed)?;
+    let mut passes = [0; 4];
+    input.read_exact(&mut passes)?;
+    let mut buffer = FrameBuffer::read_accumulation(&mut input)?;
+    buffer.set_passes(u32::from_le_bytes(passes) as i32);
+    Ok(Checkpoint {
+        seed: u64::from_le_bytes(seed),
+        buffer,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn round_trips() {
+        let mut buffer = FrameBuffer::new(3, 2);
+        buffer.add_sample(0, 0, Color::from(0.25, 0.5, 1.0));
+        buffer.add_sample(2, 1, Color::gray(2.0));
+        buffer.mark_invalid(1, 0);
+        buffer.complete_pass();
+        let path = std::env::temp_dir().join("render-checkpoint-test.ckpt");
+        save(42, &buffer, &path).unwrap();
+
+        let Checkpoint { seed, buffer } = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(seed, 42);
+        assert_eq!(buffer.samples(), 1);
+        assert_eq!(buffer.pixel_sum(0, 0), Color::from(0.25, 0.5, 1.0));
+        assert_eq!(buffer.sample_count(2, 1), 1);
+        assert!(buffer.is_invalid(1, 0));
+
+        assert!(load(Path::new("Cargo.toml")).is_err());
+    }
+}
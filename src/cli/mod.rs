@@ -0,0 +1,184 @@
+use render::camera::CameraBuilder;
+use render::error::Error;
+use render::framebuffer::FrameBuffer;
+use render::preset;
+use render::scene::Scene;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::io;
+use std::path::{Path, PathBuf};
+
+mod network;
+mod options;
+mod tools;
+
+use network::{CoordinateArgs, WorkerArgs};
+use options::Options;
+use tools::{BakeArgs, InfoArgs, LidarArgs, MergeArgs, ProbesArgs, QueueArgs};
+
+// The command line: a scene to render and how, or one of the other tools.
+#[derive(Parser)]
+#[command(name = "render", about = "Path traces scenes into images")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP render server (requires the `server` feature)
+    Serve {
+        #[arg(default_value = "127.0.0.1:8080")]
+        address: String,
+        /// Directory scripts may load models and images from (default: none)
+        #[arg(long, value_name = "DIR")]
+        assets: Option<PathBuf>,
+    },
+    /// Split SCENE into work items for workers and write the merged image
+    /// (requires the `distributed` feature)
+    Coordinate(CoordinateArgs),
+    /// Render work items from the coordinator at ADDRESS (requires the
+    /// `distributed` feature)
+    Worker(WorkerArgs),
+    /// Combine shard accumulation buffers into OUTPUT, written as an image or,
+    /// with an .accum extension, as another buffer
+    Merge(MergeArgs),
+    /// Render a batch of scenes, N at a time, and print a summary
+    Queue(QueueArgs),
+    /// Simulate a depth sensor and write the hit points as a point cloud
+    Lidar(LidarArgs),
+    /// Bake the irradiance or ambient occlusion over an object's UV layout
+    Bake(BakeArgs),
+    /// Capture the light arriving at a grid of probes as spherical harmonics
+    Probes(ProbesArgs),
+    /// Load SCENE without rendering it and report what it is made of
+    Info(InfoArgs),
+}
+
+impl Cli {
+    pub fn run(self) {
+        match self.command {
+            None => options::run(self.options),
+            Some(Command::Serve { address, assets }) => network::serve(&address, assets.as_deref()),
+            Some(Command::Coordinate(args)) => network::coordinate(args),
+            Some(Command::Worker(args)) => network::worker(args),
+            Some(Command::Merge(args)) => tools::merge(args),
+            Some(Command::Queue(args)) => tools::queue(args),
+            Some(Command::Lidar(args)) => tools::lidar(args),
+            Some(Command::Bake(args)) => tools::bake(args),
+            Some(Command::Probes(args)) => tools::probes(args),
+            Some(Command::Info(args)) => tools::info(args),
+        }
+    }
+}
+
+// Reports a command line clap's parsers couldn't catch and exits.
+pub fn usage_error(message: &str) -> ! {
+    Cli::command()
+        .error(ErrorKind::ValueValidation, message)
+        .exit()
+}
+
+// Reports a failure after the command line was accepted and exits.
+pub fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, String> {
+    load_frame(path, 0, 0.0).map(|(scene, _)| scene)
+}
+
+// The scene at `path`, or the final scene of the first book without one.
+pub fn scene_or_default(path: Option<&Path>) -> Scene {
+    match path {
+        Some(path) => load_scene(path).unwrap_or_else(|err| {
+            fail(format!("Failed to load scene {}: {}", path.display(), err))
+        }),
+        None => preset::random_spheres().0,
+    }
+}
+
+// The scene in a script or scene file, with the default camera changed by
+// the scene's camera settings.
+pub fn load_frame(path: &Path, frame: i64, time: f64) -> Result<(Scene, CameraBuilder), String> {
+    let mut camera = preset::default_camera();
+    let scene = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json" | "ron") => load_scene_file(path, &mut camera)?,
+        Some("gltf" | "glb") => load_gltf(path, &mut camera)?,
+        _ => load_script(path, frame, time, &mut camera)?,
+    };
+    Ok((scene, camera))
+}
+
+#[cfg(feature = "scripting")]
+fn load_script(
+    path: &Path,
+    frame: i64,
+    time: f64,
+    camera: &mut CameraBuilder,
+) -> Result<Scene, String> {
+    let scene = render::script::load_frame(path, frame, time).map_err(|err| err.to_string())?;
+    scene.apply_camera(camera)?;
+    Ok(scene.into_scene())
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script(
+    _path: &Path,
+    _frame: i64,
+    _time: f64,
+    _camera: &mut CameraBuilder,
+) -> Result<Scene, String> {
+    Err("rebuild with `--features scripting` to enable scene scripts".to_string())
+}
+
+#[cfg(feature = "serde")]
+fn load_scene_file(path: &Path, camera: &mut CameraBuilder) -> Result<Scene, String> {
+    render::scene_file::load(path, &render::registry::Registry::with_builtins(), camera)
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_scene_file(_path: &Path, _camera: &mut CameraBuilder) -> Result<Scene, String> {
+    Err("rebuild with `--features serde` to load JSON and RON scene files".to_string())
+}
+
+#[cfg(feature = "serde")]
+fn load_gltf(path: &Path, camera: &mut CameraBuilder) -> Result<Scene, String> {
+    render::gltf::load(path, camera).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_gltf(_path: &Path, _camera: &mut CameraBuilder) -> Result<Scene, String> {
+    Err("rebuild with `--features serde` to import glTF files".to_string())
+}
+
+// Writes the image to `output`, or as PPM to standard output.
+pub fn write_output(buffer: &FrameBuffer, output: Option<&Path>) -> Result<(), Error> {
+    match output {
+        Some(path) => buffer.write_image(path),
+        None => Ok(buffer.write_ppm(&mut io::BufWriter::new(io::stdout().lock()))?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_line_is_consistent() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["render", "probes", "--min", "-4,0,-4", "--max", "4,2,4"]);
+        assert!(matches!(cli.unwrap().command, Some(Command::Probes(_))));
+        assert!(Cli::try_parse_from(["render", "--progressive", "4"]).is_err());
+        assert!(Cli::try_parse_from(["render", "--width", "wide"]).is_err());
+        assert!(Cli::try_parse_from(["render", "--stats-json", "s.json", "--toon", "3"]).is_err());
+        let adaptive = ["render", "--stats-json", "s.json", "--adaptive", "0.05"];
+        assert!(Cli::try_parse_from(adaptive).is_ok());
+    }
+}
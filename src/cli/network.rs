@@ -0,0 +1,136 @@
+use super::fail;
+use super::options::{camera_setting, setting};
+use clap::Args;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct CoordinateArgs {
+    /// Scene script the workers render
+    scene: PathBuf,
+    /// Address workers connect to
+    #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:7878")]
+    listen: String,
+    /// Write the merged image to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Image width in pixels, overriding the scene's camera
+    #[arg(long, value_name = "N", value_parser = camera_setting("width"))]
+    width: Option<String>,
+    /// Width over height, e.g. 1.5
+    #[arg(long, value_name = "R", value_parser = camera_setting("aspect_ratio"))]
+    aspect: Option<String>,
+    /// Samples per pixel
+    #[arg(long, value_name = "N", value_parser = camera_setting("samples"))]
+    samples: Option<String>,
+    /// Bounces before a path is cut off
+    #[arg(long, value_name = "N", value_parser = camera_setting("max_depth"))]
+    max_depth: Option<String>,
+    /// Any other camera setting a scene can make, e.g. look_from=13,2,3;
+    /// vectors are written X,Y,Z
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = setting)]
+    settings: Vec<(String, String)>,
+    /// Seed the random number generator, for reproducible renders; the
+    /// workers build the scene from the same seed either way
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+}
+
+#[cfg(feature = "distributed")]
+impl CoordinateArgs {
+    // Overrides the camera the scene set up with the command-line options.
+    fn apply_camera_options(&self, camera: &mut render::camera::CameraBuilder) {
+        let flags = [
+            ("width", &self.width),
+            ("aspect_ratio", &self.aspect),
+            ("samples", &self.samples),
+            ("max_depth", &self.max_depth),
+        ];
+        let flags = flags
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?)));
+        let settings = self.settings.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        for (key, value) in settings.chain(flags) {
+            camera
+                .set(key, value)
+                .expect("camera settings are checked while parsing");
+        }
+        if self.seed.is_some() {
+            camera.seed = self.seed;
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct WorkerArgs {
+    /// Address of the coordinator
+    address: String,
+    /// Threads rendering work items (default one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+}
+
+#[cfg(feature = "server")]
+pub fn serve(address: &str, assets: Option<&Path>) {
+    if let Err(err) = render::server::serve(address, assets) {
+        fail(format!("Render server failed: {}", err));
+    }
+}
+
+#[cfg(not(feature = "server"))]
+pub fn serve(_address: &str, _assets: Option<&Path>) {
+    fail("The render server is unavailable: rebuild with `--features server`");
+}
+
+#[cfg(feature = "distributed")]
+pub fn coordinate(args: CoordinateArgs) {
+    let failed = |err: String| -> ! {
+        fail(format!(
+            "Failed to load scene {}: {}",
+            args.scene.display(),
+            err
+        ))
+    };
+    let source = std::fs::read_to_string(&args.scene).unwrap_or_else(|err| failed(err.to_string()));
+
+    // The script is evaluated here for its camera, and by every worker for
+    // the scene, all from the same seed.
+    let seed = args.seed.unwrap_or_else(rand::random);
+    render::utility::seed(seed);
+    let loaded =
+        render::script::eval_frame(&source, 0, 0.0).unwrap_or_else(|err| failed(err.to_string()));
+    let mut camera = render::preset::default_camera();
+    loaded
+        .apply_camera(&mut camera)
+        .unwrap_or_else(|err| failed(err));
+    args.apply_camera_options(&mut camera);
+    let camera = camera
+        .build()
+        .unwrap_or_else(|err| fail(format!("Invalid camera: {}", err)));
+
+    let result = render::distributed::coordinate(&args.listen, source, seed, &camera)
+        .map_err(render::error::Error::from)
+        .and_then(|buffer| super::write_output(&buffer, args.output.as_deref()));
+    if let Err(err) = result {
+        fail(format!("Distributed render failed: {}", err));
+    }
+}
+
+#[cfg(feature = "distributed")]
+pub fn worker(args: WorkerArgs) {
+    let threads = args
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    if let Err(err) = render::distributed::work(&args.address, threads) {
+        fail(format!("Worker failed: {}", err));
+    }
+}
+
+#[cfg(not(feature = "distributed"))]
+pub fn coordinate(_args: CoordinateArgs) {
+    fail("Distributed rendering is unavailable: rebuild with `--features distributed`");
+}
+
+#[cfg(not(feature = "distributed"))]
+pub fn worker(_args: WorkerArgs) {
+    fail("Distributed rendering is unavailable: rebuild with `--features distributed`");
+}
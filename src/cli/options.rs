@@ -0,0 +1,838 @@
+use render::animation::{self, FrameRange};
+use render::aov::{Aovs, Pass};
+use render::band::{render_band, Colormap};
+use render::camera::{AdaptiveTarget, Camera, CameraBuilder, NoiseTarget};
+use render::checkpoint;
+use render::control::RenderControl;
+use render::denoise::DenoiserKind;
+use render::error::Error;
+use render::framebuffer::FrameBuffer;
+use render::mode::RenderMode;
+use render::preset;
+use render::progress::{self, ProgressEvent};
+use render::sampler::SamplerKind;
+use render::scene::Scene;
+use render::shard::{Shard, ShardMode};
+use render::stats::{self, RenderStats};
+use render::temporal::Temporal;
+use render::tev::{render_to_tev, TevClient};
+use render::toon::Toon;
+use render::utility::*;
+use render::video::{VideoEncoder, VideoSettings};
+use render::watch::Watcher;
+
+use super::{fail, load_frame, usage_error, write_output};
+use clap::{Args, ValueEnum};
+use log::info;
+use std::fs::File;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Least time between checkpoints of a render.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Options rendering some other way than to a finished image with statistics.
+const WITHOUT_STATS: [&str; 9] = [
+    "watch", "frames", "shard", "tev", "band", "toon", "gui", "preview", "show",
+];
+
+#[derive(Args)]
+pub struct Options {
+    /// Rhai scene script (requires the `scripting` feature), or a .json or
+    /// .ron scene file or .gltf or .glb model (requires the `serde` feature)
+    scene: Option<PathBuf>,
+    /// Render a built-in scene instead of SCENE
+    #[arg(long, value_enum, value_name = "NAME")]
+    preset: Option<Preset>,
+    /// Write the image to FILE instead of stdout, as PNG or JPEG when it ends
+    /// in .png or .jpg (requires the `image` feature), as linear
+    /// floating-point OpenEXR when it ends in .exr (requires the `exr`
+    /// feature), else PPM
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Re-render at preview quality whenever SCENE or its assets change
+    #[arg(short, long, requires = "scene")]
+    watch: bool,
+    /// Show the render refining progressively in a window (requires the
+    /// `preview` feature)
+    #[arg(short, long)]
+    preview: bool,
+    /// Render as usual, showing tiles in a window as they finish; closing the
+    /// window aborts the render (requires the `preview` feature)
+    #[arg(long)]
+    show: bool,
+    /// Path trace on the GPU with a compute shader (requires the `gpu`
+    /// feature); scenes of anything but spheres and plain materials, or
+    /// machines without a GPU, fall back to the CPU
+    #[arg(long)]
+    gpu: bool,
+    /// Preview window with a control panel for live tweaking (requires the
+    /// `gui` feature)
+    #[arg(short, long)]
+    gui: bool,
+    /// Stream scanlines to a tev image viewer listening on ADDR (e.g.
+    /// 127.0.0.1:14158)
+    #[arg(long, value_name = "ADDR")]
+    tev: Option<String>,
+
+    /// Render frames A to B of an animated scene; -o takes a pattern like
+    /// `frame_####.ppm` (the default)
+    #[arg(long, value_name = "A..B", value_parser = FrameRange::parse, requires = "scene")]
+    frames: Option<FrameRange>,
+    /// Frames per second used to compute `time` in scripts
+    #[arg(long, value_name = "N", default_value_t = 24.0)]
+    fps: f64,
+    /// Encode the frames straight into FILE (.mp4, .webm, ...) with ffmpeg
+    #[arg(long, value_name = "FILE")]
+    video: Option<PathBuf>,
+    /// Video bitrate, e.g. 8M (default: the encoder's choice)
+    #[arg(long, value_name = "RATE")]
+    bitrate: Option<String>,
+    /// ffmpeg video codec (default libx264, or libvpx-vp9 for .webm)
+    #[arg(long, value_name = "NAME")]
+    codec: Option<String>,
+    /// Blend each frame with the previous one, reprojected along the camera
+    /// motion, with weight W from 0 to 1 (e.g. 0.5), to reduce flicker
+    #[arg(long, value_name = "W", value_parser = unit)]
+    temporal_blend: Option<f64>,
+
+    /// Render shard I of N (counting from 0) to the accumulation buffer given
+    /// with -o
+    #[arg(long, value_name = "I/N", requires = "output")]
+    shard: Option<String>,
+    /// Split shards by `samples` or interleaved `rows`
+    #[arg(long, value_name = "MODE", value_parser = shard_mode, default_value = "samples")]
+    shard_by: ShardMode,
+    /// Render the single-band response in false color through MAP (gray,
+    /// ironbow, inferno or turbo)
+    #[arg(long, value_name = "MAP", value_parser = Colormap::parse)]
+    band: Option<Colormap>,
+    /// Band values mapped to the ends of the colormap (default: the image's
+    /// range)
+    #[arg(long, value_name = "A,B", value_parser = band_range, allow_hyphen_values = true)]
+    band_range: Option<(f64, f64)>,
+    /// Band value seen by rays escaping the scene
+    #[arg(
+        long,
+        value_name = "V",
+        default_value_t = 0.0,
+        allow_hyphen_values = true
+    )]
+    band_sky: f64,
+    /// Cel-shade with BANDS flat shading steps and outlines at silhouettes and
+    /// creases, lit from the sun of a sky background or over the viewer's
+    /// shoulder
+    #[arg(long, value_name = "BANDS", value_parser = clap::value_parser!(u32).range(1..))]
+    toon: Option<u32>,
+
+    /// Add passes until the noise (relative standard error) of the
+    /// 95th-percentile pixel is below T, e.g. 0.02, up to the sample count
+    #[arg(long, value_name = "T")]
+    noise_threshold: Option<f64>,
+    /// Pixel percentile the threshold applies to, from 0 to 1
+    #[arg(long, value_name = "P", value_parser = unit, default_value_t = 0.95)]
+    noise_percentile: f64,
+    /// Stop sampling each pixel once its 95% confidence interval is narrower
+    /// than T of its brightness, up to the sample count
+    #[arg(long, value_name = "T")]
+    adaptive: Option<f64>,
+    /// Samples every pixel takes before --adaptive may stop it (default 16)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    min_samples: Option<i32>,
+    /// Render one sample per pixel per pass, writing the image so far to the
+    /// output file every N passes, so it can be checked or kept before the
+    /// render is done
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..), requires = "output")]
+    progressive: Option<i32>,
+    /// Render one sample per pixel per pass until S seconds have gone by,
+    /// keeping the passes done then, up to the sample count
+    #[arg(long, value_name = "S", value_parser = camera_setting("time_limit"))]
+    time_limit: Option<String>,
+    /// Render progressively, saving the samples so far to FILE at most once a
+    /// minute and when done, to be picked up with --resume
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+    /// Carry on with the render saved in checkpoint FILE, with its seed, up to
+    /// the sample count, checkpointing to FILE as it goes
+    #[arg(long, value_name = "FILE")]
+    resume: Option<PathBuf>,
+    /// Filter the noise out of the finished image, guided by the normals,
+    /// depth and albedo of the first hits
+    #[arg(long)]
+    denoise: bool,
+    /// Denoiser --denoise runs: filter (default, built in) or oidn for Intel
+    /// Open Image Denoise (requires the `oidn` feature); implies --denoise
+    #[arg(long, value_name = "NAME", value_parser = denoiser)]
+    denoiser: Option<DenoiserKind>,
+    /// Also write what the first hits of rays through the pixel centers are
+    /// made of: their depth, normal, albedo or object, raw in .exr files and
+    /// brought into range in others; may be repeated
+    #[arg(long = "aov", value_name = "PASS=FILE", value_parser = aov)]
+    aovs: Vec<(Pass, PathBuf)>,
+
+    /// Report progress as JSON lines on stderr instead of a progress bar
+    #[arg(long)]
+    progress_json: bool,
+    /// Also write the end-of-render statistics to FILE as JSON
+    #[arg(long, value_name = "FILE", conflicts_with_all = WITHOUT_STATS)]
+    stats_json: Option<PathBuf>,
+    /// Record the render stages as a Chrome trace viewable in chrome://tracing
+    /// or Perfetto (requires the `profiling` feature)
+    #[arg(long, value_name = "FILE")]
+    trace_chrome: Option<PathBuf>,
+
+    /// Image width in pixels, overriding the scene's camera
+    #[arg(long, value_name = "N", value_parser = camera_setting("width"))]
+    width: Option<String>,
+    /// Width over height, e.g. 1.5
+    #[arg(long, value_name = "R", value_parser = camera_setting("aspect_ratio"))]
+    aspect: Option<String>,
+    /// Samples per pixel
+    #[arg(long, value_name = "N", value_parser = camera_setting("samples"))]
+    samples: Option<String>,
+    /// Bounces before a path is cut off
+    #[arg(long, value_name = "N", value_parser = camera_setting("max_depth"))]
+    max_depth: Option<String>,
+    /// Vertical field of view in degrees
+    #[arg(long, value_name = "DEG", value_parser = camera_setting("vfov"))]
+    vfov: Option<String>,
+    /// Threads rendering the image (default one per core)
+    #[arg(long, value_name = "N", value_parser = camera_setting("threads"))]
+    threads: Option<String>,
+    /// Edge in pixels of the square tiles handed out to them (default 32)
+    #[arg(long, value_name = "N", value_parser = camera_setting("tile_size"))]
+    tile_size: Option<String>,
+    /// How radiance becomes pixel values: gamma (the default square root),
+    /// srgb or linear, which clip at white, or reinhard or aces, which roll
+    /// bright lights off smoothly
+    #[arg(long, value_name = "OP", value_parser = camera_setting("tonemap"))]
+    tonemap: Option<String>,
+    /// Any other camera setting a scene can make, e.g. look_from=13,2,3,
+    /// defocus_angle=0 or focus_dist=10; vectors are written X,Y,Z
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = setting)]
+    settings: Vec<(String, String)>,
+    /// Seed the random number generator, for reproducible renders
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Trace N scattered rays from each camera ray's first hit (default 1),
+    /// for less noise per camera ray
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    branches: Option<i32>,
+    /// Pixel and lens sample pattern: random (default), stratified, halton,
+    /// sobol or blue-noise (the default for previews)
+    #[arg(long, value_name = "NAME")]
+    sampler: Option<SamplerKind>,
+    /// shaded (default), clay to shade everything neutral gray, or wireframe
+    /// to also draw the edges of every surface
+    #[arg(long, value_name = "MODE")]
+    mode: Option<RenderMode>,
+    /// Log where NaN or infinite samples come from (such pixels are always
+    /// shown in magenta)
+    #[arg(long)]
+    debug_nan: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Preset {
+    /// The final scene of the first book
+    Random,
+    /// The Cornell box
+    Cornell,
+}
+
+// A camera setting given on the command line, checked against the keys and
+// values `CameraBuilder::set` takes. It is applied to the scene's camera once
+// that is loaded.
+pub fn camera_setting(
+    key: &'static str,
+) -> impl Fn(&str) -> Result<String, String> + Clone + Send + Sync {
+    move |value| {
+        CameraBuilder::new().set(key, value)?;
+        Ok(value.to_string())
+    }
+}
+
+pub fn setting(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or("expected KEY=VALUE")?;
+    let (key, value) = (key.trim(), value.trim());
+    CameraBuilder::new().set(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn aov(s: &str) -> Result<(Pass, PathBuf), String> {
+    let (pass, path) = s.split_once('=').ok_or("expected PASS=FILE")?;
+    Ok((pass.parse()?, PathBuf::from(path)))
+}
+
+fn denoiser(s: &str) -> Result<DenoiserKind, String> {
+    let kind = s.parse()?;
+    if kind == DenoiserKind::Oidn && !cfg!(feature = "oidn") {
+        return Err("rebuild with `--features oidn` to use the oidn denoiser".to_string());
+    }
+    Ok(kind)
+}
+
+fn shard_mode(s: &str) -> Result<ShardMode, String> {
+    match s {
+        "samples" => Ok(ShardMode::Samples),
+        "rows" => Ok(ShardMode::Rows),
+        _ => Err("expected `samples` or `rows`".to_string()),
+    }
+}
+
+fn band_range(s: &str) -> Result<(f64, f64), String> {
+    s.split_once(',')
+        .and_then(|(lo, hi)| Some((lo.trim().parse().ok()?, hi.trim().parse().ok()?)))
+        .ok_or_else(|| "expected MIN,MAX".to_string())
+}
+
+// A number from 0 to 1.
+fn unit(s: &str) -> Result<f64, String> {
+    s.parse()
+        .ok()
+        .filter(|x| (0.0..=1.0).contains(x))
+        .ok_or_else(|| "expected a number from 0 to 1".to_string())
+}
+
+impl Options {
+    // Overrides the camera a scene set up with the command-line options.
+    fn apply_camera_options(&self, camera: &mut CameraBuilder) {
+        let flags = [
+            ("width", &self.width),
+            ("aspect_ratio", &self.aspect),
+            ("samples", &self.samples),
+            ("max_depth", &self.max_depth),
+            ("vfov", &self.vfov),
+            ("threads", &self.threads),
+            ("tile_size", &self.tile_size),
+            ("tonemap", &self.tonemap),
+            ("time_limit", &self.time_limit),
+        ];
+        let flags = flags
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?)));
+        let settings = self.settings.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        for (key, value) in settings.chain(flags) {
+            camera
+                .set(key, value)
+                .expect("camera settings are checked while parsing");
+        }
+        if self.debug_nan {
+            camera.debug_nan = Some(true);
+        }
+        if self.sampler.is_some() {
+            camera.sampler = self.sampler;
+        }
+        if self.mode.is_some() {
+            camera.mode = self.mode;
+        }
+        if self.branches.is_some() {
+            camera.branches = self.branches;
+        }
+        if self.seed.is_some() {
+            camera.seed = self.seed;
+        }
+    }
+}
+
+pub fn run(mut options: Options) {
+    let _trace = options.trace_chrome.as_deref().map(record_trace);
+    // A resumed render must be seeded as before, and a checkpointed one
+    // seeded at all, so that scenes placing objects at random come out the
+    // same and the samples carry on where they left off.
+    let resumed = options.resume.as_deref().map(|path| {
+        checkpoint::load(path).unwrap_or_else(|err| {
+            fail(format!(
+                "Failed to load checkpoint {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    });
+    let seed_value = match &resumed {
+        Some(checkpoint) => Some(checkpoint.seed),
+        None if options.checkpoint.is_some() => Some(
+            options
+                .seed
+                .unwrap_or_else(|| (random() * u64::MAX as f64) as u64),
+        ),
+        None => options.seed,
+    };
+    if let Some(value) = seed_value {
+        seed(value);
+    }
+    options.seed = seed_value;
+    let denoise = options
+        .denoiser
+        .or(options.denoise.then(DenoiserKind::default));
+
+    if options.watch {
+        let scene = options.scene.as_deref().expect("--watch requires a scene");
+        let output = options
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("preview.ppm"));
+        watch(&options, scene, &output);
+    }
+
+    if let Some(range) = options.frames {
+        let scene = options.scene.as_deref().expect("--frames requires a scene");
+        let load = |frame, time| {
+            let (scene, mut camera) = load_frame(scene, frame, time)?;
+            options.apply_camera_options(&mut camera);
+            Ok((scene, camera))
+        };
+        if let Some(video) = &options.video {
+            let settings = VideoSettings {
+                bitrate: options.bitrate.clone(),
+                codec: options.codec.clone(),
+                ..VideoSettings::new(options.fps)
+            };
+            if let Err(err) = encode_video(range, video, &settings, options.temporal_blend, load) {
+                fail(format!("Failed to encode {}: {}", video.display(), err));
+            }
+            return;
+        }
+        let pattern = options
+            .output
+            .as_ref()
+            .map_or(animation::DEFAULT_PATTERN.to_string(), |p| {
+                p.display().to_string()
+            });
+        let result =
+            animation::render_sequence(range, options.fps, &pattern, options.temporal_blend, load);
+        if let Err(err) = result {
+            fail(format!("Animation failed: {}", err));
+        }
+        return;
+    }
+
+    // World
+    let load_start = Instant::now();
+    let (scene, mut settings) = {
+        render::stage_span!("build_scene");
+        match options.scene.as_deref() {
+            Some(path) => load_frame(path, 0, 0.0).unwrap_or_else(|err| {
+                fail(format!("Failed to load scene {}: {}", path.display(), err))
+            }),
+            None => match options.preset.unwrap_or(Preset::Random) {
+                Preset::Random => preset::random_spheres(),
+                Preset::Cornell => preset::cornell_box(),
+            },
+        }
+    };
+    let load_time = load_start.elapsed();
+
+    options.apply_camera_options(&mut settings);
+    if options.preview || options.gui {
+        // Blue noise looks cleaner over the first few passes these show.
+        settings.sampler.get_or_insert(SamplerKind::BlueNoise);
+    }
+    let camera = settings
+        .build()
+        .unwrap_or_else(|err| fail(format!("Invalid camera: {}", err)));
+    if let Some(shard) = &options.shard {
+        let shard = Shard::parse(shard, options.shard_by).unwrap_or_else(|err| usage_error(&err));
+        let output = options.output.as_deref().expect("--shard requires -o");
+        if let Err(err) = shard.render(&camera, &scene).save(output) {
+            fail(format!("Failed to write {}: {}", output.display(), err));
+        }
+        return;
+    }
+    if let Some(address) = &options.tev {
+        let name = options
+            .scene
+            .as_deref()
+            .map_or("render".to_string(), |p| p.display().to_string());
+        let result = TevClient::connect(address)
+            .and_then(|mut client| render_to_tev(&camera, &scene, &mut client, &name));
+        if let Err(err) = result {
+            fail(format!("Failed to stream to tev at {}: {}", address, err));
+        }
+        return;
+    }
+    if let Some(colormap) = options.band {
+        let image = render_band(&camera, &scene, options.band_sky);
+        let result = match &options.output {
+            Some(path) => File::create(path).and_then(|file| {
+                image.write_ppm(&mut io::BufWriter::new(file), colormap, options.band_range)
+            }),
+            None => image.write_ppm(
+                &mut io::BufWriter::new(io::stdout().lock()),
+                colormap,
+                options.band_range,
+            ),
+        };
+        if let Err(err) = result {
+            fail(format!("Failed to write image: {}", err));
+        }
+        return;
+    }
+    if let Some(bands) = options.toon {
+        let buffer = Toon::from(bands).render(&camera, &scene);
+        if let Err(err) = write_output(&buffer, options.output.as_deref()) {
+            fail(format!("Failed to write image: {}", err));
+        }
+        return;
+    }
+    if options.gui {
+        gui(camera, scene);
+        return;
+    }
+    if options.preview {
+        preview(&camera, &scene);
+        return;
+    }
+
+    let mut report: fn(&ProgressEvent) = if options.progress_json {
+        progress_json
+    } else if io::stderr().is_terminal() {
+        progress_bar
+    } else {
+        progress::log
+    };
+    if options.show {
+        show(&camera, &scene, &mut report, options.output.as_deref());
+        return;
+    }
+    let on_progress: &mut dyn FnMut(&ProgressEvent) = &mut report;
+    if options.noise_threshold.is_some()
+        || options.adaptive.is_some()
+        || denoise.is_some()
+        || options.gpu
+        || options.progressive.is_some()
+        || options.checkpoint.is_some()
+        || resumed.is_some()
+    {
+        let counters = stats::counters();
+        let trace_start = Instant::now();
+        let mut buffer = match (options.noise_threshold, options.adaptive) {
+            (_, Some(threshold)) => {
+                let mut target = AdaptiveTarget::from(threshold);
+                if let Some(min_samples) = options.min_samples {
+                    target.min_samples = min_samples;
+                }
+                let buffer =
+                    camera.render_adaptive(&scene, &target, &RenderControl::new(), on_progress);
+                eprintln!("Stopped after {} passes", buffer.samples());
+                buffer
+            }
+            (Some(threshold), None) => {
+                let target = NoiseTarget {
+                    percentile: options.noise_percentile,
+                    ..NoiseTarget::from(threshold)
+                };
+                let buffer =
+                    camera.render_until_clean(&scene, &target, &RenderControl::new(), on_progress);
+                eprintln!(
+                    "Stopped after {} passes with noise {:.4}",
+                    buffer.samples(),
+                    buffer.noise(target.percentile)
+                );
+                buffer
+            }
+            (None, None) if options.gpu => match camera.render_gpu(&scene, on_progress) {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    eprintln!("Rendering on the CPU: {}", err);
+                    camera.render_buffer(&scene, &RenderControl::new(), on_progress)
+                }
+            },
+            (None, None)
+                if options.progressive.is_some()
+                    || options.checkpoint.is_some()
+                    || resumed.is_some()
+                    || camera.time_limit().is_some() =>
+            {
+                let checkpoint = options.checkpoint.as_deref().or(options.resume.as_deref());
+                let seed = seed_value.unwrap_or_default();
+                let every = options.progressive.unwrap_or(0);
+                let mut last_save = Instant::now();
+                let mut on_pass = |buffer: &FrameBuffer| {
+                    if every > 0 && buffer.samples() % every == 0 {
+                        let path = options.output.as_deref().expect("checked while parsing");
+                        if let Err(err) = write_replacing(buffer, path) {
+                            eprintln!("Failed to write image: {}", err);
+                        }
+                    }
+                    if let Some(path) =
+                        checkpoint.filter(|_| last_save.elapsed() >= CHECKPOINT_INTERVAL)
+                    {
+                        save_checkpoint(seed, buffer, path);
+                        last_save = Instant::now();
+                    }
+                };
+                let start = match resumed {
+                    Some(checkpoint) => {
+                        eprintln!("Resuming after {} passes", checkpoint.buffer.samples());
+                        checkpoint.buffer
+                    }
+                    None => camera.frame_buffer(),
+                };
+                let buffer = camera
+                    .resume_progressive(
+                        &scene,
+                        start,
+                        1,
+                        &RenderControl::new(),
+                        on_progress,
+                        &mut on_pass,
+                    )
+                    .unwrap_or_else(|err| fail(format!("Failed to resume: {}", err)));
+                if let Some(path) = checkpoint {
+                    save_checkpoint(seed, &buffer, path);
+                }
+                buffer
+            }
+            (None, None) => camera.render_buffer(&scene, &RenderControl::new(), on_progress),
+        };
+        let mut stats = RenderStats {
+            counters: stats::counters() - counters,
+            samples: total_samples(&buffer),
+            build: load_time,
+            trace: trace_start.elapsed(),
+            ..RenderStats::default()
+        };
+        let aovs =
+            (denoise.is_some() || !options.aovs.is_empty()).then(|| Aovs::render(&camera, &scene));
+        if let (Some(denoiser), Some(aovs)) = (denoise, &aovs) {
+            // Keep the noisy image rather than throwing the render away.
+            match denoiser.denoise(&buffer, aovs) {
+                Ok(denoised) => buffer = denoised,
+                Err(err) => eprintln!("Failed to denoise, writing the noisy image: {}", err),
+            }
+        }
+        let write_start = Instant::now();
+        if let Err(err) = write_output(&buffer, options.output.as_deref()) {
+            fail(format!("Failed to write image: {}", err));
+        }
+        stats.write = write_start.elapsed();
+        stats.peak_memory = stats::peak_memory();
+        if let Some(aovs) = &aovs {
+            write_aovs(&options.aovs, aovs);
+        }
+        report_stats(&options, &stats);
+        return;
+    }
+    let result = match &options.output {
+        Some(path) => camera.render_to_path(&scene, path, on_progress),
+        None => camera.render_with_progress(&scene, &mut io::stdout().lock(), on_progress),
+    };
+    let mut stats = result.unwrap_or_else(|err| fail(format!("Failed to write image: {}", err)));
+    stats.build = load_time;
+    if !options.aovs.is_empty() {
+        write_aovs(&options.aovs, &Aovs::render(&camera, &scene));
+    }
+    report_stats(&options, &stats);
+}
+
+// Prints the end-of-render statistics, and writes them to --stats-json.
+fn report_stats(options: &Options, stats: &RenderStats) {
+    if options.progress_json {
+        eprintln!("{{\"event\": \"stats\", \"stats\": {}}}", stats.to_json());
+    } else {
+        eprintln!("{}", stats.report());
+    }
+    if let Some(path) = &options.stats_json {
+        if let Err(err) = std::fs::write(path, stats.to_json() + "\n") {
+            fail(format!("Failed to write {}: {}", path.display(), err));
+        }
+    }
+}
+
+// Samples taken over the whole image, which adaptive renders spread unevenly.
+fn total_samples(buffer: &FrameBuffer) -> u64 {
+    (0..buffer.height())
+        .flat_map(|j| (0..buffer.width()).map(move |i| buffer.sample_count(i, j) as u64))
+        .sum()
+}
+
+// Renders the frames produced by `load` and pipes them into an ffmpeg encoder.
+fn encode_video(
+    range: FrameRange,
+    path: &Path,
+    settings: &VideoSettings,
+    temporal_blend: Option<f64>,
+    load: impl Fn(i64, f64) -> Result<(Scene, CameraBuilder), String>,
+) -> Result<(), String> {
+    let mut encoder = VideoEncoder::spawn(path, settings).map_err(|err| err.to_string())?;
+    let mut temporal = temporal_blend.map(Temporal::from);
+    animation::render_sequence_with(range, settings.fps, load, |_, camera, scene| {
+        match &mut temporal {
+            Some(temporal) => temporal.render_to(camera, scene, encoder.frame()),
+            None => camera.render_to(scene, encoder.frame()),
+        }
+        .map_err(|err| format!("ffmpeg stopped accepting frames: {}", err))
+    })?;
+    encoder.finish().map_err(|err| err.to_string())
+}
+
+#[cfg(feature = "profiling")]
+fn record_trace(path: &Path) -> impl Drop {
+    render::profile::record_chrome_trace(path)
+}
+
+#[cfg(not(feature = "profiling"))]
+fn record_trace(_path: &Path) {
+    usage_error("rebuild with `--features profiling` to record traces");
+}
+
+fn save_checkpoint(seed: u64, buffer: &FrameBuffer, path: &Path) {
+    if let Err(err) = checkpoint::save(seed, buffer, path) {
+        eprintln!("Failed to save checkpoint {}: {}", path.display(), err);
+    }
+}
+
+// Writes the image to a hidden file next to `path` and then moves it over
+// `path`, so viewers watching the file never see half an image.
+fn write_replacing(buffer: &FrameBuffer, path: &Path) -> Result<(), Error> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let partial = path.with_file_name(format!(".{}", name));
+    buffer.write_image(&partial)?;
+    Ok(std::fs::rename(&partial, path)?)
+}
+
+// Writes each pass asked for with `--aov` to its file.
+fn write_aovs(passes: &[(Pass, PathBuf)], aovs: &Aovs) {
+    for (pass, path) in passes {
+        if let Err(err) = aovs.write(*pass, path) {
+            fail(format!(
+                "Failed to write the {} pass to {}: {}",
+                pass,
+                path.display(),
+                err
+            ));
+        }
+    }
+}
+
+// Progress bar on stderr for interactive renders, on top of the usual log.
+fn progress_bar(event: &ProgressEvent) {
+    const WIDTH: usize = 40;
+    progress::log(event);
+    match event {
+        ProgressEvent::Progress(stats) => {
+            let filled = ((stats.fraction() * WIDTH as f64) as usize).min(WIDTH);
+            let eta = stats.eta.map_or("?".to_string(), |eta| {
+                let seconds = eta.as_secs();
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            });
+            eprint!(
+                "\r[{}{}] {:3.0}% {:.2} Msamples/s, {:.2} Mrays/s, ETA {}   ",
+                "#".repeat(filled),
+                " ".repeat(WIDTH - filled),
+                stats.fraction() * 100.0,
+                stats.samples_per_sec / 1e6,
+                stats.rays_per_sec / 1e6,
+                eta
+            );
+        }
+        ProgressEvent::Finished(_) => eprintln!(),
+        _ => (),
+    }
+}
+
+// One JSON object per event on stderr, for wrappers tracking long renders.
+fn progress_json(event: &ProgressEvent) {
+    eprintln!("{}", event.to_json());
+}
+
+// Re-renders whenever the scene or an asset it loaded changes. Models and
+// images that didn't change come from the asset caches rather than disk.
+fn watch(options: &Options, scene: &Path, output: &Path) -> ! {
+    let mut watcher = Watcher::new(&[scene.to_path_buf()]);
+    loop {
+        let (loaded, assets) = render::assets::recording(|| load_frame(scene, 0, 0.0));
+        for asset in &assets {
+            watcher.watch(asset);
+        }
+        match loaded {
+            Ok((loaded, mut settings)) => {
+                // The scene's camera at preview quality: at most a few samples
+                // and shallow bounces, with blue-noise samples unless another
+                // sampler was asked for, to keep the noise fine-grained.
+                options.apply_camera_options(&mut settings);
+                settings.samples_per_pixel =
+                    Some(settings.samples_per_pixel.map_or(8, |n| n.min(8)));
+                settings.max_depth = Some(settings.max_depth.map_or(8, |n| n.min(8)));
+                settings.sampler.get_or_insert(SamplerKind::BlueNoise);
+
+                match settings.build() {
+                    Ok(camera) => {
+                        match camera.render_to_path(&loaded, output, &mut progress::log) {
+                            Ok(_) => info!("Wrote preview to {}", output.display()),
+                            Err(err) => eprintln!("Failed to write {}: {}", output.display(), err),
+                        }
+                    }
+                    Err(err) => eprintln!("Invalid camera: {}", err),
+                }
+            }
+            Err(err) => eprintln!("Failed to load scene {}: {}", scene.display(), err),
+        }
+
+        match assets.len() {
+            0 => eprintln!("Watching {} for changes...", scene.display()),
+            n => eprintln!(
+                "Watching {} and the {} file{} it loads for changes...",
+                scene.display(),
+                n,
+                if n == 1 { "" } else { "s" }
+            ),
+        }
+        watcher.wait_for_change();
+    }
+}
+
+#[cfg(feature = "preview")]
+fn preview(camera: &Camera, scene: &Scene) {
+    if let Err(err) = render::preview::run(camera, scene) {
+        fail(format!("Preview window failed: {}", err));
+    }
+}
+
+#[cfg(not(feature = "preview"))]
+fn preview(_camera: &Camera, _scene: &Scene) {
+    fail("Preview is unavailable: rebuild with `--features preview`");
+}
+
+#[cfg(feature = "preview")]
+fn show(
+    camera: &Camera,
+    scene: &Scene,
+    on_progress: &mut (dyn FnMut(&ProgressEvent) + Send),
+    output: Option<&Path>,
+) {
+    let write = |buffer: &FrameBuffer| {
+        if let Err(err) = write_output(buffer, output) {
+            fail(format!("Failed to write image: {}", err));
+        }
+    };
+    match render::preview::show_render(camera, scene, on_progress, write) {
+        Ok(true) => (),
+        Ok(false) => fail("Render aborted"),
+        Err(err) => fail(format!("Render window failed: {}", err)),
+    }
+}
+
+#[cfg(not(feature = "preview"))]
+fn show(
+    _camera: &Camera,
+    _scene: &Scene,
+    _on_progress: &mut (dyn FnMut(&ProgressEvent) + Send),
+    _output: Option<&Path>,
+) {
+    fail("The render window is unavailable: rebuild with `--features preview`");
+}
+
+#[cfg(feature = "gui")]
+fn gui(camera: Camera, scene: Scene) {
+    if let Err(err) = render::gui::run(camera, scene) {
+        fail(format!("Control panel failed: {}", err));
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn gui(_camera: Camera, _scene: Scene) {
+    fail("The control panel is unavailable: rebuild with `--features gui`");
+}
@@ -0,0 +1,318 @@
+use render::bake::{Bake, BakeKind};
+use render::framebuffer::FrameBuffer;
+use render::info::SceneInfo;
+use render::lidar::{self, ScanPattern, Sensor};
+use render::pdf::MisHeuristic;
+use render::preset;
+use render::probe::{self, ProbeGrid};
+use render::queue::{read_manifest, write_summary, QueueJob};
+use render::ray::Point3;
+
+use super::{fail, load_scene, scene_or_default, usage_error, write_output};
+use clap::Args;
+use log::info;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Args)]
+pub struct MergeArgs {
+    /// Image, or accumulation buffer with an .accum extension, to write
+    output: PathBuf,
+    /// Accumulation buffers of the shards
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct QueueArgs {
+    /// Scenes to render, after those in the manifest
+    #[arg(required_unless_present = "manifest")]
+    scenes: Vec<PathBuf>,
+    /// File of jobs, one `SCENE [OUTPUT]` per line
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+    /// Scenes rendered at a time
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Directory of the images of jobs not naming their output
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    output_dir: PathBuf,
+}
+
+#[derive(Args)]
+pub struct LidarArgs {
+    /// Scene to load (default: the final scene of the first book)
+    scene: Option<PathBuf>,
+    /// Write the points to FILE as PLY, or as XYZ with an .xyz extension,
+    /// instead of PLY to stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Scan like a depth camera, through the default camera's pixels, rather
+    /// than a spinning sensor
+    #[arg(long)]
+    grid: bool,
+    /// Sensor position (default: the camera's)
+    #[arg(long, value_name = "X,Y,Z", value_parser = vector, allow_hyphen_values = true)]
+    origin: Option<[f64; 3]>,
+    /// Lasers spread over -15..15 degrees of elevation
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    channels: u32,
+    /// Readings per turn
+    #[arg(long, value_name = "N", default_value_t = 1800)]
+    steps: u32,
+    /// Furthest distance measured (default 100)
+    #[arg(long, value_name = "R")]
+    range: Option<f64>,
+    /// Standard deviation of the noise added to distances
+    #[arg(long, value_name = "SIGMA")]
+    noise: Option<f64>,
+}
+
+#[derive(Args)]
+pub struct BakeArgs {
+    /// Scene to load (default: the final scene of the first book)
+    scene: Option<PathBuf>,
+    /// Write the texture to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Object to bake, counting from 0 in the order the scene adds them
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    object: usize,
+    /// Bake the ambient occlusion within --distance instead of the irradiance
+    #[arg(long)]
+    ao: bool,
+    /// Texture size (default 256x256)
+    #[arg(long, value_name = "WxH", value_parser = size)]
+    size: Option<(i32, i32)>,
+    /// Rays per texel (default 64)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    samples: Option<i32>,
+    /// Occluders further than D don't count towards --ao (default 1)
+    #[arg(long, value_name = "D")]
+    distance: Option<f64>,
+    /// Weighing of light found by sampling lights and by scattering: power
+    /// (default) or balance
+    #[arg(long, value_name = "NAME")]
+    mis: Option<MisHeuristic>,
+}
+
+#[derive(Args)]
+pub struct ProbesArgs {
+    /// Scene to load (default: the final scene of the first book)
+    scene: Option<PathBuf>,
+    /// Corner of the grid
+    #[arg(long, value_name = "X,Y,Z", value_parser = vector, allow_hyphen_values = true)]
+    min: [f64; 3],
+    /// Opposite corner of the grid
+    #[arg(long, value_name = "X,Y,Z", value_parser = vector, allow_hyphen_values = true)]
+    max: [f64; 3],
+    /// Write the probes to FILE as JSON, or as binary with a .bin extension,
+    /// instead of JSON to stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Probes along each axis
+    #[arg(long, value_name = "X,Y,Z", value_parser = vector, default_value = "4,4,4", allow_hyphen_values = true)]
+    count: [f64; 3],
+    /// Rays per probe (default 256)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    samples: Option<i32>,
+    /// Weighing of light found by sampling lights and by scattering: power
+    /// (default) or balance
+    #[arg(long, value_name = "NAME")]
+    mis: Option<MisHeuristic>,
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Scene to load (default: the final scene of the first book)
+    scene: Option<PathBuf>,
+}
+
+// Three comma-separated numbers, X,Y,Z.
+fn vector(s: &str) -> Result<[f64; 3], String> {
+    let parts: Option<Vec<f64>> = s.split(',').map(|p| p.trim().parse().ok()).collect();
+    match parts {
+        Some(p) if p.len() == 3 => Ok([p[0], p[1], p[2]]),
+        _ => Err("expected X,Y,Z".to_string()),
+    }
+}
+
+fn size(s: &str) -> Result<(i32, i32), String> {
+    s.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .ok_or_else(|| "expected WIDTHxHEIGHT".to_string())
+}
+
+fn point([x, y, z]: [f64; 3]) -> Point3 {
+    Point3::from(x, y, z)
+}
+
+pub fn merge(args: MergeArgs) {
+    let MergeArgs { output, inputs } = args;
+    let result = (|| -> Result<(), String> {
+        let load = |path: &PathBuf| {
+            FrameBuffer::load(path).map_err(|err| format!("{}: {}", path.display(), err))
+        };
+        let mut buffer = load(&inputs[0])?;
+        for input in &inputs[1..] {
+            buffer.merge(&load(input)?)?;
+        }
+
+        if output.extension().is_some_and(|ext| ext == "accum") {
+            buffer
+                .save(&output)
+                .map_err(|err| format!("{}: {}", output.display(), err))
+        } else {
+            buffer.write_image(&output).map_err(|err| err.to_string())
+        }
+    })();
+    if let Err(err) = result {
+        fail(format!("Merge failed: {}", err));
+    }
+}
+
+pub fn queue(args: QueueArgs) {
+    let mut jobs = match &args.manifest {
+        Some(path) => read_manifest(path, &args.output_dir).unwrap_or_else(|err| {
+            fail(format!(
+                "Failed to read manifest {}: {}",
+                path.display(),
+                err
+            ))
+        }),
+        None => Vec::new(),
+    };
+    jobs.extend(
+        args.scenes
+            .iter()
+            .map(|scene| QueueJob::from_scene(scene, &args.output_dir)),
+    );
+    if jobs.is_empty() {
+        usage_error("queue requires scene files or a manifest with jobs");
+    }
+
+    let reports = render::queue::run(&jobs, args.jobs, load_scene, preset::default_camera);
+    write_summary(&mut io::stderr(), &reports).ok();
+    if reports.iter().any(|report| report.result.is_err()) {
+        std::process::exit(1);
+    }
+}
+
+pub fn lidar(args: LidarArgs) {
+    let mut camera = preset::default_camera();
+    let origin = args.origin.map_or(camera.look_from.unwrap(), point);
+    let mut sensor = Sensor::rotating(origin);
+    if let Some(range) = args.range {
+        sensor.max_range = range;
+    }
+    if let Some(noise) = args.noise {
+        sensor.range_noise = noise;
+    }
+    sensor.pattern = if args.grid {
+        camera.look_from = Some(origin);
+        let camera = camera
+            .build()
+            .unwrap_or_else(|err| usage_error(&format!("no depth camera at the origin: {}", err)));
+        ScanPattern::Grid(Box::new(camera))
+    } else {
+        ScanPattern::Rotating {
+            channels: args.channels,
+            steps: args.steps,
+            min_elevation: -15.0,
+            max_elevation: 15.0,
+        }
+    };
+
+    let scene = scene_or_default(args.scene.as_deref());
+    let points = lidar::scan(&scene, &sensor);
+    info!("Captured {} points", points.len());
+
+    let result = match &args.output {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            if path.extension().is_some_and(|ext| ext == "xyz") {
+                lidar::write_xyz(&mut out, &points)
+            } else {
+                lidar::write_ply(&mut out, &points)
+            }
+        }),
+        None => lidar::write_ply(&mut io::BufWriter::new(io::stdout().lock()), &points),
+    };
+    if let Err(err) = result {
+        fail(format!("Failed to write point cloud: {}", err));
+    }
+}
+
+pub fn bake(args: BakeArgs) {
+    let mut bake = Bake::default();
+    if args.ao {
+        bake.kind = BakeKind::Occlusion;
+    }
+    if let Some((width, height)) = args.size {
+        (bake.width, bake.height) = (width, height);
+    }
+    if let Some(samples) = args.samples {
+        bake.samples = samples;
+    }
+    if let Some(distance) = args.distance {
+        bake.distance = distance;
+    }
+    if let Some(mis) = args.mis {
+        bake.mis = mis;
+    }
+
+    let scene = scene_or_default(args.scene.as_deref());
+    let buffer = bake
+        .render(&scene, args.object)
+        .unwrap_or_else(|err| fail(format!("Failed to bake: {}", err)));
+    if let Err(err) = write_output(&buffer, args.output.as_deref()) {
+        fail(format!("Failed to write texture: {}", err));
+    }
+}
+
+pub fn probes(args: ProbesArgs) {
+    let counts = args.count.map(|n| n.max(1.0) as u32);
+    let mut grid = ProbeGrid::from(point(args.min), point(args.max), counts);
+    if let Some(samples) = args.samples {
+        grid.samples = samples;
+    }
+    if let Some(mis) = args.mis {
+        grid.mis = mis;
+    }
+
+    let scene = scene_or_default(args.scene.as_deref());
+    let probes = grid.render(&scene);
+
+    let result = match &args.output {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                probe::write_binary(&mut out, &grid, &probes)
+            } else {
+                probe::write_json(&mut out, &grid, &probes)
+            }
+        }),
+        None => probe::write_json(&mut io::BufWriter::new(io::stdout().lock()), &grid, &probes),
+    };
+    if let Err(err) = result {
+        fail(format!("Failed to write probes: {}", err));
+    }
+}
+
+pub fn info(args: InfoArgs) {
+    let load_start = Instant::now();
+    let scene = scene_or_default(args.scene.as_deref());
+    let load_time = load_start.elapsed();
+
+    let info = SceneInfo::from(&scene);
+    let mut out = io::stdout().lock();
+    let result = info
+        .write(&mut out)
+        .and_then(|_| writeln!(out, "load time:   {:.2?}", load_time));
+    if let Err(err) = result {
+        fail(format!("Failed to write scene info: {}", err));
+    }
+}
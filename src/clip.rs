@@ -0,0 +1,211 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::Material;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+// A plane cutting away everything on the side its normal points to, for
+// cutaway views. Where the cut goes through a closed object, the cut face is
+// shaded with `cap`; without one, rays look into the object.
+#[derive(Clone)]
+pub struct ClipPlane {
+    point: Point3,
+    normal: Vec3, // Unit length, pointing into the removed half-space
+    cap: Option<Material>,
+    // Tangents spanning the plane, for the cap's texture coordinates.
+    tangent_u: Vec3,
+    tangent_v: Vec3,
+}
+
+impl ClipPlane {
+    pub fn from(point: Point3, normal: Vec3, cap: Option<Material>) -> Self {
+        let normal = unit_vector(normal);
+        let helper = if normal.x().abs() > 0.9 {
+            Vec3::from(0.0, 1.0, 0.0)
+        } else {
+            Vec3::from(1.0, 0.0, 0.0)
+        };
+        let tangent_u = unit_vector(cross(&helper, &normal));
+        let tangent_v = cross(&normal, &tangent_u);
+        Self {
+            point,
+            normal,
+            cap,
+            tangent_u,
+            tangent_v,
+        }
+    }
+
+    fn cap_record<'a>(&self, r: &Ray, t: f64, cap: &'a Material) -> HitRecord<'a> {
+        let p = r.at(t);
+        let offset = p - self.point;
+        let mut rec = HitRecord {
+            p,
+            normal: self.normal,
+            geometric_normal: self.normal,
+            t,
+            u: dot(&offset, &self.tangent_u),
+            v: dot(&offset, &self.tangent_v),
+            dpdu: self.tangent_u,
+            dpdv: self.tangent_v,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: cap,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &self.normal);
+        rec
+    }
+}
+
+// The closest hit on `world` within `ray_t`, ignoring everything the planes
+// cut away and hitting cap faces where a cut goes through a solid.
+pub fn hit<'a>(
+    world: &'a dyn Hittable,
+    planes: &'a [ClipPlane],
+    r: &Ray,
+    ray_t: Interval,
+) -> Option<HitRecord<'a>> {
+    match cut(world, planes, r, ray_t) {
+        Cut::Kept(kept) => world.hit(r, kept),
+        Cut::Ended(hit) => hit,
+    }
+}
+
+// Whether anything the planes leave of `world`, caps included, blocks the
+// ray within `ray_t`.
+pub fn is_occluded(world: &dyn Hittable, planes: &[ClipPlane], r: &Ray, ray_t: Interval) -> bool {
+    match cut(world, planes, r, ray_t) {
+        Cut::Kept(kept) => world.is_occluded(r, kept),
+        Cut::Ended(hit) => hit.is_some(),
+    }
+}
+
+// Like `hit`, but also returns the index of the object of `world` hit, which
+// caps have none of.
+pub fn hit_object<'a>(
+    world: &'a HittableList,
+    planes: &'a [ClipPlane],
+    r: &Ray,
+    ray_t: Interval,
+) -> Option<(Option<usize>, HitRecord<'a>)> {
+    match cut(world, planes, r, ray_t) {
+        Cut::Kept(kept) => world
+            .hit_object(r, kept)
+            .map(|(index, rec)| (Some(index), rec)),
+        Cut::Ended(hit) => hit.map(|rec| (None, rec)),
+    }
+}
+
+// What the planes leave of a ray: the part of it the world is still hit in,
+// or the cap it ends on, if any.
+enum Cut<'a> {
+    Kept(Interval),
+    Ended(Option<HitRecord<'a>>),
+}
+
+fn cut<'a>(world: &dyn Hittable, planes: &'a [ClipPlane], r: &Ray, ray_t: Interval) -> Cut<'a> {
+    // The part of space kept by all planes is convex, so the kept part of
+    // the ray is one interval. Track the plane the ray enters it through.
+    let mut kept = ray_t;
+    let mut entry = None;
+    for plane in planes {
+        let towards = dot(&r.direction(), &plane.normal);
+        let height = dot(&(r.origin() - plane.point), &plane.normal);
+        if towards == 0.0 {
+            if height > 0.0 {
+                return Cut::Ended(None);
+            }
+            continue;
+        }
+        let t = -height / towards;
+        if towards < 0.0 {
+            if t > kept.min {
+                kept.min = t;
+                entry = Some(plane);
+            }
+        } else {
+            kept.max = kept.max.min(t);
+        }
+    }
+    if kept.is_empty() {
+        return Cut::Ended(None);
+    }
+
+    if let Some(plane) = entry {
+        if let Some(cap) = &plane.cap {
+            if world.contains(r.at(kept.min)) {
+                return Cut::Ended(Some(plane.cap_record(r, kept.min, cap)));
+            }
+        }
+    }
+    Cut::Kept(kept)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::utility::INFINITY;
+
+    fn gray(albedo: f64) -> Material {
+        Material::Lambertian(Lambertian::from(Color::gray(albedo)))
+    }
+
+    #[test]
+    fn cuts_and_caps_a_sphere() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(Point3::new(), 1.0, gray(0.5))));
+        let ray_t = Interval::from(0.001, INFINITY);
+        // Looking down -z from z = 5.
+        let r = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let albedo = |rec: &HitRecord| rec.mat.albedo(rec).r();
+
+        // Cutting away the front half without a cap shows the back half's inside.
+        let open = [ClipPlane::from(
+            Point3::new(),
+            Vec3::from(0.0, 0.0, 1.0),
+            None,
+        )];
+        let rec = hit(&world, &open, &r, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.p.z(), -1.0);
+        assert!(!rec.front_face);
+
+        // A cap closes the cut, facing the camera.
+        let capped = [ClipPlane::from(
+            Point3::new(),
+            Vec3::from(0.0, 0.0, 1.0),
+            Some(gray(0.8)),
+        )];
+        let rec = hit(&world, &capped, &r, ray_t).unwrap();
+        assert!(is_occluded(&world, &capped, &r, ray_t));
+        approx::assert_relative_eq!(rec.p.z(), 0.0);
+        approx::assert_relative_eq!(rec.normal.z(), 1.0);
+        approx::assert_relative_eq!(albedo(&rec), 0.8);
+
+        // Rays that don't cross the cut inside the sphere see no cap.
+        let beside = Ray::from(Point3::from(2.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(hit(&world, &capped, &beside, ray_t).is_none());
+        assert!(!is_occluded(&world, &capped, &beside, ray_t));
+
+        // Shadow rays ending before the back half see nothing in the way.
+        let short = Interval::from(0.001, 5.5);
+        assert!(!is_occluded(&world, &open, &r, short));
+        assert!(is_occluded(&world, &open, &r, ray_t));
+
+        // Cutting away the back half leaves the front untouched.
+        let back = [ClipPlane::from(
+            Point3::new(),
+            Vec3::from(0.0, 0.0, -1.0),
+            Some(gray(0.8)),
+        )];
+        let rec = hit(&world, &back, &r, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.p.z(), 1.0);
+        approx::assert_relative_eq!(albedo(&rec), 0.5);
+    }
+}
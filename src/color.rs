@@ -1,36 +1,720 @@
-use crate::utility::Interval;
+use crate::utility::{random, random_in_range, Interval, PI};
+use crate::vec3::Vec3;
 
-pub type Color = crate::vec3::Vec3;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops;
+use std::str::FromStr;
+
+// Linear RGB color. Kept apart from `Vec3` so positions and colors can't be
+// mixed by accident; `From` converts between the two where it makes sense,
+// e.g. for expression-language results.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Color {
+    e: [f64; 3],
+}
+
+// Stands in for pixels whose samples were NaN or infinite, so they stand out
+// instead of turning black.
+pub const INVALID_SAMPLE: Color = Color { e: [1.0, 0.0, 1.0] };
+
+impl Color {
+    pub fn new() -> Self {
+        Self { e: [0.0; 3] }
+    }
+
+    pub fn from(r: f64, g: f64, b: f64) -> Self {
+        Self { e: [r, g, b] }
+    }
+
+    pub fn gray(value: f64) -> Self {
+        Self::from(value, value, value)
+    }
+
+    pub fn r(&self) -> f64 {
+        self.e[0]
+    }
+
+    pub fn g(&self) -> f64 {
+        self.e[1]
+    }
+
+    pub fn b(&self) -> f64 {
+        self.e[2]
+    }
+
+    pub fn random() -> Self {
+        Self::from(random(), random(), random())
+    }
+
+    pub fn random_in_range(min: f64, max: f64) -> Self {
+        Self::from(
+            random_in_range(min, max),
+            random_in_range(min, max),
+            random_in_range(min, max),
+        )
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self::from(f(self.e[0]), f(self.e[1]), f(self.e[2]))
+    }
+
+    // Mean of the three channels.
+    pub fn average(&self) -> f64 {
+        (self.e[0] + self.e[1] + self.e[2]) / 3.0
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.e.iter().all(|x| x.is_finite())
+    }
+
+    pub fn max_channel(&self) -> f64 {
+        self.e[0].max(self.e[1]).max(self.e[2])
+    }
+
+    pub fn min_channel(&self) -> f64 {
+        self.e[0].min(self.e[1]).min(self.e[2])
+    }
+
+    // Relative luminance, with Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.e[0] + 0.7152 * self.e[1] + 0.0722 * self.e[2]
+    }
+
+    // Encodes linear components with the sRGB transfer curve.
+    pub fn to_srgb(&self) -> Self {
+        self.map(|c| {
+            if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        })
+    }
+
+    // Decodes sRGB-encoded components to linear.
+    pub fn from_srgb(r: f64, g: f64, b: f64) -> Self {
+        Self::from(r, g, b).map(|c| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    }
+
+    // Hue in [0,1), saturation and value of the components as they are.
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let (r, g, b) = (self.e[0], self.e[1], self.e[2]);
+        let max = self.max_channel();
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+        let saturation = if max > 0.0 { delta / max } else { 0.0 };
+        (hue / 6.0, saturation, max)
+    }
+
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Self {
+        let h = hue.rem_euclid(1.0) * 6.0;
+        let c = value * saturation;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as i32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = value - c;
+        Self::from(r + m, g + m, b + m)
+    }
+
+    // Okhsl hue in [0,1), saturation and lightness of this linear color (see
+    // `oklab` below). Perceptually even, unlike HSV, so it suits palettes.
+    pub fn to_okhsl(&self) -> (f64, f64, f64) {
+        oklab::linear_srgb_to_okhsl(self)
+    }
+
+    // A linear color from Okhsl coordinates.
+    pub fn from_okhsl(hue: f64, saturation: f64, lightness: f64) -> Self {
+        oklab::okhsl_to_linear_srgb(hue, saturation, lightness)
+    }
+
+    // Approximate color of a black body at `kelvin` (1000 to 40000), in
+    // linear RGB normalized so the brightest channel is one. Based on Tanner
+    // Helland's fit to blackbody data.
+    pub fn from_temperature(kelvin: f64) -> Self {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let g = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        let srgb = |c: f64| c.clamp(0.0, 255.0) / 255.0;
+        Self::from_srgb(srgb(r), srgb(g), srgb(b))
+    }
+}
+
+impl From<Vec3> for Color {
+    fn from(v: Vec3) -> Self {
+        Self::from(v.x(), v.y(), v.z())
+    }
+}
+
+impl From<Color> for Vec3 {
+    fn from(c: Color) -> Self {
+        Vec3::from(c.r(), c.g(), c.b())
+    }
+}
+
+impl ops::Add for Color {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::from(
+            self.e[0] + rhs.e[0],
+            self.e[1] + rhs.e[1],
+            self.e[2] + rhs.e[2],
+        )
+    }
+}
+
+impl ops::AddAssign for Color {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub for Color {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::from(
+            self.e[0] - rhs.e[0],
+            self.e[1] - rhs.e[1],
+            self.e[2] - rhs.e[2],
+        )
+    }
+}
+
+impl ops::Mul for Color {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from(
+            self.e[0] * rhs.e[0],
+            self.e[1] * rhs.e[1],
+            self.e[2] * rhs.e[2],
+        )
+    }
+}
+
+impl ops::MulAssign for Color {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl ops::Mul<f64> for Color {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self::Output {
+        self.map(|c| c * rhs)
+    }
+}
+
+impl ops::Mul<Color> for f64 {
+    type Output = Color;
+    fn mul(self, rhs: Color) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl ops::MulAssign<f64> for Color {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs;
+    }
+}
+
+impl ops::Div<f64> for Color {
+    type Output = Self;
+    fn div(self, rhs: f64) -> Self::Output {
+        self * (1.0 / rhs)
+    }
+}
+
+impl ops::DivAssign<f64> for Color {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs;
+    }
+}
+
+impl ops::Index<usize> for Color {
+    type Output = f64;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.e[index]
+    }
+}
+
+impl ops::IndexMut<usize> for Color {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.e[index]
+    }
+}
+
+impl std::iter::Sum for Color {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Color::new(), |acc, c| acc + c)
+    }
+}
 
 fn linear_to_gamma(linear_component: f64) -> f64 {
     linear_component.sqrt()
 }
 
-pub fn write_color(pixel_color: Color, samples_per_pixel: i32) {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
+// How linear radiance becomes the values of displayed pixels. `Gamma` is the
+// square root images have always been encoded with, `Srgb` the sRGB transfer
+// curve (close to gamma 2.2) and `Linear` no encoding at all; all three clip
+// at white. `Reinhard` (x / (1 + x)) and `Aces` (Narkowicz's fit of the ACES
+// filmic curve) first roll bright values off towards white, so lights and
+// highlights keep their shape, then encode as sRGB.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Tonemap {
+    #[default]
+    Gamma,
+    Linear,
+    Srgb,
+    Reinhard,
+    Aces,
+}
+
+impl Tonemap {
+    // The displayed value of a linear color, from 0 to 1 where it isn't
+    // clipped.
+    pub fn apply(self, color: Color) -> Color {
+        match self {
+            Tonemap::Gamma => color.map(linear_to_gamma),
+            Tonemap::Linear => color,
+            Tonemap::Srgb => color.to_srgb(),
+            Tonemap::Reinhard => color.map(|c| c / (1.0 + c)).to_srgb(),
+            // The fit overshoots white slightly for very bright values.
+            Tonemap::Aces => color
+                .map(|c| ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).min(1.0))
+                .to_srgb(),
+        }
+    }
+}
+
+impl FromStr for Tonemap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gamma" => Ok(Tonemap::Gamma),
+            "linear" => Ok(Tonemap::Linear),
+            "srgb" => Ok(Tonemap::Srgb),
+            "reinhard" => Ok(Tonemap::Reinhard),
+            "aces" => Ok(Tonemap::Aces),
+            _ => Err(format!(
+                "unknown tonemap '{}', expected gamma, linear, srgb, reinhard or aces",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Tonemap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Tonemap::Gamma => "gamma",
+            Tonemap::Linear => "linear",
+            Tonemap::Srgb => "srgb",
+            Tonemap::Reinhard => "reinhard",
+            Tonemap::Aces => "aces",
+        })
+    }
+}
 
+pub fn write_color(
+    out: &mut impl Write,
+    pixel_color: Color,
+    samples_per_pixel: i32,
+    tonemap: Tonemap,
+) -> io::Result<()> {
+    // Write the translated [0,255] value of each color component.
+    let [r, g, b] = to_rgb8(pixel_color, samples_per_pixel, tonemap);
+    writeln!(out, "{} {} {}", r, g, b)
+}
+
+// Converts an accumulated pixel sum into tone-mapped 8-bit RGB. Pixels
+// without samples are black.
+pub fn to_rgb8(pixel_color: Color, samples_per_pixel: i32, tonemap: Tonemap) -> [u8; 3] {
+    if samples_per_pixel <= 0 {
+        return [0, 0, 0];
+    }
     // Divide the color by the number of samples.
     let scale = 1.0 / samples_per_pixel as f64;
+    let shown = tonemap.apply(pixel_color * scale);
+    const INTENSITY: Interval = Interval::from(0.0, 0.999);
+    let convert = |c: f64| (256.0 * INTENSITY.clamp(c)) as u8;
 
-    r *= scale;
-    g *= scale;
-    b *= scale;
+    [convert(shown.r()), convert(shown.g()), convert(shown.b())]
+}
 
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+// Oklab and the Okhsl model built on it, after Björn Ottosson's reference
+// implementation ("A perceptual color space for image processing" and
+// "Okhsv and Okhsl"). Okhsl is defined over sRGB-encoded colors; these
+// functions take and return linear colors and apply the transfer curve inside.
+mod oklab {
+    use super::{Color, PI};
 
-    // Write the translated [0,255] value of each color component.
-    static INTENSITY: Interval = Interval {
-        min: 0.0,
-        max: 0.999,
-    };
-    println!(
-        "{} {} {}",
-        255.99 * INTENSITY.clamp(r),
-        255.99 * INTENSITY.clamp(g),
-        255.99 * INTENSITY.clamp(b)
-    )
+    struct Lab {
+        l: f64,
+        a: f64,
+        b: f64,
+    }
+
+    struct Cusp {
+        l: f64,
+        c: f64,
+    }
+
+    fn linear_srgb_to_oklab(c: &Color) -> Lab {
+        let l = 0.4122214708 * c.r() + 0.5363325363 * c.g() + 0.0514459929 * c.b();
+        let m = 0.2119034982 * c.r() + 0.6806995451 * c.g() + 0.1073969566 * c.b();
+        let s = 0.0883024619 * c.r() + 0.2817188376 * c.g() + 0.6299787005 * c.b();
+        let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+        Lab {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+
+    fn oklab_to_linear_srgb(lab: &Lab) -> Color {
+        let l = lab.l + 0.3963377774 * lab.a + 0.2158037573 * lab.b;
+        let m = lab.l - 0.1055613458 * lab.a - 0.0638541728 * lab.b;
+        let s = lab.l - 0.0894841775 * lab.a - 1.2914855480 * lab.b;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+        Color::from(
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        )
+    }
+
+    // Rows of the LMS to linear sRGB matrix.
+    const RED: [f64; 3] = [4.0767416621, -3.3077115913, 0.2309699292];
+    const GREEN: [f64; 3] = [-1.2684380046, 2.6097574011, -0.3413193965];
+    const BLUE: [f64; 3] = [-0.0041960863, -0.7034186147, 1.7076147010];
+
+    // Coefficients of the LMS components along the hue direction (a, b).
+    fn k_lms(a: f64, b: f64) -> [f64; 3] {
+        [
+            0.3963377774 * a + 0.2158037573 * b,
+            -0.1055613458 * a - 0.0638541728 * b,
+            -0.0894841775 * a - 1.2914855480 * b,
+        ]
+    }
+
+    // Maximum saturation S = C/L for the normalized hue (a, b) that stays in
+    // the sRGB gamut: a polynomial estimate refined by one Halley step.
+    fn compute_max_saturation(a: f64, b: f64) -> f64 {
+        let (k, w) = if -1.88170328 * a - 0.80936493 * b > 1.0 {
+            // Red component goes below zero first
+            (
+                [1.19086277, 1.76576728, 0.59662641, 0.75515197, 0.56771245],
+                RED,
+            )
+        } else if 1.81444104 * a - 1.19445276 * b > 1.0 {
+            // Green component
+            (
+                [0.73956515, -0.45954404, 0.08285427, 0.12541070, 0.14503204],
+                GREEN,
+            )
+        } else {
+            // Blue component
+            (
+                [
+                    1.35733652,
+                    -0.00915799,
+                    -1.15130210,
+                    -0.50559606,
+                    0.00692167,
+                ],
+                BLUE,
+            )
+        };
+
+        let mut s = k[0] + k[1] * a + k[2] * b + k[3] * a * a + k[4] * a * b;
+        let k_lms = k_lms(a, b);
+
+        let mut f = 0.0;
+        let mut f1 = 0.0;
+        let mut f2 = 0.0;
+        for i in 0..3 {
+            let x = 1.0 + s * k_lms[i];
+            f += w[i] * x * x * x;
+            f1 += w[i] * 3.0 * k_lms[i] * x * x;
+            f2 += w[i] * 6.0 * k_lms[i] * k_lms[i] * x;
+        }
+        s -= f * f1 / (f1 * f1 - 0.5 * f * f2);
+        s
+    }
+
+    // Lightness and chroma of the most saturated color for a hue.
+    fn find_cusp(a: f64, b: f64) -> Cusp {
+        let s_cusp = compute_max_saturation(a, b);
+        let rgb = oklab_to_linear_srgb(&Lab {
+            l: 1.0,
+            a: s_cusp * a,
+            b: s_cusp * b,
+        });
+        let l_cusp = (1.0 / rgb.max_channel()).cbrt();
+        Cusp {
+            l: l_cusp,
+            c: l_cusp * s_cusp,
+        }
+    }
+
+    // Where the line from (L0, 0) to (L1, C1) leaves the gamut, as a fraction t.
+    fn find_gamut_intersection(a: f64, b: f64, l1: f64, c1: f64, l0: f64, cusp: &Cusp) -> f64 {
+        if (l1 - l0) * cusp.c - (cusp.l - l0) * c1 <= 0.0 {
+            // Lower half: the triangle edge is exact.
+            return cusp.c * l0 / (c1 * cusp.l + cusp.c * (l0 - l1));
+        }
+
+        // Upper half: start from the triangle and take a Halley step for each
+        // channel towards where it reaches one.
+        let mut t = cusp.c * (l0 - 1.0) / (c1 * (cusp.l - 1.0) + cusp.c * (l0 - l1));
+        let (dl, dc) = (l1 - l0, c1);
+        let k = k_lms(a, b);
+        let lightness = l0 * (1.0 - t) + t * l1;
+        let chroma = t * c1;
+
+        let mut lms = [0.0; 3];
+        let mut lms_dt = [0.0; 3];
+        let mut lms_dt2 = [0.0; 3];
+        for i in 0..3 {
+            let x = lightness + chroma * k[i];
+            let x_dt = dl + dc * k[i];
+            lms[i] = x * x * x;
+            lms_dt[i] = 3.0 * x_dt * x * x;
+            lms_dt2[i] = 6.0 * x_dt * x_dt * x;
+        }
+
+        let step = |w: [f64; 3]| {
+            let f = |v: [f64; 3]| w[0] * v[0] + w[1] * v[1] + w[2] * v[2];
+            let (x, x1, x2) = (f(lms) - 1.0, f(lms_dt), f(lms_dt2));
+            let u = x1 / (x1 * x1 - 0.5 * x * x2);
+            if u >= 0.0 {
+                -x * u
+            } else {
+                f64::MAX
+            }
+        };
+        t += step(RED).min(step(GREEN)).min(step(BLUE));
+        t
+    }
+
+    fn toe(x: f64) -> f64 {
+        const K1: f64 = 0.206;
+        const K2: f64 = 0.03;
+        const K3: f64 = (1.0 + K1) / (1.0 + K2);
+        0.5 * (K3 * x - K1 + ((K3 * x - K1) * (K3 * x - K1) + 4.0 * K2 * K3 * x).sqrt())
+    }
+
+    fn toe_inv(x: f64) -> f64 {
+        const K1: f64 = 0.206;
+        const K2: f64 = 0.03;
+        const K3: f64 = (1.0 + K1) / (1.0 + K2);
+        (x * x + K1 * x) / (K3 * (x + K2))
+    }
+
+    // Chroma at saturation 0.8 and the gamut edge, plus the low-chroma
+    // reference, that the saturation scale is interpolated between.
+    fn get_cs(l: f64, a: f64, b: f64) -> (f64, f64, f64) {
+        let cusp = find_cusp(a, b);
+        let c_max = find_gamut_intersection(a, b, l, 1.0, l, &cusp);
+        let (s_max, t_max) = (cusp.c / cusp.l, cusp.c / (1.0 - cusp.l));
+        let k = c_max / (l * s_max).min((1.0 - l) * t_max);
+
+        // Smooth approximation of the cusp's S and T values.
+        let s_mid = 0.11516993
+            + 1.0
+                / (7.44778970
+                    + 4.15901240 * b
+                    + a * (-2.19557347
+                        + 1.75198401 * b
+                        + a * (-2.13704948 - 10.02301043 * b
+                            + a * (-4.24894561 + 5.38770819 * b + 4.69891013 * a))));
+        let t_mid = 0.11239642
+            + 1.0
+                / (1.61320320 - 0.68124379 * b
+                    + a * (0.40370612
+                        + 0.90148123 * b
+                        + a * (-0.27087943
+                            + 0.61223990 * b
+                            + a * (0.00299215 - 0.45399568 * b - 0.14661872 * a))));
+
+        let (c_a, c_b) = (l * s_mid, (1.0 - l) * t_mid);
+        let c_mid = 0.9
+            * k
+            * (1.0 / (1.0 / c_a.powi(4) + 1.0 / c_b.powi(4)))
+                .sqrt()
+                .sqrt();
+        let (c_a, c_b) = (l * 0.4, (1.0 - l) * 0.8);
+        let c_0 = (1.0 / (1.0 / (c_a * c_a) + 1.0 / (c_b * c_b))).sqrt();
+        (c_0, c_mid, c_max)
+    }
+
+    const MID: f64 = 0.8;
+    const MID_INV: f64 = 1.25;
+
+    pub(super) fn okhsl_to_linear_srgb(h: f64, s: f64, l: f64) -> Color {
+        if l >= 1.0 {
+            return Color::gray(1.0);
+        }
+        if l <= 0.0 {
+            return Color::new();
+        }
+        let (a, b) = ((2.0 * PI * h).cos(), (2.0 * PI * h).sin());
+        let lightness = toe_inv(l);
+        let (c_0, c_mid, c_max) = get_cs(lightness, a, b);
+
+        let chroma = if s < MID {
+            let t = MID_INV * s;
+            let k_1 = MID * c_0;
+            let k_2 = 1.0 - k_1 / c_mid;
+            t * k_1 / (1.0 - k_2 * t)
+        } else {
+            let t = (s - MID) / (1.0 - MID);
+            let k_1 = (1.0 - MID) * c_mid * c_mid * MID_INV * MID_INV / c_0;
+            let k_2 = 1.0 - k_1 / (c_max - c_mid);
+            c_mid + t * k_1 / (1.0 - k_2 * t)
+        };
+
+        // Okhsl's gamut is gamma-encoded sRGB; return the linear equivalent.
+        let encoded = oklab_to_linear_srgb(&Lab {
+            l: lightness,
+            a: chroma * a,
+            b: chroma * b,
+        })
+        .to_srgb();
+        Color::from_srgb(encoded.r(), encoded.g(), encoded.b())
+    }
+
+    pub(super) fn linear_srgb_to_okhsl(c: &Color) -> (f64, f64, f64) {
+        let lab = linear_srgb_to_oklab(c);
+        let l = toe(lab.l);
+        let chroma = (lab.a * lab.a + lab.b * lab.b).sqrt();
+        if chroma < 1e-9 || lab.l <= 0.0 || lab.l >= 1.0 {
+            return (0.0, 0.0, l.clamp(0.0, 1.0));
+        }
+        let (a, b) = (lab.a / chroma, lab.b / chroma);
+        let h = 0.5 + 0.5 * f64::atan2(-lab.b, -lab.a) / PI;
+        let (c_0, c_mid, c_max) = get_cs(lab.l, a, b);
+
+        let s = if chroma < c_mid {
+            let k_1 = MID * c_0;
+            let k_2 = 1.0 - k_1 / c_mid;
+            let t = chroma / (k_1 + k_2 * chroma);
+            t * MID
+        } else {
+            let k_1 = (1.0 - MID) * c_mid * c_mid * MID_INV * MID_INV / c_0;
+            let k_2 = 1.0 - k_1 / (c_max - c_mid);
+            let t = (chroma - c_mid) / (k_1 + k_2 * (chroma - c_mid));
+            MID + (1.0 - MID) * t
+        };
+        (h, s, l)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn srgb_round_trip() {
+        let c = Color::from(0.2, 0.5, 0.8);
+        let encoded = c.to_srgb();
+        let back = Color::from_srgb(encoded.r(), encoded.g(), encoded.b());
+        for k in 0..3 {
+            assert_relative_eq!(back[k], c[k], epsilon = 1e-12);
+        }
+        assert_relative_eq!(Color::gray(1.0).luminance(), 1.0);
+    }
+
+    #[test]
+    fn tonemaps_roll_off_highlights() {
+        let gray = |tonemap: Tonemap, value| to_rgb8(Color::gray(value), 1, tonemap)[0];
+        assert_eq!(gray(Tonemap::Gamma, 0.25), 128);
+        assert_eq!(gray(Tonemap::Linear, 0.25), 64);
+        assert_eq!(gray(Tonemap::Srgb, 0.25), 137);
+        // Clipping curves lose the difference between bright lights.
+        assert_eq!(gray(Tonemap::Srgb, 4.0), gray(Tonemap::Srgb, 16.0));
+        for tonemap in [Tonemap::Reinhard, Tonemap::Aces] {
+            assert!(gray(tonemap, 0.5) < gray(tonemap, 2.0));
+            assert!(tonemap.apply(Color::gray(100.0)).g() <= 1.0);
+            assert_eq!(tonemap.to_string().parse(), Ok(tonemap));
+        }
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::from(1.0, 0.0, 0.0));
+        let (h, s, v) = Color::from(0.2, 0.4, 0.8).to_hsv();
+        let back = Color::from_hsv(h, s, v);
+        for k in 0..3 {
+            assert_relative_eq!(back[k], [0.2, 0.4, 0.8][k], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn okhsl_round_trip() {
+        for c in [
+            Color::from(0.8, 0.1, 0.1),
+            Color::from(0.1, 0.6, 0.2),
+            Color::from(0.05, 0.1, 0.9),
+            Color::from(0.5, 0.5, 0.2),
+        ] {
+            let (h, s, l) = c.to_okhsl();
+            assert!((0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&l));
+            let back = Color::from_okhsl(h, s, l);
+            for k in 0..3 {
+                assert_relative_eq!(back[k], c[k], epsilon = 1e-4);
+            }
+        }
+        // Grays have no saturation, and full saturation reaches the gamut edge.
+        assert!(Color::gray(0.5).to_okhsl().1 < 1e-5);
+        assert!(Color::from_okhsl(0.0, 1.0, 0.5).min_channel() < 1e-3);
+    }
+
+    #[test]
+    fn temperatures() {
+        let warm = Color::from_temperature(2700.0);
+        let daylight = Color::from_temperature(6600.0);
+        let cool = Color::from_temperature(10000.0);
+        assert!(warm.r() > warm.b());
+        assert_relative_eq!(daylight.r(), 1.0);
+        assert!(cool.b() > cool.r());
+    }
 }
@@ -0,0 +1,174 @@
+use crate::aabb::Aabb;
+use crate::cylinder::{roots, surface_hit, Axis, Nearest};
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray};
+use crate::utility::{Interval, PI};
+use crate::vec3::*;
+
+// A solid cone with a round base of `radius` around `base`, narrowing to a
+// point at `apex`, such as a funnel, a spike or a lampshade turned over. On
+// the slope, u runs around the axis as a fraction of a turn and v from the
+// base to the apex; on the base, u is the same angle and v the distance from
+// the axis as a fraction of the radius.
+#[derive(Clone)]
+pub struct Cone {
+    axis: Axis,
+    radius: f64,
+    slope: f64, // Radius lost per unit of height
+    material: Material,
+}
+
+#[derive(Clone, Copy)]
+enum Part {
+    Side,
+    Base,
+}
+
+impl Cone {
+    pub fn from(base: Point3, apex: Point3, radius: f64, material: Material) -> Self {
+        let axis = Axis::from(base, apex);
+        Self {
+            slope: radius / axis.height,
+            axis,
+            radius,
+            material,
+        }
+    }
+
+    // The nearest hit within `ray_t` and the part of the surface it is on.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, Part)> {
+        let (oz, op) = self.axis.split(r.origin() - self.axis.base);
+        let (dz, dp) = self.axis.split(r.direction());
+        let mut nearest = Nearest::within(ray_t);
+
+        // Points on the slope are slope * (height - z) from the axis; the
+        // roots include the mirrored cone above the apex, which `z` rules out.
+        let k2 = self.slope * self.slope;
+        let below_apex = self.axis.height - oz;
+        let a = dp.length_squared() - k2 * dz * dz;
+        let half_b = dot(&op, &dp) + k2 * below_apex * dz;
+        let c = op.length_squared() - k2 * below_apex * below_apex;
+        if let Some(roots) = roots(a, half_b, c) {
+            for t in roots {
+                if (0.0..=self.axis.height).contains(&(oz + t * dz)) {
+                    nearest.consider(t, Part::Side);
+                }
+            }
+        }
+        let t = -oz / dz;
+        if (op + t * dp).length_squared() <= self.radius * self.radius {
+            nearest.consider(t, Part::Base);
+        }
+        nearest.found
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, part) = self.intersect(r, &ray_t)?;
+        let (z, across) = self.axis.split(r.at(t) - self.axis.base);
+        let u = self.axis.angle(across);
+        let (radial, around) = (self.axis.radial(u), self.axis.around(u));
+        let w = self.axis.w;
+        let rec = match part {
+            Part::Side => {
+                let normal = unit_vector(radial + self.slope * w);
+                let width = self.radius * (1.0 - z / self.axis.height);
+                surface_hit(
+                    r,
+                    t,
+                    normal,
+                    (u, z / self.axis.height),
+                    (
+                        2.0 * PI * width * around,
+                        self.axis.height * w - self.radius * radial,
+                    ),
+                    (2.0 * PI * dot(&normal, &radial) * around, Vec3::new()),
+                    &self.material,
+                )
+            }
+            Part::Base => {
+                let distance = across.length();
+                surface_hit(
+                    r,
+                    t,
+                    -w,
+                    (u, distance / self.radius),
+                    (2.0 * PI * distance * around, self.radius * radial),
+                    (Vec3::new(), Vec3::new()),
+                    &self.material,
+                )
+            }
+        };
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let apex = self.axis.base + self.axis.height * self.axis.w;
+        Aabb::union(
+            &self.axis.rim_box(self.axis.base, self.radius),
+            &Aabb::from_points(apex, apex),
+        )
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        let (z, across) = self.axis.split(p - self.axis.base);
+        (0.0..=self.axis.height).contains(&z)
+            && across.length() <= self.slope * (self.axis.height - z)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("cone", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn hits_the_slope_and_base() {
+        // Radius 1 at y = 0, up to a point at y = 1: the slope is at 45°.
+        let cone = Cone::from(
+            Point3::new(),
+            Point3::from(0.0, 1.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        let across = Ray::from(Point3::from(0.0, 0.5, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = cone.hit(&across, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.5);
+        approx::assert_relative_eq!(rec.v, 0.5);
+        assert!(rec.front_face);
+        let tilted = Vec3::from(0.0, 1.0, 1.0) / 2.0f64.sqrt();
+        approx::assert_relative_eq!(dot(&rec.normal, &tilted), 1.0, epsilon = 1e-9);
+
+        let up = Ray::from(Point3::from(0.5, -1.0, 0.0), Vec3::from(0.0, 1.0, 0.0));
+        let rec = cone.hit(&up, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 1.0);
+        approx::assert_relative_eq!(rec.normal.y(), -1.0);
+        assert!(cone.contains(Point3::from(0.0, 0.9, 0.0)));
+        assert!(!cone.contains(Point3::from(0.5, 0.9, 0.0)));
+
+        // Above the apex, the mirrored cone of the equation is not hit.
+        let over = Ray::from(Point3::from(0.0, 1.5, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(cone.hit(&over, ray_t).is_none());
+        approx::assert_relative_eq!(cone.bounding_box().y.max, 1.0, epsilon = 1e-3);
+    }
+}
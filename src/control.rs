@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// Cooperative stop/pause handle for a render. Clones share the same state, so
+// an application can keep one and hand another to the render running on a
+// different thread. Renders check it between tiles or passes: a cancelled render
+// returns what it has so far, a paused one blocks until resumed.
+#[derive(Clone, Default)]
+pub struct RenderControl {
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl RenderControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+        // Wake a paused render so it can see the cancellation.
+        self.state.resumed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn pause(&self) {
+        *self.state.paused.lock().unwrap() = true;
+    }
+
+    pub fn resume(&self) {
+        *self.state.paused.lock().unwrap() = false;
+        self.state.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.paused.lock().unwrap()
+    }
+
+    // Called by renders between units of work: waits while paused, then
+    // returns whether to carry on.
+    pub fn checkpoint(&self) -> bool {
+        let mut paused = self.state.paused.lock().unwrap();
+        while *paused && !self.is_cancelled() {
+            paused = self.state.resumed.wait(paused).unwrap();
+        }
+        !self.is_cancelled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn pause_resume_cancel() {
+        let control = RenderControl::new();
+        assert!(control.checkpoint());
+
+        control.pause();
+        let waiting = control.clone();
+        let worker = thread::spawn(move || waiting.checkpoint());
+        thread::sleep(Duration::from_millis(20));
+        assert!(!worker.is_finished());
+        control.resume();
+        assert!(worker.join().unwrap());
+
+        control.pause();
+        let waiting = control.clone();
+        let worker = thread::spawn(move || waiting.checkpoint());
+        control.cancel();
+        assert!(!worker.join().unwrap());
+        assert!(control.is_cancelled());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::quad::Quad;
+use crate::ray::{Point3, Ray};
+use crate::sampler::Sampler;
+use crate::utility::Interval;
+use crate::vec3::Vec3;
+
+// An axis-aligned box between two opposite corners, made of six quads facing
+// out, such as the blocks of a Cornell box or the walls of a room. Rotate or
+// move it with a `Transform`. Each face has its own texture coordinates,
+// running from 0 to 1 over it.
+#[derive(Clone)]
+pub struct Cuboid {
+    sides: [Quad; 6],
+    bbox: Aabb,
+}
+
+impl Cuboid {
+    pub fn from(a: Point3, b: Point3, material: Material) -> Self {
+        let min = Point3::from(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::from(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::from(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::from(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::from(0.0, 0.0, max.z() - min.z());
+        let side = |q, u, v| Quad::from(q, u, v, material.clone());
+        Self {
+            sides: [
+                side(Point3::from(min.x(), min.y(), max.z()), dx, dy), // Front
+                side(Point3::from(max.x(), min.y(), max.z()), -dz, dy), // Right
+                side(Point3::from(max.x(), min.y(), min.z()), -dx, dy), // Back
+                side(Point3::from(min.x(), min.y(), min.z()), dz, dy), // Left
+                side(Point3::from(min.x(), max.y(), max.z()), dx, -dz), // Top
+                side(Point3::from(min.x(), min.y(), min.z()), dx, dz), // Bottom
+            ],
+            bbox: Aabb::from_points(min, max),
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        let mut max = ray_t.max;
+        for side in &self.sides {
+            if let Some(rec) = side.hit(r, Interval::from(ray_t.min, max)) {
+                max = rec.t;
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.sides.iter().any(|side| side.is_occluded(r, ray_t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.bbox.x.contains(p.x()) && self.bbox.y.contains(p.y()) && self.bbox.z.contains(p.z())
+    }
+
+    // Picks a side at random, then a point on it.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let sides = self.sides.iter();
+        sides
+            .map(|side| side.pdf_value(origin, direction))
+            .sum::<f64>()
+            / 6.0
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let index = ((sampler.get_1d() * 6.0) as usize).min(5);
+        self.sides[index].random(origin, sampler)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.sides
+            .iter_mut()
+            .flat_map(|side| side.materials_mut())
+            .collect()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("box", std::mem::size_of::<Self>());
+        info.add_material(self.sides[0].material());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+    use crate::vec3::dot;
+
+    #[test]
+    fn faces_point_out() {
+        let material = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let cuboid = Cuboid::from(Point3::from(1.0, 2.0, 3.0), Point3::new(), material);
+        let ray_t = Interval::from(0.001, INFINITY);
+        let center = Point3::from(0.5, 1.0, 1.5);
+
+        // From every side, the first hit is on the near face, facing the ray.
+        for direction in [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)] {
+            let direction = Vec3::from(direction.0, direction.1, direction.2);
+            for sign in [-1.0, 1.0] {
+                let origin = center + sign * 10.0 * direction;
+                let r = Ray::from(origin, -sign * direction);
+                let rec = cuboid.hit(&r, ray_t).unwrap();
+                assert!(rec.front_face);
+                approx::assert_relative_eq!(dot(&rec.normal, &(sign * direction)), 1.0);
+                approx::assert_relative_eq!(dot(&(rec.p - center), &rec.normal).abs(), {
+                    let half = Vec3::from(0.5, 1.0, 1.5);
+                    dot(&half, &direction)
+                });
+            }
+        }
+
+        // From inside, rays hit the back of a face.
+        let up = Ray::from(center, Vec3::from(0.0, 1.0, 0.0));
+        let rec = cuboid.hit(&up, ray_t).unwrap();
+        assert!(!rec.front_face);
+        approx::assert_relative_eq!(rec.t, 1.0);
+        assert!(cuboid.contains(center) && !cuboid.contains(Point3::from(0.5, 2.5, 1.5)));
+
+        let beside = Ray::from(Point3::from(2.0, 1.0, 10.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(cuboid.hit(&beside, ray_t).is_none());
+        approx::assert_relative_eq!(cuboid.bounding_box().z.max, 3.0);
+    }
+}
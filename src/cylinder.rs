@@ -0,0 +1,294 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::plane::tangents;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::{Interval, PI};
+use crate::vec3::*;
+
+// A solid cylinder of `radius` around the line from `base` to `top`, closed by
+// flat caps, such as a pipe, an axle or a column. On the side, u runs around
+// the axis as a fraction of a turn and v from the base to the top; on the
+// caps, u is the same angle and v the distance from the axis as a fraction of
+// the radius.
+#[derive(Clone)]
+pub struct Cylinder {
+    axis: Axis,
+    radius: f64,
+    material: Material,
+}
+
+#[derive(Clone, Copy)]
+enum Part {
+    Side,
+    Bottom,
+    Top,
+}
+
+impl Cylinder {
+    pub fn from(base: Point3, top: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            axis: Axis::from(base, top),
+            radius,
+            material,
+        }
+    }
+
+    // The nearest hit within `ray_t` and the part of the surface it is on.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, Part)> {
+        let (oz, op) = self.axis.split(r.origin() - self.axis.base);
+        let (dz, dp) = self.axis.split(r.direction());
+        let radius_squared = self.radius * self.radius;
+        let mut nearest = Nearest::within(ray_t);
+
+        let c = op.length_squared() - radius_squared;
+        if let Some(roots) = roots(dp.length_squared(), dot(&op, &dp), c) {
+            for t in roots {
+                if (0.0..=self.axis.height).contains(&(oz + t * dz)) {
+                    nearest.consider(t, Part::Side);
+                }
+            }
+        }
+        for (z, part) in [(0.0, Part::Bottom), (self.axis.height, Part::Top)] {
+            let t = (z - oz) / dz;
+            if (op + t * dp).length_squared() <= radius_squared {
+                nearest.consider(t, part);
+            }
+        }
+        nearest.found
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, part) = self.intersect(r, &ray_t)?;
+        let p = r.at(t);
+        let (z, across) = self.axis.split(p - self.axis.base);
+        let u = self.axis.angle(across);
+        let (radial, around) = (self.axis.radial(u), self.axis.around(u));
+        let w = self.axis.w;
+        let rec = match part {
+            Part::Side => surface_hit(
+                r,
+                t,
+                radial,
+                (u, z / self.axis.height),
+                (2.0 * PI * self.radius * around, self.axis.height * w),
+                (2.0 * PI * around, Vec3::new()),
+                &self.material,
+            ),
+            Part::Bottom | Part::Top => {
+                let distance = across.length();
+                let normal = if matches!(part, Part::Top) { w } else { -w };
+                surface_hit(
+                    r,
+                    t,
+                    normal,
+                    (u, distance / self.radius),
+                    (2.0 * PI * distance * around, self.radius * radial),
+                    (Vec3::new(), Vec3::new()),
+                    &self.material,
+                )
+            }
+        };
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let top = self.axis.base + self.axis.height * self.axis.w;
+        Aabb::union(
+            &self.axis.rim_box(self.axis.base, self.radius),
+            &self.axis.rim_box(top, self.radius),
+        )
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        let (z, across) = self.axis.split(p - self.axis.base);
+        (0.0..=self.axis.height).contains(&z) && across.length() <= self.radius
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("cylinder", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+// The axis of a shape turned around a line, such as a cylinder or cone,
+// pointing `w` from `base` for `height`, and the directions across it that
+// angles around it are measured from.
+#[derive(Clone)]
+pub(crate) struct Axis {
+    pub base: Point3,
+    pub w: Vec3,
+    pub height: f64,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Axis {
+    pub fn from(base: Point3, top: Point3) -> Self {
+        let height = (top - base).length();
+        let w = (top - base) / height;
+        let (tangent, bitangent) = tangents(w);
+        Self {
+            base,
+            w,
+            height,
+            tangent,
+            bitangent,
+        }
+    }
+
+    // A vector's length along the axis and its part across it.
+    pub fn split(&self, v: Vec3) -> (f64, Vec3) {
+        let along = dot(&v, &self.w);
+        (along, v - along * self.w)
+    }
+
+    // The angle of a direction across the axis, as a fraction of a turn.
+    pub fn angle(&self, across: Vec3) -> f64 {
+        let (x, y) = (dot(&across, &self.tangent), dot(&across, &self.bitangent));
+        y.atan2(x).rem_euclid(2.0 * PI) / (2.0 * PI)
+    }
+
+    // The unit direction away from the axis at angle `u`.
+    pub fn radial(&self, u: f64) -> Vec3 {
+        let phi = 2.0 * PI * u;
+        phi.cos() * self.tangent + phi.sin() * self.bitangent
+    }
+
+    // The unit direction of increasing angle at `u`.
+    pub fn around(&self, u: f64) -> Vec3 {
+        let phi = 2.0 * PI * u;
+        phi.cos() * self.bitangent - phi.sin() * self.tangent
+    }
+
+    // The box around a circle of `radius` across the axis at `center`.
+    pub fn rim_box(&self, center: Point3, radius: f64) -> Aabb {
+        let reach = |a: f64| radius * (1.0 - a * a).max(0.0).sqrt();
+        let extent = Vec3::from(reach(self.w.x()), reach(self.w.y()), reach(self.w.z()));
+        Aabb::from_points(center - extent, center + extent)
+    }
+}
+
+// Both roots of a t² + 2 half_b t + c, smaller first, or the one root when
+// a is 0. None without real roots.
+pub(crate) fn roots(a: f64, half_b: f64, c: f64) -> Option<[f64; 2]> {
+    if a.abs() < 1e-12 {
+        if half_b.abs() < 1e-12 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some([t, t]);
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+    Some([t0.min(t1), t0.max(t1)])
+}
+
+// The nearest of the hits on several parts of a surface.
+pub(crate) struct Nearest<'a, P> {
+    ray_t: &'a Interval,
+    pub found: Option<(f64, P)>,
+}
+
+impl<'a, P> Nearest<'a, P> {
+    pub fn within(ray_t: &'a Interval) -> Self {
+        Self { ray_t, found: None }
+    }
+
+    pub fn consider(&mut self, t: f64, part: P) {
+        let nearer = self.found.as_ref().is_none_or(|(best, _)| t < *best);
+        if self.ray_t.surrounds(t) && nearer {
+            self.found = Some((t, part));
+        }
+    }
+}
+
+// The record of a hit at `t` on a surface with the given outward normal,
+// texture coordinates and their derivatives.
+pub(crate) fn surface_hit<'a>(
+    r: &Ray,
+    t: f64,
+    outward_normal: Vec3,
+    (u, v): (f64, f64),
+    (dpdu, dpdv): (Vec3, Vec3),
+    (dndu, dndv): (Vec3, Vec3),
+    material: &'a Material,
+) -> HitRecord<'a> {
+    let mut rec = HitRecord {
+        p: r.at(t),
+        normal: outward_normal,
+        geometric_normal: outward_normal,
+        t,
+        u,
+        v,
+        dpdu,
+        dpdv,
+        dndu,
+        dndv,
+        front_face: false,
+        mat: material,
+        trace_set: ALL_SETS,
+    };
+    rec.set_face_normal(r, &outward_normal);
+    rec
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn hits_the_side_and_caps() {
+        // Radius 1 around the y axis, from y = 0 to y = 2.
+        let cylinder = Cylinder::from(
+            Point3::new(),
+            Point3::from(0.0, 2.0, 0.0),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        let across = Ray::from(Point3::from(0.0, 1.5, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = cylinder.hit(&across, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.0);
+        approx::assert_relative_eq!(rec.v, 0.75);
+        assert!(rec.front_face);
+        approx::assert_relative_eq!(rec.normal.z(), 1.0);
+
+        let down = Ray::from(Point3::from(0.5, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        let rec = cylinder.hit(&down, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 3.0);
+        approx::assert_relative_eq!(rec.normal.y(), 1.0);
+        approx::assert_relative_eq!(rec.v, 0.5);
+
+        // From inside, the bottom is seen from behind.
+        let inside = Ray::from(Point3::from(0.0, 1.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        let rec = cylinder.hit(&inside, ray_t).unwrap();
+        assert!(!rec.front_face);
+        approx::assert_relative_eq!(rec.t, 1.0);
+        assert!(cylinder.contains(Point3::from(0.5, 1.0, 0.5)));
+        assert!(!cylinder.contains(Point3::from(0.0, 2.5, 0.0)));
+
+        let over = Ray::from(Point3::from(0.0, 2.5, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(cylinder.hit(&over, ray_t).is_none());
+        approx::assert_relative_eq!(cylinder.bounding_box().x.min, -1.0, epsilon = 1e-3);
+    }
+}
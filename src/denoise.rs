@@ -0,0 +1,325 @@
+use crate::aov::{Aovs, Surface};
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::vec3::*;
+use std::fmt;
+use std::str::FromStr;
+
+// Exponent on the cosine between neighbouring normals; higher keeps creases sharper.
+const NORMAL_POWER: i32 = 32;
+// Depth difference, relative to the depth, at which neighbours stop mixing.
+const DEPTH_SIGMA: f64 = 0.05;
+// Albedo difference at which neighbours stop mixing.
+const ALBEDO_SIGMA: f64 = 0.1;
+// Smallest albedo divided out before filtering.
+const MIN_ALBEDO: f64 = 0.01;
+// Weights of the 5-tap B3-spline kernel the filter is built from.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Which denoiser `--denoise` runs over the finished image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DenoiserKind {
+    // The built-in edge-avoiding filter below.
+    #[default]
+    Filter,
+    // Intel Open Image Denoise's trained ray tracing filter, which needs the
+    // `oidn` feature and the OIDN library installed.
+    Oidn,
+}
+
+impl DenoiserKind {
+    pub fn denoise(self, buffer: &FrameBuffer, aovs: &Aovs) -> Result<FrameBuffer, String> {
+        match self {
+            DenoiserKind::Filter => Ok(Denoiser::default().denoise(buffer, aovs)),
+            DenoiserKind::Oidn => denoise_oidn(buffer, aovs),
+        }
+    }
+}
+
+impl FromStr for DenoiserKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "filter" => Ok(DenoiserKind::Filter),
+            "oidn" => Ok(DenoiserKind::Oidn),
+            _ => Err(format!("unknown denoiser '{}', expected filter or oidn", s)),
+        }
+    }
+}
+
+impl fmt::Display for DenoiserKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DenoiserKind::Filter => "filter",
+            DenoiserKind::Oidn => "oidn",
+        })
+    }
+}
+
+// An edge-avoiding a-trous wavelet filter (Dammertz et al. 2010) guided by
+// the noise-free AOVs. Pixels only mix with neighbours on the same surface,
+// judged by normal, depth and albedo, and of similar brightness. Texture is
+// divided out first and multiplied back in afterwards, so only the lighting
+// is smoothed.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    // Filter passes; each one doubles the spacing of the taps, so n passes
+    // cover 4 * (2^n - 1) + 1 pixels across.
+    pub iterations: u32,
+    // Luminance difference between neighbours, relative to their brightness,
+    // at which they stop mixing. Halved after every pass.
+    pub color_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 4,
+            color_sigma: 1.0,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn denoise(&self, buffer: &FrameBuffer, aovs: &Aovs) -> FrameBuffer {
+        crate::stage_span!("denoise");
+        let (width, height) = (buffer.width(), buffer.height());
+        assert_eq!((width, height), (aovs.width(), aovs.height()));
+
+        // Lighting, with the albedo of directly visible surfaces divided out.
+        let usable = |i, j| !buffer.is_invalid(i, j) && buffer.sample_count(i, j) > 0;
+        let mut signal = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let mean = buffer.pixel_sum(i, j) / buffer.sample_count(i, j).max(1) as f64;
+                signal.push(match aovs.get(i, j) {
+                    Some(surface) => demodulate(mean, surface.albedo),
+                    None => mean,
+                });
+            }
+        }
+
+        let mut sigma = self.color_sigma;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let mut filtered = signal.clone();
+            for j in 0..height {
+                for i in 0..width {
+                    if !usable(i, j) {
+                        continue;
+                    }
+                    let center = signal[(j * width + i) as usize];
+                    let surface = aovs.get(i, j);
+                    let mut sum = Color::new();
+                    let mut total = 0.0;
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let qi = i + (dx as i32 - 2) * step;
+                            let qj = j + (dy as i32 - 2) * step;
+                            if qi < 0 || qj < 0 || qi >= width || qj >= height || !usable(qi, qj) {
+                                continue;
+                            }
+                            let other = signal[(qj * width + qi) as usize];
+                            let weight = kx
+                                * ky
+                                * guide_weight(surface, aovs.get(qi, qj))
+                                * color_weight(center, other, sigma);
+                            sum += weight * other;
+                            total += weight;
+                        }
+                    }
+                    // The center tap always has weight, so total > 0.
+                    filtered[(j * width + i) as usize] = sum / total;
+                }
+            }
+            signal = filtered;
+            sigma *= 0.5;
+        }
+
+        rebuild(buffer, |i, j| {
+            let lighting = signal[(j * width + i) as usize];
+            match aovs.get(i, j) {
+                Some(surface) => modulate(lighting, surface.albedo),
+                None => lighting,
+            }
+        })
+    }
+}
+
+// Runs Open Image Denoise over the pixel means in HDR mode, with the albedo
+// and normals of the first hits as its auxiliary images. Its network was
+// trained on path-traced renders, so it cleans up far lower sample counts
+// than the filter, at the cost of an external library.
+#[cfg(feature = "oidn")]
+pub fn denoise_oidn(buffer: &FrameBuffer, aovs: &Aovs) -> Result<FrameBuffer, String> {
+    crate::stage_span!("denoise");
+    let (width, height) = (buffer.width(), buffer.height());
+    assert_eq!((width, height), (aovs.width(), aovs.height()));
+
+    let size = (width * height * 3) as usize;
+    let (mut color, mut albedo, mut normal) = (
+        Vec::with_capacity(size),
+        Vec::with_capacity(size),
+        Vec::with_capacity(size),
+    );
+    for j in 0..height {
+        for i in 0..width {
+            let count = buffer.sample_count(i, j);
+            let mean = if buffer.is_invalid(i, j) || count == 0 {
+                Color::new()
+            } else {
+                buffer.pixel_sum(i, j) / count as f64
+            };
+            // OIDN wants the background's own color as its albedo and a zero
+            // normal there.
+            let (a, n) = match aovs.get(i, j) {
+                Some(surface) => (surface.albedo, surface.normal),
+                None => (mean.map(|c| c.clamp(0.0, 1.0)), Vec3::new()),
+            };
+            color.extend([mean.r(), mean.g(), mean.b()].map(|c| c as f32));
+            albedo.extend([a.r(), a.g(), a.b()].map(|c| c as f32));
+            normal.extend([n.x(), n.y(), n.z()].map(|c| c as f32));
+        }
+    }
+
+    let device = oidn::Device::new().map_err(|err| err.to_string())?;
+    let mut output = vec![0.0f32; size];
+    oidn::RayTracing::try_new(&device)
+        .map_err(|err| err.to_string())?
+        .hdr(true)
+        .albedo_normal(&albedo, &normal)
+        .image_dimensions(width as usize, height as usize)
+        .filter(&color, &mut output)
+        .map_err(|err| err.to_string())?;
+    device.get_error().map_err(|err| err.to_string())?;
+
+    Ok(rebuild(buffer, |i, j| {
+        let k = ((j * width + i) * 3) as usize;
+        Color::from(output[k] as f64, output[k + 1] as f64, output[k + 2] as f64)
+    }))
+}
+
+#[cfg(not(feature = "oidn"))]
+pub fn denoise_oidn(_buffer: &FrameBuffer, _aovs: &Aovs) -> Result<FrameBuffer, String> {
+    Err("rebuild with `--features oidn` to denoise with Open Image Denoise".to_string())
+}
+
+// A buffer shaped like `buffer`, with the same sample counts and invalid
+// pixels, whose valid pixels average to `color`.
+fn rebuild(buffer: &FrameBuffer, color: impl Fn(i32, i32) -> Color) -> FrameBuffer {
+    let mut output = FrameBuffer::new(buffer.width(), buffer.height());
+    output.set_tonemap(buffer.tonemap());
+    for j in 0..buffer.height() {
+        for i in 0..buffer.width() {
+            if buffer.is_invalid(i, j) {
+                output.mark_invalid(i, j);
+                continue;
+            }
+            let count = buffer.sample_count(i, j);
+            output.add_samples(i, j, color(i, j) * count as f64, count);
+        }
+    }
+    for _ in 0..buffer.samples() {
+        output.complete_pass();
+    }
+    output
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    Color::from(
+        color.r() / albedo.r().max(MIN_ALBEDO),
+        color.g() / albedo.g().max(MIN_ALBEDO),
+        color.b() / albedo.b().max(MIN_ALBEDO),
+    )
+}
+
+fn modulate(lighting: Color, albedo: Color) -> Color {
+    lighting
+        * Color::from(
+            albedo.r().max(MIN_ALBEDO),
+            albedo.g().max(MIN_ALBEDO),
+            albedo.b().max(MIN_ALBEDO),
+        )
+}
+
+// How likely two pixels are to show the same surface, from 0 to 1.
+// Background pixels only mix with other background pixels.
+fn guide_weight(a: Option<&Surface>, b: Option<&Surface>) -> f64 {
+    match (a, b) {
+        (None, None) => 1.0,
+        (Some(a), Some(b)) => {
+            let normal = dot(&a.normal, &b.normal).max(0.0).powi(NORMAL_POWER);
+            let depth = (-(a.depth - b.depth).abs() / (DEPTH_SIGMA * a.depth)).exp();
+            let d = a.albedo - b.albedo;
+            let albedo = (-(d.max_channel().max(-d.min_channel()) / ALBEDO_SIGMA).powi(2)).exp();
+            normal * depth * albedo
+        }
+        _ => 0.0,
+    }
+}
+
+fn color_weight(a: Color, b: Color, sigma: f64) -> f64 {
+    let (la, lb) = (a.luminance(), b.luminance());
+    let scale = sigma * (0.5 * (la + lb) + MIN_ALBEDO);
+    (-(la - lb).abs() / scale).exp()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::{random, seed};
+
+    #[test]
+    fn smooths_noise_but_keeps_edges() {
+        // A wall whose left half is dark and right half bright, rendered with
+        // noise of the same relative size all over.
+        let (width, height) = (32, 16);
+        let albedo = |i: i32| Color::gray(if i < width / 2 { 0.2 } else { 0.8 });
+        let surfaces = (0..height)
+            .flat_map(|_| 0..width)
+            .map(|i| {
+                Some(Surface {
+                    position: Vec3::from(i as f64, 0.0, 0.0),
+                    normal: Vec3::from(0.0, 0.0, 1.0),
+                    albedo: albedo(i),
+                    depth: 1.0,
+                    object: Some(0),
+                })
+            })
+            .collect();
+        let aovs = Aovs::from(width, height, surfaces);
+
+        seed(1);
+        let mut buffer = FrameBuffer::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                buffer.add_samples(i, j, albedo(i) * (0.5 + random()), 1);
+            }
+        }
+        let denoised = Denoiser::default().denoise(&buffer, &aovs);
+
+        // Per half: mean stays put, spread shrinks.
+        let stats = |image: &FrameBuffer, range: std::ops::Range<i32>| {
+            let values: Vec<f64> = (0..height)
+                .flat_map(|j| range.clone().map(move |i| (i, j)))
+                .map(|(i, j)| image.pixel_sum(i, j).g() / image.sample_count(i, j) as f64)
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            (mean, variance)
+        };
+        for (half, expected) in [(0..width / 2, 0.2), (width / 2..width, 0.8)] {
+            let (noisy_mean, noisy_variance) = stats(&buffer, half.clone());
+            let (mean, variance) = stats(&denoised, half);
+            approx::assert_relative_eq!(mean, noisy_mean, max_relative = 0.02);
+            approx::assert_relative_eq!(mean, expected, max_relative = 0.1);
+            assert!(
+                variance < noisy_variance / 10.0,
+                "{} vs {}",
+                variance,
+                noisy_variance
+            );
+        }
+    }
+}
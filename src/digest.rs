@@ -0,0 +1,138 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::framebuffer::FrameBuffer;
+use crate::scene::Scene;
+
+// Golden-image checks for tests: render a scene small and deterministically,
+// then compare the hash, or the per-channel error when some noise is
+// expected, against a reference accumulation buffer saved earlier with
+// `FrameBuffer::save`.
+//
+//     let digest = render_digest(&camera, &scene, 1);
+//     let reference = FrameBuffer::load(Path::new("tests/golden/spheres.accum"))?;
+//     assert!(compare(&digest.buffer, &reference)?.iter().all(|c| c.rmse < 0.02));
+pub struct RenderDigest {
+    // FNV-1a hash of the 8-bit image, stable as long as no pixel changes.
+    pub hash: u64,
+    pub buffer: FrameBuffer,
+}
+
+// Error of one color channel over the whole image, on the linear averaged
+// pixel values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelError {
+    pub mean_abs: f64,
+    pub rmse: f64,
+    pub max: f64,
+}
+
+// Renders `samples_per_pixel` passes of the camera's image with its samples
+// drawn from `random_seed`, whatever seed the camera had, so the result only
+// depends on the scene, the camera and the seed.
+pub fn render_digest(camera: &Camera, scene: &Scene, random_seed: u64) -> RenderDigest {
+    let camera = CameraBuilder {
+        seed: Some(random_seed),
+        ..camera.to_builder()
+    }
+    .build()
+    .expect("a built camera's settings build again");
+    let mut buffer = FrameBuffer::new(camera.image_width(), camera.image_height());
+    for _ in 0..camera.samples_per_pixel() {
+        camera.render_pass(scene, &mut buffer);
+    }
+    RenderDigest {
+        hash: image_hash(&buffer),
+        buffer,
+    }
+}
+
+pub fn image_hash(buffer: &FrameBuffer) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = OFFSET;
+    let size = [buffer.width() as u32, buffer.height() as u32];
+    let pixels = buffer.to_rgb_u32();
+    let bytes = size
+        .iter()
+        .chain(pixels.iter())
+        .flat_map(|x| x.to_le_bytes());
+    for byte in bytes {
+        hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+    }
+    hash
+}
+
+pub fn compare(image: &FrameBuffer, reference: &FrameBuffer) -> Result<[ChannelError; 3], String> {
+    if image.width() != reference.width() || image.height() != reference.height() {
+        return Err(format!(
+            "image is {}x{} but the reference is {}x{}",
+            image.width(),
+            image.height(),
+            reference.width(),
+            reference.height()
+        ));
+    }
+
+    let mean = |buffer: &FrameBuffer, i, j| {
+        buffer.pixel_sum(i, j) / buffer.sample_count(i, j).max(1) as f64
+    };
+    let mut errors = [ChannelError::default(); 3];
+    let mut squared = [0.0; 3];
+    for j in 0..image.height() {
+        for i in 0..image.width() {
+            let difference = mean(image, i, j) - mean(reference, i, j);
+            for k in 0..3 {
+                let e = difference[k].abs();
+                errors[k].mean_abs += e;
+                errors[k].max = errors[k].max.max(e);
+                squared[k] += e * e;
+            }
+        }
+    }
+
+    let pixels = (image.width() * image.height()).max(1) as f64;
+    for k in 0..3 {
+        errors[k].mean_abs /= pixels;
+        errors[k].rmse = (squared[k] / pixels).sqrt();
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::ray::Point3;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn digests_are_reproducible() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            0.5,
+            Material::Lambertian(Lambertian::from(Color::from(0.7, 0.3, 0.3))),
+        )));
+        let scene = Scene::from(world);
+        let camera = CameraBuilder {
+            image_width: Some(16),
+            samples_per_pixel: Some(2),
+            look_from: Some(Point3::from(0.0, 0.0, 2.0)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        let first = render_digest(&camera, &scene, 42);
+        let second = render_digest(&camera, &scene, 42);
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(compare(&first.buffer, &second.buffer).unwrap()[0].max, 0.0);
+
+        let other = render_digest(&camera, &scene, 43);
+        assert_ne!(first.hash, other.hash);
+        let errors = compare(&first.buffer, &other.buffer).unwrap();
+        assert!(errors.iter().all(|e| e.rmse > 0.0 && e.rmse <= e.max));
+        assert!(compare(&first.buffer, &FrameBuffer::new(1, 1)).is_err());
+    }
+}
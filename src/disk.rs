@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::plane::{intersect, tangents};
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::sampler::Sampler;
+use crate::utility::{Interval, INFINITY, PI};
+use crate::vec3::*;
+
+// A flat round disk of `radius` around `center`, facing `normal`, such as a
+// round area light or a tabletop. Its texture coordinates are polar: u the
+// angle around the center as a fraction of a turn, v the distance from it as
+// a fraction of the radius.
+#[derive(Clone)]
+pub struct Disk {
+    center: Point3,
+    normal: Vec3,
+    radius: f64,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Material,
+}
+
+impl Disk {
+    pub fn from(center: Point3, normal: Vec3, radius: f64, material: Material) -> Self {
+        let normal = unit_vector(normal);
+        let (tangent, bitangent) = tangents(normal);
+        Self {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn point_at(&self, u: f64, v: f64) -> Point3 {
+        let phi = 2.0 * PI * u;
+        let r = v * self.radius;
+        self.center + r * (phi.cos() * self.tangent + phi.sin() * self.bitangent)
+    }
+
+    // The ray parameter and point where the ray meets the disk.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, Point3)> {
+        let (t, p) = intersect(self.center, self.normal, r, ray_t)?;
+        if (p - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+        Some((t, p))
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, p) = self.intersect(r, &ray_t)?;
+        let offset = p - self.center;
+        let (x, y) = (dot(&offset, &self.tangent), dot(&offset, &self.bitangent));
+        let phi = y.atan2(x).rem_euclid(2.0 * PI);
+        let radial = self.tangent * phi.cos() + self.bitangent * phi.sin();
+        let around = self.bitangent * phi.cos() - self.tangent * phi.sin();
+        let distance = offset.length();
+        let mut rec = HitRecord {
+            p,
+            normal: self.normal,
+            geometric_normal: self.normal,
+            t,
+            u: phi / (2.0 * PI),
+            v: distance / self.radius,
+            dpdu: 2.0 * PI * distance * around,
+            dpdv: self.radius * radial,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: &self.material,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &self.normal);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    // Along each axis, the rim reaches radius * sin of the angle between the
+    // normal and the axis from the center.
+    fn bounding_box(&self) -> Aabb {
+        let n = self.normal;
+        let reach = |a: f64| self.radius * (1.0 - a * a).max(0.0).sqrt();
+        let extent = Vec3::from(reach(n.x()), reach(n.y()), reach(n.z()));
+        Aabb::from_points(self.center - extent, self.center + extent)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        Some((self.point_at(u, v), self.normal))
+    }
+
+    // A uniformly chosen point of the disk, seen from `origin`, covers a solid
+    // angle of area * cos / distance² per unit area.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::from(origin, direction);
+        let Some((t, _)) = self.intersect(&r, &Interval::from(0.001, INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = dot(&direction, &self.normal).abs() / direction.length();
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.get_2d();
+        // The square root spreads points evenly over the area.
+        self.point_at(a, b.sqrt()) - origin
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("disk", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn hits_within_the_radius() {
+        // A disk of radius 2 around (0, 0, -1), facing +z.
+        let disk = Disk::from(
+            Point3::from(0.0, 0.0, -1.0),
+            Vec3::from(0.0, 0.0, 1.0),
+            2.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+        let down = |x, y| Ray::from(Point3::from(x, y, 3.0), Vec3::from(0.0, 0.0, -1.0));
+
+        let rec = disk.hit(&down(0.0, 1.0), ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.0);
+        approx::assert_relative_eq!(rec.u, 0.25);
+        approx::assert_relative_eq!(rec.v, 0.5);
+        assert!(rec.front_face && rec.normal.z() > 0.0);
+        let (p, _) = disk.surface_at(rec.u, rec.v).unwrap();
+        approx::assert_relative_eq!((p - rec.p).length(), 0.0, epsilon = 1e-9);
+        approx::assert_relative_eq!(rec.dpdu.x(), -2.0 * PI, epsilon = 1e-9);
+        approx::assert_relative_eq!(rec.dpdv.y(), 2.0, epsilon = 1e-9);
+
+        assert!(disk.hit(&down(1.5, 1.5), ray_t).is_none());
+        let bbox = disk.bounding_box();
+        approx::assert_relative_eq!(bbox.x.max, 2.0, epsilon = 1e-3);
+        approx::assert_relative_eq!(bbox.z.max, -1.0, epsilon = 1e-3);
+    }
+}
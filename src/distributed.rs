@@ -0,0 +1,538 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::script;
+use crate::utility;
+
+use log::{info, warn};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+// Coordinator/worker rendering over plain TCP. The coordinator splits the
+// frame into work items (a strip of scanlines times a range of samples) and
+// hands them out to workers on request; workers render against the scene
+// script sent by the coordinator and return raw sample sums for merging.
+//
+//   coordinator -> worker   SCENE <seed> <len>\n<script>CAMERA <settings>\n
+//   worker -> coordinator   NEXT\n
+//   coordinator -> worker   WORK <y0> <y1> <first> <samples>\n  or  DONE\n
+//   worker -> coordinator   RESULT <y0> <y1> <samples>\n<f64 LE sums, RGB, row-major>
+//
+// Every worker evaluates its own copy of the script, seeding the random number
+// generator with the coordinator's seed first, so scripts placing objects at
+// random build the same scene everywhere. The camera settings are those of the
+// coordinator's camera, the script's own included.
+const ROWS_PER_ITEM: i32 = 16;
+const SAMPLES_PER_ITEM: i32 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct WorkItem {
+    y0: i32,
+    y1: i32,
+    first: i32, // Index of the first sample, so items of a strip don't repeat samples
+    samples: i32,
+}
+
+// One of the messages above, as sent and received.
+#[derive(Debug, PartialEq)]
+enum Message {
+    Scene {
+        seed: u64,
+        script: String,
+    },
+    Camera(String),
+    Next,
+    Work(WorkItem),
+    Result {
+        y0: i32,
+        y1: i32,
+        samples: i32,
+        sums: Vec<f64>,
+    },
+    Done,
+}
+
+impl Message {
+    fn name(&self) -> &'static str {
+        match self {
+            Message::Scene { .. } => "SCENE",
+            Message::Camera(_) => "CAMERA",
+            Message::Next => "NEXT",
+            Message::Work(_) => "WORK",
+            Message::Result { .. } => "RESULT",
+            Message::Done => "DONE",
+        }
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Message::Scene { seed, script } => {
+                write!(out, "SCENE {} {}\n{}", seed, script.len(), script)
+            }
+            Message::Camera(settings) => writeln!(out, "CAMERA {}", settings),
+            Message::Next => writeln!(out, "NEXT"),
+            Message::Work(item) => writeln!(
+                out,
+                "WORK {} {} {} {}",
+                item.y0, item.y1, item.first, item.samples
+            ),
+            Message::Result {
+                y0,
+                y1,
+                samples,
+                sums,
+            } => {
+                writeln!(out, "RESULT {} {} {}", y0, y1, samples)?;
+                let data: Vec<u8> = sums.iter().flat_map(|sum| sum.to_le_bytes()).collect();
+                out.write_all(&data)
+            }
+            Message::Done => writeln!(out, "DONE"),
+        }
+    }
+
+    // Reads the next message. The sums of a RESULT aren't counted in its
+    // header: they cover its rows of an image `width` by `height` pixels, and
+    // rows outside it are refused before anything is allocated for them.
+    fn read(input: &mut impl BufRead, width: i32, height: i32) -> io::Result<Message> {
+        let line = read_line(input)?;
+        let (kind, rest) = line.split_once(' ').unwrap_or((&line, ""));
+        let numbers: Option<Vec<i32>> = rest.split(' ').map(|n| n.parse().ok()).collect();
+        let unexpected = || invalid(&format!("unexpected message '{}'", line));
+        match (kind, numbers.as_deref()) {
+            ("SCENE", _) => {
+                let (seed, length): (u64, usize) = rest
+                    .split_once(' ')
+                    .and_then(|(seed, length)| Some((seed.parse().ok()?, length.parse().ok()?)))
+                    .ok_or_else(unexpected)?;
+                let mut script = vec![0; length];
+                input.read_exact(&mut script)?;
+                let script =
+                    String::from_utf8(script).map_err(|_| invalid("scene is not UTF-8"))?;
+                Ok(Message::Scene { seed, script })
+            }
+            ("CAMERA", _) => Ok(Message::Camera(rest.to_string())),
+            ("NEXT", _) if rest.is_empty() => Ok(Message::Next),
+            ("DONE", _) if rest.is_empty() => Ok(Message::Done),
+            ("WORK", Some(&[y0, y1, first, samples])) => Ok(Message::Work(WorkItem {
+                y0,
+                y1,
+                first,
+                samples,
+            })),
+            ("RESULT", Some(&[y0, y1, samples])) => {
+                if !(0 <= y0 && y0 <= y1 && y1 <= height && samples > 0) {
+                    return Err(invalid(&format!(
+                        "result of rows {}..{} with {} samples doesn't fit the image",
+                        y0, y1, samples
+                    )));
+                }
+                let length = ((y1 - y0) as usize)
+                    .checked_mul(width.max(0) as usize)
+                    .and_then(|pixels| pixels.checked_mul(3 * 8))
+                    .ok_or_else(|| invalid("result is too large"))?;
+                let mut data = vec![0; length];
+                input.read_exact(&mut data)?;
+                let sums = data
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                    .collect();
+                Ok(Message::Result {
+                    y0,
+                    y1,
+                    samples,
+                    sums,
+                })
+            }
+            _ => Err(unexpected()),
+        }
+    }
+}
+
+struct State {
+    pending: Vec<WorkItem>,
+    remaining: usize,
+    buffer: FrameBuffer,
+}
+
+struct Job {
+    scene: String,
+    seed: u64,
+    settings: String,
+    width: i32,
+    height: i32,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+// Renders the scene script through `camera` on the workers connecting to
+// `address`. They evaluate the script after seeding with `seed`.
+pub fn coordinate(
+    address: &str,
+    scene: String,
+    seed: u64,
+    camera: &Camera,
+) -> io::Result<FrameBuffer> {
+    coordinate_on(TcpListener::bind(address)?, scene, seed, camera)
+}
+
+// Coordinates the render with the workers connecting to `listener`.
+fn coordinate_on(
+    listener: TcpListener,
+    scene: String,
+    seed: u64,
+    camera: &Camera,
+) -> io::Result<FrameBuffer> {
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let samples = camera.samples_per_pixel();
+
+    let mut pending = Vec::new();
+    for y0 in (0..height).step_by(ROWS_PER_ITEM as usize) {
+        let mut remaining_samples = samples;
+        while remaining_samples > 0 {
+            let chunk = remaining_samples.min(SAMPLES_PER_ITEM);
+            pending.push(WorkItem {
+                y0,
+                y1: (y0 + ROWS_PER_ITEM).min(height),
+                first: samples - remaining_samples,
+                samples: chunk,
+            });
+            remaining_samples -= chunk;
+        }
+    }
+    pending.reverse();
+
+    let settings = camera
+        .to_builder()
+        .settings()
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    let job = Arc::new(Job {
+        scene,
+        seed,
+        settings,
+        width,
+        height,
+        state: Mutex::new(State {
+            remaining: pending.len(),
+            pending,
+            buffer: FrameBuffer::new(width, height),
+        }),
+        changed: Condvar::new(),
+    });
+
+    info!(
+        "Coordinating {} work items on {}",
+        job.state.lock().unwrap().remaining,
+        listener.local_addr()?
+    );
+    let accept_job = job.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let job = accept_job.clone();
+            thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                info!("Worker {} connected", peer);
+                if let Err(err) = serve_worker(&job, stream) {
+                    warn!("Worker {} dropped: {}", peer, err);
+                }
+            });
+        }
+    });
+
+    let mut state = job.state.lock().unwrap();
+    while state.remaining > 0 {
+        state = job.changed.wait(state).unwrap();
+    }
+    Ok(std::mem::replace(&mut state.buffer, FrameBuffer::new(0, 0)))
+}
+
+fn serve_worker(job: &Job, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    Message::Scene {
+        seed: job.seed,
+        script: job.scene.clone(),
+    }
+    .write(&mut writer)?;
+    Message::Camera(job.settings.clone()).write(&mut writer)?;
+
+    loop {
+        let message = Message::read(&mut reader, job.width, job.height)?;
+        if message != Message::Next {
+            return Err(invalid(&format!("unexpected {} message", message.name())));
+        }
+
+        let Some(item) = next_item(job) else {
+            return Message::Done.write(&mut writer);
+        };
+        let result = Message::Work(item)
+            .write(&mut writer)
+            .and_then(|_| receive_result(job, &mut reader, item));
+        if let Err(err) = result {
+            // Hand the item to someone else.
+            job.state.lock().unwrap().pending.push(item);
+            job.changed.notify_all();
+            return Err(err);
+        }
+    }
+}
+
+// Blocks until there's an item to hand out, or returns None once every item
+// has been completed.
+fn next_item(job: &Job) -> Option<WorkItem> {
+    let mut state = job.state.lock().unwrap();
+    loop {
+        if let Some(item) = state.pending.pop() {
+            return Some(item);
+        }
+        if state.remaining == 0 {
+            return None;
+        }
+        state = job.changed.wait(state).unwrap();
+    }
+}
+
+fn receive_result(job: &Job, reader: &mut impl BufRead, item: WorkItem) -> io::Result<()> {
+    let message = Message::read(reader, job.width, job.height)?;
+    let Message::Result {
+        y0,
+        y1,
+        samples,
+        sums,
+    } = message
+    else {
+        return Err(invalid(&format!("unexpected {} message", message.name())));
+    };
+    if (y0, y1, samples) != (item.y0, item.y1, item.samples) {
+        return Err(invalid(&format!(
+            "expected the result of rows {}..{} with {} samples, got rows {}..{} with {}",
+            item.y0, item.y1, item.samples, y0, y1, samples
+        )));
+    }
+
+    let mut state = job.state.lock().unwrap();
+    for (index, rgb) in sums.chunks_exact(3).enumerate() {
+        let i = index as i32 % job.width;
+        let j = item.y0 + index as i32 / job.width;
+        state.buffer.add_samples(
+            i,
+            j,
+            Color::from(rgb[0], rgb[1], rgb[2]),
+            item.samples as u32,
+        );
+    }
+    state.remaining -= 1;
+    info!("{} work items remaining", state.remaining);
+    job.changed.notify_all();
+    Ok(())
+}
+
+// Runs `threads` worker connections against the coordinator at `address`
+// until it reports that all work is done.
+pub fn work(address: &str, threads: usize) -> io::Result<()> {
+    let handles: Vec<_> = (0..threads.max(1))
+        .map(|_| {
+            let address = address.to_string();
+            thread::spawn(move || run_worker(&address))
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().expect("worker thread panicked")?;
+    }
+    Ok(())
+}
+
+fn run_worker(address: &str) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let Message::Scene { seed, script } = Message::read(&mut reader, 0, 0)? else {
+        return Err(invalid("expected the scene"));
+    };
+    utility::seed(seed);
+    let scene = script::eval_frame(&script, 0, 0.0)
+        .map_err(|err| invalid(&err.to_string()))?
+        .into_scene();
+
+    let Message::Camera(settings) = Message::read(&mut reader, 0, 0)? else {
+        return Err(invalid("expected camera settings"));
+    };
+    let mut builder = CameraBuilder::new();
+    for pair in settings.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        builder.set(key, value).map_err(|err| invalid(&err))?;
+    }
+    let camera = builder.build().map_err(|err| invalid(&err))?;
+    let width = camera.image_width();
+    let height = camera.image_height();
+
+    loop {
+        Message::Next.write(&mut writer)?;
+        let item = match Message::read(&mut reader, width, height) {
+            Ok(Message::Work(item)) => item,
+            Ok(Message::Done) => return Ok(()),
+            // The coordinator exits as soon as the last result is in.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(message) => return Err(invalid(&format!("unexpected {} message", message.name()))),
+            Err(err) => return Err(err),
+        };
+
+        let mut sums = Vec::with_capacity(((item.y1 - item.y0) * width * 3) as usize);
+        for j in item.y0..item.y1 {
+            for i in 0..width {
+                let color = camera.sample_pixel_from(&scene, i, j, item.first, item.samples);
+                sums.extend([color[0], color[1], color[2]]);
+            }
+        }
+        Message::Result {
+            y0: item.y0,
+            y1: item.y1,
+            samples: item.samples,
+            sums,
+        }
+        .write(&mut writer)?;
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed",
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::Scene {
+                seed: u64::MAX,
+                script: "let spheres = 3;\nspheres".to_string(),
+            },
+            Message::Camera("width=3&samples=25".to_string()),
+            Message::Next,
+            Message::Work(WorkItem {
+                y0: 16,
+                y1: 18,
+                first: 50,
+                samples: 25,
+            }),
+            Message::Result {
+                y0: 16,
+                y1: 18,
+                samples: 25,
+                sums: (0..18).map(|n| n as f64 * 0.5).collect(),
+            },
+            Message::Camera(String::new()),
+            Message::Done,
+        ];
+        let mut wire = Vec::new();
+        for message in &messages {
+            message.write(&mut wire).unwrap();
+        }
+        let mut input = &wire[..];
+        for message in &messages {
+            assert_eq!(&Message::read(&mut input, 3, 18).unwrap(), message);
+        }
+        assert!(input.is_empty());
+
+        for bad in [
+            "WORK 1 2\n",
+            "NEXT 1\n",
+            "HELLO\n",
+            "SCENE 1 10\nshort",
+            "SCENE 10\n0123456789",
+            "RESULT 0 1 4\n",
+            "RESULT 0 19 4\n",
+            "RESULT 2 1 4\n",
+            "RESULT -1 1 4\n",
+            "RESULT 0 1 0\n",
+        ] {
+            assert!(
+                Message::read(&mut bad.as_bytes(), 3, 18).is_err(),
+                "{}",
+                bad
+            );
+        }
+        // Sizes that would overflow are refused rather than allocated.
+        let huge = format!("RESULT 0 {} 1\n", i32::MAX);
+        assert!(Message::read(&mut huge.as_bytes(), i32::MAX, i32::MAX).is_err());
+    }
+
+    #[test]
+    fn requeues_items_of_dropped_workers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut settings = CameraBuilder::new();
+        settings.set("width", "4").unwrap();
+        settings.set("aspect_ratio", "2").unwrap();
+        settings.set("samples", "3").unwrap();
+        let camera = settings.build().unwrap();
+        let coordinator =
+            thread::spawn(move || coordinate_on(listener, "scene".to_string(), 7, &camera));
+
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let scene = Message::read(&mut reader, 4, 2).unwrap();
+            let script = "scene".to_string();
+            assert_eq!(scene, Message::Scene { seed: 7, script });
+            let camera = Message::read(&mut reader, 4, 2).unwrap();
+            assert!(matches!(camera, Message::Camera(_)));
+            (reader, stream)
+        };
+
+        // The first worker takes the only item and drops halfway through its result.
+        let (mut reader, mut writer) = connect();
+        Message::Next.write(&mut writer).unwrap();
+        let Message::Work(item) = Message::read(&mut reader, 4, 2).unwrap() else {
+            panic!("expected work");
+        };
+        assert_eq!((item.y0, item.y1, item.first, item.samples), (0, 2, 0, 3));
+        writeln!(writer, "RESULT 0 2 3").unwrap();
+        writer.write_all(&[0; 20]).unwrap();
+        drop((reader, writer));
+
+        // The second is handed the same item.
+        let (mut reader, mut writer) = connect();
+        Message::Next.write(&mut writer).unwrap();
+        assert_eq!(
+            Message::read(&mut reader, 4, 2).unwrap(),
+            Message::Work(item)
+        );
+        Message::Result {
+            y0: 0,
+            y1: 2,
+            samples: 3,
+            sums: vec![1.5; 24],
+        }
+        .write(&mut writer)
+        .unwrap();
+
+        let buffer = coordinator.join().unwrap().unwrap();
+        assert_eq!(buffer.sample_count(3, 1), 3);
+        assert_eq!(buffer.pixel_sum(3, 1), Color::from(1.5, 1.5, 1.5));
+    }
+}
@@ -0,0 +1,16 @@
+use std::io;
+use std::path::PathBuf;
+
+// Errors from the library's entry points: rendering, loading scene files and
+// models, and writing images. Parsers and builders inside the crate report
+// problems as strings, which these variants carry along with the file they
+// concern.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{}: {message}", .path.display())]
+    Scene { path: PathBuf, message: String },
+    #[error("can't write {}: {message}", .path.display())]
+    Image { path: PathBuf, message: String },
+}
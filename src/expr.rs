@@ -0,0 +1,415 @@
+use crate::perlin::Perlin;
+use crate::ray::Point3;
+use crate::utility::PI;
+use crate::vec3::*;
+
+// A small expression language for procedural materials. An expression is
+// parsed once into a node graph and evaluated per hit, e.g.
+//
+//     mix(vec3(0.9, 0.9, 0.9), vec3(0.2, 0.3, 0.8), ramp(noise(p * 4.0), -0.3, 0.3))
+//
+// Every value is a Vec3; scalars are broadcast to all three components and
+// functions that need a scalar read the x component.
+//
+// Inputs:    u, v, p, normal, pi
+// Operators: + - * / unary -, component access with .x .y .z
+// Functions: mix(a, b, t), noise(p), turb(p), ramp(t, lo, hi), step(edge, x),
+//            smoothstep(lo, hi, x), clamp(x, lo, hi), min, max, pow, sin, cos,
+//            abs, floor, fract, sqrt, length, dot, vec3(x, y, z)
+pub struct Expr {
+    root: Node,
+    perlin: Perlin,
+}
+
+pub struct ExprInputs {
+    pub u: f64,
+    pub v: f64,
+    pub p: Point3,
+    pub normal: Vec3,
+}
+
+enum Input {
+    U,
+    V,
+    P,
+    Normal,
+}
+
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Copy)]
+enum Func {
+    Mix,
+    Noise,
+    Turb,
+    Ramp,
+    Step,
+    Smoothstep,
+    Clamp,
+    Min,
+    Max,
+    Pow,
+    Sin,
+    Cos,
+    Abs,
+    Floor,
+    Fract,
+    Sqrt,
+    Length,
+    Dot,
+    Vec3,
+}
+
+enum Node {
+    Const(Vec3),
+    Input(Input),
+    Neg(Box<Node>),
+    Binary(BinOp, Box<Node>, Box<Node>),
+    Component(Box<Node>, usize),
+    Call(Func, Vec<Node>),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected token {:?} after expression", token));
+        }
+
+        Ok(Self {
+            root,
+            perlin: Perlin::new(),
+        })
+    }
+
+    pub fn eval(&self, inputs: &ExprInputs) -> Vec3 {
+        self.eval_node(&self.root, inputs)
+    }
+
+    fn eval_node(&self, node: &Node, inputs: &ExprInputs) -> Vec3 {
+        match node {
+            Node::Const(c) => *c,
+            Node::Input(Input::U) => splat(inputs.u),
+            Node::Input(Input::V) => splat(inputs.v),
+            Node::Input(Input::P) => inputs.p,
+            Node::Input(Input::Normal) => inputs.normal,
+            Node::Neg(a) => -self.eval_node(a, inputs),
+            Node::Binary(op, a, b) => {
+                let a = self.eval_node(a, inputs);
+                let b = self.eval_node(b, inputs);
+                match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => map2(a, b, |x, y| x / y),
+                }
+            }
+            Node::Component(a, index) => splat(self.eval_node(a, inputs)[*index]),
+            Node::Call(func, args) => {
+                let args: Vec<Vec3> = args.iter().map(|a| self.eval_node(a, inputs)).collect();
+                self.call(*func, &args)
+            }
+        }
+    }
+
+    fn call(&self, func: Func, args: &[Vec3]) -> Vec3 {
+        match func {
+            Func::Mix => args[0] * (splat(1.0) - args[2]) + args[1] * args[2],
+            Func::Noise => splat(self.perlin.noise(&args[0])),
+            Func::Turb => splat(self.perlin.turb(&args[0], 7)),
+            Func::Ramp => {
+                let (t, lo, hi) = (args[0], args[1], args[2]);
+                map2(t - lo, hi - lo, |x, y| (x / y).clamp(0.0, 1.0))
+            }
+            Func::Step => map2(args[0], args[1], |edge, x| if x < edge { 0.0 } else { 1.0 }),
+            Func::Smoothstep => {
+                let t = map2(args[2] - args[0], args[1] - args[0], |x, y| {
+                    (x / y).clamp(0.0, 1.0)
+                });
+                t * t * (splat(3.0) - 2.0 * t)
+            }
+            Func::Clamp => args[0].max(&args[1]).min(&args[2]),
+            Func::Min => args[0].min(&args[1]),
+            Func::Max => args[0].max(&args[1]),
+            Func::Pow => map2(args[0], args[1], f64::powf),
+            Func::Sin => args[0].map(f64::sin),
+            Func::Cos => args[0].map(f64::cos),
+            Func::Abs => args[0].abs(),
+            Func::Floor => args[0].map(f64::floor),
+            Func::Fract => args[0].map(f64::fract),
+            Func::Sqrt => args[0].map(f64::sqrt),
+            Func::Length => splat(args[0].length()),
+            Func::Dot => splat(dot(&args[0], &args[1])),
+            Func::Vec3 => Vec3::from(args[0].x(), args[1].x(), args[2].x()),
+        }
+    }
+}
+
+fn splat(x: f64) -> Vec3 {
+    Vec3::from(x, x, x)
+}
+
+fn map2(a: Vec3, b: Vec3, f: impl Fn(f64, f64) -> f64) -> Vec3 {
+    Vec3::from(f(a.x(), b.x()), f(a.y(), b.y()), f(a.z(), b.z()))
+}
+
+fn lookup_func(name: &str) -> Option<(Func, usize)> {
+    let entry = match name {
+        "mix" => (Func::Mix, 3),
+        "noise" => (Func::Noise, 1),
+        "turb" => (Func::Turb, 1),
+        "ramp" => (Func::Ramp, 3),
+        "step" => (Func::Step, 2),
+        "smoothstep" => (Func::Smoothstep, 3),
+        "clamp" => (Func::Clamp, 3),
+        "min" => (Func::Min, 2),
+        "max" => (Func::Max, 2),
+        "pow" => (Func::Pow, 2),
+        "sin" => (Func::Sin, 1),
+        "cos" => (Func::Cos, 1),
+        "abs" => (Func::Abs, 1),
+        "floor" => (Func::Floor, 1),
+        "fract" => (Func::Fract, 1),
+        "sqrt" => (Func::Sqrt, 1),
+        "length" => (Func::Length, 1),
+        "dot" => (Func::Dot, 2),
+        "vec3" => (Func::Vec3, 3),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| format!("invalid number literal '{}'", text))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/(),.".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}', found {:?}", symbol, self.peek()))
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.term()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.unary()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    // unary := '-' unary | postfix
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat('-') {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    // postfix := primary ('.' ('x' | 'y' | 'z'))*
+    fn postfix(&mut self) -> Result<Node, String> {
+        let mut node = self.primary()?;
+        while self.eat('.') {
+            let index = match self.next() {
+                Some(Token::Ident(name)) if name == "x" || name == "r" => 0,
+                Some(Token::Ident(name)) if name == "y" || name == "g" => 1,
+                Some(Token::Ident(name)) if name == "z" || name == "b" => 2,
+                other => return Err(format!("invalid component access {:?}", other)),
+            };
+            node = Node::Component(Box::new(node), index);
+        }
+        Ok(node)
+    }
+
+    // primary := number | input | function '(' args ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(x)) => Ok(Node::Const(splat(x))),
+            Some(Token::Symbol('(')) => {
+                let node = self.expr()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => {
+                if self.eat('(') {
+                    return self.call(&name);
+                }
+                match name.as_str() {
+                    "u" => Ok(Node::Input(Input::U)),
+                    "v" => Ok(Node::Input(Input::V)),
+                    "p" => Ok(Node::Input(Input::P)),
+                    "normal" | "n" => Ok(Node::Input(Input::Normal)),
+                    "pi" => Ok(Node::Const(splat(PI))),
+                    _ => Err(format!("unknown input '{}'", name)),
+                }
+            }
+            other => Err(format!("unexpected token {:?}", other)),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Node, String> {
+        let (func, arity) = lookup_func(name).ok_or(format!("unknown function '{}'", name))?;
+
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(')') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+
+        if args.len() != arity {
+            return Err(format!(
+                "function '{}' takes {} argument(s), {} given",
+                name,
+                arity,
+                args.len()
+            ));
+        }
+        Ok(Node::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inputs() -> ExprInputs {
+        ExprInputs {
+            u: 0.25,
+            v: 0.75,
+            p: Point3::from(1.0, 2.0, 3.0),
+            normal: Vec3::from(0.0, 1.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let expr = Expr::parse("p * 2.0 - vec3(1.0, 1.0, 1.0) / 2.0").unwrap();
+        assert_eq!(expr.eval(&inputs()), Vec3::from(1.5, 3.5, 5.5));
+
+        let expr = Expr::parse("-(u + v) * p.y").unwrap();
+        assert_eq!(expr.eval(&inputs()), Vec3::from(-2.0, -2.0, -2.0));
+    }
+
+    #[test]
+    fn functions() {
+        let expr = Expr::parse("mix(vec3(0.0, 0.0, 0.0), vec3(1.0, 2.0, 4.0), u)").unwrap();
+        assert_eq!(expr.eval(&inputs()), Vec3::from(0.25, 0.5, 1.0));
+
+        let expr = Expr::parse("ramp(p, 1.0, 3.0)").unwrap();
+        assert_eq!(expr.eval(&inputs()), Vec3::from(0.0, 0.5, 1.0));
+
+        let expr = Expr::parse("clamp(noise(p * 4.0), -1.0, 1.0)").unwrap();
+        let value = expr.eval(&inputs());
+        assert!(value.x() >= -1.0 && value.x() <= 1.0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expr::parse("mix(u, v)").is_err());
+        assert!(Expr::parse("foo(u)").is_err());
+        assert!(Expr::parse("p.w").is_err());
+        assert!(Expr::parse("u +").is_err());
+        assert!(Expr::parse("u v").is_err());
+    }
+}
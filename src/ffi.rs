@@ -0,0 +1,300 @@
+// C API for embedding the renderer; include/render.h is generated from this
+// file by build.rs. Every function takes pointers the caller got from this
+// API (or null, which is reported as an error), and none of them keep
+// borrowed pointers past the call. Panics are caught at the boundary and
+// reported like any other error.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::Color;
+use crate::control::RenderControl;
+use crate::material::{Dieletric, DiffuseLight, Lambertian, Material, Metal};
+use crate::ray::Point3;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+/// A scene under construction together with the camera that renders it.
+pub struct RenderScene {
+    scene: Scene,
+    camera: CameraBuilder,
+    materials: Vec<Material>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Runs `f`, turning errors and panics into -1 with the message kept for
+// `render_last_error`.
+fn guard(f: impl FnOnce() -> Result<i32, String>) -> i32 {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "panic".to_string(),
+            },
+        })
+    });
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            let message = CString::new(err.replace('\0', " ")).unwrap();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            -1
+        }
+    }
+}
+
+unsafe fn scene_mut<'a>(scene: *mut RenderScene) -> Result<&'a mut RenderScene, String> {
+    scene.as_mut().ok_or_else(|| "scene is null".to_string())
+}
+
+unsafe fn string<'a>(s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is null", what));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", what))
+}
+
+impl RenderScene {
+    fn add_material(&mut self, material: Material) -> i32 {
+        self.materials.push(material);
+        self.materials.len() as i32 - 1
+    }
+
+    fn material(&self, id: i32) -> Result<Material, String> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|id| self.materials.get(id))
+            .cloned()
+            .ok_or_else(|| format!("unknown material {}", id))
+    }
+
+    // The camera as it will render, with every default filled in, or what
+    // is wrong with its settings.
+    fn built_camera(&self) -> Result<Camera, String> {
+        self.camera.build()
+    }
+}
+
+/// The message of the last error on this thread, or null if there was none.
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn render_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// An empty scene with the default camera and background. Free it with
+/// `render_scene_free`.
+#[no_mangle]
+pub extern "C" fn render_scene_new() -> *mut RenderScene {
+    Box::into_raw(Box::new(RenderScene {
+        scene: Scene::default(),
+        camera: CameraBuilder::new(),
+        materials: Vec::new(),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_free(scene: *mut RenderScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Adds a diffuse material and returns its id, or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_add_lambertian(
+    scene: *mut RenderScene,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let albedo = Color::from(r, g, b);
+        Ok(scene.add_material(Material::Lambertian(Lambertian::from(albedo))))
+    })
+}
+
+/// Adds a reflective material and returns its id, or -1 on error. `fuzz`
+/// blurs the reflection, from 0 (mirror) to 1.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_add_metal(
+    scene: *mut RenderScene,
+    r: f64,
+    g: f64,
+    b: f64,
+    fuzz: f64,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let albedo = Color::from(r, g, b);
+        Ok(scene.add_material(Material::Metal(Metal::from(albedo, fuzz))))
+    })
+}
+
+/// Adds a glass-like material with the given index of refraction and returns
+/// its id, or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_add_dielectric(scene: *mut RenderScene, ir: f64) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        Ok(scene.add_material(Material::Dieletric(Dieletric::from(ir))))
+    })
+}
+
+/// Adds a material that gives off light of the given color and returns its
+/// id, or -1 on error. Components above 1 make brighter lights.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_add_diffuse_light(
+    scene: *mut RenderScene,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let emit = Color::from(r, g, b);
+        Ok(scene.add_material(Material::DiffuseLight(DiffuseLight::from(emit))))
+    })
+}
+
+/// Adds a sphere made of a material returned by one of the
+/// `render_scene_add_*` material functions. Returns 0, or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_add_sphere(
+    scene: *mut RenderScene,
+    x: f64,
+    y: f64,
+    z: f64,
+    radius: f64,
+    material: i32,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let material = scene.material(material)?;
+        let center = Point3::from(x, y, z);
+        scene
+            .scene
+            .world
+            .add(Box::new(Sphere::from(center, radius, material)));
+        Ok(0)
+    })
+}
+
+/// Sets a camera option by name, with the keys of a script's `set_camera`
+/// and vectors written as "x,y,z", e.g. "look_from" = "13,2,3". Returns 0,
+/// or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_set_camera(
+    scene: *mut RenderScene,
+    key: *const c_char,
+    value: *const c_char,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let key = string(key, "key")?;
+        let value = string(value, "value")?;
+        scene.camera.set(key, value)?;
+        Ok(0)
+    })
+}
+
+/// Stores the size of the image `render_scene_render` will produce.
+/// Returns 0, or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_image_size(
+    scene: *mut RenderScene,
+    width: *mut i32,
+    height: *mut i32,
+) -> i32 {
+    guard(|| {
+        let camera = scene_mut(scene)?.built_camera()?;
+        if width.is_null() || height.is_null() {
+            return Err("size output is null".to_string());
+        }
+        *width = camera.image_width();
+        *height = camera.image_height();
+        Ok(0)
+    })
+}
+
+/// Renders the scene into `pixels`, which must hold `len` bytes, at least
+/// width * height * 3. Pixels are written as gamma-corrected 8-bit RGB,
+/// row by row from the top. Returns 0, or -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn render_scene_render(
+    scene: *mut RenderScene,
+    pixels: *mut u8,
+    len: usize,
+) -> i32 {
+    guard(|| {
+        let scene = scene_mut(scene)?;
+        let camera = scene.built_camera()?;
+        let needed = camera.image_width() as usize * camera.image_height() as usize * 3;
+        if pixels.is_null() || len < needed {
+            return Err(format!("pixel buffer needs {} bytes", needed));
+        }
+        let buffer = camera.render_buffer(&scene.scene, &RenderControl::new(), &mut |_| {});
+        let out = std::slice::from_raw_parts_mut(pixels, needed);
+        for (rgb, p) in out.chunks_exact_mut(3).zip(buffer.to_rgb_u32()) {
+            rgb.copy_from_slice(&[(p >> 16) as u8, (p >> 8) as u8, p as u8]);
+        }
+        Ok(0)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_through_the_c_api() {
+        unsafe {
+            let scene = render_scene_new();
+            let red = render_scene_add_lambertian(scene, 0.8, 0.1, 0.1);
+            assert_eq!(red, 0);
+            assert_eq!(render_scene_add_sphere(scene, 0.0, 0.0, 0.0, 0.5, red), 0);
+            for (key, value) in [(c"width", c"16"), (c"samples", c"2")] {
+                assert_eq!(
+                    render_scene_set_camera(scene, key.as_ptr(), value.as_ptr()),
+                    0
+                );
+            }
+
+            let (mut width, mut height) = (0, 0);
+            assert_eq!(render_scene_image_size(scene, &mut width, &mut height), 0);
+            assert_eq!((width, height), (16, 16));
+            let mut pixels = vec![0u8; (width * height * 3) as usize];
+            assert_eq!(
+                render_scene_render(scene, pixels.as_mut_ptr(), pixels.len()),
+                0
+            );
+            // The sphere fills the center and is mostly red.
+            let center = ((8 * width + 8) * 3) as usize;
+            assert!(pixels[center] > pixels[center + 1] && pixels[center] > pixels[center + 2]);
+
+            // Errors come back as -1 with a message.
+            assert_eq!(render_scene_add_sphere(scene, 0.0, 0.0, 0.0, 1.0, 7), -1);
+            let message = CStr::from_ptr(render_last_error()).to_str().unwrap();
+            assert_eq!(message, "unknown material 7");
+            assert_eq!(render_scene_render(scene, pixels.as_mut_ptr(), 3), -1);
+            let bad = render_scene_set_camera(scene, c"zoom".as_ptr(), c"2".as_ptr());
+            assert_eq!(bad, -1);
+
+            render_scene_free(scene);
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use crate::color::{to_rgb8, write_color, Color, Tonemap, INVALID_SAMPLE};
+use crate::error::Error;
+
+use crate::utility::INFINITY;
+
+use log::warn;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Accumulation buffer holding the running sum of samples, and the number of
+// samples taken, for every pixel.
+pub struct FrameBuffer {
+    width: i32,
+    height: i32,
+    passes: i32,
+    pixels: Vec<Color>,
+    counts: Vec<u32>,
+    // Luminance sums and squared sums of the samples added one at a time, for
+    // estimating noise. Samples added in bulk do not contribute.
+    luminance: Vec<[f64; 2]>,
+    luminance_counts: Vec<u32>,
+    // Pixels that received a NaN or infinite sample. They are shown as
+    // `INVALID_SAMPLE` from then on.
+    invalid: Vec<bool>,
+    // Curve the 8-bit images are encoded with.
+    tonemap: Tonemap,
+}
+
+// Magic bytes at the start of a saved accumulation buffer. The header is
+// followed by the little-endian u32 width and height, then for every pixel
+// the f64 RGB sums and the u32 sample count. Invalid pixels are stored with
+// NaN sums.
+const ACCUM_MAGIC: &[u8; 8] = b"RTACCUM1";
+
+// The largest buffers read back, so a damaged or hostile header can't make
+// the width times the height overflow.
+const MAX_SIDE: u32 = 1 << 16;
+const MAX_PIXELS: u64 = 1 << 28;
+
+// Formats images are written in, chosen by file extension. PNG and JPEG need
+// the `image` feature and OpenEXR the `exr` feature; anything else is written
+// as text PPM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Jpeg,
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("png") => Self::Png,
+            Some("jpg" | "jpeg") => Self::Jpeg,
+            Some("exr") => Self::Exr,
+            _ => Self::Ppm,
+        }
+    }
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        Self {
+            width,
+            height,
+            passes: 0,
+            pixels: vec![Color::new(); size],
+            counts: vec![0; size],
+            luminance: vec![[0.0; 2]; size],
+            luminance_counts: vec![0; size],
+            invalid: vec![false; size],
+            tonemap: Tonemap::default(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+    }
+
+    // Number of completed progressive passes.
+    pub fn samples(&self) -> i32 {
+        self.passes
+    }
+
+    pub fn clear(&mut self) {
+        self.passes = 0;
+        self.pixels.fill(Color::new());
+        self.counts.fill(0);
+        self.luminance.fill([0.0; 2]);
+        self.luminance_counts.fill(0);
+        self.invalid.fill(false);
+    }
+
+    pub fn add_sample(&mut self, i: i32, j: i32, color: Color) {
+        self.add_samples(i, j, color, 1);
+        if color.is_finite() {
+            let index = (j * self.width + i) as usize;
+            let y = color.luminance();
+            self.luminance[index][0] += y;
+            self.luminance[index][1] += y * y;
+            self.luminance_counts[index] += 1;
+        }
+    }
+
+    // Adds the sum of `count` samples to pixel (i, j).
+    pub fn add_samples(&mut self, i: i32, j: i32, sum: Color, count: u32) {
+        if !sum.is_finite() {
+            warn!("non-finite sample {:?} at pixel ({}, {})", sum, i, j);
+            self.mark_invalid(i, j);
+            return;
+        }
+        let index = (j * self.width + i) as usize;
+        self.pixels[index] += sum;
+        self.counts[index] += count;
+    }
+
+    pub fn mark_invalid(&mut self, i: i32, j: i32) {
+        self.invalid[(j * self.width + i) as usize] = true;
+    }
+
+    pub fn is_invalid(&self, i: i32, j: i32) -> bool {
+        self.invalid[(j * self.width + i) as usize]
+    }
+
+    // Relative standard error of the mean luminance of pixel (i, j), or
+    // infinity with fewer than two samples added one at a time. The error is
+    // taken relative to the pixel brightness plus a small floor, so that noise
+    // in near-black pixels, which is hard to see, does not dominate.
+    pub fn pixel_noise(&self, i: i32, j: i32) -> f64 {
+        const DARK: f64 = 0.01;
+        let index = (j * self.width + i) as usize;
+        let n = self.luminance_counts[index] as f64;
+        if n < 2.0 {
+            return INFINITY;
+        }
+        let [sum, sum_sq] = self.luminance[index];
+        let mean = sum / n;
+        let variance = ((sum_sq - n * mean * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / (mean + DARK)
+    }
+
+    // Image-wide noise: the `percentile` (0 to 1) of the per-pixel noise, so
+    // 0.5 is the median pixel and 1.0 the noisiest one. Invalid pixels are
+    // left out; they do not get better with more samples.
+    pub fn noise(&self, percentile: f64) -> f64 {
+        let mut noise: Vec<f64> = (0..self.height)
+            .flat_map(|j| (0..self.width).map(move |i| (i, j)))
+            .filter(|&(i, j)| !self.is_invalid(i, j))
+            .map(|(i, j)| self.pixel_noise(i, j))
+            .collect();
+        if noise.is_empty() {
+            return 0.0;
+        }
+        let rank = ((noise.len() - 1) as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        let (_, value, _) = noise.select_nth_unstable_by(rank, f64::total_cmp);
+        *value
+    }
+
+    // Number of pixels that received a NaN or infinite sample.
+    pub fn invalid_pixels(&self) -> usize {
+        self.invalid.iter().filter(|&&invalid| invalid).count()
+    }
+
+    // The pixel sums and counts, with invalid pixels replaced by the marker.
+    fn shown_pixels(&self) -> impl Iterator<Item = (Color, u32)> + '_ {
+        self.pixels
+            .iter()
+            .zip(self.counts.iter())
+            .zip(self.invalid.iter())
+            .map(|((&pixel, &count), &invalid)| {
+                if invalid {
+                    (INVALID_SAMPLE, 1)
+                } else {
+                    (pixel, count.max(1))
+                }
+            })
+    }
+
+    pub fn complete_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    // Restores the pass count of a buffer read back from a file.
+    pub(crate) fn set_passes(&mut self, passes: i32) {
+        self.passes = passes;
+    }
+
+    pub fn pixel_sum(&self, i: i32, j: i32) -> Color {
+        self.pixels[(j * self.width + i) as usize]
+    }
+
+    pub fn sample_count(&self, i: i32, j: i32) -> u32 {
+        self.counts[(j * self.width + i) as usize]
+    }
+
+    // Adds the samples of another buffer of the same size, e.g. a shard of
+    // the same frame rendered elsewhere.
+    pub fn merge(&mut self, other: &FrameBuffer) -> Result<(), String> {
+        if other.width != self.width || other.height != self.height {
+            return Err(format!(
+                "cannot merge a {}x{} buffer into a {}x{} one",
+                other.width, other.height, self.width, self.height
+            ));
+        }
+        for (pixel, sum) in self.pixels.iter_mut().zip(other.pixels.iter()) {
+            *pixel += *sum;
+        }
+        for (count, other_count) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other_count;
+        }
+        for (luminance, other_luminance) in self.luminance.iter_mut().zip(other.luminance.iter()) {
+            luminance[0] += other_luminance[0];
+            luminance[1] += other_luminance[1];
+        }
+        for (count, other_count) in self
+            .luminance_counts
+            .iter_mut()
+            .zip(other.luminance_counts.iter())
+        {
+            *count += other_count;
+        }
+        for (invalid, other_invalid) in self.invalid.iter_mut().zip(other.invalid.iter()) {
+            *invalid |= other_invalid;
+        }
+        self.passes = self.passes.max(other.passes);
+        Ok(())
+    }
+
+    // Tone-mapped pixels packed as 0RGB, the layout used by window buffers.
+    pub fn to_rgb_u32(&self) -> Vec<u32> {
+        self.shown_pixels()
+            .map(|(pixel, count)| {
+                let [r, g, b] = to_rgb8(pixel, count as i32, self.tonemap);
+                (r as u32) << 16 | (g as u32) << 8 | b as u32
+            })
+            .collect()
+    }
+
+    // Tone-mapped pixels as RGB bytes, row by row from the top.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.shown_pixels()
+            .flat_map(|(pixel, count)| to_rgb8(pixel, count as i32, self.tonemap))
+            .collect()
+    }
+
+    // Average linear color of every pixel, row by row from the top, before
+    // any gamma or clamping.
+    pub fn to_linear(&self) -> Vec<Color> {
+        self.shown_pixels()
+            .map(|(pixel, count)| pixel / count as f64)
+            .collect()
+    }
+
+    // Writes the image to `path` in the format its extension names.
+    pub fn write_image(&self, path: &Path) -> Result<(), Error> {
+        let failed = |message: String| Error::Image {
+            path: path.to_path_buf(),
+            message,
+        };
+        match ImageFormat::from_path(path) {
+            ImageFormat::Ppm => File::create(path)
+                .and_then(|file| self.write_ppm(&mut BufWriter::new(file)))
+                .map_err(|err| failed(err.to_string())),
+            ImageFormat::Exr => self.write_exr(path).map_err(failed),
+            format => self.encode(path, format).map_err(failed),
+        }
+    }
+
+    // Writes the linear colors as 32-bit floats, keeping highlights brighter
+    // than white for compositing and tone mapping elsewhere.
+    #[cfg(feature = "exr")]
+    fn write_exr(&self, path: &Path) -> Result<(), String> {
+        crate::stage_span!("write_image");
+        let pixels = self.to_linear();
+        let width = self.width as usize;
+        exr::prelude::write_rgb_file(path, width, self.height as usize, |x, y| {
+            let pixel = pixels[y * width + x];
+            (pixel.r() as f32, pixel.g() as f32, pixel.b() as f32)
+        })
+        .map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "exr"))]
+    fn write_exr(&self, _path: &Path) -> Result<(), String> {
+        Err("rebuild with `--features exr` to write OpenEXR files".to_string())
+    }
+
+    #[cfg(feature = "image")]
+    fn encode(&self, path: &Path, format: ImageFormat) -> Result<(), String> {
+        crate::stage_span!("write_image");
+        let image =
+            image::RgbImage::from_raw(self.width as u32, self.height as u32, self.to_rgb8())
+                .expect("buffer size matches its dimensions");
+        let format = match format {
+            ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+            _ => image::ImageFormat::Png,
+        };
+        image
+            .save_with_format(path, format)
+            .map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "image"))]
+    fn encode(&self, _path: &Path, format: ImageFormat) -> Result<(), String> {
+        Err(format!(
+            "rebuild with `--features image` to write {:?} files",
+            format
+        ))
+    }
+
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        crate::stage_span!("write_image");
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for (pixel, count) in self.shown_pixels() {
+            write_color(out, pixel, count as i32, self.tonemap)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        crate::stage_span!("save_buffer");
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_accumulation(&mut out)?;
+        out.flush()
+    }
+
+    // Writes the buffer in the format `save` uses, e.g. as part of a larger file.
+    pub fn write_accumulation(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(ACCUM_MAGIC)?;
+        out.write_all(&(self.width as u32).to_le_bytes())?;
+        out.write_all(&(self.height as u32).to_le_bytes())?;
+        for ((pixel, count), invalid) in self
+            .pixels
+            .iter()
+            .zip(self.counts.iter())
+            .zip(self.invalid.iter())
+        {
+            for k in 0..3 {
+                let value = if *invalid { f64::NAN } else { pixel[k] };
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&count.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_accumulation(&mut BufReader::new(File::open(path)?)).map_err(|err| {
+            if err.kind() == io::ErrorKind::InvalidData {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not an accumulation buffer", path.display()),
+                )
+            } else {
+                err
+            }
+        })
+    }
+
+    // Reads a buffer written by `write_accumulation`.
+    pub fn read_accumulation(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != ACCUM_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an accumulation buffer",
+            ));
+        }
+
+        let width = read_u32(input)?;
+        let height = read_u32(input)?;
+        if width > MAX_SIDE || height > MAX_SIDE || width as u64 * height as u64 > MAX_PIXELS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a {}x{} buffer is too large", width, height),
+            ));
+        }
+
+        // The pixels are read before the buffer is made, so that a header
+        // claiming more pixels than follow fails at the end of the input
+        // rather than allocating for all of them first.
+        let mut pixels = Vec::new();
+        for _ in 0..width * height {
+            let mut rgb = [0.0; 3];
+            for value in rgb.iter_mut() {
+                let mut bytes = [0; 8];
+                input.read_exact(&mut bytes)?;
+                *value = f64::from_le_bytes(bytes);
+            }
+            let pixel = Color::from(rgb[0], rgb[1], rgb[2]);
+            pixels.push((pixel, read_u32(input)?));
+        }
+        let mut buffer = Self::new(width as i32, height as i32);
+        for (index, (pixel, count)) in pixels.into_iter().enumerate() {
+            if pixel.is_finite() {
+                buffer.pixels[index] = pixel;
+            } else {
+                buffer.invalid[index] = true;
+            }
+            buffer.counts[index] = count;
+        }
+        Ok(buffer)
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn noise_falls_with_samples() {
+        let mut buffer = FrameBuffer::new(2, 1);
+        assert_eq!(buffer.noise(0.5), INFINITY);
+
+        // Pixel 0 alternates between two values, pixel 1 is constant.
+        let mut noise = Vec::new();
+        for pass in 0..64 {
+            let value = if pass % 2 == 0 { 0.2 } else { 0.8 };
+            buffer.add_sample(0, 0, Color::gray(value));
+            buffer.add_sample(1, 0, Color::gray(0.5));
+            if pass > 0 {
+                noise.push(buffer.pixel_noise(0, 0));
+            }
+        }
+        assert_eq!(buffer.pixel_noise(1, 0), 0.0);
+        assert!(noise.last().unwrap() < &(noise[1] / 4.0));
+        assert_eq!(buffer.noise(1.0), buffer.pixel_noise(0, 0));
+        assert_eq!(buffer.noise(0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_bad_accumulation_headers() {
+        let header = |width: u32, height: u32| {
+            let mut data = ACCUM_MAGIC.to_vec();
+            data.extend(width.to_le_bytes());
+            data.extend(height.to_le_bytes());
+            data
+        };
+        let read = |data: Vec<u8>| FrameBuffer::read_accumulation(&mut io::Cursor::new(data));
+
+        for (width, height) in [(u32::MAX, 1), (1, 1 << 31), (1 << 16, 1 << 16)] {
+            let err = read(header(width, height)).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        // A plausible size with too few pixels after it.
+        let err = read(header(4000, 4000)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut buffer = FrameBuffer::new(2, 1);
+        buffer.add_sample(1, 0, Color::gray(0.5));
+        let mut data = Vec::new();
+        buffer.write_accumulation(&mut data).unwrap();
+        let again = read(data).unwrap();
+        assert_eq!(again.pixel_sum(1, 0), Color::gray(0.5));
+        assert_eq!(again.sample_count(1, 0), 1);
+    }
+
+    #[test]
+    fn writes_images_by_extension() {
+        assert_eq!(ImageFormat::from_path(Path::new("a.PNG")), ImageFormat::Png);
+        assert_eq!(
+            ImageFormat::from_path(Path::new("a.jpeg")),
+            ImageFormat::Jpeg
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("a")), ImageFormat::Ppm);
+
+        let mut buffer = FrameBuffer::new(3, 2);
+        buffer.add_sample(2, 1, Color::gray(1.0));
+        assert_eq!(buffer.to_rgb8().len(), 18);
+        assert_eq!(buffer.to_rgb8()[15..], [255, 255, 255]);
+
+        let path = std::env::temp_dir().join(format!("image-{}.png", std::process::id()));
+        let written = buffer.write_image(&path);
+        #[cfg(feature = "image")]
+        {
+            written.unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            assert_eq!(&bytes[1..4], b"PNG");
+            std::fs::remove_file(&path).ok();
+        }
+        #[cfg(not(feature = "image"))]
+        assert!(written.is_err());
+
+        // OpenEXR keeps what is brighter than white.
+        assert_eq!(ImageFormat::from_path(Path::new("a.exr")), ImageFormat::Exr);
+        buffer.add_sample(0, 0, Color::from(4.0, 0.5, 0.0));
+        buffer.add_sample(0, 0, Color::from(2.0, 0.5, 0.0));
+        assert_eq!(buffer.to_linear()[0].r(), 3.0);
+        let path = path.with_extension("exr");
+        let written = buffer.write_image(&path);
+        #[cfg(feature = "exr")]
+        {
+            written.unwrap();
+            let image = exr::prelude::read_first_rgba_layer_from_file(
+                &path,
+                |size, _| vec![0.0f32; size.width() * size.height()],
+                |pixels, position, (r, _, _, _): (f32, f32, f32, f32)| {
+                    pixels[position.y() * 3 + position.x()] = r
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                image.layer_data.channel_data.pixels[..],
+                [3.0, 0.0, 0.0, 0.0, 0.0, 1.0]
+            );
+            std::fs::remove_file(&path).ok();
+        }
+        #[cfg(not(feature = "exr"))]
+        assert!(written.is_err());
+
+        // Failures name the file they were writing.
+        let missing = std::env::temp_dir().join("no-such-dir").join("image.ppm");
+        let err = buffer.write_image(&missing).unwrap_err();
+        assert!(matches!(&err, Error::Image { path, .. } if path == &missing));
+        assert!(err.to_string().contains("image.ppm"));
+    }
+}
@@ -0,0 +1,737 @@
+use crate::camera::CameraBuilder;
+use crate::color::Color;
+use crate::error::Error;
+use crate::hittable_list::HittableList;
+use crate::mat4::Mat4;
+use crate::material::{Dieletric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::Mesh;
+use crate::quat::Quat;
+use crate::ray::Point3;
+use crate::scene::Scene;
+use crate::vec3::*;
+
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Imports glTF 2.0 scenes, as `.gltf` JSON with its buffers in data URIs or
+// files beside it, or as binary `.glb`. Every triangle primitive of the
+// default scene becomes a `Mesh` with the transforms of its nodes applied to
+// its vertices, normals and texture coordinates. Other primitives, such as
+// the lines and points of loose edges, are skipped.
+//
+// Materials keep their factors but not their textures, and are mapped onto
+// the crate's: emissive ones become lights, ones with
+// `KHR_materials_transmission` glass of the `KHR_materials_ior` index, mostly
+// metallic ones metal as fuzzy as they are rough, and the rest lambertian in
+// their base color. Primitives without a material are light gray.
+//
+// Perspective cameras are added to the scene under the names of their nodes,
+// and the first one found also sets up `camera`.
+pub fn load(path: &Path, camera: &mut CameraBuilder) -> Result<Scene, Error> {
+    crate::stage_span!("load_gltf");
+    let failed = |message: String| Error::Scene {
+        path: path.to_path_buf(),
+        message,
+    };
+    let data = std::fs::read(path).map_err(|err| failed(err.to_string()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    parse(&data, base, camera).map_err(failed)
+}
+
+// Imports a `.gltf` or `.glb` file's contents. Buffers in other files are
+// read from `base`.
+pub fn parse(data: &[u8], base: &Path, camera: &mut CameraBuilder) -> Result<Scene, String> {
+    let (json, binary) = if data.starts_with(GLB_MAGIC) {
+        split_glb(data)?
+    } else {
+        (data, None)
+    };
+    let document: Document = serde_json::from_slice(json).map_err(|err| err.to_string())?;
+    let buffers = document
+        .buffers
+        .iter()
+        .enumerate()
+        .map(|(index, buffer)| load_buffer(buffer, index, binary, base))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut import = Import {
+        document: &document,
+        buffers,
+        world: HittableList::default(),
+        scene: Scene::default(),
+        camera,
+        has_camera: false,
+    };
+
+    let roots = match document
+        .scene
+        .or((!document.scenes.is_empty()).then_some(0))
+    {
+        Some(index) => document
+            .scenes
+            .get(index)
+            .ok_or_else(|| format!("scene {} doesn't exist", index))?
+            .nodes
+            .clone(),
+        // Without scenes, every node that isn't another's child is a root.
+        None => (0..document.nodes.len())
+            .filter(|index| !document.nodes.iter().any(|n| n.children.contains(index)))
+            .collect(),
+    };
+    for root in roots {
+        import.node(root, Mat4::IDENTITY, 0)?;
+    }
+    let Import {
+        world, mut scene, ..
+    } = import;
+    scene.world = world;
+    Ok(scene)
+}
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON: u32 = 0x4E4F534A;
+const GLB_BIN: u32 = 0x004E4942;
+
+// Nodes nested deeper than this are taken to be a cycle.
+const MAX_DEPTH: usize = 256;
+
+const TRIANGLES: u32 = 4;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    #[serde(default)]
+    accessors: Vec<Accessor>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    buffers: Vec<Buffer>,
+    #[serde(default)]
+    cameras: Vec<GltfCamera>,
+    #[serde(default)]
+    materials: Vec<PbrMaterial>,
+    #[serde(default)]
+    meshes: Vec<GltfMesh>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    scenes: Vec<GltfScene>,
+    scene: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+    uri: Option<String>,
+    byte_length: usize,
+}
+
+#[derive(Deserialize)]
+struct GltfCamera {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    perspective: Option<Perspective>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Perspective {
+    yfov: f64,
+    aspect_ratio: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PbrMaterial {
+    #[serde(default)]
+    pbr_metallic_roughness: MetallicRoughness,
+    #[serde(default)]
+    emissive_factor: [f64; 3],
+    #[serde(default)]
+    extensions: MaterialExtensions,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetallicRoughness {
+    #[serde(default = "white")]
+    base_color_factor: [f64; 4],
+    #[serde(default = "one")]
+    metallic_factor: f64,
+    #[serde(default = "one")]
+    roughness_factor: f64,
+}
+
+impl Default for MetallicRoughness {
+    fn default() -> Self {
+        Self {
+            base_color_factor: white(),
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+        }
+    }
+}
+
+fn white() -> [f64; 4] {
+    [1.0; 4]
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+#[derive(Default, Deserialize)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<Ior>,
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    emissive_strength: Option<EmissiveStrength>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transmission {
+    #[serde(default)]
+    transmission_factor: f64,
+}
+
+#[derive(Deserialize)]
+struct Ior {
+    #[serde(default = "glass")]
+    ior: f64,
+}
+
+fn glass() -> f64 {
+    1.5
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmissiveStrength {
+    #[serde(default = "one")]
+    emissive_strength: f64,
+}
+
+#[derive(Deserialize)]
+struct GltfMesh {
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Deserialize)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    #[serde(default = "triangles")]
+    mode: u32,
+}
+
+fn triangles() -> u32 {
+    TRIANGLES
+}
+
+#[derive(Deserialize)]
+struct Node {
+    name: Option<String>,
+    camera: Option<usize>,
+    mesh: Option<usize>,
+    #[serde(default)]
+    children: Vec<usize>,
+    matrix: Option<[f64; 16]>,
+    translation: Option<[f64; 3]>,
+    rotation: Option<[f64; 4]>, // x, y, z, w
+    scale: Option<[f64; 3]>,
+}
+
+impl Node {
+    fn local(&self) -> Mat4 {
+        if let Some(m) = self.matrix {
+            let column = |k: usize| [m[4 * k], m[4 * k + 1], m[4 * k + 2], m[4 * k + 3]];
+            return Mat4::from_cols([column(0), column(1), column(2), column(3)]);
+        }
+        let [tx, ty, tz] = self.translation.unwrap_or([0.0; 3]);
+        let [x, y, z, w] = self.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+        let [sx, sy, sz] = self.scale.unwrap_or([1.0; 3]);
+        Mat4::from_trs(
+            &Vec3::from(tx, ty, tz),
+            &Quat::from(w, x, y, z).normalize(),
+            &Vec3::from(sx, sy, sz),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct GltfScene {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+// The JSON chunk of a GLB file, and its binary chunk if it has one.
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let word = |at: usize| {
+        data.get(at..at + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or("truncated GLB file")
+    };
+    if word(4)? != 2 {
+        return Err(format!("GLB version {} isn't supported", word(4)?));
+    }
+    let end = (word(8)? as usize).min(data.len());
+    let (mut json, mut binary) = (None, None);
+    let mut at = 12;
+    while at + 8 <= end {
+        let length = word(at)? as usize;
+        let chunk = data
+            .get(at + 8..at + 8 + length)
+            .ok_or("truncated GLB chunk")?;
+        match word(at + 4)? {
+            GLB_JSON => json = json.or(Some(chunk)),
+            GLB_BIN => binary = binary.or(Some(chunk)),
+            _ => {}
+        }
+        at += 8 + length;
+    }
+    Ok((json.ok_or("GLB file without a JSON chunk")?, binary))
+}
+
+fn load_buffer(
+    buffer: &Buffer,
+    index: usize,
+    binary: Option<&[u8]>,
+    base: &Path,
+) -> Result<Vec<u8>, String> {
+    let data = match &buffer.uri {
+        None => binary
+            .filter(|_| index == 0)
+            .ok_or_else(|| format!("buffer {} has no data", index))?
+            .to_vec(),
+        Some(uri) if uri.starts_with("data:") => {
+            let (_, encoded) = uri
+                .split_once(";base64,")
+                .ok_or_else(|| format!("buffer {} isn't base64", index))?;
+            decode_base64(encoded).ok_or_else(|| format!("buffer {} isn't base64", index))?
+        }
+        Some(uri) => {
+            let path = base.join(uri);
+            crate::assets::opened(&path);
+            std::fs::read(&path).map_err(|err| format!("{}: {}", uri, err))?
+        }
+    };
+    if data.len() < buffer.byte_length {
+        return Err(format!(
+            "buffer {} has {} bytes, not {}",
+            index,
+            data.len(),
+            buffer.byte_length
+        ));
+    }
+    Ok(data)
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in encoded.trim_end_matches('=').bytes() {
+        bits = bits << 6 | value(c)? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+struct Import<'a> {
+    document: &'a Document,
+    buffers: Vec<Vec<u8>>,
+    world: HittableList,
+    scene: Scene,
+    camera: &'a mut CameraBuilder,
+    has_camera: bool,
+}
+
+impl Import<'_> {
+    fn node(&mut self, index: usize, parent: Mat4, depth: usize) -> Result<(), String> {
+        let document = self.document;
+        let node = document
+            .nodes
+            .get(index)
+            .ok_or_else(|| format!("node {} doesn't exist", index))?;
+        if depth > MAX_DEPTH {
+            return Err(format!("node {} is its own ancestor", index));
+        }
+        let matrix = parent * node.local();
+        if let Some(mesh) = node.mesh {
+            self.mesh(mesh, &matrix)
+                .map_err(|err| format!("mesh {}: {}", mesh, err))?;
+        }
+        if let Some(camera) = node.camera {
+            self.camera(camera, node, &matrix)?;
+        }
+        for &child in &node.children {
+            self.node(child, matrix, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, index: usize, node: &Node, matrix: &Mat4) -> Result<(), String> {
+        let camera = self
+            .document
+            .cameras
+            .get(index)
+            .ok_or_else(|| format!("camera {} doesn't exist", index))?;
+        let Some(perspective) = camera
+            .perspective
+            .as_ref()
+            .filter(|_| camera.kind == "perspective")
+        else {
+            warn!(
+                "Skipping glTF camera {}: only perspective cameras are supported",
+                index
+            );
+            return Ok(());
+        };
+        // glTF cameras look down their -z axis with +y up.
+        let look_from = matrix.transform_point(&Point3::new());
+        let mut settings = CameraBuilder::new();
+        settings.look_from = Some(look_from);
+        settings.look_at =
+            Some(look_from + unit_vector(matrix.transform_vector(&Vec3::from(0.0, 0.0, -1.0))));
+        settings.vup = Some(matrix.transform_vector(&Vec3::from(0.0, 1.0, 0.0)));
+        settings.vfov = Some(perspective.yfov.to_degrees());
+        settings.aspect_ratio = perspective.aspect_ratio;
+
+        if !self.has_camera {
+            self.has_camera = true;
+            self.camera.look_from = settings.look_from;
+            self.camera.look_at = settings.look_at;
+            self.camera.vup = settings.vup;
+            self.camera.vfov = settings.vfov;
+            self.camera.aspect_ratio = settings.aspect_ratio.or(self.camera.aspect_ratio);
+        }
+        let name = node
+            .name
+            .clone()
+            .or_else(|| camera.name.clone())
+            .unwrap_or_else(|| format!("camera {}", index));
+        self.scene.add_camera(&name, settings);
+        Ok(())
+    }
+
+    fn mesh(&mut self, index: usize, matrix: &Mat4) -> Result<(), String> {
+        let mesh = self.document.meshes.get(index).ok_or("doesn't exist")?;
+        // Normals go through the inverse transpose, and mirroring transforms
+        // turn the triangles' winding around.
+        let normal_matrix = matrix
+            .inverse()
+            .ok_or("transform isn't invertible")?
+            .transpose();
+        let mirrored = matrix.determinant() < 0.0;
+        for (number, primitive) in mesh.primitives.iter().enumerate() {
+            if primitive.mode != TRIANGLES {
+                warn!(
+                    "Skipping primitive {} of glTF mesh {}: only triangles are supported",
+                    number, index
+                );
+                continue;
+            }
+            let attribute = |name: &str| primitive.attributes.get(name).copied();
+            let position = attribute("POSITION").ok_or("primitive without positions")?;
+            let positions: Vec<Point3> = self
+                .read(position, "VEC3")?
+                .chunks(3)
+                .map(|p| matrix.transform_point(&Vec3::from(p[0], p[1], p[2])))
+                .collect();
+            let indices = match primitive.indices {
+                Some(accessor) => self
+                    .read(accessor, "SCALAR")?
+                    .into_iter()
+                    .map(|i| i as usize)
+                    .collect(),
+                None => (0..positions.len()).collect::<Vec<_>>(),
+            };
+            let triangles: Vec<[usize; 3]> = indices
+                .chunks_exact(3)
+                .map(|t| {
+                    if mirrored {
+                        [t[0], t[2], t[1]]
+                    } else {
+                        [t[0], t[1], t[2]]
+                    }
+                })
+                .collect();
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let material = match primitive.material {
+                Some(material) => self
+                    .document
+                    .materials
+                    .get(material)
+                    .map(to_material)
+                    .ok_or_else(|| format!("material {} doesn't exist", material))?,
+                None => Material::Lambertian(Lambertian::from(Color::gray(0.8))),
+            };
+            let mut built = Mesh::from(positions, triangles, material)?;
+            if let Some(normal) = attribute("NORMAL") {
+                let normals = self
+                    .read(normal, "VEC3")?
+                    .chunks(3)
+                    .map(|n| normal_matrix.transform_vector(&Vec3::from(n[0], n[1], n[2])))
+                    .collect();
+                built = built.with_normals(normals)?;
+            }
+            if let Some(uv) = attribute("TEXCOORD_0") {
+                // glTF measures v down from the top of the texture.
+                let uvs = self
+                    .read(uv, "VEC2")?
+                    .chunks(2)
+                    .map(|uv| (uv[0], 1.0 - uv[1]))
+                    .collect();
+                built = built.with_uvs(uvs)?;
+            }
+            self.world.add(Box::new(built));
+        }
+        Ok(())
+    }
+
+    // The accessor's elements, which must be of type `kind`, with their
+    // components flattened into floats. Normalized integers are brought into
+    // [0, 1] or [-1, 1]; others keep their values.
+    fn read(&self, index: usize, kind: &str) -> Result<Vec<f64>, String> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| format!("accessor {} doesn't exist", index))?;
+        if accessor.kind != kind {
+            return Err(format!(
+                "accessor {} is {}, not {}",
+                index, accessor.kind, kind
+            ));
+        }
+        if accessor.sparse.is_some() {
+            return Err(format!(
+                "accessor {} is sparse, which isn't supported",
+                index
+            ));
+        }
+        let components = match kind {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            _ => 3,
+        };
+        let Some(view) = accessor.buffer_view else {
+            return Ok(vec![0.0; accessor.count * components]);
+        };
+        let view = self
+            .document
+            .buffer_views
+            .get(view)
+            .ok_or_else(|| format!("buffer view {} doesn't exist", view))?;
+        let buffer = self
+            .buffers
+            .get(view.buffer)
+            .ok_or_else(|| format!("buffer {} doesn't exist", view.buffer))?;
+        let data = buffer
+            .get(view.byte_offset..view.byte_offset + view.byte_length)
+            .ok_or_else(|| format!("accessor {} runs past its buffer", index))?;
+
+        let size = match accessor.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            other => return Err(format!("accessor {} has component type {}", index, other)),
+        };
+        let stride = view.byte_stride.unwrap_or(size * components);
+        let last =
+            accessor.byte_offset + stride * accessor.count.saturating_sub(1) + size * components;
+        if accessor.count > 0 && last > data.len() {
+            return Err(format!("accessor {} runs past its buffer view", index));
+        }
+        let normalized = accessor.normalized;
+        let component = |at: usize| {
+            let bytes = &data[at..at + size];
+            match accessor.component_type {
+                5120 if normalized => (bytes[0] as i8 as f64 / 127.0).max(-1.0),
+                5120 => bytes[0] as i8 as f64,
+                5121 if normalized => bytes[0] as f64 / 255.0,
+                5121 => bytes[0] as f64,
+                5122 | 5123 => {
+                    let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    match (accessor.component_type, normalized) {
+                        (5122, true) => (value as i16 as f64 / 32767.0).max(-1.0),
+                        (5122, false) => value as i16 as f64,
+                        (_, true) => value as f64 / 65535.0,
+                        _ => value as f64,
+                    }
+                }
+                5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            }
+        };
+        Ok((0..accessor.count)
+            .flat_map(|i| {
+                let element = accessor.byte_offset + i * stride;
+                (0..components).map(move |k| element + k * size)
+            })
+            .map(component)
+            .collect())
+    }
+}
+
+fn to_material(material: &PbrMaterial) -> Material {
+    let extensions = &material.extensions;
+    let strength = extensions
+        .emissive_strength
+        .as_ref()
+        .map_or(1.0, |e| e.emissive_strength);
+    let [r, g, b] = material.emissive_factor.map(|c| c * strength);
+    if r > 0.0 || g > 0.0 || b > 0.0 {
+        return Material::DiffuseLight(DiffuseLight::from(Color::from(r, g, b)));
+    }
+    let pbr = &material.pbr_metallic_roughness;
+    let [r, g, b, _] = pbr.base_color_factor;
+    let base = Color::from(r, g, b);
+    let transmission = extensions
+        .transmission
+        .as_ref()
+        .map_or(0.0, |t| t.transmission_factor);
+    if transmission >= 0.5 {
+        let ior = extensions.ior.as_ref().map_or(1.5, |ior| ior.ior);
+        Material::Dieletric(Dieletric::from(ior))
+    } else if pbr.metallic_factor >= 0.5 {
+        Material::Metal(Metal::from(base, pbr.roughness_factor.clamp(0.0, 1.0)))
+    } else {
+        Material::Lambertian(Lambertian::from(base))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::utility::Interval;
+
+    // A GLB file of one triangle in the z = 0 plane, placed by a node 2 units
+    // down -z, with an emissive material, and a camera at the origin turned
+    // to look along +x.
+    fn glb() -> Vec<u8> {
+        let mut binary = Vec::new();
+        for value in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend(value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0] {
+            binary.extend(index.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 44 }],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "materials": [{ "emissiveFactor": [1, 0.5, 0] }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } }],
+            "nodes": [
+                { "mesh": 0, "translation": [0, 0, -2] },
+                { "name": "side", "camera": 0, "rotation": [0, -0.70710678, 0, 0.70710678] }
+            ],
+            "scenes": [{ "nodes": [0, 1] }]
+        }"#;
+        let mut json = json.as_bytes().to_vec();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut glb = Vec::new();
+        glb.extend(GLB_MAGIC);
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(GLB_JSON.to_le_bytes());
+        glb.extend(json);
+        glb.extend((binary.len() as u32).to_le_bytes());
+        glb.extend(GLB_BIN.to_le_bytes());
+        glb.extend(binary);
+        glb
+    }
+
+    #[test]
+    fn imports_meshes_and_cameras() {
+        let mut camera = CameraBuilder::new();
+        let scene = parse(&glb(), Path::new(""), &mut camera).unwrap();
+
+        let ray = Ray::from(Point3::new(), Vec3::from(0.0, 0.0, -1.0));
+        let rec = scene
+            .world
+            .hit(&ray, Interval::from(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-9);
+        assert!(matches!(rec.mat, Material::DiffuseLight(_)));
+
+        let look = camera.look_at.unwrap() - camera.look_from.unwrap();
+        assert!((look - Vec3::from(1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((camera.vfov.unwrap() - 0.5f64.to_degrees()).abs() < 1e-9);
+        assert_eq!(scene.camera("side").unwrap().look_at, camera.look_at);
+
+        assert!(parse(b"{\"meshes\": 3}", Path::new(""), &mut camera).is_err());
+        let mut truncated = glb();
+        truncated.truncate(40);
+        assert!(parse(&truncated, Path::new(""), &mut camera).is_err());
+    }
+
+    #[test]
+    fn decodes_data_uris() {
+        assert_eq!(
+            decode_base64("AACAPw=="),
+            Some(1.0f32.to_le_bytes().to_vec())
+        );
+        assert_eq!(decode_base64("TWFu"), Some(b"Man".to_vec()));
+        assert_eq!(decode_base64("T!Fu"), None);
+    }
+}
@@ -0,0 +1,486 @@
+use crate::aabb::Aabb;
+use crate::background::Background;
+use crate::bvh::Bvh;
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::progress::{ProgressEvent, Tracker};
+use crate::ray::{Point3, DEFAULT_SET};
+use crate::scene::Scene;
+use crate::vec3::*;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = include_str!("gpu.wgsl");
+const WORKGROUP_SIZE: u32 = 8;
+// Spheres per leaf of the uploaded hierarchy.
+const LEAF_SIZE: usize = 2;
+// Paths traced per dispatch, summed over the pixels. Dispatches are kept
+// short so the driver doesn't take the GPU for hung, and so progress can be
+// reported between them.
+const PATHS_PER_DISPATCH: u64 = 1 << 21;
+
+// Material kinds, matching the constants of the shader.
+const LAMBERTIAN: u32 = 0;
+const METAL: u32 = 1;
+const DIELECTRIC: u32 = 2;
+const LIGHT: u32 = 3;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuSphere {
+    center: [f32; 4], // w: radius
+    velocity: [f32; 3],
+    material: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuMaterial {
+    color: [f32; 3],
+    kind: u32,
+    param: f32,
+    _pad: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuNode {
+    min: [f32; 3],
+    first: u32,
+    max: [f32; 3],
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    pixel00: [f32; 4],
+    delta_u: [f32; 4],
+    delta_v: [f32; 4],
+    center: [f32; 4],
+    defocus_u: [f32; 4],
+    defocus_v: [f32; 4],
+    background_top: [f32; 4],
+    background_bottom: [f32; 4],
+    width: u32,
+    height: u32,
+    first_sample: u32,
+    samples: u32,
+    max_depth: u32,
+    seed: u32,
+    node_count: u32,
+    defocus: u32,
+    shutter_open: f32,
+    shutter_close: f32,
+    _pad: [u32; 2],
+}
+
+// Where the camera's rays start and the pixel grid they pass through, as
+// `CameraBuilder::build` sets them up.
+pub(crate) struct View {
+    pub width: i32,
+    pub height: i32,
+    pub samples: i32,
+    pub max_depth: i32,
+    pub center: Point3,
+    pub pixel00: Point3,
+    pub delta_u: Vec3,
+    pub delta_v: Vec3,
+    // Zero without defocus blur.
+    pub defocus_u: Vec3,
+    pub defocus_v: Vec3,
+    pub shutter: (f64, f64),
+}
+
+// A scene flattened into the arrays the shader reads: spheres ordered so
+// every leaf of the hierarchy covers a run of them, their materials, and the
+// hierarchy's nodes.
+#[derive(Default)]
+pub struct GpuScene {
+    spheres: Vec<GpuSphere>,
+    materials: Vec<GpuMaterial>,
+    nodes: Vec<GpuNode>,
+    background: (Color, Color), // Top and bottom of the gradient
+}
+
+impl GpuScene {
+    // Flattens `scene`, or says what in it the GPU can't render.
+    pub fn from(scene: &Scene) -> Result<Self, String> {
+        if !scene.clip_planes.is_empty() {
+            return Err("the GPU doesn't support clip planes".to_string());
+        }
+        if scene.ambient.is_some() {
+            return Err("the GPU doesn't support ambient light".to_string());
+        }
+        if !scene.punctual_lights.is_empty() {
+            return Err("the GPU doesn't support point, directional or spot lights".to_string());
+        }
+        let background = match &scene.background {
+            Background::Solid(color) => (*color, *color),
+            Background::Gradient { top, bottom } => (*top, *bottom),
+            _ => return Err("the GPU supports only solid and gradient backgrounds".to_string()),
+        };
+        let mut gpu = Self {
+            background,
+            ..Self::default()
+        };
+        scene.world.upload(&mut gpu)?;
+        gpu.build_bvh();
+        Ok(gpu)
+    }
+
+    pub fn sphere_count(&self) -> usize {
+        self.spheres.len()
+    }
+
+    // Adds a sphere centered at `center` at ray time 0, moving by `velocity`
+    // per unit of time.
+    pub fn add_sphere(
+        &mut self,
+        center: Point3,
+        velocity: Vec3,
+        radius: f64,
+        material: &Material,
+    ) -> Result<(), String> {
+        let material = Self::material(material)?;
+        self.materials.push(material);
+        self.spheres.push(GpuSphere {
+            center: [
+                center.x() as f32,
+                center.y() as f32,
+                center.z() as f32,
+                radius as f32,
+            ],
+            velocity: to_f32(velocity),
+            material: self.materials.len() as u32 - 1,
+        });
+        Ok(())
+    }
+
+    fn material(material: &Material) -> Result<GpuMaterial, String> {
+        let (color, kind, param) = match material {
+            Material::Lambertian(l) if !l.is_textured() => (l.albedo, LAMBERTIAN, 0.0),
+            Material::Metal(m) => (m.albedo, METAL, m.fuzz),
+            Material::Dieletric(d) => (Color::gray(1.0), DIELECTRIC, d.ir),
+            Material::DiffuseLight(d) => (d.emit, LIGHT, 0.0),
+            Material::Lambertian(_) => {
+                return Err("the GPU doesn't support textured materials".to_string())
+            }
+            other => {
+                return Err(format!(
+                    "the GPU doesn't support {} materials",
+                    other.name()
+                ))
+            }
+        };
+        Ok(GpuMaterial {
+            color: [color.r() as f32, color.g() as f32, color.b() as f32],
+            kind,
+            param: param as f32,
+            _pad: [0.0; 3],
+        })
+    }
+
+    // Builds the hierarchy over the spheres and puts them in its leaf order.
+    fn build_bvh(&mut self) {
+        let bounds = |i: usize| {
+            let [x, y, z, r] = self.spheres[i].center.map(|c| c as f64);
+            let [vx, vy, vz] = self.spheres[i].velocity.map(|c| c as f64);
+            let (center0, center1) = (Point3::from(x, y, z), Point3::from(x + vx, y + vy, z + vz));
+            let rvec = Vec3::from(r, r, r);
+            Aabb::union(
+                &Aabb::from_points(center0 - rvec, center0 + rvec),
+                &Aabb::from_points(center1 - rvec, center1 + rvec),
+            )
+        };
+        let bvh = Bvh::build(self.spheres.len(), LEAF_SIZE, bounds, |_| DEFAULT_SET);
+        self.nodes = bvh
+            .nodes()
+            .map(|(bbox, first, count)| GpuNode {
+                min: [0, 1, 2].map(|axis| bbox.axis_interval(axis).min as f32),
+                first,
+                max: [0, 1, 2].map(|axis| bbox.axis_interval(axis).max as f32),
+                count,
+            })
+            .collect();
+        self.spheres = bvh
+            .order()
+            .iter()
+            .map(|&i| self.spheres[i as usize])
+            .collect();
+    }
+}
+
+fn to_f32(v: Vec3) -> [f32; 3] {
+    [v.x() as f32, v.y() as f32, v.z() as f32]
+}
+
+fn to_f32x4(v: Vec3) -> [f32; 4] {
+    [v.x() as f32, v.y() as f32, v.z() as f32, 0.0]
+}
+
+// Renders `scene` through `view` with a compute shader, reporting a pass
+// after every dispatch. Fails if the scene has anything the GPU can't
+// render, or if there is no GPU to render on.
+pub(crate) fn render(
+    view: &View,
+    scene: &Scene,
+    seed: u64,
+    on_progress: &mut dyn FnMut(&ProgressEvent),
+) -> Result<FrameBuffer, String> {
+    let gpu = GpuScene::from(scene)?;
+    crate::stage_span!("render_gpu", spheres = gpu.sphere_count());
+
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))
+    .ok_or("no GPU adapter found")?;
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("render"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        },
+        None,
+    ))
+    .map_err(|err| err.to_string())?;
+    log::info!("Rendering on {}", adapter.get_info().name);
+
+    // Storage buffers can't be empty, so empty scenes get a dummy element
+    // that `node_count` keeps the shader from reading.
+    let storage = |label, contents: &[u8]| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: if contents.is_empty() {
+                &[0; 32]
+            } else {
+                contents
+            },
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    };
+    let spheres = storage("spheres", bytemuck::cast_slice(&gpu.spheres));
+    let materials = storage("materials", bytemuck::cast_slice(&gpu.materials));
+    let nodes = storage("nodes", bytemuck::cast_slice(&gpu.nodes));
+
+    let pixels = (view.width * view.height) as u64;
+    let accumulator_size = pixels * std::mem::size_of::<[f32; 4]>() as u64;
+    let accumulator = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("accumulator"),
+        size: accumulator_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: accumulator_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("params"),
+        size: std::mem::size_of::<Params>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("path tracer"),
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("path tracer"),
+        layout: None,
+        module: &module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[&uniforms, &spheres, &materials, &nodes, &accumulator]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+    });
+
+    let (top, bottom) = gpu.background;
+    let mut params = Params {
+        pixel00: to_f32x4(view.pixel00),
+        delta_u: to_f32x4(view.delta_u),
+        delta_v: to_f32x4(view.delta_v),
+        center: to_f32x4(view.center),
+        defocus_u: to_f32x4(view.defocus_u),
+        defocus_v: to_f32x4(view.defocus_v),
+        background_top: [top.r() as f32, top.g() as f32, top.b() as f32, 0.0],
+        background_bottom: [bottom.r() as f32, bottom.g() as f32, bottom.b() as f32, 0.0],
+        width: view.width as u32,
+        height: view.height as u32,
+        first_sample: 0,
+        samples: 0,
+        max_depth: view.max_depth.max(0) as u32,
+        seed: (seed ^ (seed >> 32)) as u32,
+        node_count: gpu.nodes.len() as u32,
+        defocus: (view.defocus_u.length_squared() > 0.0) as u32,
+        shutter_open: view.shutter.0 as f32,
+        shutter_close: view.shutter.1 as f32,
+        _pad: [0; 2],
+    };
+
+    let samples = view.samples.max(0) as u32;
+    let batch = (PATHS_PER_DISPATCH / pixels.max(1)).clamp(1, samples.max(1) as u64) as u32;
+    let groups = |n: i32| (n as u32).div_ceil(WORKGROUP_SIZE);
+    let mut progress = Tracker::start(view.width, view.height, view.samples, on_progress);
+    while params.first_sample < samples {
+        params.samples = batch.min(samples - params.first_sample);
+        queue.write_buffer(&uniforms, 0, bytemuck::bytes_of(&params));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups(view.width), groups(view.height), 1);
+        }
+        queue.submit([encoder.finish()]);
+        device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        params.first_sample += params.samples;
+        progress.pass_complete(
+            params.first_sample as i32,
+            view.samples,
+            pixels * params.samples as u64,
+        );
+    }
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(&accumulator, 0, &readback, 0, accumulator_size);
+    queue.submit([encoder.finish()]);
+    let slice = readback.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+    receiver
+        .recv()
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+    let mut buffer = FrameBuffer::new(view.width, view.height);
+    {
+        let data = slice.get_mapped_range();
+        let sums: &[[f32; 4]] = bytemuck::cast_slice(&data);
+        for j in 0..view.height {
+            for i in 0..view.width {
+                let [r, g, b, _] = sums[(j * view.width + i) as usize];
+                let sum = Color::from(r as f64, g as f64, b as f64);
+                if sum.is_finite() {
+                    buffer.add_samples(i, j, sum, samples);
+                } else {
+                    buffer.mark_invalid(i, j);
+                }
+            }
+        }
+    }
+    readback.unmap();
+    for _ in 0..samples {
+        buffer.complete_pass();
+    }
+    progress.finish();
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Dieletric, Lambertian};
+    use crate::sphere::Sphere;
+
+    fn two_spheres() -> Scene {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, -100.5, -1.0),
+            100.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, 0.0, -1.0),
+            0.5,
+            Material::Dieletric(Dieletric::from(1.5)),
+        )));
+        Scene::from(world)
+    }
+
+    #[test]
+    fn flattens_spheres_in_leaf_order() {
+        let gpu = GpuScene::from(&two_spheres()).unwrap();
+        assert_eq!(gpu.sphere_count(), 2);
+        assert!(!gpu.nodes.is_empty());
+        // Every sphere is covered by exactly one leaf.
+        let covered: u32 = gpu.nodes.iter().map(|node| node.count).sum();
+        assert_eq!(covered, 2);
+        for sphere in &gpu.spheres {
+            let material = gpu.materials[sphere.material as usize];
+            let expected = if sphere.center[3] == 0.5 {
+                DIELECTRIC
+            } else {
+                LAMBERTIAN
+            };
+            assert_eq!(material.kind, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_what_the_shader_lacks() {
+        let mut scene = two_spheres();
+        scene.background = Background::Sky(crate::background::Sky::from(Vec3::from(0.0, 1.0, 0.0)));
+        assert!(GpuScene::from(&scene).is_err());
+
+        let mut world = HittableList::default();
+        world.add(Box::new(crate::quad::Quad::from(
+            Point3::new(),
+            Vec3::from(1.0, 0.0, 0.0),
+            Vec3::from(0.0, 1.0, 0.0),
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        assert!(GpuScene::from(&Scene::from(world)).is_err());
+    }
+
+    // Renders on whatever adapter the machine has, if any, and compares the
+    // mean brightness with the CPU's.
+    #[test]
+    fn matches_the_cpu() {
+        let scene = two_spheres();
+        let camera = CameraBuilder {
+            image_width: Some(32),
+            samples_per_pixel: Some(64),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let Ok(gpu) = camera.render_gpu(&scene, &mut |_| {}) else {
+            return;
+        };
+        let cpu = camera.render_to_buffer(&scene);
+        let mean = |buffer: &FrameBuffer| {
+            buffer
+                .to_linear()
+                .iter()
+                .map(|c| c.luminance())
+                .sum::<f64>()
+                / 1024.0
+        };
+        approx::assert_relative_eq!(mean(&gpu), mean(&cpu), max_relative = 0.05);
+    }
+}
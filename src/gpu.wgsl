@@ -0,0 +1,270 @@
+// The path tracer of `Camera::trace`, restricted to what `GpuScene` uploads:
+// spheres with lambertian, metal, dielectric or light materials, under a
+// solid or gradient background. One invocation traces `samples` samples of
+// one pixel and adds their sum to the pixel's accumulator.
+
+struct Params {
+    pixel00: vec4<f32>,
+    delta_u: vec4<f32>,
+    delta_v: vec4<f32>,
+    center: vec4<f32>,
+    defocus_u: vec4<f32>,
+    defocus_v: vec4<f32>,
+    background_top: vec4<f32>,
+    background_bottom: vec4<f32>,
+    width: u32,
+    height: u32,
+    first_sample: u32,
+    samples: u32,
+    max_depth: u32,
+    seed: u32,
+    node_count: u32,
+    defocus: u32,
+    shutter_open: f32,
+    shutter_close: f32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+struct Sphere {
+    center: vec4<f32>, // w: radius
+    velocity: vec3<f32>,
+    material: u32,
+}
+
+const LAMBERTIAN: u32 = 0u;
+const METAL: u32 = 1u;
+const DIELECTRIC: u32 = 2u;
+const LIGHT: u32 = 3u;
+
+struct Material {
+    color: vec3<f32>, // Albedo, or emitted radiance for lights
+    kind: u32,
+    param: f32, // Fuzz of metals, refractive index of dielectrics
+}
+
+// A node of `Bvh`: leaves cover `count` spheres from `first`; interior nodes
+// have `count` 0, their left child right after them and their right at `first`.
+struct Node {
+    min: vec3<f32>,
+    first: u32,
+    max: vec3<f32>,
+    count: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> spheres: array<Sphere>;
+@group(0) @binding(2) var<storage, read> materials: array<Material>;
+@group(0) @binding(3) var<storage, read> nodes: array<Node>;
+@group(0) @binding(4) var<storage, read_write> accumulator: array<vec4<f32>>;
+
+const PI: f32 = 3.14159265358979;
+const T_MIN: f32 = 0.001;
+const T_MAX: f32 = 3.4e38;
+const STACK_SIZE: u32 = 64u;
+
+// PCG hash (Jarzynski and Olano 2020).
+fn hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+var<private> rng_state: u32;
+
+fn next_u32() -> u32 {
+    rng_state = hash(rng_state);
+    return rng_state;
+}
+
+fn random() -> f32 {
+    return f32(next_u32() >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = 1.0 - 2.0 * random();
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    let phi = 2.0 * PI * random();
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn concentric_disk(u: f32, v: f32) -> vec2<f32> {
+    let a = 2.0 * u - 1.0;
+    let b = 2.0 * v - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+    if abs(a) > abs(b) {
+        let theta = PI / 4.0 * (b / a);
+        return a * vec2<f32>(cos(theta), sin(theta));
+    }
+    let theta = PI / 2.0 - PI / 4.0 * (a / b);
+    return b * vec2<f32>(cos(theta), sin(theta));
+}
+
+struct Hit {
+    t: f32,
+    sphere: u32,
+}
+
+// The nearer root of the ray-sphere equation within (T_MIN, t_max), or
+// t_max. Solved from the closest approach to the center, which keeps the
+// precision of single floats on huge spheres such as ground planes.
+fn hit_sphere(sphere: Sphere, origin: vec3<f32>, direction: vec3<f32>, time: f32, t_max: f32) -> f32 {
+    let radius = sphere.center.w;
+    let oc = origin - (sphere.center.xyz + time * sphere.velocity);
+    let b = dot(oc, direction);
+    let qc = oc - b * direction;
+    let h = radius * radius - dot(qc, qc);
+    if h < 0.0 {
+        return t_max;
+    }
+    let root = sqrt(h);
+    var t = -b - root;
+    if t <= T_MIN || t >= t_max {
+        t = -b + root;
+        if t <= T_MIN || t >= t_max {
+            return t_max;
+        }
+    }
+    return t;
+}
+
+fn hit_box(node: Node, origin: vec3<f32>, inverse: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.min - origin) * inverse;
+    let t1 = (node.max - origin) * inverse;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), T_MIN));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+// The closest sphere along the ray, found by walking the hierarchy with a
+// stack. `hit.t` is T_MAX on a miss.
+fn hit_world(origin: vec3<f32>, direction: vec3<f32>, time: f32) -> Hit {
+    var hit = Hit(T_MAX, 0u);
+    if params.node_count == 0u {
+        return hit;
+    }
+    let inverse = 1.0 / direction;
+    var stack: array<u32, STACK_SIZE>;
+    var top = 1u;
+    stack[0] = 0u;
+    while top > 0u {
+        top -= 1u;
+        let index = stack[top];
+        let node = nodes[index];
+        if !hit_box(node, origin, inverse, hit.t) {
+            continue;
+        }
+        if node.count > 0u {
+            for (var k = node.first; k < node.first + node.count; k++) {
+                let t = hit_sphere(spheres[k], origin, direction, time, hit.t);
+                if t < hit.t {
+                    hit = Hit(t, k);
+                }
+            }
+        } else if top + 2u <= STACK_SIZE {
+            stack[top] = node.first;
+            stack[top + 1u] = index + 1u;
+            top += 2u;
+        }
+    }
+    return hit;
+}
+
+fn background(direction: vec3<f32>) -> vec3<f32> {
+    let a = 0.5 * (direction.y + 1.0);
+    return (1.0 - a) * params.background_bottom.xyz + a * params.background_top.xyz;
+}
+
+fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
+    var r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+fn ray_color(start: vec3<f32>, first_direction: vec3<f32>, time: f32) -> vec3<f32> {
+    var radiance = vec3<f32>(0.0);
+    var throughput = vec3<f32>(1.0);
+    var origin = start;
+    var direction = first_direction;
+    for (var bounce = 0u; bounce < params.max_depth; bounce++) {
+        let hit = hit_world(origin, direction, time);
+        if hit.t >= T_MAX {
+            radiance += throughput * background(direction);
+            break;
+        }
+        let sphere = spheres[hit.sphere];
+        let material = materials[sphere.material];
+        let p = origin + hit.t * direction;
+        let outward = (p - (sphere.center.xyz + time * sphere.velocity)) / sphere.center.w;
+        let front_face = dot(direction, outward) < 0.0;
+        let normal = select(-outward, outward, front_face);
+
+        var scattered = vec3<f32>(0.0);
+        var absorbed = false;
+        switch material.kind {
+            case LAMBERTIAN: {
+                scattered = normal + random_unit_vector();
+                if all(abs(scattered) < vec3<f32>(1e-8)) {
+                    scattered = normal;
+                }
+                throughput *= material.color;
+            }
+            case METAL: {
+                scattered = reflect(direction, normal) + material.param * random_unit_vector();
+                absorbed = dot(scattered, normal) <= 0.0;
+                throughput *= material.color;
+            }
+            case DIELECTRIC: {
+                let ratio = select(material.param, 1.0 / material.param, front_face);
+                let cos_theta = min(dot(-direction, normal), 1.0);
+                let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+                let u = random();
+                if ratio * sin_theta > 1.0 || reflectance(cos_theta, ratio) > u {
+                    scattered = reflect(direction, normal);
+                } else {
+                    scattered = refract(direction, normal, ratio);
+                }
+            }
+            case LIGHT, default: {
+                radiance += throughput * material.color;
+                absorbed = true;
+            }
+        }
+        if absorbed {
+            break;
+        }
+        origin = p;
+        direction = normalize(scattered);
+    }
+    return radiance;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+    let pixel = id.y * params.width + id.x;
+    var sum = vec3<f32>(0.0);
+    for (var s = params.first_sample; s < params.first_sample + params.samples; s++) {
+        rng_state = hash(pixel ^ hash(s ^ hash(params.seed)));
+
+        let pixel_center = params.pixel00.xyz + f32(id.x) * params.delta_u.xyz
+            + f32(id.y) * params.delta_v.xyz;
+        let pixel_sample = pixel_center + (random() - 0.5) * params.delta_u.xyz
+            + (random() - 0.5) * params.delta_v.xyz;
+        var origin = params.center.xyz;
+        if params.defocus != 0u {
+            let disk = concentric_disk(random(), random());
+            origin += disk.x * params.defocus_u.xyz + disk.y * params.defocus_v.xyz;
+        }
+        var time = params.shutter_open;
+        if params.shutter_close > params.shutter_open {
+            time += random() * (params.shutter_close - params.shutter_open);
+        }
+        sum += ray_color(origin, normalize(pixel_sample - origin), time);
+    }
+    accumulator[pixel] += vec4<f32>(sum, 0.0);
+}
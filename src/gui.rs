@@ -0,0 +1,326 @@
+use crate::aov::Aovs;
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::Color;
+use crate::control::RenderControl;
+use crate::denoise::Denoiser;
+use crate::framebuffer::FrameBuffer;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::scene::Scene;
+use crate::utility::{Interval, INFINITY};
+use crate::vec3::Vec3;
+
+use eframe::egui;
+
+// Preview window with an egui side panel for live parameter tweaking. Clicking
+// the image selects the material under the cursor for editing. Every change
+// restarts accumulation.
+pub fn run(camera: Camera, scene: Scene) -> eframe::Result {
+    let options = eframe::NativeOptions::default();
+    eframe::run_native(
+        "render",
+        options,
+        Box::new(|_cc| Ok(Box::new(App::new(camera, scene)))),
+    )
+}
+
+struct App {
+    // The panel edits the settings, and the camera is rebuilt from them.
+    settings: CameraBuilder,
+    camera: Camera,
+    // Why the settings don't make a camera, until they are fixed.
+    error: Option<String>,
+    scene: Scene,
+    buffer: FrameBuffer,
+    texture: Option<egui::TextureHandle>,
+    selected: Option<usize>,
+    control: RenderControl,
+    // Denoise the displayed image, guided by AOVs traced once per restart.
+    denoise: bool,
+    aovs: Option<Aovs>,
+}
+
+impl App {
+    fn new(camera: Camera, scene: Scene) -> Self {
+        let buffer = camera.frame_buffer();
+        Self {
+            settings: camera.to_builder(),
+            camera,
+            error: None,
+            scene,
+            buffer,
+            texture: None,
+            selected: None,
+            control: RenderControl::new(),
+            denoise: false,
+            aovs: None,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.control = RenderControl::new();
+        self.aovs = None;
+        self.rebuild();
+        let (width, height) = (self.camera.image_width(), self.camera.image_height());
+        if width != self.buffer.width() || height != self.buffer.height() {
+            self.buffer = self.camera.frame_buffer();
+        } else {
+            self.buffer.clear();
+        }
+    }
+
+    // Takes up the edited settings, if they make a camera; otherwise the
+    // last good camera stays.
+    fn rebuild(&mut self) {
+        match self.settings.build() {
+            Ok(camera) => {
+                self.camera = camera;
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let camera = &mut self.settings;
+
+        ui.heading("Camera");
+        changed |= ui
+            .add(egui::Slider::new(camera.vfov.as_mut().unwrap(), 1.0..=150.0).text("vfov"))
+            .changed();
+        changed |= vec3_edit(ui, "look from", camera.look_from.as_mut().unwrap());
+        changed |= vec3_edit(ui, "look at", camera.look_at.as_mut().unwrap());
+        changed |= ui
+            .add(
+                egui::Slider::new(camera.defocus_angle.as_mut().unwrap(), 0.0..=10.0)
+                    .text("defocus angle"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                egui::Slider::new(camera.focus_dist.as_mut().unwrap(), 0.1..=50.0)
+                    .text("focus distance"),
+            )
+            .changed();
+
+        ui.separator();
+        ui.heading("Sampling");
+        // Raising the sample target just keeps accumulating.
+        let resampled = ui
+            .add(
+                egui::Slider::new(camera.samples_per_pixel.as_mut().unwrap(), 1..=2000)
+                    .logarithmic(true)
+                    .text("samples per pixel"),
+            )
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(camera.max_depth.as_mut().unwrap(), 1..=100).text("max depth"))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(camera.branches.as_mut().unwrap(), 1..=16).text("branches"))
+            .changed();
+        if resampled && !changed {
+            self.rebuild();
+        }
+        ui.label(format!(
+            "{} / {} spp",
+            self.buffer.samples(),
+            self.camera.samples_per_pixel()
+        ));
+        ui.checkbox(&mut self.denoise, "denoise");
+        ui.horizontal(|ui| {
+            let control = &self.control;
+            if control.is_paused() {
+                if ui.button("Resume").clicked() {
+                    control.resume();
+                }
+            } else if ui.button("Pause").clicked() {
+                control.pause();
+            }
+            // Stopping keeps the image; any change starts a new render.
+            if ui
+                .add_enabled(!control.is_cancelled(), egui::Button::new("Stop"))
+                .clicked()
+            {
+                control.cancel();
+            }
+        });
+
+        if let Some(err) = &self.error {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+
+        ui.separator();
+        ui.heading("Material");
+        match self.selected {
+            Some(index) => {
+                let mut materials = self.scene.world.materials_mut();
+                changed |= material_edit(ui, materials[index]);
+            }
+            None => {
+                ui.label("Click the image to select a material.");
+            }
+        }
+
+        changed
+    }
+
+    fn pick(&mut self, i: i32, j: i32) {
+        let r = self.camera.primary_ray(i, j);
+        let hit = self.scene.hit(&r, Interval::from(0.001, INFINITY));
+        let Some(target) = hit.map(|rec| rec.mat as *const Material) else {
+            self.selected = None;
+            return;
+        };
+        self.selected = self
+            .scene
+            .world
+            .materials_mut()
+            .iter()
+            .position(|m| std::ptr::eq(&**m, target));
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut changed = false;
+        egui::SidePanel::left("controls").show(ctx, |ui| {
+            changed = self.controls(ui);
+        });
+        if changed {
+            self.restart();
+        }
+
+        // The pass runs on the UI thread, so check for a pause here rather
+        // than letting the pass block on it.
+        let running = !self.control.is_paused() && !self.control.is_cancelled();
+        if running && self.buffer.samples() < self.camera.samples_per_pixel() {
+            self.camera
+                .render_pass_controlled(&self.scene, &mut self.buffer, &self.control);
+            ctx.request_repaint();
+        }
+
+        let size = [self.buffer.width() as usize, self.buffer.height() as usize];
+        let shown = if self.denoise {
+            let aovs = self
+                .aovs
+                .get_or_insert_with(|| Aovs::render(&self.camera, &self.scene));
+            Denoiser::default().denoise(&self.buffer, aovs).to_rgb_u32()
+        } else {
+            self.buffer.to_rgb_u32()
+        };
+        let pixels: Vec<u8> = shown
+            .iter()
+            .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+            .collect();
+        let image = egui::ColorImage::from_rgb(size, &pixels);
+        match &mut self.texture {
+            Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+            None => {
+                self.texture =
+                    Some(ctx.load_texture("render", image, egui::TextureOptions::NEAREST))
+            }
+        }
+
+        let mut clicked = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(texture) = &self.texture {
+                let response = ui.add(egui::Image::new(texture).sense(egui::Sense::click()));
+                if let Some(pos) = response
+                    .interact_pointer_pos()
+                    .filter(|_| response.clicked())
+                {
+                    let local = pos - response.rect.min;
+                    let scale = size[0] as f32 / response.rect.width();
+                    clicked = Some(((local.x * scale) as i32, (local.y * scale) as i32));
+                }
+            }
+        });
+        if let Some((i, j)) = clicked {
+            self.pick(i, j);
+        }
+    }
+}
+
+fn vec3_edit(ui: &mut egui::Ui, label: &str, v: &mut Vec3) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for k in 0..3 {
+            changed |= ui
+                .add(egui::DragValue::new(&mut v[k]).speed(0.05))
+                .changed();
+        }
+    });
+    changed
+}
+
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut Color) -> bool {
+    let mut rgb = [color.r() as f32, color.g() as f32, color.b() as f32];
+    let changed = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            ui.color_edit_button_rgb(&mut rgb).changed()
+        })
+        .inner;
+    if changed {
+        *color = Color::from(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64);
+    }
+    changed
+}
+
+fn material_edit(ui: &mut egui::Ui, material: &mut Material) -> bool {
+    match material {
+        Material::Lambertian(l) if l.is_textured() => {
+            ui.label("Lambertian (edit the texture in the scene file)");
+            false
+        }
+        Material::Lambertian(l) => {
+            ui.label("Lambertian");
+            color_edit(ui, "albedo", &mut l.albedo)
+        }
+        Material::Metal(m) => {
+            ui.label("Metal");
+            let mut changed = color_edit(ui, "albedo", &mut m.albedo);
+            changed |= ui
+                .add(egui::Slider::new(&mut m.fuzz, 0.0..=1.0).text("roughness"))
+                .changed();
+            changed
+        }
+        Material::Dieletric(d) => {
+            ui.label("Dielectric");
+            ui.add(egui::Slider::new(&mut d.ir, 1.0..=3.0).text("index of refraction"))
+                .changed()
+        }
+        Material::Procedural(_) => {
+            ui.label("Procedural (edit the expression in the scene file)");
+            false
+        }
+        Material::Banded(b) => {
+            let mut changed = ui
+                .add(egui::Slider::new(&mut b.emission, 0.0..=10.0).text("band emission"))
+                .changed();
+            changed |= material_edit(ui, &mut b.base);
+            changed
+        }
+        Material::Wireframe(w) => {
+            ui.label("Wireframe");
+            let mut changed = color_edit(ui, "face", &mut w.face);
+            changed |= color_edit(ui, "edge", &mut w.edge);
+            changed
+        }
+        Material::DiffuseLight(_) => {
+            ui.label("Diffuse light (edit the emission in the scene file)");
+            false
+        }
+        Material::Isotropic(i) => {
+            ui.label("Isotropic medium");
+            color_edit(ui, "albedo", &mut i.albedo)
+        }
+        Material::Custom(_) => {
+            ui.label("Custom material");
+            false
+        }
+    }
+}
@@ -1,14 +1,32 @@
+use crate::aabb::Aabb;
+use crate::info::SceneInfo;
 use crate::material::Material;
-use crate::ray::{Point3, Ray};
+use crate::ray::{Point3, Ray, DEFAULT_SET};
+use crate::sampler::Sampler;
 use crate::utility::Interval;
 use crate::vec3::*;
 
+use std::sync::Arc;
+
 pub struct HitRecord<'material> {
     pub p: Point3,
-    pub normal: Vec3,
+    pub normal: Vec3, // Shading normal, facing against the ray
+    // The surface's true normal, also facing against the ray. It differs from
+    // `normal` only where shading normals are interpolated, as on meshes.
+    pub geometric_normal: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    // Surface derivatives with respect to the texture coordinates. dp/du and
+    // dp/dv span the tangent plane; dn/du and dn/dv describe how the outward
+    // normal bends across it (zero for flat surfaces).
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub dndu: Vec3,
+    pub dndv: Vec3,
     pub front_face: bool,
     pub mat: &'material Material,
+    pub trace_set: u32, // Trace sets rays scattered from here can hit
 }
 
 impl<'material> HitRecord<'material> {
@@ -21,9 +39,119 @@ impl<'material> HitRecord<'material> {
         } else {
             -*outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
+
+    // Whether anything blocks the ray within `ray_t`. Unlike `hit`, this may
+    // stop at the first intersection found and skips building a record, which
+    // makes it the cheaper query for shadow rays.
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.hit(r, ray_t).is_some()
+    }
+
+    // A box enclosing the whole object, for acceleration structures.
+    fn bounding_box(&self) -> Aabb;
+
+    // Trace sets the object belongs to. Groups report the union of their
+    // members', so traversal can skip a whole group a ray cannot hit.
+    fn mask(&self) -> u32 {
+        DEFAULT_SET
+    }
+
+    // Whether `p` lies inside the solid the object encloses, for capping
+    // where a clipping plane cuts through it. Open surfaces enclose nothing.
+    fn contains(&self, _p: Point3) -> bool {
+        false
+    }
+
+    // The point and outward normal where the surface has texture coordinates
+    // (u, v), for baking into textures. None without a UV layout.
+    fn surface_at(&self, _u: f64, _v: f64) -> Option<(Point3, Vec3)> {
+        None
+    }
+
+    // Density, per unit solid angle, with which `random` picks `direction`
+    // from `origin`: 0 for directions that miss the object, and for objects
+    // that can't be sampled as lights.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // A direction from `origin` towards a random point of the object, for
+    // sampling lights.
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::from(1.0, 0.0, 0.0)
+    }
+
+    // Mutable access to the materials of this object, for live editing.
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        Vec::new()
+    }
+
+    // Adds what the object is made of to `info`, for `render info`.
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("other", std::mem::size_of_val(self));
+    }
+
+    // Adds the object to `scene` for rendering on the GPU, or says why it
+    // can't be.
+    #[cfg(feature = "gpu")]
+    fn upload(&self, _scene: &mut crate::gpu::GpuScene) -> Result<(), String> {
+        Err("the GPU renders only spheres".to_string())
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
+// Shared objects, so many instances can reference one prototype.
+impl Hittable for Arc<dyn Hittable> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(r, ray_t)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.as_ref().is_occluded(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.as_ref().bounding_box()
+    }
+
+    fn mask(&self) -> u32 {
+        self.as_ref().mask()
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.as_ref().contains(p)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        self.as_ref().surface_at(u, v)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.as_ref().random(origin, sampler)
+    }
+
+    // A prototype's materials can only be edited while nothing else shares it.
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        Arc::get_mut(self).map_or(Vec::new(), |object| object.materials_mut())
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        if info.first_sight(Arc::as_ptr(self) as *const () as usize) {
+            self.as_ref().describe(info);
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    fn upload(&self, scene: &mut crate::gpu::GpuScene) -> Result<(), String> {
+        self.as_ref().upload(scene)
+    }
 }
@@ -1,39 +1,178 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
 use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::Point3;
+use crate::sampler::Sampler;
 use crate::utility::Interval;
+use crate::vec3::Vec3;
+
+use std::sync::OnceLock;
+
+// Lists this short are scanned; longer ones build a hierarchy over their
+// objects the first time a ray is traced through them.
+const BVH_THRESHOLD: usize = 4;
+const LEAF_SIZE: usize = 2;
 
 #[derive(Default)]
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
+    mask: u32,          // Union of the objects' trace sets
+    bvh: OnceLock<Bvh>, // Rebuilt after objects are added
 }
 
 impl HittableList {
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.bbox = Aabb::union(&self.bbox, &object.bounding_box());
+        self.mask |= object.mask();
         self.objects.push(object);
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // The object at `index`, in order of `add`.
+    pub fn get(&self, index: usize) -> Option<&dyn Hittable> {
+        self.objects.get(index).map(|obj| obj.as_ref())
+    }
+
+    // The hierarchy over the objects, built on first use; None for short lists.
+    pub fn bvh(&self) -> Option<&Bvh> {
+        if self.objects.len() < BVH_THRESHOLD {
+            return None;
+        }
+        Some(self.bvh.get_or_init(|| {
+            Bvh::build(
+                self.objects.len(),
+                LEAF_SIZE,
+                |i| self.objects[i].bounding_box(),
+                |i| self.objects[i].mask(),
+            )
+        }))
+    }
+
+    // Like `hit`, but also returns the index (in order of `add`) of the object hit.
+    pub fn hit_object(
+        &self,
+        r: &crate::ray::Ray,
+        ray_t: Interval,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut hit_anything = None;
+        let mut closest_so_far = ray_t.max;
+        // Tests one object, returning the distance to the closest hit so far.
+        let mut visit = |index: usize| {
+            let obj = &self.objects[index];
+            if obj.mask() & r.mask() != 0 {
+                if let Some(rec) = obj.hit(r, Interval::from(ray_t.min, closest_so_far)) {
+                    closest_so_far = rec.t;
+                    hit_anything = Some((index, rec));
+                }
+            }
+            closest_so_far
+        };
+
+        match self.bvh() {
+            Some(bvh) => bvh.traverse(r, ray_t, |index, _| Some(visit(index))),
+            None => (0..self.objects.len()).for_each(|index| {
+                visit(index);
+            }),
+        }
+        hit_anything
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bbox = Aabb::EMPTY;
+        self.mask = 0;
+        self.bvh = OnceLock::new();
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: Interval) -> Option<HitRecord> {
-        let mut hit_anything = None;
-        let mut closest_so_far = ray_t.max;
+    fn hit(&self, r: &crate::ray::Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.hit_object(r, ray_t).map(|(_, rec)| rec)
+    }
 
-        for obj in self.objects.iter() {
-            if let Some(rec) = obj.hit(
-                r,
-                Interval {
-                    min: ray_t.min,
-                    max: closest_so_far,
-                },
-            ) {
-                closest_so_far = rec.t;
-                hit_anything = Some(rec);
-            }
+    fn is_occluded(&self, r: &crate::ray::Ray, ray_t: Interval) -> bool {
+        let blocks = |index: usize| {
+            let obj = &self.objects[index];
+            obj.mask() & r.mask() != 0 && obj.is_occluded(r, ray_t)
+        };
+        let Some(bvh) = self.bvh() else {
+            return (0..self.objects.len()).any(blocks);
+        };
+        let mut occluded = false;
+        bvh.traverse(r, ray_t, |index, _| {
+            occluded = blocks(index);
+            (!occluded).then_some(ray_t.max)
+        });
+        occluded
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.objects.iter().any(|obj| obj.contains(p))
+    }
+
+    // Sampling picks one of the objects at random, so the density is the
+    // average of theirs.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
         }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|obj| obj.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
 
-        hit_anything
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let count = self.objects.len();
+        if count == 0 {
+            return Vec3::from(1.0, 0.0, 0.0);
+        }
+        let index = ((sampler.get_1d() * count as f64) as usize).min(count - 1);
+        self.objects[index].random(origin, sampler)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.objects
+            .iter_mut()
+            .flat_map(|obj| obj.materials_mut())
+            .collect()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.memory += std::mem::size_of::<Self>()
+            + self.objects.capacity() * std::mem::size_of::<Box<dyn Hittable>>();
+        if let Some(bvh) = self.bvh() {
+            info.add_bvh(bvh.node_count(), bvh.depth());
+            info.memory += bvh.memory();
+        }
+        for obj in &self.objects {
+            obj.describe(info);
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    fn upload(&self, scene: &mut crate::gpu::GpuScene) -> Result<(), String> {
+        self.objects.iter().try_for_each(|obj| obj.upload(scene))
     }
 }
@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::background::Background;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::scene::Scene;
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+
+// What a scene is made of, gathered without rendering it, to find out why a
+// scene is slow or large. Objects describe themselves through
+// `Hittable::describe`; objects shared between instances are counted once.
+#[derive(Default)]
+pub struct SceneInfo {
+    pub objects: usize, // Top-level objects in the world
+    pub primitives: BTreeMap<&'static str, usize>,
+    pub triangles: usize,
+    pub instances: usize,
+    pub materials: BTreeMap<&'static str, usize>, // Uses of each kind of material
+    pub bounds: Aabb,
+    pub lights: usize, // The sky and objects made of light
+    pub memory: usize, // Estimated bytes held by the world
+    pub mapped: usize, // Bytes of memory-mapped files, paged in on demand
+    pub bvh_nodes: usize,
+    pub bvh_depth: usize, // Of the deepest hierarchy
+    shared: HashSet<usize>,
+}
+
+impl SceneInfo {
+    pub fn from(scene: &Scene) -> Self {
+        let mut info = Self {
+            objects: scene.world.len(),
+            bounds: scene.world.bounding_box(),
+            lights: scene.lights.len(),
+            ..Self::default()
+        };
+        // The procedural sky has a sun.
+        if matches!(scene.background, Background::Sky(_)) {
+            info.lights += 1;
+        }
+        scene.world.describe(&mut info);
+        info
+    }
+
+    pub fn add_primitive(&mut self, kind: &'static str, bytes: usize) {
+        *self.primitives.entry(kind).or_default() += 1;
+        self.memory += bytes;
+    }
+
+    pub fn add_material(&mut self, material: &Material) {
+        let kind = match material {
+            Material::Lambertian(_) => "lambertian",
+            Material::Metal(_) => "metal",
+            Material::Dieletric(_) => "dielectric",
+            Material::Procedural(_) => "procedural",
+            Material::Banded(_) => "banded",
+            Material::Wireframe(_) => "wireframe",
+            Material::DiffuseLight(_) => {
+                self.lights += 1;
+                "diffuse light"
+            }
+            Material::Isotropic(_) => "isotropic",
+            Material::Custom(_) => "custom",
+        };
+        *self.materials.entry(kind).or_default() += 1;
+    }
+
+    pub fn add_bvh(&mut self, nodes: usize, depth: usize) {
+        self.bvh_nodes += nodes;
+        self.bvh_depth = self.bvh_depth.max(depth);
+    }
+
+    // Whether the object at `address` is seen for the first time, for
+    // objects shared between several instances.
+    pub fn first_sight(&mut self, address: usize) -> bool {
+        self.shared.insert(address)
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let counts = |map: &BTreeMap<&str, usize>| {
+            if map.is_empty() {
+                return "none".to_string();
+            }
+            let entries: Vec<String> = map.iter().map(|(k, n)| format!("{} {}", n, k)).collect();
+            entries.join(", ")
+        };
+        let b = &self.bounds;
+        writeln!(out, "objects:     {}", self.objects)?;
+        writeln!(out, "primitives:  {}", counts(&self.primitives))?;
+        writeln!(out, "triangles:   {}", self.triangles)?;
+        writeln!(out, "instances:   {}", self.instances)?;
+        writeln!(out, "materials:   {}", counts(&self.materials))?;
+        writeln!(out, "lights:      {}", self.lights)?;
+        writeln!(
+            out,
+            "bounds:      ({}, {}, {}) to ({}, {}, {})",
+            b.x.min, b.y.min, b.z.min, b.x.max, b.y.max, b.z.max
+        )?;
+        writeln!(out, "memory:      {}", megabytes(self.memory))?;
+        if self.mapped > 0 {
+            writeln!(out, "mapped:      {}", megabytes(self.mapped))?;
+        }
+        writeln!(
+            out,
+            "bvh:         {} nodes, depth {}",
+            self.bvh_nodes, self.bvh_depth
+        )
+    }
+}
+
+fn megabytes(bytes: usize) -> String {
+    format!("{:.2} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::mat4::Mat4;
+    use crate::material::Lambertian;
+    use crate::mesh::Mesh;
+    use crate::ray::Point3;
+    use crate::sphere::Sphere;
+    use crate::transform::Transform;
+    use crate::vec3::Vec3;
+    use std::sync::Arc;
+
+    #[test]
+    fn counts_shared_prototypes_once() {
+        let material = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let triangle = Mesh::from(
+            vec![
+                Point3::new(),
+                Point3::from(1.0, 0.0, 0.0),
+                Point3::from(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+            material.clone(),
+        )
+        .unwrap();
+        let prototype: Arc<dyn Hittable> = Arc::new(triangle);
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(Point3::new(), 1.0, material)));
+        for x in 0..3 {
+            let offset = Mat4::translation(&Vec3::from(x as f64 * 2.0, 0.0, 0.0));
+            world.add(Box::new(
+                Transform::from(Box::new(prototype.clone()), offset).unwrap(),
+            ));
+        }
+
+        let info = SceneInfo::from(&Scene::from(world));
+        assert_eq!(info.objects, 4);
+        assert_eq!(info.instances, 3);
+        assert_eq!(info.triangles, 1);
+        assert_eq!(info.primitives.get("sphere"), Some(&1));
+        assert_eq!(info.materials.get("lambertian"), Some(&2));
+        assert!(info.memory > 0);
+        let mut report = Vec::new();
+        info.write(&mut report).unwrap();
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .contains("triangles:   1"));
+    }
+}
@@ -1,9 +1,100 @@
+pub mod aabb;
+pub mod ambient;
+pub mod animation;
+pub mod aov;
+pub mod assets;
+pub mod background;
+pub mod bake;
+pub mod band;
+pub mod bluenoise;
+pub mod bvh;
 pub mod camera;
+pub mod capsule;
+pub mod checkpoint;
+pub mod clip;
 pub mod color;
+pub mod cone;
+pub mod control;
+pub mod cuboid;
+pub mod cylinder;
+pub mod denoise;
+pub mod digest;
+pub mod disk;
+#[cfg(feature = "distributed")]
+pub mod distributed;
+pub mod error;
+pub mod expr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod framebuffer;
+#[cfg(feature = "serde")]
+pub mod gltf;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "gui")]
+pub mod gui;
 pub mod hittable;
 pub mod hittable_list;
+pub mod info;
+pub mod lidar;
+pub mod light;
+pub mod lod;
+pub mod lsystem;
+#[cfg(feature = "mmap")]
+pub mod mapped;
+pub mod masked;
+pub mod mat4;
 pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod mode;
+pub mod obj;
+pub mod ocean;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod plane;
+pub mod ply;
+pub mod preset;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod probe;
+pub mod profile;
+pub mod progress;
+pub mod quad;
+pub mod quat;
+pub mod queue;
 pub mod ray;
+pub mod registry;
+pub mod sampler;
+pub mod scatter;
+pub mod scene;
+#[cfg(feature = "serde")]
+pub mod scene_file;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shard;
 pub mod sphere;
+pub mod stats;
+pub mod stl;
+pub mod temporal;
+pub mod tev;
+pub mod texture;
+pub mod tiles;
+pub mod toon;
+pub mod trace;
+pub mod transform;
+pub mod triangle;
 pub mod utility;
 pub mod vec3;
+pub mod video;
+pub mod watch;
+#[cfg(feature = "web")]
+pub mod web;
+
+pub use camera::{Camera, CameraBuilder};
+pub use error::Error;
+pub use framebuffer::FrameBuffer;
+pub use scene::Scene;
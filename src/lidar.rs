@@ -0,0 +1,204 @@
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::ray::{Point3, Ray};
+use crate::scene::Scene;
+use crate::utility::{degrees_to_radians, random, Interval, PI};
+use crate::vec3::*;
+
+use std::io::{self, Write};
+
+// Depth-sensor simulation: fires rays in a scan pattern and records the hit
+// points as a point cloud instead of shading them.
+
+pub enum ScanPattern {
+    // A spinning multi-beam sensor: `channels` beams spread evenly over the
+    // elevation range (degrees), each sampled at `steps` azimuths per turn
+    // around the Y axis.
+    Rotating {
+        channels: u32,
+        steps: u32,
+        min_elevation: f64,
+        max_elevation: f64,
+    },
+    // One ray through the center of every pixel of a camera, like a
+    // time-of-flight depth camera. The rays leave from the camera, which
+    // belongs at the sensor's origin.
+    Grid(Box<Camera>),
+}
+
+pub struct Sensor {
+    pub origin: Point3,
+    pub pattern: ScanPattern,
+    pub max_range: f64,
+    pub range_noise: f64, // Standard deviation of Gaussian noise added to each distance
+}
+
+impl Sensor {
+    // A 16-beam spinning sensor with a 30 degree vertical field of view.
+    pub fn rotating(origin: Point3) -> Self {
+        Self {
+            origin,
+            pattern: ScanPattern::Rotating {
+                channels: 16,
+                steps: 1800,
+                min_elevation: -15.0,
+                max_elevation: 15.0,
+            },
+            max_range: 100.0,
+            range_noise: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LidarPoint {
+    pub p: Point3,
+    pub distance: f64,
+    pub intensity: f64, // Surface reflectance times the cosine of incidence, in [0,1]
+    pub channel: u32,   // Beam index, or image row for grid scans
+}
+
+pub fn scan(scene: &Scene, sensor: &Sensor) -> Vec<LidarPoint> {
+    let mut points = Vec::new();
+    let mut fire = |direction: Vec3, channel: u32| {
+        let direction = unit_vector(direction);
+        let ray = Ray::from(sensor.origin, direction);
+        let Some(rec) = scene
+            .world
+            .hit(&ray, Interval::from(0.001, sensor.max_range))
+        else {
+            return;
+        };
+
+        let cos_incidence = dot(&rec.normal, &-direction).abs();
+        let albedo = rec.mat.albedo(&rec);
+        let reflectance = albedo.average();
+        let distance = rec.t + sensor.range_noise * gaussian();
+        points.push(LidarPoint {
+            p: sensor.origin + distance * direction,
+            distance,
+            intensity: (reflectance * cos_incidence).clamp(0.0, 1.0),
+            channel,
+        });
+    };
+
+    match &sensor.pattern {
+        ScanPattern::Rotating {
+            channels,
+            steps,
+            min_elevation,
+            max_elevation,
+        } => {
+            for step in 0..*steps {
+                let azimuth = 2.0 * PI * step as f64 / *steps as f64;
+                for channel in 0..*channels {
+                    let elevation = if *channels > 1 {
+                        let a = channel as f64 / (*channels - 1) as f64;
+                        min_elevation + a * (max_elevation - min_elevation)
+                    } else {
+                        0.5 * (min_elevation + max_elevation)
+                    };
+                    let elevation = degrees_to_radians(elevation);
+                    let direction = Vec3::from(
+                        elevation.cos() * azimuth.cos(),
+                        elevation.sin(),
+                        elevation.cos() * azimuth.sin(),
+                    );
+                    fire(direction, channel);
+                }
+            }
+        }
+        ScanPattern::Grid(camera) => {
+            for j in 0..camera.image_height() {
+                for i in 0..camera.image_width() {
+                    fire(camera.primary_ray(i, j).direction(), j as u32);
+                }
+            }
+        }
+    }
+    points
+}
+
+// Standard normal sample by the Box-Muller transform.
+fn gaussian() -> f64 {
+    let u1 = 1.0 - random(); // in (0, 1], keeping ln finite
+    let u2 = random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+// ASCII PLY with per-vertex distance, intensity and channel properties.
+pub fn write_ply(out: &mut impl Write, points: &[LidarPoint]) -> io::Result<()> {
+    writeln!(out, "ply\nformat ascii 1.0")?;
+    writeln!(out, "element vertex {}", points.len())?;
+    writeln!(
+        out,
+        "property float x\nproperty float y\nproperty float z\n\
+         property float distance\nproperty float intensity\nproperty uint channel\nend_header"
+    )?;
+    for point in points {
+        writeln!(
+            out,
+            "{} {} {} {} {} {}",
+            point.p.x(),
+            point.p.y(),
+            point.p.z(),
+            point.distance,
+            point.intensity,
+            point.channel
+        )?;
+    }
+    Ok(())
+}
+
+// Plain "x y z intensity" lines.
+pub fn write_xyz(out: &mut impl Write, points: &[LidarPoint]) -> io::Result<()> {
+    for point in points {
+        writeln!(
+            out,
+            "{} {} {} {}",
+            point.p.x(),
+            point.p.y(),
+            point.p.z(),
+            point.intensity
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn rotating_scan_inside_sphere() {
+        // Every beam hits the inside of a sphere of radius 5 around the sensor.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            5.0,
+            Material::Lambertian(Lambertian::from(Color::from(0.8, 0.8, 0.8))),
+        )));
+        let scene = Scene::from(world);
+        let mut sensor = Sensor::rotating(Point3::new());
+        sensor.pattern = ScanPattern::Rotating {
+            channels: 4,
+            steps: 90,
+            min_elevation: -10.0,
+            max_elevation: 10.0,
+        };
+
+        let points = scan(&scene, &sensor);
+        assert_eq!(points.len(), 360);
+        for point in &points {
+            assert!((point.distance - 5.0).abs() < 1e-9);
+            assert!((point.intensity - 0.8).abs() < 1e-9);
+        }
+
+        sensor.max_range = 4.0;
+        assert!(scan(&scene, &sensor).is_empty());
+    }
+}
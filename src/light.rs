@@ -0,0 +1,164 @@
+use crate::color::Color;
+use crate::ray::Point3;
+use crate::utility::INFINITY;
+use crate::vec3::*;
+
+// Lights without a surface, which no ray can hit; they light only what can
+// see them, through the shadow rays `Camera::trace` casts at every hit.
+// Point and spot light intensities are power per unit solid angle, dimming
+// with the square of the distance; a directional light's irradiance is what
+// reaches a surface facing it, anywhere in the scene.
+#[derive(Clone, Debug)]
+pub enum Light {
+    Point {
+        position: Point3,
+        intensity: Color,
+    },
+    Directional {
+        direction: Vec3, // Unit vector the light travels along
+        irradiance: Color,
+    },
+    // A point light shining only into a cone around `direction`: at full
+    // intensity within `cos_inner`, fading out smoothly by `cos_outer`.
+    Spot {
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        cos_inner: f64,
+        cos_outer: f64,
+    },
+}
+
+// The light arriving at a point from one of the lights.
+pub struct LightSample {
+    pub direction: Vec3, // Unit vector from the point towards the light
+    pub distance: f64,   // To the light, infinite for directional lights
+    pub irradiance: Color,
+}
+
+impl Light {
+    pub fn point(position: Point3, intensity: Color) -> Self {
+        Light::Point {
+            position,
+            intensity,
+        }
+    }
+
+    pub fn directional(direction: Vec3, irradiance: Color) -> Self {
+        Light::Directional {
+            direction: unit_vector(direction),
+            irradiance,
+        }
+    }
+
+    // A spot light at full intensity up to `angle` degrees off its axis,
+    // fading to dark `falloff` degrees further out.
+    pub fn spot(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        angle: f64,
+        falloff: f64,
+    ) -> Self {
+        let angle = angle.clamp(0.0, 180.0);
+        Light::Spot {
+            position,
+            direction: unit_vector(direction),
+            intensity,
+            cos_inner: angle.to_radians().cos(),
+            cos_outer: (angle + falloff.max(0.0)).min(180.0).to_radians().cos(),
+        }
+    }
+
+    // The light reaching `p`, or None if none does, ignoring what may stand
+    // in its way.
+    pub fn sample(&self, p: Point3) -> Option<LightSample> {
+        match *self {
+            Light::Point {
+                position,
+                intensity,
+            } => towards(p, position, intensity),
+            Light::Directional {
+                direction,
+                irradiance,
+            } => Some(LightSample {
+                direction: -direction,
+                distance: INFINITY,
+                irradiance,
+            }),
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                cos_inner,
+                cos_outer,
+            } => {
+                let sample = towards(p, position, intensity)?;
+                let cosine = -dot(&sample.direction, &direction);
+                let fade = if cosine >= cos_inner {
+                    1.0
+                } else if cosine <= cos_outer {
+                    return None;
+                } else {
+                    let t = (cosine - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                Some(LightSample {
+                    irradiance: sample.irradiance * fade,
+                    ..sample
+                })
+            }
+        }
+    }
+}
+
+fn towards(p: Point3, position: Point3, intensity: Color) -> Option<LightSample> {
+    let offset = position - p;
+    let distance_squared = offset.length_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    Some(LightSample {
+        direction: offset / distance,
+        distance,
+        irradiance: intensity / distance_squared,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lights_dim_and_fade() {
+        let point = Light::point(Point3::from(0.0, 2.0, 0.0), Color::gray(8.0));
+        let sample = point.sample(Point3::new()).unwrap();
+        approx::assert_relative_eq!(sample.distance, 2.0);
+        approx::assert_relative_eq!(sample.direction.y(), 1.0);
+        approx::assert_relative_eq!(sample.irradiance.r(), 2.0);
+
+        let sun = Light::directional(Vec3::from(0.0, -3.0, 0.0), Color::gray(1.5));
+        let sample = sun.sample(Point3::from(5.0, 0.0, 5.0)).unwrap();
+        approx::assert_relative_eq!(sample.direction.y(), 1.0);
+        approx::assert_relative_eq!(sample.irradiance.r(), 1.5);
+
+        // A spot pointing down, full within 30 degrees and dark past 40.
+        let spot = Light::spot(
+            Point3::from(0.0, 1.0, 0.0),
+            Vec3::from(0.0, -1.0, 0.0),
+            Color::gray(1.0),
+            30.0,
+            10.0,
+        );
+        let at = |degrees: f64| Point3::from(degrees.to_radians().tan(), 0.0, 0.0);
+        let full = spot.sample(at(0.0)).unwrap().irradiance.r();
+        approx::assert_relative_eq!(full, 1.0);
+        let edge = spot.sample(at(29.0)).unwrap().irradiance;
+        approx::assert_relative_eq!(edge.r(), 29f64.to_radians().cos().powi(2));
+        let fading = spot.sample(at(35.0)).unwrap().irradiance.r();
+        assert!(fading > 0.0 && fading < 35f64.to_radians().cos().powi(2));
+        assert!(spot.sample(at(45.0)).is_none());
+        assert!(spot.sample(Point3::from(0.0, 1.0, 0.0)).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+// Levels of detail of one asset. Each ray sees the level chosen by the
+// distance from its eye, the camera its path started at, to the asset, so a
+// path sees one level from its first hit to its last shadow ray and an
+// asset's shadow doesn't change shape with the surface it falls on.
+pub struct Lod {
+    levels: Vec<Box<dyn Hittable>>, // Finest first
+    // Distance beyond which level k + 1 takes over from level k.
+    switch_distances: Vec<f64>,
+    center: Point3,
+    bbox: Aabb,
+}
+
+impl Lod {
+    pub fn from(
+        levels: Vec<Box<dyn Hittable>>,
+        switch_distances: Vec<f64>,
+    ) -> Result<Self, String> {
+        if levels.is_empty() {
+            return Err("a level of detail set needs at least one level".to_string());
+        }
+        if switch_distances.len() + 1 != levels.len() {
+            return Err(format!(
+                "{} levels of detail need {} switch distances, not {}",
+                levels.len(),
+                levels.len() - 1,
+                switch_distances.len()
+            ));
+        }
+        if switch_distances.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err("switch distances must be increasing".to_string());
+        }
+        let bbox = levels.iter().fold(Aabb::EMPTY, |bbox, level| {
+            Aabb::union(&bbox, &level.bounding_box())
+        });
+        let mid = |axis: &Interval| (axis.min + axis.max) / 2.0;
+        let center = Point3::from(mid(&bbox.x), mid(&bbox.y), mid(&bbox.z));
+        Ok(Self {
+            levels,
+            switch_distances,
+            center,
+            bbox,
+        })
+    }
+
+    // The level seen from `eye`.
+    pub fn select(&self, eye: &Point3) -> &dyn Hittable {
+        let d = distance(eye, &self.center);
+        let level = self.switch_distances.partition_point(|&switch| switch < d);
+        self.levels[level].as_ref()
+    }
+}
+
+impl Hittable for Lod {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.select(&r.eye()).hit(r, ray_t)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.select(&r.eye()).is_occluded(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn mask(&self) -> u32 {
+        self.levels
+            .iter()
+            .fold(0, |mask, level| mask | level.mask())
+    }
+
+    // Point queries have no viewer, so they go to the finest level.
+    fn contains(&self, p: Point3) -> bool {
+        self.levels[0].contains(p)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        self.levels[0].surface_at(u, v)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.levels
+            .iter_mut()
+            .flat_map(|level| level.materials_mut())
+            .collect()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.memory += std::mem::size_of::<Self>()
+            + self.switch_distances.capacity() * std::mem::size_of::<f64>();
+        for level in &self.levels {
+            level.describe(info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::utility::INFINITY;
+
+    fn sphere(radius: f64) -> Box<dyn Hittable> {
+        Box::new(Sphere::from(
+            Point3::new(),
+            radius,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        ))
+    }
+
+    #[test]
+    fn switches_levels_with_distance() {
+        let lod = Lod::from(vec![sphere(1.0), sphere(0.5)], vec![10.0]).unwrap();
+        let ray_t = Interval::from(0.001, INFINITY);
+        let towards = |z: f64| Ray::from(Point3::from(0.0, 0.0, z), Vec3::from(0.0, 0.0, -1.0));
+        approx::assert_relative_eq!(lod.hit(&towards(5.0), ray_t).unwrap().t, 4.0);
+        approx::assert_relative_eq!(lod.hit(&towards(20.0), ray_t).unwrap().t, 19.5);
+        // A bounce near the asset still sees the level its camera picked.
+        let bounce = towards(5.0).with_eye(Point3::from(0.0, 0.0, 20.0));
+        approx::assert_relative_eq!(lod.hit(&bounce, ray_t).unwrap().t, 4.5);
+
+        assert!(Lod::from(vec![sphere(1.0), sphere(0.5)], vec![]).is_err());
+        assert!(Lod::from(vec![sphere(1.0), sphere(0.5), sphere(0.2)], vec![5.0, 2.0]).is_err());
+    }
+}
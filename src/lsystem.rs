@@ -0,0 +1,217 @@
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::quat::Quat;
+use crate::ray::Point3;
+use crate::utility::degrees_to_radians;
+use crate::vec3::*;
+
+use std::collections::HashMap;
+
+// Trees and plants from Lindenmayer systems. The axiom is rewritten by the
+// rules a number of times and the result is drawn by a turtle that starts at
+// the origin heading up +y:
+//
+//     F      grow a branch segment      L      add a leaf card
+//     + -    turn left / right          & ^    pitch down / up
+//     \ /    roll left / right          [ ]    start / end a side branch
+//
+// Other symbols only take part in rewriting. Side branches are thinner than
+// their parent by `taper`. Branches become tubes shaded smoothly, leaves
+// flat quads, all in one mesh with the bark and leaf materials.
+pub struct LSystem {
+    pub axiom: String,
+    pub rules: HashMap<char, String>,
+    pub iterations: u32,
+    pub angle: f64, // Degrees per turn, pitch or roll
+    pub length: f64,
+    pub radius: f64,
+    pub taper: f64,
+    pub leaf_size: f64,
+    pub sides: usize, // Around each branch tube
+}
+
+// Rewriting stops growing the string past this many symbols, so a typo in a
+// rule can't exhaust memory.
+const MAX_SYMBOLS: usize = 1 << 22;
+
+#[derive(Clone, Copy)]
+struct Turtle {
+    position: Point3,
+    orientation: Quat, // Takes +y to the heading, +x to the left, +z to the up
+    radius: f64,
+}
+
+impl LSystem {
+    pub fn from(axiom: &str, rules: HashMap<char, String>) -> Self {
+        Self {
+            axiom: axiom.to_string(),
+            rules,
+            iterations: 4,
+            angle: 25.0,
+            length: 0.1,
+            radius: 0.02,
+            taper: 0.7,
+            leaf_size: 0.08,
+            sides: 6,
+        }
+    }
+
+    // The axiom after `iterations` rewrites.
+    pub fn expand(&self) -> Result<String, String> {
+        let mut symbols = self.axiom.clone();
+        for _ in 0..self.iterations {
+            let mut next = String::with_capacity(symbols.len() * 2);
+            for c in symbols.chars() {
+                match self.rules.get(&c) {
+                    Some(replacement) => next.push_str(replacement),
+                    None => next.push(c),
+                }
+                if next.len() > MAX_SYMBOLS {
+                    return Err(format!(
+                        "L-system grows past {} symbols in {} iterations",
+                        MAX_SYMBOLS, self.iterations
+                    ));
+                }
+            }
+            symbols = next;
+        }
+        Ok(symbols)
+    }
+
+    pub fn build(&self, bark: Material, leaves: Material) -> Result<Mesh, String> {
+        if self.sides < 3 {
+            return Err("branches need at least 3 sides".to_string());
+        }
+        let symbols = self.expand()?;
+        crate::stage_span!("lsystem", symbols = symbols.len());
+
+        let mut geometry = Geometry::default();
+        let angle = degrees_to_radians(self.angle);
+        let (left, heading, up) = (
+            Vec3::from(1.0, 0.0, 0.0),
+            Vec3::from(0.0, 1.0, 0.0),
+            Vec3::from(0.0, 0.0, 1.0),
+        );
+        let mut turtle = Turtle {
+            position: Point3::new(),
+            orientation: Quat::IDENTITY,
+            radius: self.radius,
+        };
+        let mut stack = Vec::new();
+        for c in symbols.chars() {
+            let turn = |turtle: &mut Turtle, axis: &Vec3, angle: f64| {
+                turtle.orientation =
+                    (turtle.orientation * Quat::from_axis_angle(axis, angle)).normalize();
+            };
+            match c {
+                'F' => {
+                    let end = turtle.position + self.length * turtle.orientation.rotate(&heading);
+                    geometry.branch(&turtle, end, self.sides);
+                    turtle.position = end;
+                }
+                'L' => geometry.leaf(&turtle, self.leaf_size),
+                '+' => turn(&mut turtle, &up, angle),
+                '-' => turn(&mut turtle, &up, -angle),
+                '&' => turn(&mut turtle, &left, angle),
+                '^' => turn(&mut turtle, &left, -angle),
+                '\\' => turn(&mut turtle, &heading, angle),
+                '/' => turn(&mut turtle, &heading, -angle),
+                '[' => {
+                    stack.push(turtle);
+                    turtle.radius *= self.taper;
+                }
+                ']' => turtle = stack.pop().ok_or("unbalanced ']' in L-system")?,
+                _ => {}
+            }
+        }
+
+        // Bark faces first, then leaves.
+        let mut faces = vec![0; geometry.triangles.len()];
+        faces.resize(faces.len() + geometry.leaf_triangles.len(), 1);
+        let mut triangles = geometry.triangles;
+        triangles.extend(geometry.leaf_triangles);
+        Mesh::from(geometry.positions, triangles, bark.clone())?
+            .with_normals(geometry.normals)?
+            .with_face_materials(vec![bark, leaves], faces)
+    }
+}
+
+#[derive(Default)]
+struct Geometry {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    triangles: Vec<[usize; 3]>, // Branches
+    leaf_triangles: Vec<[usize; 3]>,
+}
+
+impl Geometry {
+    // A tube from the turtle to `end`, its rings turned with the turtle.
+    fn branch(&mut self, turtle: &Turtle, end: Point3, sides: usize) {
+        let first = self.positions.len();
+        for k in 0..sides {
+            let theta = 2.0 * std::f64::consts::PI * k as f64 / sides as f64;
+            let radial = turtle
+                .orientation
+                .rotate(&Vec3::from(theta.cos(), 0.0, theta.sin()));
+            for center in [turtle.position, end] {
+                self.positions.push(center + turtle.radius * radial);
+                self.normals.push(radial);
+            }
+        }
+        for k in 0..sides {
+            let (a, b) = (first + 2 * k, first + 2 * ((k + 1) % sides));
+            // Wound so the faces point outwards.
+            self.triangles.push([a, a + 1, b + 1]);
+            self.triangles.push([a, b + 1, b]);
+        }
+    }
+
+    // A square card hanging off the turtle along its heading, facing its up.
+    fn leaf(&mut self, turtle: &Turtle, size: f64) {
+        let along = size * turtle.orientation.rotate(&Vec3::from(0.0, 1.0, 0.0));
+        let across = 0.5 * size * turtle.orientation.rotate(&Vec3::from(1.0, 0.0, 0.0));
+        let normal = turtle.orientation.rotate(&Vec3::from(0.0, 0.0, 1.0));
+        let first = self.positions.len();
+        let p = turtle.position;
+        for corner in [
+            p - across,
+            p + across,
+            p + across + along,
+            p - across + along,
+        ] {
+            self.positions.push(corner);
+            self.normals.push(normal);
+        }
+        self.leaf_triangles
+            .extend([[first, first + 1, first + 2], [first, first + 2, first + 3]]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn grows_a_branching_tree() {
+        let rules = HashMap::from([('F', "F[+FL]F".to_string())]);
+        let mut tree = LSystem::from("F", rules);
+        tree.iterations = 2;
+        assert_eq!(tree.expand().unwrap(), "F[+FL]F[+F[+FL]FL]F[+FL]F");
+
+        let gray = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        let mesh = tree.build(gray(0.3), gray(0.6)).unwrap();
+        // Nine segments of six sides, four leaves.
+        assert_eq!(mesh.len(), 9 * 12 + 4 * 2);
+        // The trunk is four segments tall, the top side branch and its leaf
+        // reaching past it.
+        let bbox = mesh.bounding_box();
+        assert!((0.4..0.5).contains(&bbox.y.max));
+        assert!(bbox.x.min < -0.05);
+
+        tree.axiom = "F]".to_string();
+        assert!(tree.build(gray(0.3), gray(0.6)).is_err());
+    }
+}
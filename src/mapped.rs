@@ -0,0 +1,276 @@
+use crate::aabb::Aabb;
+use crate::assets::Cache;
+use crate::bvh::{self, Bvh};
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::mesh::{self, intersect_triangle, TriangleHit};
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// Triangle meshes too large to copy into memory, read straight from a
+// memory-mapped file so the OS pages vertices in and out as rays need them.
+// Only the bounding volume hierarchy over the triangles, about 20 bytes per
+// triangle, lives in memory.
+//
+// The file holds the magic, the vertex and triangle counts as little-endian
+// u64s, the vertex positions as little-endian f32 triples, then the vertex
+// indices as little-endian u32 triples. `write_mesh` produces it.
+pub const MAGIC: &[u8; 8] = b"RMESH001";
+const HEADER: usize = 24;
+const LEAF_SIZE: usize = 8;
+
+// Copies share the mapping and hierarchy, so a file opened again while
+// unchanged, such as for the next frame, is neither remapped nor indexed.
+#[derive(Clone)]
+pub struct MappedMesh {
+    map: Arc<Mmap>,
+    vertices: usize,
+    triangles: usize,
+    bvh: Arc<Bvh>,
+    material: Material,
+}
+
+pub fn write_mesh(
+    out: &mut impl Write,
+    positions: &[Point3],
+    triangles: &[[usize; 3]],
+) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&(positions.len() as u64).to_le_bytes())?;
+    out.write_all(&(triangles.len() as u64).to_le_bytes())?;
+    for p in positions {
+        for k in 0..3 {
+            out.write_all(&(p[k] as f32).to_le_bytes())?;
+        }
+    }
+    for triangle in triangles {
+        for &i in triangle {
+            out.write_all(&(i as u32).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl MappedMesh {
+    pub fn open(path: &Path, material: Material) -> io::Result<Self> {
+        static MESHES: Cache<MappedMesh> = Cache::new();
+        let mesh = MESHES.get(path, Self::map)?;
+        Ok(Self {
+            material,
+            ..MappedMesh::clone(&mesh)
+        })
+    }
+
+    fn map(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the mapping is only read, and is assumed not to be
+        // truncated or rewritten by another process while in use.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER || &map[..8] != MAGIC {
+            return Err(invalid(format!("{} is not a mesh file", path.display())));
+        }
+        let count = |at: usize| u64::from_le_bytes(map[at..at + 8].try_into().unwrap()) as usize;
+        let (vertices, triangles) = (count(8), count(16));
+        let expected = HEADER + 12 * vertices + 12 * triangles;
+        if map.len() != expected {
+            return Err(invalid(format!(
+                "{} should be {} bytes for {} vertices and {} triangles, not {}",
+                path.display(),
+                expected,
+                vertices,
+                triangles,
+                map.len()
+            )));
+        }
+        if vertices > u32::MAX as usize || triangles > u32::MAX as usize {
+            return Err(invalid(
+                "meshes are limited to 2^32 vertices and triangles".to_string(),
+            ));
+        }
+
+        let mut mesh = Self {
+            map: Arc::new(map),
+            vertices,
+            triangles,
+            bvh: Arc::default(),
+            material: mesh::stand_in(),
+        };
+        if let Some(index) = (0..triangles * 3)
+            .map(|i| mesh.index(i))
+            .find(|&i| i >= vertices)
+        {
+            return Err(invalid(format!(
+                "vertex index {} out of range for {} vertices",
+                index, vertices
+            )));
+        }
+        mesh.bvh = Arc::new(Bvh::build(
+            triangles,
+            LEAF_SIZE,
+            |t| {
+                let [a, b, c] = mesh.triangle(t);
+                Aabb::union(&Aabb::from_points(a, b), &Aabb::from_points(c, c))
+            },
+            bvh::all_sets,
+        ));
+        Ok(mesh)
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles == 0
+    }
+
+    // The `i`th entry of the index buffer.
+    fn index(&self, i: usize) -> usize {
+        let at = HEADER + 12 * self.vertices + 4 * i;
+        u32::from_le_bytes(self.map[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    fn vertex(&self, i: usize) -> Point3 {
+        let at = HEADER + 12 * i;
+        let coordinate =
+            |k: usize| f32::from_le_bytes(self.map[at + 4 * k..at + 4 * k + 4].try_into().unwrap());
+        Point3::from(
+            coordinate(0) as f64,
+            coordinate(1) as f64,
+            coordinate(2) as f64,
+        )
+    }
+
+    fn triangle(&self, t: usize) -> [Point3; 3] {
+        [0, 1, 2].map(|k| self.vertex(self.index(3 * t + k)))
+    }
+}
+
+impl Hittable for MappedMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, TriangleHit)> = None;
+        self.bvh.traverse(r, ray_t, |t, ray_t| {
+            match intersect_triangle(self.triangle(t), r, &ray_t) {
+                Some(hit) => {
+                    let max = hit.t;
+                    closest = Some((t, hit));
+                    Some(max)
+                }
+                None => Some(ray_t.max),
+            }
+        });
+        let (t, hit) = closest?;
+        let [a, b, c] = self.triangle(t);
+        let (e1, e2) = (b - a, c - a);
+        let normal = unit_vector(cross(&e1, &e2));
+        let mut rec = HitRecord {
+            p: r.at(hit.t),
+            normal,
+            geometric_normal: normal,
+            t: hit.t,
+            u: hit.b1,
+            v: hit.b2,
+            dpdu: e1,
+            dpdv: e2,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: &self.material,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &normal);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        let mut occluded = false;
+        self.bvh.traverse(r, ray_t, |t, ray_t| {
+            occluded = intersect_triangle(self.triangle(t), r, &ray_t).is_some();
+            (!occluded).then_some(ray_t.max)
+        });
+        occluded
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive(
+            "mapped mesh",
+            std::mem::size_of::<Self>() + self.bvh.memory(),
+        );
+        info.triangles += self.len();
+        info.mapped += self.map.len();
+        info.add_material(&self.material);
+        info.add_bvh(self.bvh.node_count(), self.bvh.depth());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn traces_a_mapped_grid() {
+        // A 20 x 20 grid of squares in the z = 0 plane, enough for a few
+        // levels of hierarchy.
+        let n = 20;
+        let positions: Vec<Point3> = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| Point3::from(i as f64, j as f64, 0.0)))
+            .collect();
+        let triangles: Vec<[usize; 3]> = (0..n)
+            .flat_map(|j| {
+                (0..n).flat_map(move |i| {
+                    let a = j * (n + 1) + i;
+                    [[a, a + 1, a + n + 2], [a, a + n + 2, a + n + 1]]
+                })
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("mapped-{}.rmesh", std::process::id()));
+        write_mesh(&mut File::create(&path).unwrap(), &positions, &triangles).unwrap();
+
+        let material = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let mesh = MappedMesh::open(&path, material.clone()).unwrap();
+        assert_eq!(mesh.len(), 800);
+        assert!(mesh.bvh.node_count() > 100);
+        let mut info = SceneInfo::default();
+        mesh.describe(&mut info);
+        assert_eq!(info.triangles, 800);
+        assert!(info.bvh_depth > 5);
+        let ray_t = Interval::from(0.001, INFINITY);
+        for (x, y) in [(0.3, 0.6), (13.7, 2.2), (19.9, 19.1)] {
+            let r = Ray::from(Point3::from(x, y, 3.0), Vec3::from(0.0, 0.0, -1.0));
+            let rec = mesh.hit(&r, ray_t).unwrap();
+            approx::assert_relative_eq!(rec.t, 3.0);
+            approx::assert_relative_eq!(rec.p.x(), x, epsilon = 1e-9);
+            assert!(mesh.is_occluded(&r, ray_t));
+        }
+        let outside = Ray::from(Point3::from(20.5, 3.0, 3.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(mesh.hit(&outside, ray_t).is_none());
+        assert!(!mesh.is_occluded(&outside, ray_t));
+
+        std::fs::write(&path, &MAGIC[..]).unwrap();
+        assert!(MappedMesh::open(&path, material).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray};
+use crate::sampler::Sampler;
+use crate::utility::Interval;
+use crate::vec3::Vec3;
+
+// Puts an object in other trace sets than the default one and limits what
+// the rays it scatters can hit. For a mirror that only reflects the hero
+// objects, give the heroes mask 0b11 (so that other rays still see them) and
+// the mirror trace set 0b10.
+pub struct Masked {
+    object: Box<dyn Hittable>,
+    mask: u32,      // Trace sets the object belongs to
+    trace_set: u32, // Trace sets rays scattered from the object can hit
+}
+
+impl Masked {
+    pub fn from(object: Box<dyn Hittable>, mask: u32, trace_set: u32) -> Self {
+        Self {
+            object,
+            mask,
+            trace_set,
+        }
+    }
+}
+
+impl Hittable for Masked {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        if r.mask() & self.mask == 0 {
+            return None;
+        }
+        let mut rec = self.object.hit(r, ray_t)?;
+        rec.trace_set &= self.trace_set;
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        r.mask() & self.mask != 0 && self.object.is_occluded(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.object.contains(p)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        self.object.surface_at(u, v)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.object.materials_mut()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.memory += std::mem::size_of::<Self>();
+        self.object.describe(info);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Metal, Scatterable};
+    use crate::sampler::RandomSampler;
+    use crate::sphere::Sphere;
+    use crate::utility::INFINITY;
+    use crate::vec3::Vec3;
+
+    fn sphere(z: f64, material: Material) -> Box<dyn Hittable> {
+        Box::new(Sphere::from(Point3::from(0.0, 0.0, z), 1.0, material))
+    }
+
+    #[test]
+    fn mirror_reflects_only_its_trace_set() {
+        const HERO: u32 = 0b10;
+        const ANY: u32 = u32::MAX;
+        let gray = || Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let mirror = Material::Metal(Metal::from(Color::gray(1.0), 0.0));
+
+        // From the camera at the origin looking down -z: a mirror in front,
+        // and behind the camera an ordinary sphere hiding a hero.
+        let mut world = HittableList::default();
+        world.add(Box::new(Masked::from(sphere(-5.0, mirror), 1, HERO)));
+        world.add(sphere(5.0, gray()));
+        world.add(Box::new(Masked::from(sphere(10.0, gray()), 1 | HERO, ANY)));
+        assert_eq!(world.mask(), 1 | HERO);
+
+        let camera_ray = Ray::from(Point3::new(), Vec3::from(0.0, 0.0, -1.0));
+        let rec = world
+            .hit(&camera_ray, Interval::from(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.trace_set, HERO);
+        let (reflected, _) = rec
+            .mat
+            .scatter(&camera_ray, &rec, &mut RandomSampler)
+            .unwrap();
+        assert_eq!(reflected.mask(), HERO);
+        let seen = world
+            .hit(&reflected, Interval::from(0.001, INFINITY))
+            .unwrap();
+        approx::assert_relative_eq!(seen.p.z(), 9.0);
+
+        // The reflection doesn't see the mirror itself either.
+        let back = Ray::from(Point3::new(), Vec3::from(0.0, 0.0, -1.0)).with_mask(HERO);
+        assert!(world.hit(&back, Interval::from(0.001, INFINITY)).is_none());
+    }
+}
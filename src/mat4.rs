@@ -0,0 +1,319 @@
+use crate::quat::Quat;
+use crate::ray::Point3;
+use crate::vec3::*;
+
+use std::ops;
+
+// Row-major 4x4 matrix for affine transforms. Points are column vectors, so
+// `a * b` applies `b` first.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_rows(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    // Column-major input, as stored by glTF and most graphics APIs.
+    pub fn from_cols(c: [[f64; 4]; 4]) -> Self {
+        Self { m: c }.transpose()
+    }
+
+    pub fn translation(t: &Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.m[0][3] = t.x();
+        m.m[1][3] = t.y();
+        m.m[2][3] = t.z();
+        m
+    }
+
+    pub fn scale(s: &Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        for k in 0..3 {
+            m.m[k][k] = s[k];
+        }
+        m
+    }
+
+    pub fn rotation(q: &Quat) -> Self {
+        let q = q.normalize();
+        let (w, x, y, z) = (q.w(), q.x(), q.y(), q.z());
+        Self::from_rows([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Scale, then rotate, then translate.
+    pub fn from_trs(translation: &Vec3, rotation: &Quat, scale: &Vec3) -> Self {
+        Self::translation(translation) * Self::rotation(rotation) * Self::scale(scale)
+    }
+
+    pub fn row(&self, i: usize) -> [f64; 4] {
+        self.m[i]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = Self::IDENTITY;
+        for i in 0..4 {
+            for j in 0..4 {
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let (_, det) = self.cofactors();
+        det
+    }
+
+    // None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let (adjugate, det) = self.cofactors();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let mut inv = adjugate;
+        for row in inv.m.iter_mut() {
+            for x in row.iter_mut() {
+                *x /= det;
+            }
+        }
+        Some(inv)
+    }
+
+    // The adjugate (transposed cofactor matrix) and the determinant.
+    fn cofactors(&self) -> (Self, f64) {
+        let a = &self.m;
+        // 2x2 minors of the bottom two rows and the top two rows.
+        let s0 = a[0][0] * a[1][1] - a[1][0] * a[0][1];
+        let s1 = a[0][0] * a[1][2] - a[1][0] * a[0][2];
+        let s2 = a[0][0] * a[1][3] - a[1][0] * a[0][3];
+        let s3 = a[0][1] * a[1][2] - a[1][1] * a[0][2];
+        let s4 = a[0][1] * a[1][3] - a[1][1] * a[0][3];
+        let s5 = a[0][2] * a[1][3] - a[1][2] * a[0][3];
+        let c5 = a[2][2] * a[3][3] - a[3][2] * a[2][3];
+        let c4 = a[2][1] * a[3][3] - a[3][1] * a[2][3];
+        let c3 = a[2][1] * a[3][2] - a[3][1] * a[2][2];
+        let c2 = a[2][0] * a[3][3] - a[3][0] * a[2][3];
+        let c1 = a[2][0] * a[3][2] - a[3][0] * a[2][2];
+        let c0 = a[2][0] * a[3][1] - a[3][0] * a[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        let adjugate = Self::from_rows([
+            [
+                a[1][1] * c5 - a[1][2] * c4 + a[1][3] * c3,
+                -a[0][1] * c5 + a[0][2] * c4 - a[0][3] * c3,
+                a[3][1] * s5 - a[3][2] * s4 + a[3][3] * s3,
+                -a[2][1] * s5 + a[2][2] * s4 - a[2][3] * s3,
+            ],
+            [
+                -a[1][0] * c5 + a[1][2] * c2 - a[1][3] * c1,
+                a[0][0] * c5 - a[0][2] * c2 + a[0][3] * c1,
+                -a[3][0] * s5 + a[3][2] * s2 - a[3][3] * s1,
+                a[2][0] * s5 - a[2][2] * s2 + a[2][3] * s1,
+            ],
+            [
+                a[1][0] * c4 - a[1][1] * c2 + a[1][3] * c0,
+                -a[0][0] * c4 + a[0][1] * c2 - a[0][3] * c0,
+                a[3][0] * s4 - a[3][1] * s2 + a[3][3] * s0,
+                -a[2][0] * s4 + a[2][1] * s2 - a[2][3] * s0,
+            ],
+            [
+                -a[1][0] * c3 + a[1][1] * c1 - a[1][2] * c0,
+                a[0][0] * c3 - a[0][1] * c1 + a[0][2] * c0,
+                -a[3][0] * s3 + a[3][1] * s1 - a[3][2] * s0,
+                a[2][0] * s3 - a[2][1] * s1 + a[2][2] * s0,
+            ],
+        ]);
+        (adjugate, det)
+    }
+
+    // Splits an affine matrix without shear into translation, rotation and
+    // (possibly negative) scale, the inverse of `from_trs`.
+    pub fn decompose(&self) -> (Vec3, Quat, Vec3) {
+        let translation = Vec3::from(self.m[0][3], self.m[1][3], self.m[2][3]);
+        let column = |j: usize| Vec3::from(self.m[0][j], self.m[1][j], self.m[2][j]);
+        let mut scale = Vec3::from(column(0).length(), column(1).length(), column(2).length());
+        if self.determinant() < 0.0 {
+            scale[0] = -scale[0];
+        }
+
+        // Rotation matrix entries r[i][j] with the scale divided out.
+        let r = |i: usize, j: usize| self.m[i][j] / scale[j];
+        let trace = r(0, 0) + r(1, 1) + r(2, 2);
+        let rotation = if trace > 0.0 {
+            let s = 0.5 / (trace + 1.0).sqrt();
+            Quat::from(
+                0.25 / s,
+                (r(2, 1) - r(1, 2)) * s,
+                (r(0, 2) - r(2, 0)) * s,
+                (r(1, 0) - r(0, 1)) * s,
+            )
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let s = 2.0 * (1.0 + r(0, 0) - r(1, 1) - r(2, 2)).sqrt();
+            Quat::from(
+                (r(2, 1) - r(1, 2)) / s,
+                0.25 * s,
+                (r(0, 1) + r(1, 0)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+            )
+        } else if r(1, 1) > r(2, 2) {
+            let s = 2.0 * (1.0 + r(1, 1) - r(0, 0) - r(2, 2)).sqrt();
+            Quat::from(
+                (r(0, 2) - r(2, 0)) / s,
+                (r(0, 1) + r(1, 0)) / s,
+                0.25 * s,
+                (r(1, 2) + r(2, 1)) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + r(2, 2) - r(0, 0) - r(1, 1)).sqrt();
+            Quat::from(
+                (r(1, 0) - r(0, 1)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+                (r(1, 2) + r(2, 1)) / s,
+                0.25 * s,
+            )
+        };
+        (translation, rotation.normalize(), scale)
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            Point3::from(x, y, z)
+        } else {
+            Point3::from(x, y, z) / w
+        }
+    }
+
+    // Directions ignore the translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::from(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Normals transform by the inverse transpose to stay perpendicular to
+    // the surface under non-uniform scale. The result is not normalized.
+    // When transforming many normals, keep `inverse().transpose()` around and
+    // use `transform_vector` with it instead.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        match self.inverse() {
+            Some(inverse) => inverse.transpose().transform_vector(n),
+            None => *n,
+        }
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Self { m }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::PI;
+    use approx::assert_relative_eq;
+
+    fn assert_mat_eq(a: &Mat4, b: &Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_relative_eq!(a.m[i][j], b.m[i][j], epsilon = 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_and_transforms() {
+        let rotation = Quat::from_axis_angle(&Vec3::from(1.0, 1.0, 0.0), 0.7);
+        let m = Mat4::from_trs(
+            &Vec3::from(1.0, -2.0, 3.0),
+            &rotation,
+            &Vec3::from(2.0, 0.5, 3.0),
+        );
+        assert_mat_eq(&(m * m.inverse().unwrap()), &Mat4::IDENTITY);
+        assert_relative_eq!(m.determinant(), 3.0, epsilon = 1e-10);
+        assert!(Mat4::scale(&Vec3::from(1.0, 0.0, 1.0)).inverse().is_none());
+
+        let t = Mat4::translation(&Vec3::from(1.0, 2.0, 3.0));
+        assert_eq!(
+            t.transform_point(&Point3::new()),
+            Point3::from(1.0, 2.0, 3.0)
+        );
+        assert_eq!(
+            t.transform_vector(&Vec3::from(1.0, 0.0, 0.0)),
+            Vec3::from(1.0, 0.0, 0.0)
+        );
+
+        // A normal of the plane x + y = 0 stays perpendicular after stretching x.
+        let stretch = Mat4::scale(&Vec3::from(4.0, 1.0, 1.0));
+        let tangent = stretch.transform_vector(&Vec3::from(1.0, -1.0, 0.0));
+        let normal = stretch.transform_normal(&Vec3::from(1.0, 1.0, 0.0));
+        assert_relative_eq!(dot(&tangent, &normal), 0.0);
+    }
+
+    #[test]
+    fn decompose_round_trip() {
+        let rotation = Quat::from_euler(0.3, -1.2, PI * 0.9);
+        let translation = Vec3::from(5.0, 0.0, -1.0);
+        let scale = Vec3::from(1.5, 2.0, 0.25);
+        let m = Mat4::from_trs(&translation, &rotation, &scale);
+
+        let (t, r, s) = m.decompose();
+        assert_mat_eq(&Mat4::from_trs(&t, &r, &s), &m);
+        assert_relative_eq!(r.dot(&rotation).abs(), 1.0, epsilon = 1e-10);
+        assert_relative_eq!(s.y(), 2.0, epsilon = 1e-10);
+    }
+}
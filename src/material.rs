@@ -1,48 +1,255 @@
 use crate::color::Color;
+use crate::expr::{Expr, ExprInputs};
 use crate::hittable::HitRecord;
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
 use crate::ray::Ray;
-use crate::utility::random;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
 use crate::vec3::*;
 
+use std::sync::Arc;
+
+#[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dieletric(Dieletric),
+    Procedural(Procedural),
+    // Another material with an explicit single-band response
+    Banded(Banded),
+    Wireframe(Wireframe),
+    // Area light: emits light and scatters none
+    DiffuseLight(DiffuseLight),
+    // Phase function of participating media, scattering in every direction
+    Isotropic(Isotropic),
+    // User-defined materials registered by downstream crates
+    Custom(Arc<dyn Scatterable + Send + Sync>),
 }
 
 impl Scatterable for Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    // Scattered rays are limited to the trace sets of the surface they leave,
+    // and are traced at the same moment as the ray that arrived.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let scattered = match self {
+            Material::Lambertian(l) => l.scatter(r_in, rec, sampler),
+            Material::Metal(m) => m.scatter(r_in, rec, sampler),
+            Material::Dieletric(d) => d.scatter(r_in, rec, sampler),
+            Material::Procedural(p) => p.scatter(r_in, rec, sampler),
+            Material::Banded(b) => b.base.scatter(r_in, rec, sampler),
+            Material::Wireframe(w) => w.scatter(r_in, rec, sampler),
+            Material::DiffuseLight(d) => d.scatter(r_in, rec, sampler),
+            Material::Isotropic(i) => i.scatter(r_in, rec, sampler),
+            Material::Custom(c) => c.scatter(r_in, rec, sampler),
+        };
+        scattered.map(|(r, attenuation)| {
+            let r = r.with_mask(rec.trace_set).with_time(r_in.time());
+            (r, attenuation)
+        })
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        match self {
+            Material::DiffuseLight(d) => d.emitted(rec),
+            Material::Banded(b) => b.base.emitted(rec),
+            Material::Custom(c) => c.emitted(rec),
+            _ => Color::new(),
+        }
+    }
+
+    fn scatter_pdf(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        match self {
+            Material::Lambertian(l) => l.scatter_pdf(r_in, rec),
+            Material::Procedural(p) => p.scatter_pdf(r_in, rec),
+            Material::Banded(b) => b.base.scatter_pdf(r_in, rec),
+            Material::Wireframe(w) => w.scatter_pdf(r_in, rec),
+            Material::Isotropic(i) => i.scatter_pdf(r_in, rec),
+            Material::Custom(c) => c.scatter_pdf(r_in, rec),
+            _ => None,
+        }
+    }
+}
+
+impl Material {
+    // Approximate fraction of light the surface reflects at the hit, used where
+    // a single reflectance figure is needed rather than a scattered ray.
+    pub fn albedo(&self, rec: &HitRecord) -> Color {
+        match self {
+            Material::Lambertian(l) => l.albedo_at(rec),
+            Material::Metal(m) => m.albedo,
+            Material::Dieletric(d) => {
+                let r0 = reflectance(1.0, d.ir);
+                Color::gray(r0)
+            }
+            Material::Procedural(p) => p.albedo_at(rec),
+            Material::Banded(b) => b.base.albedo(rec),
+            Material::Wireframe(w) => w.albedo_at(rec),
+            Material::DiffuseLight(_) => Color::new(),
+            Material::Isotropic(i) => i.albedo,
+            Material::Custom(_) => Color::from(0.5, 0.5, 0.5),
+        }
+    }
+
+    // Short name of the material kind, for diagnostics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Lambertian(_) => "lambertian",
+            Material::Metal(_) => "metal",
+            Material::Dieletric(_) => "dielectric",
+            Material::Procedural(_) => "procedural",
+            Material::Banded(_) => "banded",
+            Material::Wireframe(_) => "wireframe",
+            Material::DiffuseLight(_) => "diffuse_light",
+            Material::Isotropic(_) => "isotropic",
+            Material::Custom(_) => "custom",
+        }
+    }
+
+    // Whether the material scatters inside a volume rather than off a
+    // surface. Render modes leave such materials alone, as their hits have
+    // no surface to shade.
+    pub fn is_volumetric(&self) -> bool {
+        matches!(self, Material::Isotropic(_))
+    }
+
+    // The explicit band response, if the material has one.
+    pub fn band(&self) -> Option<&Banded> {
         match self {
-            Material::Lambertian(l) => l.scatter(r_in, rec),
-            Material::Metal(m) => m.scatter(r_in, rec),
-            Material::Dieletric(d) => d.scatter(r_in, rec),
+            Material::Banded(b) => Some(b),
+            _ => None,
         }
     }
 }
 
+// Materials draw whatever random numbers they need from `sampler`.
 pub trait Scatterable {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)>;
+
+    // Light the surface gives off at the hit, on top of what it scatters.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new()
+    }
+
+    // For materials that scatter diffusely, the attenuation and the density
+    // of scattered directions, so an integrator can sample the lights as
+    // well and use the density to weigh both estimates. None for
+    // materials such as mirrors and glass, which only `scatter`.
+    fn scatter_pdf(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        None
+    }
 }
 
-#[derive(Clone, Copy)]
+// Ideal diffuse reflection of `albedo`, or of the color a texture gives at
+// each hit.
+#[derive(Clone)]
 pub struct Lambertian {
-    albedo: Color,
+    pub(crate) albedo: Color,
+    texture: Option<Arc<dyn Texture>>,
 }
 
 #[derive(Clone, Copy)]
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    pub(crate) albedo: Color,
+    pub(crate) fuzz: f64,
 }
 
 #[derive(Clone, Copy)]
 pub struct Dieletric {
-    ir: f64,
+    pub(crate) ir: f64,
+}
+
+// Diffuse material whose albedo is an expression graph evaluated per hit.
+#[derive(Clone)]
+pub struct Procedural {
+    albedo: Arc<Expr>,
+}
+
+// Emits `emit` from both sides of the surface, the same in every direction.
+// Values above 1 are common: a small light must be bright to light a room.
+#[derive(Clone, Copy)]
+pub struct DiffuseLight {
+    pub(crate) emit: Color,
+}
+
+// Scatters `albedo` of the light reaching a point in a medium, in a direction
+// picked uniformly over the sphere.
+#[derive(Clone, Copy)]
+pub struct Isotropic {
+    pub(crate) albedo: Color,
+}
+
+// Response at a single wavelength band, e.g. thermal infrared, wrapped around
+// the material that decides how rays scatter.
+#[derive(Clone)]
+pub struct Banded {
+    pub(crate) base: Box<Material>,
+    pub(crate) emission: f64,
+    // Fraction of the band carried by scattered rays; by default the mean of
+    // the base material's attenuation.
+    pub(crate) reflectance: Option<f64>,
+}
+
+// Diffuse material drawing the edges of the surface's (u, v) parameter grid,
+// as if it were tessellated into `segments` quads in each direction.
+#[derive(Clone, Copy)]
+pub struct Wireframe {
+    pub(crate) face: Color,
+    pub(crate) edge: Color,
+    pub(crate) segments: (f64, f64),
+    pub(crate) width: f64, // Line width as a fraction of a grid cell
+}
+
+impl Banded {
+    pub fn from(base: Material, emission: f64, reflectance: Option<f64>) -> Self {
+        Self {
+            base: Box::new(base),
+            emission,
+            reflectance,
+        }
+    }
+
+    pub fn emission(&self) -> f64 {
+        self.emission
+    }
+
+    pub fn reflectance(&self) -> Option<f64> {
+        self.reflectance
+    }
 }
 
 impl Lambertian {
     pub fn from(albedo: Color) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            texture: None,
+        }
+    }
+
+    pub fn textured(texture: Arc<dyn Texture>) -> Self {
+        Self {
+            albedo: Color::gray(0.5),
+            texture: Some(texture),
+        }
+    }
+
+    pub fn is_textured(&self) -> bool {
+        self.texture.is_some()
+    }
+
+    fn albedo_at(&self, rec: &HitRecord) -> Color {
+        match &self.texture {
+            Some(texture) => texture.value(rec.u, rec.v, &rec.p),
+            None => self.albedo,
+        }
     }
 }
 
@@ -61,23 +268,171 @@ impl Dieletric {
     }
 }
 
+impl DiffuseLight {
+    pub fn from(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Isotropic {
+    pub fn from(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Procedural {
+    pub fn from(albedo: Expr) -> Self {
+        Self {
+            albedo: Arc::new(albedo),
+        }
+    }
+
+    fn albedo_at(&self, rec: &HitRecord) -> Color {
+        self.albedo
+            .eval(&ExprInputs {
+                u: rec.u,
+                v: rec.v,
+                p: rec.p,
+                normal: rec.normal,
+            })
+            .into()
+    }
+}
+
+impl Wireframe {
+    // Edges of a UV sphere with 24 segments around and 12 rings.
+    pub fn from(face: Color, edge: Color) -> Self {
+        Self {
+            face,
+            edge,
+            segments: (24.0, 12.0),
+            width: 0.06,
+        }
+    }
+
+    fn albedo_at(&self, rec: &HitRecord) -> Color {
+        // Distance to the nearest grid line, in cells.
+        let distance = |t: f64, segments: f64| {
+            let x = (t * segments).rem_euclid(1.0);
+            x.min(1.0 - x)
+        };
+        let d = distance(rec.u, self.segments.0).min(distance(rec.v, self.segments.1));
+        if d < self.width / 2.0 {
+            self.edge
+        } else {
+            self.face
+        }
+    }
+}
+
 impl Scatterable for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = rec.normal + random_unit_vector();
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
         // Catch degenerate scatter direction
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
 
         let scattered = Ray::from(rec.p, scatter_direction);
+        Some((scattered, self.albedo_at(rec)))
+    }
+
+    fn scatter_pdf(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        Some((self.albedo_at(rec), Box::new(CosinePdf::from(rec.normal))))
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+impl Scatterable for Isotropic {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let scattered = Ray::from(rec.p, sample_unit_vector(sampler.get_2d()));
         Some((scattered, self.albedo))
     }
+
+    fn scatter_pdf(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        Some((self.albedo, Box::new(SpherePdf)))
+    }
+}
+
+impl Scatterable for Procedural {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
+        // Catch degenerate scatter direction
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let scattered = Ray::from(rec.p, scatter_direction);
+        Some((scattered, self.albedo_at(rec)))
+    }
+
+    fn scatter_pdf(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        Some((self.albedo_at(rec), Box::new(CosinePdf::from(rec.normal))))
+    }
+}
+
+impl Scatterable for Wireframe {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let scattered = Ray::from(rec.p, scatter_direction);
+        Some((scattered, self.albedo_at(rec)))
+    }
+
+    fn scatter_pdf(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Box<dyn Pdf>)> {
+        Some((self.albedo_at(rec), Box::new(CosinePdf::from(rec.normal))))
+    }
 }
 
 impl Scatterable for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         let reflected = reflect(&unit_vector(r_in.direction()), &rec.normal);
-        let scattered = Ray::from(rec.p, reflected + self.fuzz * random_unit_vector());
+        let scattered = Ray::from(
+            rec.p,
+            reflected + self.fuzz * sample_unit_vector(sampler.get_2d()),
+        );
         if dot(&scattered.direction(), &rec.normal) > 0.0 {
             Some((scattered, self.albedo))
         } else {
@@ -87,7 +442,12 @@ impl Scatterable for Metal {
 }
 
 impl Scatterable for Dieletric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         let attenuation = Color::from(1.0, 1.0, 1.0);
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
@@ -101,22 +461,19 @@ impl Scatterable for Dieletric {
         let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
         let cannot_refract = sin_theta * refraction_ratio > 1.0;
 
-        let direction: Vec3 =
-            if cannot_refract || reflectance(cos_theta, refraction_ratio) > random() {
-                reflect(&unit_direction, &rec.normal)
-            } else {
-                refract(&unit_direction, &rec.normal, refraction_ratio)
-            };
+        // Always drawn, so the path uses the same dimensions either way.
+        let u = sampler.get_1d();
+        let direction: Vec3 = if cannot_refract || reflectance(cos_theta, refraction_ratio) > u {
+            reflect(&unit_direction, &rec.normal)
+        } else {
+            refract(&unit_direction, &rec.normal, refraction_ratio)
+        };
 
         let scattered = Ray::from(rec.p, direction);
         Some((scattered, attenuation))
     }
 }
 
-fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
-    (*v) - 2.0 * dot(v, n) * (*n)
-}
-
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     // Use Schlick's approximation for reflectance
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -124,11 +481,3 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 
     r0 + (1.0 - r0) * f64::powi(1.0 - cosine, 5)
 }
-
-fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = f64::min(dot(&-(*uv), n), 1.0);
-    let r_out_perp = etai_over_etat * (*uv + cos_theta * *n);
-    let r_out_parallel = -f64::sqrt(f64::abs(1.0 - r_out_perp.length_squared())) * *n;
-
-    r_out_parallel + r_out_perp
-}
@@ -0,0 +1,196 @@
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::{Isotropic, Material};
+use crate::ray::{Ray, ALL_SETS};
+use crate::sampler::{hash, to_unit};
+use crate::utility::{random, Interval, INFINITY};
+use crate::vec3::Vec3;
+
+use std::sync::Arc;
+
+// Fog or smoke of constant density filling a closed boundary, such as a box
+// or sphere. A ray passing through scatters at a random distance inside, with
+// exponentially falling odds of getting further, and the thinner the medium
+// the likelier it passes straight through. Hits are points in the volume,
+// scattered by `phase` (normally an `Isotropic` material) in any direction;
+// their normal and front face mean nothing.
+//
+// Rays starting inside the boundary, as those scattered in the medium do, are
+// handled, but the boundary must be convex: only the span between its first
+// two crossings is filled.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase: Material,
+}
+
+impl ConstantMedium {
+    pub fn from(boundary: Arc<dyn Hittable>, density: f64, phase: Material) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase,
+        }
+    }
+
+    // A medium scattering light of `albedo` equally in all directions.
+    pub fn isotropic(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self::from(
+            boundary,
+            density,
+            Material::Isotropic(Isotropic::from(albedo)),
+        )
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let entry = self.boundary.hit(r, Interval::UNIVERSE)?.t;
+        let exit = self
+            .boundary
+            .hit(r, Interval::from(entry + 0.0001, INFINITY))?
+            .t;
+
+        let enter = entry.max(ray_t.min).max(0.0);
+        let leave = exit.min(ray_t.max);
+        if enter >= leave {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (leave - enter) * ray_length;
+        // Drawn from the ray's key, if it has one, so that seeded renders
+        // repeat whichever thread traces the ray; mixed with where the ray
+        // enters, so each medium along it draws its own distance.
+        let u = match r.key() {
+            Some(key) => to_unit(hash(&[key, entry.to_bits()])),
+            None => random(),
+        };
+        let hit_distance = self.neg_inv_density * u.ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = enter + hit_distance / ray_length;
+        let normal = Vec3::from(1.0, 0.0, 0.0); // Arbitrary
+        Some(HitRecord {
+            p: r.at(t),
+            normal,
+            geometric_normal: normal,
+            t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::from(0.0, 1.0, 0.0),
+            dpdv: Vec3::from(0.0, 0.0, 1.0),
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: true,
+            mat: &self.phase,
+            trace_set: ALL_SETS,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.phase]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("constant medium", std::mem::size_of::<Self>());
+        info.add_material(&self.phase);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::camera::CameraBuilder;
+    use crate::control::RenderControl;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::ray::Point3;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn scatters_inside_the_boundary() {
+        let ball = |density| {
+            let sphere = Sphere::from(
+                Point3::new(),
+                1.0,
+                Material::Lambertian(Lambertian::from(Color::new())),
+            );
+            ConstantMedium::isotropic(Arc::new(sphere), density, Color::gray(0.5))
+        };
+        let r = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        // Dense fog stops rays right where they enter.
+        let dense = ball(1e6);
+        let rec = dense.hit(&r, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.0, epsilon = 1e-3);
+        assert_eq!(rec.mat.name(), "isotropic");
+        // Rays starting inside scatter within the rest of it.
+        let inside = Ray::from(Point3::new(), Vec3::from(0.0, 0.0, 1.0));
+        let rec = dense.hit(&inside, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.p.z(), 0.001, epsilon = 1e-3);
+
+        // Thin fog lets most through: with a path of 2 inside, the odds of
+        // passing are exp(-2 * 0.05), about 90%.
+        let thin = ball(0.05);
+        let passed = (0..2000).filter(|_| thin.hit(&r, ray_t).is_none()).count();
+        assert!((1650..1950).contains(&passed), "{} passed", passed);
+        for _ in 0..100 {
+            if let Some(rec) = thin.hit(&r, ray_t) {
+                assert!(rec.p.length() <= 1.0 + 1e-9);
+            }
+        }
+        let beside = Ray::from(Point3::from(2.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(dense.hit(&beside, ray_t).is_none());
+
+        // Rays with a key always scatter at the same place, whoever traces them.
+        let keyed = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0)).with_key(9);
+        let distances: Vec<_> = (0..10)
+            .map(|_| thin.hit(&keyed, ray_t).map(|rec| rec.t))
+            .collect();
+        assert!(distances.iter().all(|t| *t == distances[0]));
+    }
+
+    #[test]
+    fn seeded_fog_renders_repeat() {
+        let mut world = HittableList::default();
+        let boundary = Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        );
+        world.add(Box::new(ConstantMedium::isotropic(
+            Arc::new(boundary),
+            0.8,
+            Color::gray(0.7),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        // Small tiles on several threads, which take them in any order.
+        let camera = CameraBuilder {
+            image_width: Some(8),
+            samples_per_pixel: Some(2),
+            look_from: Some(Point3::from(0.0, 0.0, 3.0)),
+            tile_size: Some(2),
+            threads: Some(4),
+            seed: Some(5),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let render = || camera.render_buffer(&scene, &RenderControl::new(), &mut |_| {});
+        assert_eq!(render().to_linear(), render().to_linear());
+    }
+}
@@ -0,0 +1,443 @@
+use crate::aabb::Aabb;
+use crate::bvh::{self, Bvh};
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::{Lambertian, Material};
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+// A triangle mesh with shared vertices. With per-vertex normals the surface
+// is shaded smoothly: the normal is interpolated across each triangle, while
+// the triangle's own (geometric) normal still decides which side a ray hit.
+// Faces pick their material from a table, so a model with several materials
+// stays one mesh.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,   // One per vertex, or empty for flat shading
+    uvs: Vec<(f64, f64)>, // One per vertex, or empty to use barycentrics
+    triangles: Vec<[usize; 3]>,
+    materials: Vec<Material>,
+    face_materials: Vec<u32>, // Index into `materials` per triangle, or empty for the first
+    bvh: Bvh,                 // Over the triangles
+}
+
+const LEAF_SIZE: usize = 4;
+
+// The material cached models are built in, until `with_materials` gives each
+// copy the scene's.
+pub(crate) fn stand_in() -> Material {
+    Material::Lambertian(Lambertian::from(Color::gray(0.5)))
+}
+
+pub(crate) struct TriangleHit {
+    pub t: f64,
+    pub b1: f64, // Barycentric weights of the second and third vertex
+    pub b2: f64,
+}
+
+// Moller-Trumbore intersection of a ray with the triangle abc.
+pub(crate) fn intersect_triangle(
+    [a, b, c]: [Point3; 3],
+    r: &Ray,
+    ray_t: &Interval,
+) -> Option<TriangleHit> {
+    let (e1, e2) = (b - a, c - a);
+    let pvec = cross(&r.direction(), &e2);
+    let det = dot(&e1, &pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.origin() - a;
+    let b1 = dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = cross(&tvec, &e1);
+    let b2 = dot(&r.direction(), &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = dot(&e2, &qvec) * inv_det;
+    ray_t.surrounds(t).then_some(TriangleHit { t, b1, b2 })
+}
+
+impl Mesh {
+    pub fn from(
+        positions: Vec<Point3>,
+        triangles: Vec<[usize; 3]>,
+        material: Material,
+    ) -> Result<Self, String> {
+        if let Some(index) = triangles.iter().flatten().find(|&&i| i >= positions.len()) {
+            return Err(format!(
+                "vertex index {} out of range for {} vertices",
+                index,
+                positions.len()
+            ));
+        }
+        let bvh = Bvh::build(
+            triangles.len(),
+            LEAF_SIZE,
+            |t| {
+                let [a, b, c] = triangles[t].map(|i| positions[i]);
+                Aabb::union(&Aabb::from_points(a, b), &Aabb::from_points(c, c))
+            },
+            bvh::all_sets,
+        );
+        Ok(Self {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+            materials: vec![material],
+            face_materials: Vec::new(),
+            bvh,
+        })
+    }
+
+    // Shades the mesh smoothly with one normal per vertex.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Result<Self, String> {
+        if normals.len() != self.positions.len() {
+            return Err(format!(
+                "{} normals for {} vertices",
+                normals.len(),
+                self.positions.len()
+            ));
+        }
+        self.normals = normals.into_iter().map(unit_vector).collect();
+        Ok(self)
+    }
+
+    // Shades the mesh smoothly with normals averaged over the triangles
+    // around each vertex, weighted by their areas, for models that come
+    // without normals. Meshes that have normals keep them. Edges meant to
+    // stay sharp need their vertices split, as they are in models exported
+    // with normals, and the triangles must all wind the same way.
+    pub fn with_smooth_normals(mut self) -> Self {
+        if !self.normals.is_empty() {
+            return self;
+        }
+        let mut normals = vec![Vec3::new(); self.positions.len()];
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|i| self.positions[i]);
+            // As long as twice the triangle's area.
+            let normal = cross(&(b - a), &(c - a));
+            for &i in triangle {
+                normals[i] += normal;
+            }
+        }
+        // Vertices on no triangle, or only on degenerate ones, are never
+        // shaded, but need a normal all the same.
+        self.normals = normals
+            .into_iter()
+            .map(|n| {
+                if n.near_zero() {
+                    Vec3::from(0.0, 0.0, 1.0)
+                } else {
+                    unit_vector(n)
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Result<Self, String> {
+        if uvs.len() != self.positions.len() {
+            return Err(format!(
+                "{} texture coordinates for {} vertices",
+                uvs.len(),
+                self.positions.len()
+            ));
+        }
+        self.uvs = uvs;
+        Ok(self)
+    }
+
+    // Replaces the material with a table of them and an index into it per
+    // triangle.
+    pub fn with_face_materials(
+        mut self,
+        materials: Vec<Material>,
+        face_materials: Vec<usize>,
+    ) -> Result<Self, String> {
+        if face_materials.len() != self.triangles.len() {
+            return Err(format!(
+                "{} face materials for {} triangles",
+                face_materials.len(),
+                self.triangles.len()
+            ));
+        }
+        if let Some(index) = face_materials.iter().find(|&&i| i >= materials.len()) {
+            return Err(format!(
+                "material index {} out of range for {} materials",
+                index,
+                materials.len()
+            ));
+        }
+        self.materials = materials;
+        self.face_materials = face_materials.into_iter().map(|i| i as u32).collect();
+        Ok(self)
+    }
+
+    // The same geometry in other materials, which replace the table entry
+    // for entry. Loaders cache models in stand-in materials and dress each
+    // copy they hand out this way.
+    pub fn with_materials(mut self, materials: Vec<Material>) -> Result<Self, String> {
+        if materials.len() != self.materials.len() {
+            return Err(format!(
+                "{} materials for a table of {}",
+                materials.len(),
+                self.materials.len()
+            ));
+        }
+        self.materials = materials;
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    fn intersect(&self, index: usize, r: &Ray, ray_t: &Interval) -> Option<TriangleHit> {
+        intersect_triangle(self.triangles[index].map(|i| self.positions[i]), r, ray_t)
+    }
+
+    fn record(&self, index: usize, r: &Ray, hit: TriangleHit) -> HitRecord<'_> {
+        let vertices = self.triangles[index];
+        let [a, b, c] = vertices.map(|i| self.positions[i]);
+        let (e1, e2) = (b - a, c - a);
+        let weights = [1.0 - hit.b1 - hit.b2, hit.b1, hit.b2];
+        let interpolate = |values: [Vec3; 3]| {
+            weights[0] * values[0] + weights[1] * values[1] + weights[2] * values[2]
+        };
+
+        // Texture differences along the edges, for the tangents.
+        let (u, v, duv1, duv2) = if self.uvs.is_empty() {
+            (hit.b1, hit.b2, (1.0, 0.0), (0.0, 1.0))
+        } else {
+            let [ta, tb, tc] = vertices.map(|i| self.uvs[i]);
+            let u = weights[0] * ta.0 + weights[1] * tb.0 + weights[2] * tc.0;
+            let v = weights[0] * ta.1 + weights[1] * tb.1 + weights[2] * tc.1;
+            (u, v, (tb.0 - ta.0, tb.1 - ta.1), (tc.0 - ta.0, tc.1 - ta.1))
+        };
+        let det = duv1.0 * duv2.1 - duv1.1 * duv2.0;
+        // Solves d1 = du1 d/du + dv1 d/dv, d2 = du2 d/du + dv2 d/dv.
+        let tangents = |d1: Vec3, d2: Vec3| {
+            (
+                (duv2.1 * d1 - duv1.1 * d2) / det,
+                (duv1.0 * d2 - duv2.0 * d1) / det,
+            )
+        };
+        let (dpdu, dpdv) = if det.abs() > 1e-12 {
+            tangents(e1, e2)
+        } else {
+            (e1, e2)
+        };
+
+        let geometric = unit_vector(cross(&e1, &e2));
+        let (shading, dndu, dndv) = if self.normals.is_empty() {
+            (geometric, Vec3::new(), Vec3::new())
+        } else {
+            let normals = vertices.map(|i| self.normals[i]);
+            let shading = unit_vector(interpolate(normals));
+            let (dndu, dndv) = if det.abs() > 1e-12 {
+                tangents(normals[1] - normals[0], normals[2] - normals[0])
+            } else {
+                (Vec3::new(), Vec3::new())
+            };
+            (shading, dndu, dndv)
+        };
+
+        let mut rec = HitRecord {
+            p: r.at(hit.t),
+            normal: geometric,
+            geometric_normal: geometric,
+            t: hit.t,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            dndu,
+            dndv,
+            front_face: false,
+            mat: &self.materials[self.face_materials.get(index).map_or(0, |&i| i as usize)],
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &geometric);
+        // The shading normal is on the side of the geometric one the ray hit.
+        rec.normal = if rec.front_face { shading } else { -shading };
+        rec
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, TriangleHit)> = None;
+        self.bvh.traverse(r, ray_t, |index, ray_t| {
+            if let Some(hit) = self.intersect(index, r, &ray_t) {
+                let max = hit.t;
+                closest = Some((index, hit));
+                return Some(max);
+            }
+            Some(ray_t.max)
+        });
+        closest.map(|(index, hit)| self.record(index, r, hit))
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        let mut occluded = false;
+        self.bvh.traverse(r, ray_t, |index, ray_t| {
+            occluded = self.intersect(index, r, &ray_t).is_some();
+            (!occluded).then_some(ray_t.max)
+        });
+        occluded
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.materials.iter_mut().collect()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        let bytes = std::mem::size_of::<Self>()
+            + self.positions.capacity() * std::mem::size_of::<Point3>()
+            + self.normals.capacity() * std::mem::size_of::<Vec3>()
+            + self.uvs.capacity() * std::mem::size_of::<(f64, f64)>()
+            + self.triangles.capacity() * std::mem::size_of::<[usize; 3]>()
+            + self.materials.capacity() * std::mem::size_of::<Material>()
+            + self.face_materials.capacity() * std::mem::size_of::<u32>()
+            + self.bvh.memory();
+        info.add_primitive("mesh", bytes);
+        info.triangles += self.triangles.len();
+        info.add_bvh(self.bvh.node_count(), self.bvh.depth());
+        for material in &self.materials {
+            info.add_material(material);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn interpolates_vertex_normals() {
+        // A square in the z = 0 plane, with normals tilted outwards like a
+        // patch of a sphere.
+        let positions = vec![
+            Point3::from(-1.0, -1.0, 0.0),
+            Point3::from(1.0, -1.0, 0.0),
+            Point3::from(1.0, 1.0, 0.0),
+            Point3::from(-1.0, 1.0, 0.0),
+        ];
+        let normals = positions
+            .iter()
+            .map(|p| *p + Vec3::from(0.0, 0.0, 2.0))
+            .collect();
+        let material = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let flat = Mesh::from(positions, vec![[0, 1, 2], [0, 2, 3]], material).unwrap();
+        let smooth = flat.clone().with_normals(normals).unwrap();
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        let r = Ray::from(Point3::from(0.5, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = flat.hit(&r, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 5.0);
+        approx::assert_relative_eq!(rec.normal.z(), 1.0);
+        assert!(rec.front_face);
+
+        // The shading normal leans towards +x, the geometric one doesn't.
+        let rec = smooth.hit(&r, ray_t).unwrap();
+        assert!(rec.normal.x() > 0.1);
+        approx::assert_relative_eq!(rec.geometric_normal.z(), 1.0);
+        assert!(dot(&rec.dpdu, &rec.geometric_normal).abs() < 1e-9);
+
+        // From behind, both normals flip to face the ray.
+        let back = Ray::from(Point3::from(0.5, 0.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        let rec = smooth.hit(&back, ray_t).unwrap();
+        assert!(!rec.front_face);
+        assert!(rec.normal.z() < 0.0 && rec.geometric_normal.z() < 0.0);
+
+        let beside = Ray::from(Point3::from(1.5, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(smooth.hit(&beside, ray_t).is_none());
+        assert!(flat.clone().with_normals(vec![Vec3::new()]).is_err());
+    }
+
+    #[test]
+    fn smooths_normals_across_shared_vertices() {
+        // Two faces of a roof meeting at a ridge along the y axis.
+        let positions = vec![
+            Point3::from(-1.0, -1.0, 0.0),
+            Point3::from(0.0, -1.0, 1.0),
+            Point3::from(0.0, 1.0, 1.0),
+            Point3::from(-1.0, 1.0, 0.0),
+            Point3::from(1.0, -1.0, 0.0),
+            Point3::from(1.0, 1.0, 0.0),
+        ];
+        let triangles = vec![[0, 1, 2], [0, 2, 3], [1, 4, 5], [1, 5, 2]];
+        let material = Material::Lambertian(Lambertian::from(Color::gray(0.5)));
+        let flat = Mesh::from(positions, triangles, material).unwrap();
+        let roof = flat.clone().with_smooth_normals();
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        // On the ridge the normal points straight up; halfway down the left
+        // face it leans left, but less than the face does.
+        let down = Vec3::from(0.0, 0.0, -1.0);
+        let rec = roof
+            .hit(&Ray::from(Point3::from(0.0, 0.0, 5.0), down), ray_t)
+            .unwrap();
+        approx::assert_relative_eq!(rec.normal.z(), 1.0, epsilon = 1e-9);
+        let rec = roof
+            .hit(&Ray::from(Point3::from(-0.5, 0.0, 5.0), down), ray_t)
+            .unwrap();
+        assert!(rec.normal.x() < -0.1 && rec.normal.x() > -0.7);
+
+        // Normals the mesh has are kept.
+        let tilted = vec![Vec3::from(1.0, 0.0, 1.0); 6];
+        let kept = flat.with_normals(tilted).unwrap().with_smooth_normals();
+        let rec = kept
+            .hit(&Ray::from(Point3::from(0.0, 0.0, 5.0), down), ray_t)
+            .unwrap();
+        assert!(rec.normal.x() > 0.5);
+    }
+
+    #[test]
+    fn faces_pick_their_material() {
+        let gray = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        let positions = vec![
+            Point3::from(-1.0, -1.0, 0.0),
+            Point3::from(1.0, -1.0, 0.0),
+            Point3::from(1.0, 1.0, 0.0),
+            Point3::from(-1.0, 1.0, 0.0),
+        ];
+        let mesh = Mesh::from(positions, vec![[0, 1, 2], [0, 2, 3]], gray(0.5))
+            .unwrap()
+            .with_face_materials(vec![gray(0.2), gray(0.8)], vec![1, 0])
+            .unwrap();
+        let albedo = |x: f64, y: f64| {
+            let r = Ray::from(Point3::from(x, y, 5.0), Vec3::from(0.0, 0.0, -1.0));
+            let rec = mesh.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+            rec.mat.albedo(&rec).r()
+        };
+        approx::assert_relative_eq!(albedo(0.5, -0.5), 0.8);
+        approx::assert_relative_eq!(albedo(-0.5, 0.5), 0.2);
+
+        assert!(mesh
+            .clone()
+            .with_face_materials(vec![gray(0.2)], vec![0, 1])
+            .is_err());
+        assert!(mesh.with_face_materials(vec![gray(0.2)], vec![0]).is_err());
+    }
+}
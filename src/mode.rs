@@ -0,0 +1,96 @@
+use crate::color::Color;
+use crate::material::{Lambertian, Material, Wireframe};
+
+use std::fmt;
+use std::str::FromStr;
+
+// Shading overrides for reviewing a scene. `Clay` shades every surface with
+// the same neutral gray, so that only lighting and form show; `Wireframe`
+// also draws the edges of each surface's parameter grid, to check topology.
+// Geometry, the camera, the background and the emission of lights, which
+// light the scene, are unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RenderMode {
+    #[default]
+    Shaded,
+    Clay,
+    Wireframe,
+}
+
+const CLAY: f64 = 0.5;
+
+impl RenderMode {
+    // The material used for every surface instead of its own, if any.
+    pub fn material(self) -> Option<Material> {
+        match self {
+            RenderMode::Shaded => None,
+            RenderMode::Clay => Some(Material::Lambertian(Lambertian::from(Color::gray(CLAY)))),
+            RenderMode::Wireframe => Some(Material::Wireframe(Wireframe::from(
+                Color::gray(CLAY),
+                Color::gray(0.05),
+            ))),
+        }
+    }
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shaded" => Ok(RenderMode::Shaded),
+            "clay" => Ok(RenderMode::Clay),
+            "wireframe" => Ok(RenderMode::Wireframe),
+            _ => Err(format!(
+                "unknown render mode '{}', expected shaded, clay or wireframe",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RenderMode::Shaded => "shaded",
+            RenderMode::Clay => "clay",
+            RenderMode::Wireframe => "wireframe",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::Metal;
+    use crate::ray::Point3;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn clay_replaces_materials() {
+        // A red mirror under a white sky renders as the clay albedo, since
+        // every ray scattered off a convex diffuse sphere escapes.
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Metal(Metal::from(Color::from(1.0, 0.0, 0.0), 0.0)),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        let mut settings = CameraBuilder::new();
+        settings.image_width = Some(3);
+        settings.look_from = Some(Point3::from(0.0, 0.0, 2.0));
+        settings.set("mode", "clay").unwrap();
+        let camera = settings.build().unwrap();
+        let color = camera.sample_pixel_with(&scene, 1, 1, 4) / 4.0;
+        approx::assert_relative_eq!(color.r(), CLAY);
+        approx::assert_relative_eq!(color.g(), CLAY);
+
+        assert_eq!("wireframe".parse(), Ok(RenderMode::Wireframe));
+        assert!("solid".parse::<RenderMode>().is_err());
+    }
+}
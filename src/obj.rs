@@ -0,0 +1,308 @@
+use crate::assets::Cache;
+use crate::error::Error;
+use crate::material::Material;
+use crate::mesh::{self, Mesh};
+use crate::ray::Point3;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// Loads Wavefront OBJ models into a `Mesh`. Supported are `v`, `vt`, `vn`
+// and `f` with any of the `v`, `v/vt`, `v//vn` and `v/vt/vn` corner forms
+// (negative indices count back from the end), polygons split into fans of
+// triangles, and `usemtl`, which picks the material of that name from
+// `materials` for the faces after it. Faces before any `usemtl`, or under a
+// name not in `materials`, get `default`. Other statements, such as `mtllib`,
+// `o`, `g` and `s`, are skipped.
+//
+// Each distinct combination of position, texture coordinate and normal
+// becomes a mesh vertex. The model is shaded smoothly only if every corner
+// has a normal, and textured only if every corner has coordinates.
+//
+// Loaded models are cached until their file changes, so scenes rebuilt for
+// each frame or edit only copy them and look their materials up again.
+pub fn load(
+    path: &Path,
+    default: Material,
+    materials: &HashMap<String, Material>,
+) -> Result<Mesh, Error> {
+    static MODELS: Cache<(Mesh, Vec<String>)> = Cache::new();
+    let model = MODELS.get(path, |path| {
+        crate::stage_span!("load_obj");
+        let failed = |message: String| Error::Scene {
+            path: path.to_path_buf(),
+            message,
+        };
+        let source = std::fs::read_to_string(path).map_err(|err| failed(err.to_string()))?;
+        parse_geometry(&source).map_err(|err| failed(format!("line {}", err)))
+    })?;
+    let (mesh, names) = &*model;
+    Ok(dress(mesh.clone(), names, default, materials))
+}
+
+pub fn parse(
+    source: &str,
+    default: Material,
+    materials: &HashMap<String, Material>,
+) -> Result<Mesh, String> {
+    let (mesh, names) = parse_geometry(source)?;
+    Ok(dress(mesh, &names, default, materials))
+}
+
+// The model in stand-in materials, with the names its faces `usemtl`, which
+// follow the default in its material table.
+fn parse_geometry(source: &str) -> Result<(Mesh, Vec<String>), String> {
+    let mut obj = Obj::default();
+    for (number, line) in source.lines().enumerate() {
+        obj.statement(line)
+            .map_err(|err| format!("{}: {}", number + 1, err))?;
+    }
+    obj.build()
+}
+
+// The model's geometry in `default` and the named `materials`.
+fn dress(
+    mesh: Mesh,
+    names: &[String],
+    default: Material,
+    materials: &HashMap<String, Material>,
+) -> Mesh {
+    let named = names
+        .iter()
+        .map(|name| materials.get(name).unwrap_or(&default).clone());
+    let table = std::iter::once(default.clone()).chain(named).collect();
+    mesh.with_materials(table)
+        .expect("models are parsed with a material per name")
+}
+
+// Index of a corner into the positions, texture coordinates and normals.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct Obj {
+    positions: Vec<Point3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    corners: Vec<Corner>,
+    vertices: HashMap<Corner, usize>, // Into `corners`
+    triangles: Vec<[usize; 3]>,
+    face_materials: Vec<usize>,
+    names: Vec<String>, // Materials in use, after the default
+    material: usize,    // For the next faces; 0 for the default
+}
+
+impl Obj {
+    fn statement(&mut self, line: &str) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            return Ok(());
+        };
+        let numbers = |words: std::str::SplitWhitespace, needed: usize| {
+            let values = words
+                .map(|w| w.parse::<f64>().map_err(|_| format!("bad number '{}'", w)))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() < needed {
+                return Err(format!("'{}' needs {} numbers", keyword, needed));
+            }
+            Ok(values)
+        };
+        match keyword {
+            "v" | "vn" => {
+                let v = numbers(words, 3)?;
+                let v = Vec3::from(v[0], v[1], v[2]);
+                if keyword == "v" {
+                    self.positions.push(v);
+                } else {
+                    self.normals.push(v);
+                }
+            }
+            "vt" => {
+                let uv = numbers(words, 1)?;
+                self.uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                let polygon = words
+                    .map(|corner| self.vertex(corner))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if polygon.len() < 3 {
+                    return Err("faces need at least three corners".to_string());
+                }
+                for k in 1..polygon.len() - 1 {
+                    self.triangles
+                        .push([polygon[0], polygon[k], polygon[k + 1]]);
+                    self.face_materials.push(self.material);
+                }
+            }
+            "usemtl" => {
+                let name = words.next().unwrap_or_default();
+                self.material = match self.names.iter().position(|n| n == name) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.names.push(name.to_string());
+                        self.names.len()
+                    }
+                };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // The mesh vertex for a face corner like `3/1/2`.
+    fn vertex(&mut self, corner: &str) -> Result<usize, String> {
+        let mut parts = corner.split('/');
+        let mut index = |count: usize, what: &str| -> Result<Option<usize>, String> {
+            let Some(part) = parts.next().filter(|p| !p.is_empty()) else {
+                return Ok(None);
+            };
+            let i: i64 = part
+                .parse()
+                .map_err(|_| format!("bad {} index '{}'", what, part))?;
+            let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+            if resolved < 0 || resolved >= count as i64 {
+                return Err(format!("{} index {} out of range", what, i));
+            }
+            Ok(Some(resolved as usize))
+        };
+        let position = index(self.positions.len(), "vertex")?
+            .ok_or_else(|| format!("face corner '{}' has no vertex", corner))?;
+        let key = (
+            position,
+            index(self.uvs.len(), "texture")?,
+            index(self.normals.len(), "normal")?,
+        );
+        let next = self.corners.len();
+        let vertex = *self.vertices.entry(key).or_insert(next);
+        if vertex == next {
+            self.corners.push(key);
+        }
+        Ok(vertex)
+    }
+
+    fn build(self) -> Result<(Mesh, Vec<String>), String> {
+        if self.triangles.is_empty() {
+            return Err("model has no faces".to_string());
+        }
+        let positions = self.corners.iter().map(|c| self.positions[c.0]).collect();
+        let uvs: Option<Vec<_>> = self
+            .corners
+            .iter()
+            .map(|c| c.1.map(|i| self.uvs[i]))
+            .collect();
+        let normals: Option<Vec<_>> = self
+            .corners
+            .iter()
+            .map(|c| c.2.map(|i| self.normals[i]))
+            .collect();
+
+        let mut mesh = Mesh::from(positions, self.triangles, mesh::stand_in())?;
+        if let Some(normals) = normals {
+            mesh = mesh.with_normals(normals)?;
+        }
+        if let Some(uvs) = uvs {
+            mesh = mesh.with_uvs(uvs)?;
+        }
+        if !self.names.is_empty() {
+            let table = vec![mesh::stand_in(); self.names.len() + 1];
+            mesh = mesh.with_face_materials(table, self.face_materials)?;
+        }
+        Ok((mesh, self.names))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::utility::{Interval, INFINITY};
+
+    #[test]
+    fn parses_faces_and_materials() {
+        let gray = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        // A unit square as a quad, and a triangle beside it with negative
+        // indices. The triangle's corners share no normals with the quad's.
+        let source = "
+            # square
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vn 0 0 1
+            f 1//1 2//1 3//1 4//1
+            usemtl red
+            v 2 0 0
+            v 3 0 0
+            v 3 1 0
+            f -3 -2 -1
+        ";
+        let materials = HashMap::from([("red".to_string(), gray(0.8))]);
+        let mesh = parse(source, gray(0.2), &materials).unwrap();
+        assert_eq!(mesh.len(), 3);
+        approx::assert_relative_eq!(mesh.bounding_box().x.max, 3.0, epsilon = 1e-3);
+
+        let albedo = |x: f64| {
+            let r = Ray::from(Point3::from(x, 0.2, 5.0), Vec3::from(0.0, 0.0, -1.0));
+            let rec = mesh.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+            assert!(rec.front_face);
+            rec.mat.albedo(&rec).r()
+        };
+        approx::assert_relative_eq!(albedo(0.5), 0.2);
+        approx::assert_relative_eq!(albedo(2.8), 0.8);
+
+        let Err(error) = parse("v 0 0 0\nf 1 2 3\n", gray(0.2), &materials) else {
+            panic!("parsed a face with missing vertices");
+        };
+        assert!(error.starts_with("2: vertex index 2"));
+        assert!(parse("v 0 0 x\n", gray(0.2), &materials).is_err());
+    }
+
+    #[test]
+    fn cached_models_take_new_materials() {
+        let gray = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        let path = std::env::temp_dir().join(format!("cached-{}.obj", std::process::id()));
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 2 0 0\nv 3 0 0\nv 2 1 0\n\
+                      usemtl a\nf 1 2 3\nusemtl b\nf 4 5 6\n";
+        std::fs::write(&path, source).unwrap();
+        let albedo = |mesh: &Mesh, x: f64| {
+            let r = Ray::from(Point3::from(x, 0.2, 5.0), Vec3::from(0.0, 0.0, -1.0));
+            let rec = mesh.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+            rec.mat.albedo(&rec).r()
+        };
+
+        let named = HashMap::from([("a".to_string(), gray(0.8))]);
+        let mesh = load(&path, gray(0.2), &named).unwrap();
+        approx::assert_relative_eq!(albedo(&mesh, 0.2), 0.8);
+        approx::assert_relative_eq!(albedo(&mesh, 2.2), 0.2);
+        let renamed = HashMap::from([("b".to_string(), gray(0.6))]);
+        let mesh = load(&path, gray(0.4), &renamed).unwrap();
+        approx::assert_relative_eq!(albedo(&mesh, 0.2), 0.4);
+        approx::assert_relative_eq!(albedo(&mesh, 2.2), 0.6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn loads_the_example_model() {
+        let gray = |albedo| Material::Lambertian(Lambertian::from(Color::gray(albedo)));
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models/gem.obj");
+        let named = HashMap::from([("crown".to_string(), gray(0.8))]);
+        let mesh = load(&path, gray(0.2), &named).unwrap();
+        let albedo = |y: f64, dy: f64| {
+            let r = Ray::from(Point3::from(0.1, y, 0.1), Vec3::from(0.0, dy, 0.0));
+            let rec = mesh.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+            (rec.p.y(), rec.mat.albedo(&rec).r())
+        };
+        // Down onto the crown and up into the pavilion, which isn't named.
+        let (top, crown) = albedo(5.0, -1.0);
+        let (bottom, pavilion) = albedo(-5.0, 1.0);
+        approx::assert_relative_eq!(top, 1.4, epsilon = 1e-9);
+        approx::assert_relative_eq!(bottom, 0.2, epsilon = 1e-9);
+        approx::assert_relative_eq!(crown, 0.8);
+        approx::assert_relative_eq!(pavilion, 0.2);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::material::{Dieletric, Material};
+use crate::mesh::Mesh;
+use crate::ray::Point3;
+use crate::utility::PI;
+use crate::vec3::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::ops;
+
+// Deep-water ocean waves after Tessendorf, "Simulating Ocean Water": random
+// wave amplitudes drawn from the Phillips spectrum for a given wind are
+// advanced in time with the deep-water dispersion relation and summed with an
+// inverse FFT into a periodic patch of heights and horizontal displacements,
+// which becomes a displaced grid mesh. The same seed gives the same sea at
+// every time, so animations stay coherent from frame to frame.
+pub struct Ocean {
+    pub resolution: usize, // Grid points along each side; a power of two
+    pub size: f64,         // Side of the square patch, in meters
+    pub wind: Vec3,        // Wind direction in the xz plane; y is ignored
+    pub wind_speed: f64,   // Meters per second
+    // Phillips constant scaling every wave. The default gives about the wave
+    // heights of a fully developed sea for the wind speed.
+    pub amplitude: f64,
+    // Horizontal displacement towards crests; 0 gives rounded sine-like
+    // waves, around 1 sharp ones.
+    pub choppiness: f64,
+    pub seed: u64,
+}
+
+const GRAVITY: f64 = 9.81;
+
+impl Default for Ocean {
+    fn default() -> Self {
+        Self {
+            resolution: 64,
+            size: 50.0,
+            wind: Vec3::from(1.0, 0.0, 0.0),
+            wind_speed: 8.0,
+            amplitude: 1e-3,
+            choppiness: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+// Open water: clear, with water's index of refraction.
+pub fn water() -> Material {
+    Material::Dieletric(Dieletric::from(1.333))
+}
+
+#[derive(Clone, Copy, Default)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn from(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn from_phase(phase: f64) -> Self {
+        Self::from(phase.cos(), phase.sin())
+    }
+
+    fn conj(self) -> Self {
+        Self::from(self.re, -self.im)
+    }
+
+    fn scale(self, s: f64) -> Self {
+        Self::from(self.re * s, self.im * s)
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::from(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::from(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+// In-place inverse DFT without normalization, x_j = sum_k X_k e^(2 pi i jk/n),
+// by iterative radix-2 Cooley-Tukey. `values.len()` must be a power of two.
+fn inverse_fft(values: &mut [Complex]) {
+    let n = values.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let step = Complex::from_phase(2.0 * PI / len as f64);
+        for start in (0..n).step_by(len) {
+            let mut w = Complex::from(1.0, 0.0);
+            for k in 0..len / 2 {
+                let (a, b) = (values[start + k], values[start + k + len / 2] * w);
+                values[start + k] = a + b;
+                values[start + k + len / 2] = a - b;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// Inverse FFT of an n x n grid stored row by row.
+fn inverse_fft_2d(values: &mut [Complex], n: usize) {
+    for row in values.chunks_mut(n) {
+        inverse_fft(row);
+    }
+    let mut column = vec![Complex::default(); n];
+    for i in 0..n {
+        for j in 0..n {
+            column[j] = values[j * n + i];
+        }
+        inverse_fft(&mut column);
+        for j in 0..n {
+            values[j * n + i] = column[j];
+        }
+    }
+}
+
+impl Ocean {
+    // Wave vector of spectrum entry (i, j), with frequencies above n/2
+    // standing for negative ones as in FFT order.
+    fn wave_vector(&self, i: usize, j: usize) -> (f64, f64) {
+        let n = self.resolution;
+        let frequency = |i: usize| {
+            if i < n / 2 {
+                i as f64
+            } else {
+                i as f64 - n as f64
+            }
+        };
+        let scale = 2.0 * PI / self.size;
+        (scale * frequency(i), scale * frequency(j))
+    }
+
+    fn phillips(&self, kx: f64, kz: f64) -> f64 {
+        let k2 = kx * kx + kz * kz;
+        if k2 < 1e-12 {
+            return 0.0;
+        }
+        let wind = unit_vector(Vec3::from(self.wind.x(), 0.0, self.wind.z()));
+        let largest = self.wind_speed * self.wind_speed / GRAVITY;
+        let alignment = (kx * wind.x() + kz * wind.z()) / k2.sqrt();
+        // Suppress ripples much smaller than the largest waves.
+        let smallest = largest / 1000.0;
+        self.amplitude * (-1.0 / (k2 * largest * largest)).exp() / (k2 * k2)
+            * alignment
+            * alignment
+            * (-k2 * smallest * smallest).exp()
+    }
+
+    // Heights and x and z displacements at time `time` (seconds) for every
+    // grid point, row by row with x varying fastest.
+    pub fn displacements(&self, time: f64) -> Result<Vec<Vec3>, String> {
+        let n = self.resolution;
+        if n < 2 || !n.is_power_of_two() {
+            return Err(format!("ocean resolution {} must be a power of two", n));
+        }
+        if self.wind.x() == 0.0 && self.wind.z() == 0.0 {
+            return Err("ocean wind must point along the water".to_string());
+        }
+        crate::stage_span!("ocean", resolution = n);
+
+        // Amplitudes at time zero, a complex Gaussian per wave vector.
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut gaussian = || {
+            let (u1, u2): (f64, f64) = (rng.gen::<f64>().max(1e-300), rng.gen());
+            (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+        };
+        // Each entry stands for a cell of the spectrum dk on a side wide.
+        let dk = 2.0 * PI / self.size;
+        let h0: Vec<Complex> = (0..n * n)
+            .map(|index| {
+                let (kx, kz) = self.wave_vector(index % n, index / n);
+                let (re, im) = (gaussian(), gaussian());
+                Complex::from(re, im).scale((self.phillips(kx, kz) / 2.0).sqrt() * dk)
+            })
+            .collect();
+
+        let mut height = vec![Complex::default(); n * n];
+        let mut dx = height.clone();
+        let mut dz = height.clone();
+        for j in 0..n {
+            for i in 0..n {
+                let (kx, kz) = self.wave_vector(i, j);
+                let k = (kx * kx + kz * kz).sqrt();
+                let omega = (GRAVITY * k).sqrt() * time;
+                // h(k, t) = h0(k) e^(i w t) + conj(h0(-k)) e^(-i w t) keeps the
+                // heights real.
+                let opposite = h0[((n - j) % n) * n + (n - i) % n];
+                let h = h0[j * n + i] * Complex::from_phase(omega)
+                    + opposite.conj() * Complex::from_phase(-omega);
+                height[j * n + i] = h;
+                if k > 1e-12 {
+                    // -i k / |k| h, pulling points towards the crests.
+                    let towards = Complex::from(h.im, -h.re);
+                    dx[j * n + i] = towards.scale(kx / k);
+                    dz[j * n + i] = towards.scale(kz / k);
+                }
+            }
+        }
+        for grid in [&mut height, &mut dx, &mut dz] {
+            inverse_fft_2d(grid, n);
+        }
+        Ok((0..n * n)
+            .map(|index| {
+                Vec3::from(
+                    self.choppiness * dx[index].re,
+                    height[index].re,
+                    self.choppiness * dz[index].re,
+                )
+            })
+            .collect())
+    }
+
+    // The patch at `time` as a smooth mesh centered on the origin, its rest
+    // surface at y = 0. Opposite edges match, so patches tile.
+    pub fn mesh(&self, time: f64, material: Material) -> Result<Mesh, String> {
+        let n = self.resolution;
+        let displacement = self.displacements(time)?;
+        let spacing = self.size / n as f64;
+        // One more row and column than grid points, repeating the first.
+        let position = |i: usize, j: usize| {
+            let d = displacement[(j % n) * n + i % n];
+            Point3::from(
+                i as f64 * spacing - self.size / 2.0,
+                0.0,
+                j as f64 * spacing - self.size / 2.0,
+            ) + d
+        };
+        let mut positions = Vec::with_capacity((n + 1) * (n + 1));
+        let mut normals = Vec::with_capacity((n + 1) * (n + 1));
+        for j in 0..=n {
+            for i in 0..=n {
+                positions.push(position(i, j));
+                // Central differences, wrapping around the periodic patch.
+                let wrap = |i: usize| (i + n - 1) % n;
+                let across = position(i + 1, j) - position(wrap(i), j)
+                    + Vec3::from(if i == 0 { self.size } else { 0.0 }, 0.0, 0.0);
+                let along = position(i, j + 1) - position(i, wrap(j))
+                    + Vec3::from(0.0, 0.0, if j == 0 { self.size } else { 0.0 });
+                normals.push(cross(&along, &across));
+            }
+        }
+        let mut triangles = Vec::with_capacity(2 * n * n);
+        for j in 0..n {
+            for i in 0..n {
+                let a = j * (n + 1) + i;
+                triangles.push([a, a + n + 1, a + 1]);
+                triangles.push([a + 1, a + n + 1, a + n + 2]);
+            }
+        }
+        Mesh::from(positions, triangles, material)?.with_normals(normals)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::utility::{Interval, INFINITY};
+
+    #[test]
+    fn inverse_fft_matches_the_sum() {
+        let input: Vec<Complex> = (0..8)
+            .map(|i| Complex::from(i as f64, (i * i) as f64 * 0.1))
+            .collect();
+        let mut output = input.clone();
+        inverse_fft(&mut output);
+        for (j, value) in output.iter().enumerate() {
+            let sum = input
+                .iter()
+                .enumerate()
+                .fold(Complex::default(), |sum, (k, x)| {
+                    sum + *x * Complex::from_phase(2.0 * PI * (j * k) as f64 / 8.0)
+                });
+            approx::assert_relative_eq!(value.re, sum.re, epsilon = 1e-9);
+            approx::assert_relative_eq!(value.im, sum.im, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn builds_a_seeded_sea() {
+        let ocean = Ocean {
+            resolution: 16,
+            ..Default::default()
+        };
+        let calm = ocean.displacements(0.0).unwrap();
+        assert_eq!(calm.len(), 256);
+        assert!(calm.iter().any(|d| d.y().abs() > 0.01));
+        // Waves roll on over time, but the same seed gives the same sea.
+        let later = ocean.displacements(1.0).unwrap();
+        assert!(calm
+            .iter()
+            .zip(&later)
+            .any(|(a, b)| (a.y() - b.y()).abs() > 1e-3));
+        approx::assert_relative_eq!(ocean.displacements(0.0).unwrap()[5].y(), calm[5].y());
+
+        let mesh = ocean.mesh(0.0, water()).unwrap();
+        assert_eq!(mesh.len(), 2 * 16 * 16);
+        let down = Ray::from(Point3::from(1.0, 50.0, 2.0), Vec3::from(0.0, -1.0, 0.0));
+        let rec = mesh.hit(&down, Interval::from(0.001, INFINITY)).unwrap();
+        assert!(rec.front_face && rec.normal.y() > 0.0);
+
+        let coarse = Ocean {
+            resolution: 12,
+            ..Default::default()
+        };
+        assert!(coarse.displacements(0.0).is_err());
+    }
+}
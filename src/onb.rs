@@ -0,0 +1,40 @@
+use crate::vec3::*;
+
+// An orthonormal basis around a direction `w`, for turning directions sampled
+// about the z axis, such as a cosine lobe, into directions about `w`.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn from(n: Vec3) -> Self {
+        let w = unit_vector(n);
+        // Any vector not parallel to w completes the basis.
+        let a = if w.x().abs() > 0.9 {
+            Vec3::from(0.0, 1.0, 0.0)
+        } else {
+            Vec3::from(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(cross(&w, &a));
+        let u = cross(&w, &v);
+        Self { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    // The world direction with coordinates `local` in this basis.
+    pub fn transform(&self, local: Vec3) -> Vec3 {
+        local.x() * self.axis[0] + local.y() * self.axis[1] + local.z() * self.axis[2]
+    }
+}
@@ -0,0 +1,215 @@
+use crate::hittable::Hittable;
+use crate::onb::Onb;
+use crate::ray::Point3;
+use crate::sampler::Sampler;
+use crate::utility::PI;
+use crate::vec3::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+// A probability density over directions, for importance sampling: `generate`
+// draws a direction with density `value`, per unit solid angle. Integrators
+// weigh what a sampled direction brings by the scattering density over
+// `value`, so tracing more rays where the light comes from, such as towards
+// the lights, cuts noise without biasing the image.
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+// Every direction alike, as scattered in a medium.
+#[derive(Clone, Copy)]
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        sample_unit_vector(sampler.get_2d())
+    }
+}
+
+// Directions about a normal, with density proportional to the cosine of their
+// angle to it, as a Lambertian surface scatters.
+#[derive(Clone, Copy)]
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn from(normal: Vec3) -> Self {
+        Self {
+            uvw: Onb::from(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = dot(&unit_vector(*direction), &self.uvw.w());
+        cosine.max(0.0) / PI
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        // Points spread evenly over the unit disk, lifted onto the hemisphere.
+        let disk = concentric_disk(u, v);
+        let z = (1.0 - disk.length_squared()).max(0.0).sqrt();
+        self.uvw.transform(Vec3::from(disk.x(), disk.y(), z))
+    }
+}
+
+// Directions from `origin` towards an object, usually the scene's lights,
+// spread as the object's `random` and `pdf_value` give them.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn from(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, *direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.objects.random(self.origin, sampler)
+    }
+}
+
+// An even mix of two densities, drawing from either with equal odds. Mixing
+// a material's own density into light sampling keeps the estimate sound where
+// the lights are hidden or the material is glossy.
+pub struct MixturePdf<'a> {
+    a: &'a dyn Pdf,
+    b: &'a dyn Pdf,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn from(a: &'a dyn Pdf, b: &'a dyn Pdf) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.a.value(direction) + 0.5 * self.b.value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < 0.5 {
+            self.a.generate(sampler)
+        } else {
+            self.b.generate(sampler)
+        }
+    }
+}
+
+// How an integrator that finds light by two strategies, sampling the lights
+// and scattering off the material, weighs what each finds, given the density
+// each has of drawing the direction. The weights of the two always sum to
+// one, so the image is the same either way; `Power` favors the strategy more
+// likely to find the light more strongly than `Balance`, which takes out
+// most of the fireflies left where one is much better than the other.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    // The weight of light found by a strategy with density `pdf` for its
+    // direction, where the other strategy had density `other`.
+    pub fn weight(self, pdf: f64, other: f64) -> f64 {
+        let (pdf, other) = match self {
+            MisHeuristic::Balance => (pdf, other),
+            MisHeuristic::Power => (pdf * pdf, other * other),
+        };
+        if pdf + other > 0.0 {
+            pdf / (pdf + other)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl FromStr for MisHeuristic {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "balance" => Ok(MisHeuristic::Balance),
+            "power" => Ok(MisHeuristic::Power),
+            _ => Err(format!(
+                "unknown MIS heuristic '{}', expected balance or power",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MisHeuristic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MisHeuristic::Balance => "balance",
+            MisHeuristic::Power => "power",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Material};
+    use crate::quad::Quad;
+    use crate::sampler::RandomSampler;
+
+    #[test]
+    fn densities_match_their_samples() {
+        let up = CosinePdf::from(Vec3::from(0.0, 0.0, 1.0));
+        for _ in 0..100 {
+            let direction = up.generate(&mut RandomSampler);
+            assert!(direction.z() >= 0.0);
+            approx::assert_relative_eq!(direction.length(), 1.0, epsilon = 1e-9);
+        }
+        approx::assert_relative_eq!(up.value(&Vec3::from(0.0, 0.0, 2.0)), 1.0 / PI);
+        assert_eq!(up.value(&Vec3::from(0.0, 0.0, -1.0)), 0.0);
+
+        // A 2 by 2 light 1 above the origin. Over uniformly drawn directions,
+        // its density averages to 1 / (4 pi), as over the sphere it sums to 1.
+        let light = Quad::from(
+            Point3::from(-1.0, -1.0, 1.0),
+            Vec3::from(2.0, 0.0, 0.0),
+            Vec3::from(0.0, 2.0, 0.0),
+            Material::DiffuseLight(DiffuseLight::from(Color::gray(1.0))),
+        );
+        let towards = HittablePdf::from(&light, Point3::new());
+        let n = 20000;
+        let mean = (0..n)
+            .map(|_| towards.value(&SpherePdf.generate(&mut RandomSampler)))
+            .sum::<f64>()
+            / n as f64;
+        approx::assert_relative_eq!(mean * 4.0 * PI, 1.0, epsilon = 0.1);
+        for _ in 0..100 {
+            let direction = towards.generate(&mut RandomSampler);
+            assert!(towards.value(&direction) > 0.0);
+        }
+
+        let mixture = MixturePdf::from(&towards, &up);
+        let straight_up = Vec3::from(0.0, 0.0, 1.0);
+        approx::assert_relative_eq!(
+            mixture.value(&straight_up),
+            0.5 * towards.value(&straight_up) + 0.5 / PI
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+// An infinite plane through `point`, facing `normal`, such as a ground or a
+// backdrop. Texture coordinates are the position in the plane measured from
+// `point`, wrapping every unit, so image textures tile over it.
+#[derive(Clone)]
+pub struct Plane {
+    point: Point3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Material,
+}
+
+impl Plane {
+    pub fn from(point: Point3, normal: Vec3, material: Material) -> Self {
+        let normal = unit_vector(normal);
+        let (tangent, bitangent) = tangents(normal);
+        Self {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+// Unit directions along the plane with the given normal, with
+// cross(tangent, bitangent) == normal. For walls facing z they run along x
+// and y, for floors facing up along x and -z, as the edges of a quad would.
+pub(crate) fn tangents(normal: Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.y().abs() < 0.9 {
+        Vec3::from(0.0, 1.0, 0.0)
+    } else {
+        Vec3::from(0.0, 0.0, -normal.y().signum())
+    };
+    let tangent = unit_vector(cross(&helper, &normal));
+    (tangent, cross(&normal, &tangent))
+}
+
+// The ray parameter and point where the ray meets the plane through `point`
+// facing `normal`, if within `ray_t`.
+pub(crate) fn intersect(
+    point: Point3,
+    normal: Vec3,
+    r: &Ray,
+    ray_t: &Interval,
+) -> Option<(f64, Point3)> {
+    let denom = dot(&normal, &r.direction());
+    // Rays parallel to the plane miss it.
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+    let t = dot(&normal, &(point - r.origin())) / denom;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, r.at(t)))
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, p) = intersect(self.point, self.normal, r, &ray_t)?;
+        let offset = p - self.point;
+        let mut rec = HitRecord {
+            p,
+            normal: self.normal,
+            geometric_normal: self.normal,
+            t,
+            u: dot(&offset, &self.tangent).rem_euclid(1.0),
+            v: dot(&offset, &self.bitangent).rem_euclid(1.0),
+            dpdu: self.tangent,
+            dpdv: self.bitangent,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: &self.material,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &self.normal);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        intersect(self.point, self.normal, r, &ray_t).is_some()
+    }
+
+    // Unbounded, except across a plane facing along an axis.
+    fn bounding_box(&self) -> Aabb {
+        let mut bbox = Aabb::UNIVERSE;
+        for axis in 0..3 {
+            if self.normal[axis].abs() == 1.0 {
+                let at = self.point[axis];
+                let flat = Interval::from(at, at);
+                match axis {
+                    0 => bbox.x = flat,
+                    1 => bbox.y = flat,
+                    _ => bbox.z = flat,
+                }
+            }
+        }
+        Aabb::from(bbox.x, bbox.y, bbox.z)
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("plane", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn ground_plane_tiles_its_texture() {
+        let ground = Plane::from(
+            Point3::from(0.0, -1.0, 0.0),
+            Vec3::from(0.0, 2.0, 0.0),
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+        let r = Ray::from(Point3::from(0.25, 1.0, 0.0), Vec3::from(1.0, -1.0, 0.0));
+        let rec = ground.hit(&r, ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 2.0);
+        approx::assert_relative_eq!(rec.p.x(), 2.25);
+        approx::assert_relative_eq!(rec.u, 0.25);
+        approx::assert_relative_eq!(rec.v, 0.0);
+        assert!(rec.front_face && rec.normal.y() == 1.0);
+        approx::assert_relative_eq!(cross(&rec.dpdu, &rec.dpdv).y(), 1.0);
+
+        let up = Ray::from(Point3::from(0.0, -2.0, -0.3), Vec3::from(0.0, 1.0, 0.0));
+        let rec = ground.hit(&up, ray_t).unwrap();
+        assert!(!rec.front_face);
+        approx::assert_relative_eq!(rec.v, 0.3);
+        let along = Ray::from(Point3::new(), Vec3::from(1.0, 0.0, 0.0));
+        assert!(ground.hit(&along, ray_t).is_none());
+
+        let bbox = ground.bounding_box();
+        approx::assert_relative_eq!(bbox.y.min, -1.0, epsilon = 1e-3);
+        assert!(bbox.x.max == INFINITY && bbox.z.min == -INFINITY);
+    }
+}
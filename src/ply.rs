@@ -0,0 +1,374 @@
+use crate::assets::Cache;
+use crate::error::Error;
+use crate::material::Material;
+use crate::mesh::{self, Mesh};
+use crate::vec3::Vec3;
+
+use std::path::Path;
+
+// Loads Stanford PLY models into a `Mesh`, in ASCII or either binary byte
+// order. Vertices take their position from `x`, `y` and `z`, and a normal
+// from `nx`, `ny` and `nz` and texture coordinates from `u` and `v` (or `s`
+// and `t`) when every vertex has them; faces are lists of vertex indices,
+// split into fans of triangles. Other elements and properties, such as the
+// colors and confidences of scans, are skipped.
+//
+// Loaded models are cached until their file changes, so scenes rebuilt for
+// each frame or edit only copy them.
+pub fn load(path: &Path, material: Material) -> Result<Mesh, Error> {
+    static MODELS: Cache<Mesh> = Cache::new();
+    let model = MODELS.get(path, |path| {
+        crate::stage_span!("load_ply");
+        let failed = |message: String| Error::Scene {
+            path: path.to_path_buf(),
+            message,
+        };
+        let data = std::fs::read(path).map_err(|err| failed(err.to_string()))?;
+        parse(&data, mesh::stand_in()).map_err(failed)
+    })?;
+    Ok(Mesh::clone(&model)
+        .with_materials(vec![material])
+        .expect("models are parsed in one material"))
+}
+
+pub fn parse(data: &[u8], material: Material) -> Result<Mesh, String> {
+    let (header, body) = split_header(data)?;
+    let mut input = match header.format {
+        Format::Ascii => Input::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| "ASCII body isn't text".to_string())?
+                .split_ascii_whitespace(),
+        ),
+        Format::Binary { big_endian } => Input::Binary {
+            data: body,
+            at: 0,
+            big_endian,
+        },
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &header.elements {
+        for _ in 0..element.count {
+            match element.name.as_str() {
+                "vertex" => {
+                    let mut values = [0.0; 8];
+                    for property in &element.properties {
+                        let value = input.scalar(property.kind)?;
+                        if let Some(slot) = property.slot {
+                            values[slot] = value;
+                        }
+                    }
+                    positions.push(Vec3::from(values[0], values[1], values[2]));
+                    normals.push(Vec3::from(values[3], values[4], values[5]));
+                    uvs.push((values[6], values[7]));
+                }
+                "face" => {
+                    for property in &element.properties {
+                        let Some((count_kind, kind)) = property.list else {
+                            input.scalar(property.kind)?;
+                            continue;
+                        };
+                        let count = input.scalar(count_kind)? as usize;
+                        let polygon = (0..count)
+                            .map(|_| input.scalar(kind).map(|i| i as usize))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if !matches!(property.name.as_str(), "vertex_indices" | "vertex_index") {
+                            continue;
+                        }
+                        if polygon.len() < 3 {
+                            return Err("faces need at least three corners".to_string());
+                        }
+                        for k in 1..polygon.len() - 1 {
+                            triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+                        }
+                    }
+                }
+                _ => {
+                    for property in &element.properties {
+                        match property.list {
+                            Some((count_kind, kind)) => {
+                                for _ in 0..input.scalar(count_kind)? as usize {
+                                    input.scalar(kind)?;
+                                }
+                            }
+                            None => {
+                                input.scalar(property.kind)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if triangles.is_empty() {
+        return Err("model has no faces".to_string());
+    }
+
+    let has = |slots: &[usize]| {
+        let vertex = header.elements.iter().find(|e| e.name == "vertex");
+        vertex.is_some_and(|v| {
+            slots
+                .iter()
+                .all(|s| v.properties.iter().any(|p| p.slot == Some(*s)))
+        })
+    };
+    let mut mesh = Mesh::from(positions, triangles, material)?;
+    if has(&[3, 4, 5]) {
+        mesh = mesh.with_normals(normals)?;
+    }
+    if has(&[6, 7]) {
+        mesh = mesh.with_uvs(uvs)?;
+    }
+    Ok(mesh)
+}
+
+enum Format {
+    Ascii,
+    Binary { big_endian: bool },
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(format!("unknown property type '{}'", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+    list: Option<(Kind, Kind)>, // Types of the count and the items of a list
+    slot: Option<usize>,        // Where a vertex property goes, in x y z nx ny nz u v order
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+// Parses the header and returns it with the data after `end_header`.
+fn split_header(data: &[u8]) -> Result<(Header, &[u8]), String> {
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or("not a PLY file")?;
+    let mut body = end + END.len();
+    body += data[body..].iter().take_while(|&&b| b == b'\r').count();
+    if data.get(body) == Some(&b'\n') {
+        body += 1;
+    }
+    let text = std::str::from_utf8(&data[..end]).map_err(|_| "PLY header isn't text")?;
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("not a PLY file".to_string());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::Binary { big_endian: false },
+                    "binary_big_endian" => Format::Binary { big_endian: true },
+                    _ => return Err(format!("unknown format '{}'", name)),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("bad element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Kind::U8,
+                    list: Some((Kind::parse(count)?, Kind::parse(item)?)),
+                    slot: None,
+                });
+            }
+            ["property", kind, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                let slot = match (element.name.as_str(), *name) {
+                    ("vertex", "x") => Some(0),
+                    ("vertex", "y") => Some(1),
+                    ("vertex", "z") => Some(2),
+                    ("vertex", "nx") => Some(3),
+                    ("vertex", "ny") => Some(4),
+                    ("vertex", "nz") => Some(5),
+                    ("vertex", "u" | "s" | "texture_u") => Some(6),
+                    ("vertex", "v" | "t" | "texture_v") => Some(7),
+                    _ => None,
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Kind::parse(kind)?,
+                    list: None,
+                    slot,
+                });
+            }
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            _ => return Err(format!("bad header line '{}'", line)),
+        }
+    }
+    let format = format.ok_or("PLY header without a format")?;
+    Ok((Header { format, elements }, &data[body..]))
+}
+
+enum Input<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        at: usize,
+        big_endian: bool,
+    },
+}
+
+impl Input<'_> {
+    fn scalar(&mut self, kind: Kind) -> Result<f64, String> {
+        match self {
+            Input::Ascii(words) => {
+                let word = words.next().ok_or("file ends early")?;
+                word.parse().map_err(|_| format!("bad number '{}'", word))
+            }
+            Input::Binary {
+                data,
+                at,
+                big_endian,
+            } => {
+                let size = kind.size();
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(data.get(*at..*at + size).ok_or("file ends early")?);
+                *at += size;
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = bytes;
+                Ok(match kind {
+                    Kind::I8 => b0 as i8 as f64,
+                    Kind::U8 => b0 as f64,
+                    Kind::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    Kind::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    Kind::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Kind::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Kind::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Kind::F64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::{Point3, Ray};
+    use crate::utility::{Interval, INFINITY};
+
+    fn gray() -> Material {
+        Material::Lambertian(Lambertian::from(Color::gray(0.5)))
+    }
+
+    fn hits(mesh: &Mesh, x: f64, y: f64) -> bool {
+        let r = Ray::from(Point3::from(x, y, 1.0), Vec3::from(0.0, 0.0, -1.0));
+        mesh.hit(&r, Interval::from(0.001, INFINITY)).is_some()
+    }
+
+    #[test]
+    fn parses_ascii_and_binary() {
+        // A unit square in z = 0 as one quad, with a color per vertex to skip.
+        let ascii = b"ply
+format ascii 1.0
+comment made by hand
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255
+1 0 0 255
+1 1 0 255
+0 1 0 255
+4 0 1 2 3
+";
+        let mesh = parse(ascii, gray()).unwrap();
+        assert!(hits(&mesh, 0.25, 0.75));
+        assert!(!hits(&mesh, 1.5, 0.5));
+
+        let mut binary = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\n\
+            property double x\nproperty double y\nproperty double z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            element face 1\nproperty list uchar uint vertex_index\nend_header\n"
+            .to_vec();
+        for [x, y] in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] {
+            for value in [x, y, 0.0f64] {
+                binary.extend(value.to_be_bytes());
+            }
+            for value in [0.0, 0.0, 1.0f32] {
+                binary.extend(value.to_be_bytes());
+            }
+        }
+        binary.push(3);
+        for index in [0u32, 1, 2] {
+            binary.extend(index.to_be_bytes());
+        }
+        let mesh = parse(&binary, gray()).unwrap();
+        assert!(hits(&mesh, 0.25, 0.25));
+        assert!(!hits(&mesh, 0.75, 0.75));
+
+        binary.truncate(binary.len() - 2);
+        assert!(parse(&binary, gray()).is_err());
+        assert!(parse(b"solid cube", gray()).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::background::Background;
+use crate::camera::CameraBuilder;
+use crate::color::Color;
+use crate::hittable_list::HittableList;
+use crate::material::{Dieletric, DiffuseLight, Lambertian, Material, Metal};
+use crate::quad::Quad;
+use crate::ray::Point3;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::utility::{random, random_in_range};
+use crate::vec3::Vec3;
+
+// Built-in scenes with their cameras, for checking the renderer against
+// well-known reference images.
+
+// The final scene of the first book: three large spheres of glass, diffuse
+// and metal among a field of small random ones.
+pub fn random_spheres() -> (Scene, CameraBuilder) {
+    (random_spheres_world(), default_camera())
+}
+
+// The camera of the first book's final scene, which other scenes start from
+// before applying their own settings.
+pub fn default_camera() -> CameraBuilder {
+    let mut camera = CameraBuilder::new();
+    camera.aspect_ratio = Some(16.0 / 9.0);
+    camera.image_width = Some(400);
+    camera.samples_per_pixel = Some(500);
+    camera.max_depth = Some(50);
+
+    camera.vfov = Some(20.0);
+    camera.look_from = Some(Point3::from(13.0, 2.0, 3.0));
+    camera.look_at = Some(Point3::from(0.0, 0.0, 0.0));
+    camera.vup = Some(Vec3::from(0.0, 1.0, 0.0));
+
+    camera.defocus_angle = Some(0.6);
+    camera.focus_dist = Some(10.0);
+
+    camera
+}
+
+fn random_spheres_world() -> Scene {
+    let mut world: HittableList = HittableList::default();
+
+    let ground_material = Lambertian::from(Color::from(0.5, 0.5, 0.5));
+    world.add(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::Lambertian(ground_material),
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random();
+            let center = Point3::from(a as f64 + 0.9 * random(), 0.2, b as f64 + 0.9 * random());
+
+            if (center - Point3::from(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Material;
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random() * Color::random();
+                    sphere_material = Material::Lambertian(Lambertian::from(albedo));
+                    world.add(Box::new(Sphere::from(center, 0.2, sphere_material)))
+                } else if choose_mat < 0.95 {
+                    // diffuse
+                    let albedo = Color::random() * Color::random();
+                    let fuzz = random_in_range(0.0, 0.5);
+                    sphere_material = Material::Metal(Metal::from(albedo, fuzz));
+                    world.add(Box::new(Sphere::from(center, 0.2, sphere_material)))
+                } else {
+                    // diffuse
+                    sphere_material = Material::Dieletric(Dieletric::from(1.5));
+                    world.add(Box::new(Sphere::from(center, 0.2, sphere_material)))
+                }
+            }
+        }
+    }
+
+    let material1 = Material::Dieletric(Dieletric::from(1.5));
+    world.add(Box::new(Sphere::from(
+        Point3::from(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = Material::Lambertian(Lambertian::from(Color::from(0.4, 0.2, 0.1)));
+    world.add(Box::new(Sphere::from(
+        Point3::from(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Material::Metal(Metal::from(Color::from(0.7, 0.6, 0.5), 0.0));
+    world.add(Box::new(Sphere::from(
+        Point3::from(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    Scene::from(world)
+}
+
+// The Cornell box as set up in *The Next Week*: a 555 unit cube of white
+// walls, green on the left and red on the right, lit only by a square light
+// in the ceiling. The ceiling and the color bleeding onto the white walls are
+// lit only by light bounced off the others, so it tests emission and global
+// illumination together.
+pub fn cornell_box() -> (Scene, CameraBuilder) {
+    let lambertian = |r, g, b| Material::Lambertian(Lambertian::from(Color::from(r, g, b)));
+    let red = lambertian(0.65, 0.05, 0.05);
+    let white = lambertian(0.73, 0.73, 0.73);
+    let green = lambertian(0.12, 0.45, 0.15);
+    let light = Material::DiffuseLight(DiffuseLight::from(Color::gray(15.0)));
+
+    let wall = |q: (f64, f64, f64), u: (f64, f64, f64), v: (f64, f64, f64), material| {
+        Quad::from(
+            Point3::from(q.0, q.1, q.2),
+            Vec3::from(u.0, u.1, u.2),
+            Vec3::from(v.0, v.1, v.2),
+            material,
+        )
+    };
+    let lamp = wall(
+        (343.0, 554.0, 332.0),
+        (-130.0, 0.0, 0.0),
+        (0.0, 0.0, -105.0),
+        light,
+    );
+
+    let mut world = HittableList::default();
+    world.add(Box::new(wall(
+        (555.0, 0.0, 0.0),
+        (0.0, 555.0, 0.0),
+        (0.0, 0.0, 555.0),
+        green,
+    )));
+    world.add(Box::new(wall(
+        (0.0, 0.0, 0.0),
+        (0.0, 555.0, 0.0),
+        (0.0, 0.0, 555.0),
+        red,
+    )));
+    world.add(Box::new(lamp.clone()));
+    world.add(Box::new(wall(
+        (0.0, 0.0, 0.0),
+        (555.0, 0.0, 0.0),
+        (0.0, 0.0, 555.0),
+        white.clone(),
+    )));
+    world.add(Box::new(wall(
+        (555.0, 555.0, 555.0),
+        (-555.0, 0.0, 0.0),
+        (0.0, 0.0, -555.0),
+        white.clone(),
+    )));
+    world.add(Box::new(wall(
+        (0.0, 0.0, 555.0),
+        (555.0, 0.0, 0.0),
+        (0.0, 555.0, 0.0),
+        white,
+    )));
+
+    let mut scene = Scene::from(world);
+    scene.lights.add(Box::new(lamp));
+    scene.background = Background::Solid(Color::new());
+
+    let mut camera = CameraBuilder::new();
+    camera.aspect_ratio = Some(1.0);
+    camera.image_width = Some(600);
+    camera.samples_per_pixel = Some(200);
+    camera.max_depth = Some(50);
+
+    camera.vfov = Some(40.0);
+    camera.look_from = Some(Point3::from(278.0, 278.0, -800.0));
+    camera.look_at = Some(Point3::from(278.0, 278.0, 0.0));
+    camera.vup = Some(Vec3::from(0.0, 1.0, 0.0));
+
+    camera.defocus_angle = Some(0.0);
+
+    (scene, camera)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Scatterable;
+    use crate::ray::Ray;
+    use crate::utility::{Interval, INFINITY};
+
+    #[test]
+    fn cornell_box_is_closed_but_for_the_front() {
+        let (scene, camera) = cornell_box();
+        let camera = camera.build().unwrap();
+        // The view through the middle of the image ends on the back wall.
+        let r = camera.primary_ray(300, 300);
+        let rec = scene.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+        approx::assert_relative_eq!(rec.p.z(), 555.0, epsilon = 1e-6);
+
+        // Looking up from the floor, below the light, reaches it.
+        let up = Ray::from(Point3::from(278.0, 1.0, 278.0), Vec3::from(0.0, 1.0, 0.0));
+        let rec = scene.hit(&up, Interval::from(0.001, INFINITY)).unwrap();
+        approx::assert_relative_eq!(rec.mat.emitted(&rec).r(), 15.0);
+        assert_eq!(scene.lights.len(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::Color;
+use crate::control::RenderControl;
+use crate::framebuffer::FrameBuffer;
+use crate::progress::{ProgressEvent, Tile};
+use crate::quat::Quat;
+use crate::ray::Point3;
+use crate::scene::Scene;
+use crate::vec3::*;
+
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::sync::mpsc;
+use std::thread;
+
+// Opens a window showing the accumulation buffer, refined one sample per
+// pixel per pass until `samples_per_pixel` is reached or the window closes.
+//
+// Controls: left drag orbits around `look_at`, right drag pans, the scroll
+// wheel zooms, WASD/QE fly the camera and space pauses refinement. Any camera
+// change restarts accumulation; the resulting camera parameters are printed
+// once the camera comes to rest.
+pub fn run(camera: &Camera, scene: &Scene) -> Result<(), minifb::Error> {
+    let mut camera = camera.clone();
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let target_samples = camera.samples_per_pixel();
+
+    let mut window = Window::new(
+        "render preview",
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    )?;
+    window.set_target_fps(60);
+
+    let mut buffer = camera.frame_buffer();
+    let mut controls = Controls::default();
+    let mut paused = false;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+        }
+        if controls.update(&window, &mut camera) {
+            buffer.clear();
+        } else if controls.settled() {
+            print_camera(&camera);
+        }
+
+        if !paused && buffer.samples() < target_samples {
+            camera.render_pass(scene, &mut buffer);
+            window.set_title(&format!(
+                "render preview - {}/{} spp",
+                buffer.samples(),
+                target_samples
+            ));
+            window.update_with_buffer(&buffer.to_rgb_u32(), width as usize, height as usize)?;
+        } else {
+            window.update();
+        }
+    }
+
+    Ok(())
+}
+
+// Renders the final image as usual while a window shows each tile as it
+// finishes, handing the finished image to `on_done` and keeping the window
+// open until it is closed. Closing the window or pressing Escape before the
+// render is done cancels it, returning false. Progress is reported from the
+// rendering thread.
+pub fn show_render(
+    camera: &Camera,
+    scene: &Scene,
+    on_progress: &mut (dyn FnMut(&ProgressEvent) + Send),
+    on_done: impl FnOnce(&FrameBuffer),
+) -> Result<bool, minifb::Error> {
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let mut window = Window::new(
+        "render - 0%",
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    )?;
+    window.set_target_fps(60);
+
+    let samples = camera.samples_per_pixel() as u32;
+    let mut shown = camera.frame_buffer();
+    let control = RenderControl::new();
+    let (sender, receiver) = mpsc::channel::<(Tile, Vec<Color>)>();
+    thread::scope(|scope| {
+        let mut render = Some(scope.spawn(|| {
+            let mut on_tile = |tile, pixels: &[Color]| {
+                // The window may be gone already; the render is then cancelled.
+                let _ = sender.send((tile, pixels.to_vec()));
+            };
+            camera.render_buffer_showing(scene, &control, on_progress, &mut on_tile)
+        }));
+
+        let total = width as u64 * height as u64;
+        let (mut done, mut on_done) = (0, Some(on_done));
+        while window.is_open() && !window.is_key_down(Key::Escape) {
+            let mut changed = false;
+            for (tile, pixels) in receiver.try_iter() {
+                let rows = pixels.chunks((tile.x1 - tile.x0) as usize);
+                for (j, row) in (tile.y0..tile.y1).zip(rows) {
+                    for (i, &sum) in (tile.x0..tile.x1).zip(row) {
+                        shown.add_samples(i, j, sum, samples);
+                    }
+                }
+                done += tile.pixels();
+                changed = true;
+            }
+            if render.as_ref().is_some_and(|render| render.is_finished()) {
+                shown = render.take().unwrap().join().unwrap();
+                on_done.take().unwrap()(&shown);
+                window.set_title("render - done");
+                changed = true;
+            } else if changed {
+                window.set_title(&format!("render - {}%", done * 100 / total));
+            }
+            if changed {
+                window.update_with_buffer(&shown.to_rgb_u32(), width as usize, height as usize)?;
+            } else {
+                window.update();
+            }
+        }
+
+        match render {
+            Some(render) => {
+                control.cancel();
+                render.join().unwrap();
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    })
+}
+
+#[derive(Default)]
+struct Controls {
+    last_mouse: Option<(f32, f32)>,
+    moving: bool,
+    was_moving: bool,
+}
+
+impl Controls {
+    const ORBIT_SPEED: f64 = 0.01; // Radians per pixel dragged
+    const PAN_SPEED: f64 = 0.002; // Fraction of the orbit distance per pixel dragged
+    const ZOOM_SPEED: f64 = 0.1; // Fraction of the orbit distance per scroll step
+    const FLY_SPEED: f64 = 0.02; // Fraction of the orbit distance per frame
+
+    // Applies this frame's input to the camera, returning true if it moved.
+    fn update(&mut self, window: &Window, camera: &mut Camera) -> bool {
+        let look_from = camera.look_from();
+        let look_at = camera.look_at();
+        let vup = camera.vup();
+
+        let offset = look_from - look_at;
+        let distance = offset.length();
+        let w = unit_vector(offset);
+        let u = unit_vector(cross(&vup, &w));
+        let v = cross(&w, &u);
+
+        let mut new_from = look_from;
+        let mut new_at = look_at;
+
+        // Mouse drags
+        let mouse = window.get_mouse_pos(MouseMode::Pass);
+        let left = window.get_mouse_down(MouseButton::Left);
+        let right = window.get_mouse_down(MouseButton::Right);
+        if let (Some((x, y)), Some((last_x, last_y))) = (mouse, self.last_mouse) {
+            let dx = (x - last_x) as f64;
+            let dy = (y - last_y) as f64;
+            if left && (dx != 0.0 || dy != 0.0) {
+                let yaw = Quat::from_axis_angle(&vup, -dx * Self::ORBIT_SPEED);
+                let yawed = yaw.rotate(&offset);
+                let pitch_axis = cross(&vup, &yawed);
+                let pitched =
+                    Quat::from_axis_angle(&pitch_axis, -dy * Self::ORBIT_SPEED).rotate(&yawed);
+                // Stop short of the poles so the basis stays well defined.
+                let next = if dot(&unit_vector(pitched), &unit_vector(vup)).abs() < 0.99 {
+                    pitched
+                } else {
+                    yawed
+                };
+                new_from = look_at + next;
+            } else if right && (dx != 0.0 || dy != 0.0) {
+                let pan = (-dx * u + dy * v) * distance * Self::PAN_SPEED;
+                new_from += pan;
+                new_at += pan;
+            }
+        }
+        self.last_mouse = mouse;
+
+        // Scroll zoom
+        if let Some((_, scroll)) = window.get_scroll_wheel() {
+            let scale = (1.0 - Self::ZOOM_SPEED).powf(scroll as f64);
+            new_from = new_at + (new_from - new_at) * scale;
+        }
+
+        // WASD fly mode, moving both ends of the view.
+        let step = distance * Self::FLY_SPEED;
+        let mut fly = Vec3::new();
+        if window.is_key_down(Key::W) {
+            fly += -step * w;
+        }
+        if window.is_key_down(Key::S) {
+            fly += step * w;
+        }
+        if window.is_key_down(Key::A) {
+            fly += -step * u;
+        }
+        if window.is_key_down(Key::D) {
+            fly += step * u;
+        }
+        if window.is_key_down(Key::E) {
+            fly += step * v;
+        }
+        if window.is_key_down(Key::Q) {
+            fly += -step * v;
+        }
+        new_from += fly;
+        new_at += fly;
+
+        self.was_moving = self.moving;
+        self.moving = new_from != look_from || new_at != look_at;
+        if self.moving {
+            let moved = CameraBuilder {
+                look_from: Some(new_from),
+                look_at: Some(new_at),
+                ..camera.to_builder()
+            };
+            // A view the camera can't take, such as straight along `vup`,
+            // leaves it where it was.
+            if let Ok(moved) = moved.build() {
+                *camera = moved;
+            }
+        }
+        self.moving
+    }
+
+    // True on the first frame after the camera stopped moving.
+    fn settled(&self) -> bool {
+        self.was_moving && !self.moving
+    }
+}
+
+fn print_camera(camera: &Camera) {
+    let format = |p: Point3| format!("Point3::from({:.4}, {:.4}, {:.4})", p.x(), p.y(), p.z());
+    println!("camera.look_from = Some({});", format(camera.look_from()));
+    println!("camera.look_at = Some({});", format(camera.look_at()));
+    println!("camera.vfov = Some({:.4});", camera.vfov());
+}
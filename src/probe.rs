@@ -0,0 +1,247 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::pdf::MisHeuristic;
+use crate::progress::{self, Tile, Tracker};
+use crate::ray::{Point3, Ray};
+use crate::sampler::{PixelSampler, Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::utility::PI;
+use crate::vec3::*;
+
+use std::io::{self, Write};
+
+// Irradiance probes for real-time global illumination: the radiance arriving
+// at each point of a grid, from every direction, projected onto the first
+// three bands of real spherical harmonics (9 coefficients per channel), the
+// form engines evaluate per pixel to light dynamic objects.
+pub const SH_COEFFICIENTS: usize = 9;
+
+pub struct ProbeGrid {
+    pub min: Point3,
+    pub max: Point3,
+    pub counts: [u32; 3], // Probes along x, y and z; one probe sits in the middle
+    pub samples: i32,     // Rays per probe
+    pub max_depth: i32,
+    pub sampler: SamplerKind,
+    pub mis: MisHeuristic,
+}
+
+pub struct Probe {
+    pub p: Point3,
+    pub sh: [Color; SH_COEFFICIENTS],
+}
+
+impl ProbeGrid {
+    pub fn from(min: Point3, max: Point3, counts: [u32; 3]) -> Self {
+        Self {
+            min,
+            max,
+            counts: counts.map(|n| n.max(1)),
+            samples: 256,
+            max_depth: 10,
+            sampler: SamplerKind::Sobol,
+            mis: MisHeuristic::default(),
+        }
+    }
+
+    // Probe positions with x varying fastest, then y, then z.
+    pub fn positions(&self) -> Vec<Point3> {
+        let along = |axis: usize, i: u32| {
+            let (lo, hi) = (self.min[axis], self.max[axis]);
+            match self.counts[axis] {
+                1 => (lo + hi) / 2.0,
+                n => lo + (hi - lo) * i as f64 / (n - 1) as f64,
+            }
+        };
+        let [nx, ny, nz] = self.counts;
+        (0..nz)
+            .flat_map(|k| (0..ny).flat_map(move |j| (0..nx).map(move |i| (i, j, k))))
+            .map(|(i, j, k)| Point3::from(along(0, i), along(1, j), along(2, k)))
+            .collect()
+    }
+
+    pub fn render(&self, scene: &Scene) -> Vec<Probe> {
+        let positions = self.positions();
+        let width = self.counts[0] as i32;
+        let rows = positions.len() as i32 / width;
+        crate::stage_span!("probes", width, rows, self.samples);
+
+        let mut on_progress = progress::log;
+        let mut progress = Tracker::start(width, rows, self.samples, &mut on_progress);
+        let mut probes = Vec::with_capacity(positions.len());
+        for (j, row) in positions.chunks(width as usize).enumerate() {
+            let tile = Tile::row(j as i32, width);
+            progress.tile_started(tile);
+            for (i, &p) in row.iter().enumerate() {
+                probes.push(self.probe(scene, p, i as i32, j as i32));
+            }
+            progress.tile_finished(tile, tile.pixels() * self.samples as u64);
+        }
+        progress.finish();
+        probes
+    }
+
+    // Monte Carlo projection with uniformly distributed directions.
+    fn probe(&self, scene: &Scene, p: Point3, i: i32, j: i32) -> Probe {
+        let mut sh = [Color::new(); SH_COEFFICIENTS];
+        for s in 0..self.samples {
+            let mut sampler =
+                PixelSampler::start(self.sampler, i, j, s as u64, self.samples as u64, None);
+            let direction = sample_unit_vector(sampler.get_2d());
+            let r = Ray::from(p, direction);
+            let radiance =
+                Camera::ray_color(&r, self.max_depth, 1, None, self.mis, scene, &mut sampler);
+            if !radiance.is_finite() {
+                continue;
+            }
+            for (c, y) in sh.iter_mut().zip(sh_basis(&direction)) {
+                *c += radiance * y;
+            }
+        }
+        let weight = 4.0 * PI / self.samples as f64;
+        Probe {
+            p,
+            sh: sh.map(|c| c * weight),
+        }
+    }
+}
+
+impl Probe {
+    // Radiance arriving from direction `d` (unit length), as reconstructed
+    // from the coefficients.
+    pub fn radiance(&self, d: &Vec3) -> Color {
+        self.sh
+            .iter()
+            .zip(sh_basis(d))
+            .fold(Color::new(), |sum, (c, y)| sum + *c * y)
+    }
+
+    // Irradiance over pi on a surface with normal `n`: the brightness a white
+    // diffuse surface would have there.
+    pub fn irradiance(&self, n: &Vec3) -> Color {
+        // Convolving with the clamped cosine scales each band (Ramamoorthi
+        // and Hanrahan).
+        const BAND: [f64; SH_COEFFICIENTS] = [
+            PI,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+        ];
+        self.sh
+            .iter()
+            .zip(sh_basis(n))
+            .zip(BAND)
+            .fold(Color::new(), |sum, ((c, y), a)| sum + *c * (y * a / PI))
+    }
+}
+
+// Real spherical harmonics up to l = 2, ordered (l, m) = (0, 0), (1, -1),
+// (1, 0), (1, 1), (2, -2), ..., (2, 2) as in most engines.
+fn sh_basis(d: &Vec3) -> [f64; SH_COEFFICIENTS] {
+    let (x, y, z) = (d.x(), d.y(), d.z());
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+// Writes the grid and its probes as JSON. Each probe's `sh` holds the nine
+// coefficients in `sh_basis` order, each as [r, g, b].
+pub fn write_json(out: &mut impl Write, grid: &ProbeGrid, probes: &[Probe]) -> io::Result<()> {
+    let triple = |[a, b, c]: [f64; 3]| format!("[{}, {}, {}]", a, b, c);
+    let vector = |v: &Vec3| triple([v.x(), v.y(), v.z()]);
+    let [nx, ny, nz] = grid.counts;
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"counts\": [{}, {}, {}],", nx, ny, nz)?;
+    writeln!(out, "  \"min\": {},", vector(&grid.min))?;
+    writeln!(out, "  \"max\": {},", vector(&grid.max))?;
+    writeln!(out, "  \"probes\": [")?;
+    for (index, probe) in probes.iter().enumerate() {
+        let sh: Vec<String> = probe
+            .sh
+            .iter()
+            .map(|c| triple([c.r(), c.g(), c.b()]))
+            .collect();
+        let comma = if index + 1 < probes.len() { "," } else { "" };
+        writeln!(
+            out,
+            "    {{\"position\": {}, \"sh\": [{}]}}{}",
+            vector(&probe.p),
+            sh.join(", "),
+            comma
+        )?;
+    }
+    writeln!(out, "  ]\n}}")
+}
+
+pub const BINARY_MAGIC: &[u8; 8] = b"SHPROBE1";
+
+// Writes the probes in a compact binary form: the magic, the counts as three
+// little-endian u32s, then per probe its position and the nine rgb
+// coefficients, all as little-endian f32s.
+pub fn write_binary(out: &mut impl Write, grid: &ProbeGrid, probes: &[Probe]) -> io::Result<()> {
+    out.write_all(BINARY_MAGIC)?;
+    for n in grid.counts {
+        out.write_all(&n.to_le_bytes())?;
+    }
+    for probe in probes {
+        let position = [probe.p.x(), probe.p.y(), probe.p.z()];
+        let coefficients = probe.sh.iter().flat_map(|c| [c.r(), c.g(), c.b()]);
+        for value in position.into_iter().chain(coefficients) {
+            out.write_all(&(value as f32).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable_list::HittableList;
+
+    #[test]
+    fn projects_the_sky_onto_spherical_harmonics() {
+        let mut scene = Scene::from(HittableList::default());
+        scene.background = Background::Gradient {
+            top: Color::gray(1.0),
+            bottom: Color::gray(0.0),
+        };
+        let mut grid = ProbeGrid::from(Point3::new(), Point3::from(2.0, 0.0, 0.0), [3, 1, 1]);
+        grid.samples = 1024;
+        let positions = grid.positions();
+        approx::assert_relative_eq!(positions[1].x(), 1.0);
+        approx::assert_relative_eq!(positions[2].x(), 2.0);
+
+        let probes = grid.render(&scene);
+        assert_eq!(probes.len(), 3);
+        let up = Vec3::from(0.0, 1.0, 0.0);
+        let probe = &probes[0];
+        assert!(probe.radiance(&up).g() > 0.8);
+        assert!(probe.radiance(&-up).g() < 0.2);
+        // Irradiance is smoother than radiance but ordered the same way.
+        let (top, bottom) = (probe.irradiance(&up).g(), probe.irradiance(&-up).g());
+        assert!(top > bottom && top < 1.0 && bottom > 0.0);
+
+        let mut json = Vec::new();
+        write_json(&mut json, &grid, &probes).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"counts\": [3, 1, 1]"));
+        assert_eq!(json.matches("\"position\"").count(), 3);
+        let mut binary = Vec::new();
+        write_binary(&mut binary, &grid, &probes).unwrap();
+        assert_eq!(binary.len(), 8 + 12 + 3 * 10 * 12);
+    }
+}
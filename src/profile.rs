@@ -0,0 +1,33 @@
+// Profiling hooks. With the `profiling` feature, the stages of a render (scene
+// loading, passes, scanlines, image output) are `tracing` spans that any
+// subscriber can record, e.g. tracing-chrome for chrome://tracing and Perfetto,
+// or tracing-tracy for Tracy. Without it the spans compile to nothing.
+
+#[cfg(feature = "profiling")]
+pub use tracing;
+
+// Opens a span named `$name`, with optional `key = value` fields, that lasts
+// until the end of the enclosing block.
+#[macro_export]
+macro_rules! stage_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "profiling")]
+        let _stage_span = $crate::profile::tracing::info_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
+// Records all spans to a Chrome trace file at `path`, written out when the
+// returned guard is dropped.
+#[cfg(feature = "profiling")]
+pub fn record_chrome_trace(path: &std::path::Path) -> tracing_chrome::FlushGuard {
+    use tracing_subscriber::prelude::*;
+
+    let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+        .file(path)
+        .include_args(true)
+        .build();
+    // Not `init()`, which would also take over the `log` crate from env_logger.
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .expect("a tracing subscriber is already installed");
+    guard
+}
@@ -0,0 +1,294 @@
+use crate::stats;
+use crate::utility::Instant;
+
+use log::info;
+use std::time::Duration;
+
+// Typed progress reports from a render, for library users and front ends.
+// Subscribe by passing a callback, which can forward the events anywhere,
+// e.g. into a channel read by a UI thread:
+//
+//     let (sender, receiver) = std::sync::mpsc::channel();
+//     camera.render_with_progress(&scene, &mut out, &mut |event| {
+//         sender.send(event.clone()).ok();
+//     })?;
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    Started {
+        width: i32,
+        height: i32,
+        samples_per_pixel: i32,
+    },
+    TileStarted(Tile),
+    TileFinished {
+        tile: Tile,
+        elapsed: Duration,
+    },
+    // A progressive pass over the whole image, counting from 1.
+    PassComplete {
+        pass: i32,
+        passes: i32,
+    },
+    Progress(Stats),
+    Finished(Stats),
+}
+
+// Rectangle of pixels [x0, x1) x [y0, y1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl Tile {
+    pub fn row(j: i32, width: i32) -> Self {
+        Self {
+            x0: 0,
+            y0: j,
+            x1: width,
+            y1: j + 1,
+        }
+    }
+
+    // Square tiles of `size` pixels covering the image row by row from the
+    // top left, those on the right and bottom edges cut to fit.
+    pub fn grid(width: i32, height: i32, size: i32) -> Vec<Self> {
+        let size = size.max(1);
+        let mut tiles = Vec::new();
+        for y0 in (0..height).step_by(size as usize) {
+            for x0 in (0..width).step_by(size as usize) {
+                tiles.push(Self {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(width),
+                    y1: (y0 + size).min(height),
+                });
+            }
+        }
+        tiles
+    }
+
+    pub fn pixels(&self) -> u64 {
+        ((self.x1 - self.x0).max(0) as u64) * ((self.y1 - self.y0).max(0) as u64)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub samples_done: u64,
+    pub samples_total: u64,
+    pub rays: u64,
+    pub elapsed: Duration,
+    pub samples_per_sec: f64,
+    pub rays_per_sec: f64,
+    // The most recently finished tile.
+    pub tile: Option<Tile>,
+    // None until there is enough data for an estimate.
+    pub eta: Option<Duration>,
+}
+
+impl Stats {
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            return 1.0;
+        }
+        self.samples_done as f64 / self.samples_total as f64
+    }
+}
+
+// Turns tile and pass boundaries into events, keeping the timing needed for
+// rates and the ETA.
+pub struct Tracker<'a> {
+    sink: &'a mut dyn FnMut(&ProgressEvent),
+    start: Instant,
+    tile_start: Instant,
+    start_rays: u64,
+    tile: Option<Tile>,
+    samples_done: u64,
+    samples_total: u64,
+}
+
+impl<'a> Tracker<'a> {
+    pub fn start(
+        width: i32,
+        height: i32,
+        samples_per_pixel: i32,
+        sink: &'a mut dyn FnMut(&ProgressEvent),
+    ) -> Self {
+        sink(&ProgressEvent::Started {
+            width,
+            height,
+            samples_per_pixel,
+        });
+        let now = Instant::now();
+        Self {
+            sink,
+            start: now,
+            tile_start: now,
+            start_rays: stats::rays_traced(),
+            tile: None,
+            samples_done: 0,
+            samples_total: width.max(0) as u64 * height.max(0) as u64 * samples_per_pixel as u64,
+        }
+    }
+
+    pub fn tile_started(&mut self, tile: Tile) {
+        self.tile_start = Instant::now();
+        (self.sink)(&ProgressEvent::TileStarted(tile));
+    }
+
+    // Reports the tile and overall progress after `samples` more samples.
+    pub fn tile_finished(&mut self, tile: Tile, samples: u64) {
+        self.tile_finished_after(tile, self.tile_start.elapsed(), samples);
+    }
+
+    // Like `tile_finished`, for tiles rendered elsewhere in `elapsed`, such
+    // as on another thread while other tiles were started.
+    pub fn tile_finished_after(&mut self, tile: Tile, elapsed: Duration, samples: u64) {
+        (self.sink)(&ProgressEvent::TileFinished { tile, elapsed });
+        self.tile = Some(tile);
+        self.add_samples(samples);
+    }
+
+    pub fn pass_complete(&mut self, pass: i32, passes: i32, samples: u64) {
+        (self.sink)(&ProgressEvent::PassComplete { pass, passes });
+        self.add_samples(samples);
+    }
+
+    pub fn finish(self) -> Stats {
+        let stats = self.stats();
+        (self.sink)(&ProgressEvent::Finished(stats));
+        stats
+    }
+
+    fn add_samples(&mut self, samples: u64) {
+        self.samples_done += samples;
+        let stats = self.stats();
+        (self.sink)(&ProgressEvent::Progress(stats));
+    }
+
+    fn stats(&self) -> Stats {
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs_f64();
+        let rate = |count: u64| {
+            if seconds > 0.0 {
+                count as f64 / seconds
+            } else {
+                0.0
+            }
+        };
+        let rays = stats::rays_traced() - self.start_rays;
+        let samples_per_sec = rate(self.samples_done);
+        let remaining = self.samples_total.saturating_sub(self.samples_done);
+        let eta = (samples_per_sec > 0.0)
+            .then(|| Duration::from_secs_f64(remaining as f64 / samples_per_sec));
+        Stats {
+            samples_done: self.samples_done,
+            samples_total: self.samples_total,
+            rays,
+            elapsed,
+            samples_per_sec,
+            rays_per_sec: rate(rays),
+            tile: self.tile,
+            eta,
+        }
+    }
+}
+
+impl ProgressEvent {
+    // One line of JSON, e.g.
+    //     {"event": "progress", "percent": 42.5, "elapsed": 3.2, "eta": 4.3,
+    //      "samples_per_sec": 81234, "rays_per_sec": 243702, "tile": [0, 17, 400, 18]}
+    // Durations are in seconds, and unknown values are null.
+    pub fn to_json(&self) -> String {
+        let tile = |tile: &Tile| format!("[{}, {}, {}, {}]", tile.x0, tile.y0, tile.x1, tile.y1);
+        let stats = |event: &str, stats: &Stats| {
+            format!(
+                "{{\"event\": \"{}\", \"percent\": {:.2}, \"elapsed\": {:.3}, \"eta\": {}, \"samples\": {}, \"rays\": {}, \"samples_per_sec\": {:.0}, \"rays_per_sec\": {:.0}, \"tile\": {}}}",
+                event,
+                stats.fraction() * 100.0,
+                stats.elapsed.as_secs_f64(),
+                stats.eta.map_or("null".to_string(), |eta| format!("{:.3}", eta.as_secs_f64())),
+                stats.samples_done,
+                stats.rays,
+                stats.samples_per_sec,
+                stats.rays_per_sec,
+                stats.tile.as_ref().map_or("null".to_string(), tile)
+            )
+        };
+        match self {
+            ProgressEvent::Started {
+                width,
+                height,
+                samples_per_pixel,
+            } => format!(
+                "{{\"event\": \"started\", \"width\": {}, \"height\": {}, \"samples_per_pixel\": {}}}",
+                width, height, samples_per_pixel
+            ),
+            ProgressEvent::TileStarted(t) => {
+                format!("{{\"event\": \"tile_started\", \"tile\": {}}}", tile(t))
+            }
+            ProgressEvent::TileFinished { tile: t, elapsed } => format!(
+                "{{\"event\": \"tile_finished\", \"tile\": {}, \"elapsed\": {:.3}}}",
+                tile(t),
+                elapsed.as_secs_f64()
+            ),
+            ProgressEvent::PassComplete { pass, passes } => format!(
+                "{{\"event\": \"pass_complete\", \"pass\": {}, \"passes\": {}}}",
+                pass, passes
+            ),
+            ProgressEvent::Progress(s) => stats("progress", s),
+            ProgressEvent::Finished(s) => stats("finished", s),
+        }
+    }
+}
+
+// Sink that reports through the log, as renders did before progress events.
+pub fn log(event: &ProgressEvent) {
+    match event {
+        ProgressEvent::TileStarted(tile) => info!(
+            "Rendering pixels {}..{} of rows {}..{}",
+            tile.x0, tile.x1, tile.y0, tile.y1
+        ),
+        ProgressEvent::PassComplete { pass, passes } => info!("Pass {} of {} done", pass, passes),
+        ProgressEvent::Finished(stats) => info!(
+            "Done in {:?} ({:.0} samples/s).",
+            stats.elapsed, stats.samples_per_sec
+        ),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tracker_events() {
+        let mut events = Vec::new();
+        let mut sink = |event: &ProgressEvent| events.push(event.clone());
+        let mut tracker = Tracker::start(4, 2, 3, &mut sink);
+        for j in 0..2 {
+            tracker.tile_started(Tile::row(j, 4));
+            tracker.tile_finished(Tile::row(j, 4), 12);
+        }
+        let stats = tracker.finish();
+
+        assert_eq!(stats.samples_total, 24);
+        assert_eq!(stats.fraction(), 1.0);
+        assert_eq!(events.len(), 8);
+        assert!(matches!(events[0], ProgressEvent::Started { width: 4, .. }));
+        match &events[3] {
+            ProgressEvent::Progress(stats) => assert_eq!(stats.fraction(), 0.5),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(events[7], ProgressEvent::Finished(_)));
+        assert_eq!(
+            events[2].to_json(),
+            "{\"event\": \"tile_finished\", \"tile\": [0, 0, 4, 1], \"elapsed\": 0.000}"
+        );
+        assert!(events[3].to_json().contains("\"percent\": 50.00"));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::sampler::Sampler;
+use crate::utility::{Interval, INFINITY};
+use crate::vec3::*;
+
+// A parallelogram with a corner at `q` and edges `u` and `v` from it, such as
+// a wall or an area light. Its texture coordinates run from 0 to 1 along the
+// edges, and it faces the side of cross(u, v).
+#[derive(Clone)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3, // n / dot(n, n) for the unnormalized normal n, to find the coordinates of hits
+    normal: Vec3,
+    d: f64, // Offset of the plane: dot(normal, p) == d on it
+    area: f64,
+    material: Material,
+}
+
+impl Quad {
+    pub fn from(q: Point3, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = cross(&u, &v);
+        let normal = unit_vector(n);
+        Self {
+            q,
+            u,
+            v,
+            w: n / dot(&n, &n),
+            normal,
+            d: dot(&normal, &q),
+            area: n.length(),
+            material,
+        }
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // The ray parameter and texture coordinates where the ray meets the quad.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, f64, f64)> {
+        let denom = dot(&self.normal, &r.direction());
+        // Rays parallel to the plane miss it.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - dot(&self.normal, &r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let planar = r.at(t) - self.q;
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        let unit = 0.0..=1.0;
+        if !unit.contains(&alpha) || !unit.contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (t, u, v) = self.intersect(r, &ray_t)?;
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: self.normal,
+            geometric_normal: self.normal,
+            t,
+            u,
+            v,
+            dpdu: self.u,
+            dpdv: self.v,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: &self.material,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &self.normal);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::union(
+            &Aabb::from_points(self.q, self.q + self.u + self.v),
+            &Aabb::from_points(self.q + self.u, self.q + self.v),
+        )
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        Some((self.q + u * self.u + v * self.v, self.normal))
+    }
+
+    // A uniformly chosen point of the quad, seen from `origin`, covers a solid
+    // angle of area * cos / distance² per unit area.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::from(origin, direction);
+        let Some((t, _, _)) = self.intersect(&r, &Interval::from(0.001, INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = dot(&direction, &self.normal).abs() / direction.length();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.get_2d();
+        self.q + a * self.u + b * self.v - origin
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("quad", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn hits_inside_the_edges() {
+        // A 2 by 4 rectangle in the z = 0 plane, facing +z.
+        let quad = Quad::from(
+            Point3::new(),
+            Vec3::from(2.0, 0.0, 0.0),
+            Vec3::from(0.0, 4.0, 0.0),
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+        let down = |x, y| Ray::from(Point3::from(x, y, 3.0), Vec3::from(0.0, 0.0, -1.0));
+
+        let rec = quad.hit(&down(0.5, 3.0), ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 3.0);
+        approx::assert_relative_eq!(rec.u, 0.25);
+        approx::assert_relative_eq!(rec.v, 0.75);
+        assert!(rec.front_face && rec.normal.z() > 0.0);
+
+        let up = Ray::from(Point3::from(1.0, 1.0, -3.0), Vec3::from(0.0, 0.0, 1.0));
+        assert!(!quad.hit(&up, ray_t).unwrap().front_face);
+        assert!(quad.hit(&down(2.5, 1.0), ray_t).is_none());
+        assert!(!quad.is_occluded(&down(1.0, 1.0), Interval::from(0.001, 2.0)));
+        let along = Ray::from(Point3::from(-1.0, 1.0, 0.0), Vec3::from(1.0, 0.0, 0.0));
+        assert!(quad.hit(&along, ray_t).is_none());
+        approx::assert_relative_eq!(quad.bounding_box().y.max, 4.0, epsilon = 1e-3);
+    }
+}
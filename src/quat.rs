@@ -0,0 +1,222 @@
+use crate::vec3::*;
+
+use std::ops;
+
+// Unit quaternion rotation, w + xi + yj + zk. Composes with `*`, where
+// `a * b` applies `b` first.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Quat {
+    w: f64,
+    v: Vec3,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        w: 1.0,
+        v: Vec3::ZERO,
+    };
+
+    pub fn from(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self {
+            w,
+            v: Vec3::from(x, y, z),
+        }
+    }
+
+    // Rotation by `angle` radians counter-clockwise about `axis`.
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Self {
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Self {
+            w: cos,
+            v: unit_vector(*axis) * sin,
+        }
+    }
+
+    // Rotation from Euler angles in radians: roll about Z, then pitch about
+    // X, then yaw about Y (the usual order for a Y-up camera).
+    pub fn from_euler(yaw: f64, pitch: f64, roll: f64) -> Self {
+        Self::from_axis_angle(&Vec3::from(0.0, 1.0, 0.0), yaw)
+            * Self::from_axis_angle(&Vec3::from(1.0, 0.0, 0.0), pitch)
+            * Self::from_axis_angle(&Vec3::from(0.0, 0.0, 1.0), roll)
+    }
+
+    // The shortest rotation taking direction `from` onto direction `to`.
+    pub fn from_rotation_arc(from: &Vec3, to: &Vec3) -> Self {
+        let (a, b) = (unit_vector(*from), unit_vector(*to));
+        let d = dot(&a, &b);
+        if d < -1.0 + 1e-12 {
+            // Opposite directions: turn half way round any perpendicular axis.
+            let mut axis = cross(&Vec3::from(1.0, 0.0, 0.0), &a);
+            if axis.near_zero() {
+                axis = cross(&Vec3::from(0.0, 1.0, 0.0), &a);
+            }
+            return Self::from_axis_angle(&axis, std::f64::consts::PI);
+        }
+        Self {
+            w: 1.0 + d,
+            v: cross(&a, &b),
+        }
+        .normalize()
+    }
+
+    pub fn w(&self) -> f64 {
+        self.w
+    }
+
+    pub fn x(&self) -> f64 {
+        self.v.x()
+    }
+
+    pub fn y(&self) -> f64 {
+        self.v.y()
+    }
+
+    pub fn z(&self) -> f64 {
+        self.v.z()
+    }
+
+    pub fn dot(&self, other: &Quat) -> f64 {
+        self.w * other.w + dot(&self.v, &other.v)
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        Self {
+            w: self.w / length,
+            v: self.v / length,
+        }
+    }
+
+    // The inverse rotation, for unit quaternions.
+    pub fn conjugate(&self) -> Self {
+        Self {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    // Axis and angle in radians; the axis is arbitrary for the identity.
+    pub fn to_axis_angle(&self) -> (Vec3, f64) {
+        let q = if self.w < 0.0 { -*self } else { *self };
+        let sin = q.v.length();
+        if sin < 1e-12 {
+            return (Vec3::from(1.0, 0.0, 0.0), 0.0);
+        }
+        (q.v / sin, 2.0 * sin.atan2(q.w))
+    }
+
+    pub fn rotate(&self, p: &Vec3) -> Vec3 {
+        // p' = q p q*, expanded to avoid building the pure quaternion.
+        let t = 2.0 * cross(&self.v, p);
+        *p + self.w * t + cross(&self.v, &t)
+    }
+
+    // Spherical interpolation along the shorter arc: `a` at t = 0, `b` at t = 1.
+    pub fn slerp(a: &Quat, b: &Quat, t: f64) -> Self {
+        let mut b = *b;
+        let mut cos = a.dot(&b);
+        if cos < 0.0 {
+            b = -b;
+            cos = -cos;
+        }
+        if cos > 0.9995 {
+            // Nearly parallel: fall back to a normalized lerp.
+            return Self {
+                w: a.w + t * (b.w - a.w),
+                v: lerp(&a.v, &b.v, t),
+            }
+            .normalize();
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let (wa, wb) = (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin);
+        Self {
+            w: wa * a.w + wb * b.w,
+            v: wa * a.v + wb * b.v,
+        }
+    }
+}
+
+impl ops::Mul for Quat {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            w: self.w * rhs.w - dot(&self.v, &rhs.v),
+            v: self.w * rhs.v + rhs.w * self.v + cross(&self.v, &rhs.v),
+        }
+    }
+}
+
+impl ops::Neg for Quat {
+    type Output = Self;
+    fn neg(self) -> Self::Output {
+        Self {
+            w: -self.w,
+            v: -self.v,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::PI;
+    use approx::assert_relative_eq;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        for k in 0..3 {
+            assert_relative_eq!(a[k], b[k], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        let q = Quat::from_axis_angle(&Vec3::from(0.0, 1.0, 0.0), PI / 2.0);
+        assert_vec_eq(
+            q.rotate(&Vec3::from(1.0, 0.0, 0.0)),
+            Vec3::from(0.0, 0.0, -1.0),
+        );
+        assert_vec_eq(
+            (q * q.conjugate()).rotate(&Vec3::from(1.0, 2.0, 3.0)),
+            Vec3::from(1.0, 2.0, 3.0),
+        );
+
+        let (axis, angle) = (q * q).to_axis_angle();
+        assert_vec_eq(axis, Vec3::from(0.0, 1.0, 0.0));
+        assert_relative_eq!(angle, PI, epsilon = 1e-12);
+
+        // The yaw is applied last.
+        let e = Quat::from_euler(PI / 2.0, PI / 2.0, 0.0);
+        assert_vec_eq(
+            e.rotate(&Vec3::from(0.0, 0.0, 1.0)),
+            Vec3::from(0.0, -1.0, 0.0),
+        );
+
+        let arc = Quat::from_rotation_arc(&Vec3::from(1.0, 0.0, 0.0), &Vec3::from(0.0, 3.0, 0.0));
+        assert_vec_eq(
+            arc.rotate(&Vec3::from(1.0, 0.0, 0.0)),
+            Vec3::from(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn slerp_halfway() {
+        let a = Quat::IDENTITY;
+        let b = Quat::from_axis_angle(&Vec3::from(0.0, 0.0, 1.0), PI / 2.0);
+        let mid = Quat::slerp(&a, &b, 0.5);
+        let expected = Quat::from_axis_angle(&Vec3::from(0.0, 0.0, 1.0), PI / 4.0);
+        assert_relative_eq!(mid.dot(&expected), 1.0, epsilon = 1e-12);
+        assert_eq!(Quat::slerp(&a, &b, 0.0), a);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::camera::CameraBuilder;
+use crate::scene::Scene;
+use crate::utility::Instant;
+
+use log::info;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// A batch of scenes rendered one after another, or several at a time with
+// bounded parallelism. A failing job is reported and doesn't stop the others.
+pub struct QueueJob {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+}
+
+pub struct JobReport {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+    pub result: Result<Duration, String>,
+}
+
+impl QueueJob {
+    // Output named after the scene, e.g. scenes/spiral.rhai -> DIR/spiral.ppm
+    pub fn from_scene(scene: &Path, output_dir: &Path) -> Self {
+        let stem = scene
+            .file_stem()
+            .map_or("render".into(), |s| s.to_string_lossy());
+        Self {
+            scene: scene.to_path_buf(),
+            output: output_dir.join(format!("{}.ppm", stem)),
+        }
+    }
+}
+
+// Reads a manifest listing one job per line as `SCENE [OUTPUT]`. Blank lines
+// and lines starting with '#' are skipped; relative paths are resolved against
+// the manifest's directory, outputs default to DIR/<scene name>.ppm. A line
+// with more than the two fields is an error rather than a guess.
+pub fn read_manifest(path: &Path, output_dir: &Path) -> io::Result<Vec<QueueJob>> {
+    let base = path.parent().unwrap_or(Path::new("."));
+    let contents = fs::read_to_string(path)?;
+
+    let mut jobs = Vec::new();
+    for (number, line) in contents.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let scene = base.join(parts.next().unwrap());
+        let output = parts.next();
+        if parts.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "line {}: expected `SCENE [OUTPUT]`, got '{}'",
+                    number + 1,
+                    line
+                ),
+            ));
+        }
+        let job = match output {
+            Some(output) => QueueJob {
+                scene,
+                output: output_dir.join(output),
+            },
+            None => QueueJob::from_scene(&scene, output_dir),
+        };
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+pub fn run<L, C>(jobs: &[QueueJob], parallelism: usize, load: L, camera: C) -> Vec<JobReport>
+where
+    L: Fn(&Path) -> Result<Scene, String> + Sync,
+    C: Fn() -> CameraBuilder + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(index) else {
+                    return;
+                };
+
+                info!("Rendering {}", job.scene.display());
+                let result = render_job(job, &load, &camera);
+                reports.lock().unwrap().push((
+                    index,
+                    JobReport {
+                        scene: job.scene.clone(),
+                        output: job.output.clone(),
+                        result,
+                    },
+                ));
+            });
+        }
+    });
+
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(index, _)| *index);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+fn render_job<L, C>(job: &QueueJob, load: &L, camera: &C) -> Result<Duration, String>
+where
+    L: Fn(&Path) -> Result<Scene, String>,
+    C: Fn() -> CameraBuilder,
+{
+    let time_start = Instant::now();
+    let camera = camera().build()?;
+    let scene = load(&job.scene)?;
+
+    if let Some(dir) = job.output.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    let file = File::create(&job.output).map_err(|err| err.to_string())?;
+    camera
+        .render_to(&scene, &mut BufWriter::new(file))
+        .map_err(|err| err.to_string())?;
+
+    Ok(time_start.elapsed())
+}
+
+pub fn write_summary(out: &mut impl Write, reports: &[JobReport]) -> io::Result<()> {
+    let failed = reports.iter().filter(|r| r.result.is_err()).count();
+    let total: Duration = reports.iter().filter_map(|r| r.result.as_ref().ok()).sum();
+
+    writeln!(out, "Render queue summary")?;
+    for report in reports {
+        match &report.result {
+            Ok(duration) => writeln!(
+                out,
+                "  ok      {:>10.2?}  {} -> {}",
+                duration,
+                report.scene.display(),
+                report.output.display()
+            )?,
+            Err(err) => writeln!(
+                out,
+                "  FAILED              {}: {}",
+                report.scene.display(),
+                err
+            )?,
+        }
+    }
+    writeln!(
+        out,
+        "{} succeeded, {} failed, {:.2?} total render time",
+        reports.len() - failed,
+        failed,
+        total
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_manifests() {
+        let dir = std::env::temp_dir().join(format!("queue-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("jobs.txt");
+        let out = Path::new("renders");
+
+        fs::write(
+            &manifest,
+            "# overnight\n\nscenes/spiral.rhai\n  tree.rhai   big/tree.png  \n",
+        )
+        .unwrap();
+        let jobs = read_manifest(&manifest, out).unwrap();
+        let jobs: Vec<_> = jobs.iter().map(|j| (&j.scene, &j.output)).collect();
+        assert_eq!(
+            jobs,
+            [
+                (&dir.join("scenes/spiral.rhai"), &out.join("spiral.ppm")),
+                (&dir.join("tree.rhai"), &out.join("big/tree.png")),
+            ]
+        );
+
+        fs::write(&manifest, "a.rhai\nb.rhai out.ppm extra\n").unwrap();
+        let err = read_manifest(&manifest, out).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(read_manifest(&manifest, out).is_err());
+    }
+
+    #[test]
+    fn summarizes_reports() {
+        let reports = [
+            JobReport {
+                scene: PathBuf::from("a.rhai"),
+                output: PathBuf::from("a.ppm"),
+                result: Ok(Duration::from_millis(1500)),
+            },
+            JobReport {
+                scene: PathBuf::from("b.rhai"),
+                output: PathBuf::from("b.ppm"),
+                result: Err("no such file".to_string()),
+            },
+            JobReport {
+                scene: PathBuf::from("c.rhai"),
+                output: PathBuf::from("c.ppm"),
+                result: Ok(Duration::from_millis(500)),
+            },
+        ];
+        let mut out = Vec::new();
+        write_summary(&mut out, &reports).unwrap();
+        let summary = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = summary.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "Render queue summary");
+        assert!(lines[1].starts_with("  ok ") && lines[1].ends_with("a.rhai -> a.ppm"));
+        assert_eq!(lines[2], "  FAILED              b.rhai: no such file");
+        assert_eq!(lines[4], "2 succeeded, 1 failed, 2.00s total render time");
+    }
+}
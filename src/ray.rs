@@ -1,9 +1,25 @@
 use crate::vec3::Vec3;
 pub type Point3 = crate::vec3::Vec3;
 
+// Trace sets are bits of a u32 mask. A ray only hits objects whose mask
+// shares a bit with its own. Objects are in the default set unless put in
+// others, and rays can hit every set unless restricted.
+pub const DEFAULT_SET: u32 = 1;
+pub const ALL_SETS: u32 = u32::MAX;
+
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    mask: u32, // Trace sets this ray can hit
+    time: f64, // Moment within the camera's shutter interval the ray is traced at
+    // Seed of random choices made along the ray, such as where it scatters in
+    // fog, drawn from the camera sample tracing it. Rays without one use the
+    // thread's generator.
+    key: Option<u64>,
+    // Where the path this ray belongs to was seen from, which picks levels of
+    // detail. Camera rays start there and the rays traced on from them keep
+    // it; other rays are their own viewer.
+    eye: Point3,
 }
 
 impl Ray {
@@ -12,9 +28,33 @@ impl Ray {
         Self {
             orig: origin,
             dir: direction,
+            mask: ALL_SETS,
+            time: 0.0,
+            key: None,
+            eye: origin,
         }
     }
 
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_key(mut self, key: u64) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn with_eye(mut self, eye: Point3) -> Self {
+        self.eye = eye;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         // data copied
         self.orig + self.dir * t
@@ -27,4 +67,20 @@ impl Ray {
     pub fn direction(&self) -> Vec3 {
         self.dir
     }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn key(&self) -> Option<u64> {
+        self.key
+    }
+
+    pub fn eye(&self) -> Point3 {
+        self.eye
+    }
 }
@@ -0,0 +1,444 @@
+use crate::capsule::Capsule;
+use crate::cone::Cone;
+use crate::cuboid::Cuboid;
+use crate::cylinder::Cylinder;
+use crate::disk::Disk;
+use crate::error::Error;
+use crate::hittable::Hittable;
+use crate::material::{Dieletric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::mesh::Mesh;
+use crate::obj;
+use crate::plane::Plane;
+use crate::ply;
+use crate::quad::Quad;
+use crate::sphere::Sphere;
+use crate::stl;
+use crate::texture::{CheckerTexture, ImageTexture, SolidColor, Texture};
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// A registry of named factories. Scene files refer to geometry, materials and
+// textures by name, so downstream crates can register their own `Hittable`s,
+// `Scatterable`s (through `Material::Custom`) or `Texture`s without touching
+// the core.
+//
+//     let mut registry = Registry::with_builtins();
+//     registry.register_hittable("torus", |params| {
+//         Ok(Box::new(Torus::from(params.vec3("center")?, params.float("radius")?)))
+//     });
+#[derive(Clone)]
+pub enum Param {
+    Float(f64),
+    Vec3(Vec3),
+    Str(String),
+    Bool(bool),
+    Material(Material),
+    Texture(Arc<dyn Texture>),
+}
+
+#[derive(Clone, Default)]
+pub struct Params {
+    values: HashMap<String, Param>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: &str, value: Param) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.values.get(name)
+    }
+
+    pub fn float(&self, name: &str) -> Result<f64, String> {
+        match self.get(name) {
+            Some(Param::Float(x)) => Ok(*x),
+            Some(_) => Err(format!("parameter '{}' must be a number", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+
+    pub fn float_or(&self, name: &str, default: f64) -> Result<f64, String> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(_) => self.float(name),
+        }
+    }
+
+    pub fn vec3(&self, name: &str) -> Result<Vec3, String> {
+        match self.get(name) {
+            Some(Param::Vec3(v)) => Ok(*v),
+            Some(_) => Err(format!("parameter '{}' must be a vec3", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+
+    pub fn str(&self, name: &str) -> Result<&str, String> {
+        match self.get(name) {
+            Some(Param::Str(s)) => Ok(s),
+            Some(_) => Err(format!("parameter '{}' must be a string", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Result<bool, String> {
+        match self.get(name) {
+            Some(Param::Bool(b)) => Ok(*b),
+            Some(_) => Err(format!("parameter '{}' must be a bool", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+
+    pub fn bool_or(&self, name: &str, default: bool) -> Result<bool, String> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(_) => self.bool(name),
+        }
+    }
+
+    pub fn material(&self, name: &str) -> Result<Material, String> {
+        match self.get(name) {
+            Some(Param::Material(m)) => Ok(m.clone()),
+            Some(_) => Err(format!("parameter '{}' must be a material", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+
+    // A texture, or a color standing for a solid one.
+    pub fn texture(&self, name: &str) -> Result<Arc<dyn Texture>, String> {
+        match self.get(name) {
+            Some(Param::Texture(t)) => Ok(t.clone()),
+            Some(Param::Vec3(v)) => Ok(Arc::new(SolidColor::from((*v).into()))),
+            Some(_) => Err(format!("parameter '{}' must be a texture or a color", name)),
+            None => Err(format!("missing parameter '{}'", name)),
+        }
+    }
+}
+
+pub type HittableFactory = Box<dyn Fn(&Params) -> Result<Box<dyn Hittable>, String> + Send + Sync>;
+pub type MaterialFactory = Box<dyn Fn(&Params) -> Result<Material, String> + Send + Sync>;
+pub type TextureFactory = Box<dyn Fn(&Params) -> Result<Arc<dyn Texture>, String> + Send + Sync>;
+
+#[derive(Default)]
+pub struct Registry {
+    hittables: HashMap<String, HittableFactory>,
+    materials: HashMap<String, MaterialFactory>,
+    textures: HashMap<String, TextureFactory>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+
+        // With a "center1", the sphere moves there by ray time 1.
+        registry.register_hittable("sphere", |params| {
+            let (center, radius) = (params.vec3("center")?, params.float("radius")?);
+            let material = params.material("material")?;
+            Ok(Box::new(match params.get("center1") {
+                Some(_) => Sphere::moving(center, params.vec3("center1")?, radius, material),
+                None => Sphere::from(center, radius, material),
+            }))
+        });
+
+        registry.register_hittable("quad", |params| {
+            Ok(Box::new(Quad::from(
+                params.vec3("q")?,
+                params.vec3("u")?,
+                params.vec3("v")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("cuboid", |params| {
+            Ok(Box::new(Cuboid::from(
+                params.vec3("min")?,
+                params.vec3("max")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("plane", |params| {
+            Ok(Box::new(Plane::from(
+                params.vec3("point")?,
+                params.vec3("normal")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("disk", |params| {
+            Ok(Box::new(Disk::from(
+                params.vec3("center")?,
+                params.vec3("normal")?,
+                params.float("radius")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("cylinder", |params| {
+            Ok(Box::new(Cylinder::from(
+                params.vec3("base")?,
+                params.vec3("top")?,
+                params.float("radius")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("cone", |params| {
+            Ok(Box::new(Cone::from(
+                params.vec3("base")?,
+                params.vec3("apex")?,
+                params.float("radius")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("capsule", |params| {
+            Ok(Box::new(Capsule::from(
+                params.vec3("a")?,
+                params.vec3("b")?,
+                params.float("radius")?,
+                params.material("material")?,
+            )))
+        });
+
+        registry.register_hittable("triangle", |params| {
+            Ok(Box::new(Triangle::from(
+                params.vec3("a")?,
+                params.vec3("b")?,
+                params.vec3("c")?,
+                params.material("material")?,
+            )))
+        });
+        // Wavefront OBJ, Stanford PLY and STL models in one material, with
+        // `smooth` set to shade smoothly where the file has no normals.
+        registry.register_hittable("obj", |params| {
+            let path = Path::new(params.str("path")?);
+            let mesh = obj::load(path, params.material("material")?, &HashMap::new());
+            model(mesh, params)
+        });
+        registry.register_hittable("ply", |params| {
+            let path = Path::new(params.str("path")?);
+            model(ply::load(path, params.material("material")?), params)
+        });
+        registry.register_hittable("stl", |params| {
+            let path = Path::new(params.str("path")?);
+            model(stl::load(path, params.material("material")?), params)
+        });
+
+        // Lambertian surfaces take their albedo from a "texture" if given one.
+        registry.register_material("lambertian", |params| {
+            Ok(Material::Lambertian(match params.get("texture") {
+                Some(_) => Lambertian::textured(params.texture("texture")?),
+                None => Lambertian::from(params.vec3("albedo")?.into()),
+            }))
+        });
+        registry.register_material("metal", |params| {
+            Ok(Material::Metal(Metal::from(
+                params.vec3("albedo")?.into(),
+                params.float_or("fuzz", 0.0)?,
+            )))
+        });
+        registry.register_material("dielectric", |params| {
+            Ok(Material::Dieletric(Dieletric::from(params.float("ir")?)))
+        });
+        registry.register_material("diffuse_light", |params| {
+            Ok(Material::DiffuseLight(DiffuseLight::from(
+                params.vec3("emit")?.into(),
+            )))
+        });
+        registry.register_material("isotropic", |params| {
+            Ok(Material::Isotropic(Isotropic::from(
+                params.vec3("albedo")?.into(),
+            )))
+        });
+
+        registry.register_texture("solid_color", |params| {
+            Ok(Arc::new(SolidColor::from(params.vec3("color")?.into())))
+        });
+        registry.register_texture("checker", |params| {
+            Ok(Arc::new(CheckerTexture::from(
+                params.float("scale")?,
+                params.texture("even")?,
+                params.texture("odd")?,
+            )))
+        });
+        registry.register_texture("image", |params| {
+            let path = params.str("path")?;
+            let image = ImageTexture::open_shared(Path::new(path))
+                .map_err(|err| format!("cannot load {}: {}", path, err))?;
+            Ok(image)
+        });
+
+        registry
+    }
+
+    pub fn register_hittable<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&Params) -> Result<Box<dyn Hittable>, String> + Send + Sync + 'static,
+    {
+        self.hittables.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn register_material<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&Params) -> Result<Material, String> + Send + Sync + 'static,
+    {
+        self.materials.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn register_texture<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&Params) -> Result<Arc<dyn Texture>, String> + Send + Sync + 'static,
+    {
+        self.textures.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn create_hittable(
+        &self,
+        name: &str,
+        params: &Params,
+    ) -> Result<Box<dyn Hittable>, String> {
+        let factory = self
+            .hittables
+            .get(name)
+            .ok_or(format!("unknown object type '{}'", name))?;
+        factory(params).map_err(|err| format!("{}: {}", name, err))
+    }
+
+    pub fn create_material(&self, name: &str, params: &Params) -> Result<Material, String> {
+        let factory = self
+            .materials
+            .get(name)
+            .ok_or(format!("unknown material type '{}'", name))?;
+        factory(params).map_err(|err| format!("{}: {}", name, err))
+    }
+
+    pub fn create_texture(&self, name: &str, params: &Params) -> Result<Arc<dyn Texture>, String> {
+        let factory = self
+            .textures
+            .get(name)
+            .ok_or(format!("unknown texture type '{}'", name))?;
+        factory(params).map_err(|err| format!("{}: {}", name, err))
+    }
+}
+
+// A loaded model as a factory's object, smoothed if `params` ask for it.
+fn model(mesh: Result<Mesh, Error>, params: &Params) -> Result<Box<dyn Hittable>, String> {
+    let mesh = mesh.map_err(|err| err.to_string())?;
+    Ok(Box::new(if params.bool_or("smooth", false)? {
+        mesh.with_smooth_normals()
+    } else {
+        mesh
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::ray::{Point3, Ray};
+    use crate::utility::{Interval, INFINITY};
+
+    // A texture a downstream crate might add: red above the xz plane, blue below.
+    struct Split;
+
+    impl Texture for Split {
+        fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+            if p.y() >= 0.0 {
+                Color::from(1.0, 0.0, 0.0)
+            } else {
+                Color::from(0.0, 0.0, 1.0)
+            }
+        }
+    }
+
+    fn albedo(material: &Material, p: Point3) -> Color {
+        let sphere = Sphere::from(Point3::new(), 1.0, material.clone());
+        let r = Ray::from(p * 3.0, -p);
+        material.albedo(&sphere.hit(&r, Interval::from(0.001, INFINITY)).unwrap())
+    }
+
+    #[test]
+    fn creates_registered_types() {
+        let mut registry = Registry::with_builtins();
+        registry.register_texture("split", |_| Ok(Arc::new(Split)));
+
+        let mut params = Params::new();
+        params.set(
+            "texture",
+            Param::Texture(registry.create_texture("split", &params).unwrap()),
+        );
+        let material = registry.create_material("lambertian", &params).unwrap();
+        let (up, down) = (Point3::from(0.0, 1.0, 0.0), Point3::from(0.0, -1.0, 0.0));
+        assert_eq!(albedo(&material, up), Color::from(1.0, 0.0, 0.0));
+        assert_eq!(albedo(&material, down), Color::from(0.0, 0.0, 1.0));
+
+        params.set("center", Param::Vec3(Point3::new()));
+        params.set("radius", Param::Float(1.0));
+        params.set("material", Param::Material(material));
+        let sphere = registry.create_hittable("sphere", &params).unwrap();
+        let r = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&r, Interval::from(0.001, INFINITY)).is_some());
+    }
+
+    #[test]
+    fn colors_stand_for_solid_textures() {
+        let registry = Registry::with_builtins();
+        let mut params = Params::new();
+        params.set("scale", Param::Float(1.0));
+        params.set("even", Param::Vec3(Vec3::from(1.0, 1.0, 1.0)));
+        params.set("odd", Param::Vec3(Vec3::from(0.0, 0.0, 0.0)));
+        let checker = registry.create_texture("checker", &params).unwrap();
+        assert_eq!(
+            checker.value(0.0, 0.0, &Point3::from(0.5, 0.5, 0.5)),
+            Color::gray(1.0)
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, &Point3::from(1.5, 0.5, 0.5)),
+            Color::gray(0.0)
+        );
+    }
+
+    #[test]
+    fn reports_unknown_types_and_bad_parameters() {
+        let registry = Registry::with_builtins();
+        let mut params = Params::new();
+        let unknown = |result: Result<Arc<dyn Texture>, String>| result.err().unwrap();
+        assert_eq!(
+            unknown(registry.create_texture("marble", &params)),
+            "unknown texture type 'marble'"
+        );
+        assert!(registry.create_hittable("torus", &params).is_err());
+        assert!(registry.create_material("velvet", &params).is_err());
+
+        assert_eq!(
+            registry.create_material("metal", &params).err().unwrap(),
+            "metal: missing parameter 'albedo'"
+        );
+        params.set("albedo", Param::Str("red".to_string()));
+        assert_eq!(
+            registry.create_material("metal", &params).err().unwrap(),
+            "metal: parameter 'albedo' must be a vec3"
+        );
+        params.set("texture", Param::Bool(true));
+        assert_eq!(
+            registry
+                .create_material("lambertian", &params)
+                .err()
+                .unwrap(),
+            "lambertian: parameter 'texture' must be a texture or a color"
+        );
+    }
+}
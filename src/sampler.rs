@@ -0,0 +1,446 @@
+use crate::bluenoise;
+use crate::utility::random;
+
+use std::fmt;
+use std::str::FromStr;
+
+// Where the random numbers for a camera sample come from. The quasi-Monte
+// Carlo sequences fill the sample space much more evenly than independent
+// random numbers, so smooth integrands (soft shadows, defocus, antialiasing)
+// converge faster.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SamplerKind {
+    #[default]
+    Random,
+    // Jittered samples, one per cell of a square grid over each pair of
+    // dimensions, as many cells as the pixel has samples.
+    Stratified,
+    // Halton sequence with a random digit permutation per dimension.
+    Halton,
+    // Sobol sequence with hash-based Owen scrambling.
+    Sobol,
+    // Rank-1 lattice per pixel, offset by a blue-noise mask so that the error
+    // left at low sample counts is spread as high-frequency noise.
+    BlueNoise,
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SamplerKind::Random),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue-noise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler '{}', expected random, stratified, halton, sobol or blue-noise",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SamplerKind::Random => "random",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue-noise",
+        })
+    }
+}
+
+// A source of sample values in [0, 1), handed out one dimension at a time.
+// Everything random about a camera sample (the position in the pixel, the
+// point on the lens and every scattering decision along the path) is drawn
+// from the sampler of that sample, in order, so a path's choices use
+// successive dimensions of the sampler's sequence.
+pub trait Sampler {
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Independent random numbers, for tracing that is not part of a camera sample.
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn get_1d(&mut self) -> f64 {
+        random()
+    }
+}
+
+// The sample values for one camera sample of one pixel. Every pixel gets its own decorrelated version of the
+// sequence, and consecutive sample indices of a pixel are consecutive points
+// of it, so a pixel's samples stay well distributed however many are taken.
+pub struct PixelSampler {
+    kind: SamplerKind,
+    pixel: (i32, i32),
+    seed: u64,
+    index: u64,
+    samples: u64, // Samples the pixel gets in all, which stratified samples share out
+    dimension: u32,
+    stream: Option<u64>, // The render's seed, for random samples that repeat
+}
+
+impl PixelSampler {
+    // The sampler for sample `sample_index` of pixel (i, j). With a `seed`,
+    // random samples are drawn from it rather than from the thread's
+    // generator, so the same seed gives the same samples.
+    pub fn start(
+        kind: SamplerKind,
+        i: i32,
+        j: i32,
+        sample_index: u64,
+        samples: u64,
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            kind,
+            pixel: (i, j),
+            seed: hash(&[i as u32 as u64, j as u32 as u64]),
+            index: sample_index,
+            samples,
+            dimension: 0,
+            stream: seed,
+        }
+    }
+
+    // A uniform random value for `dimension` of this sample.
+    fn uniform(&self, dimension: u32) -> f64 {
+        match self.stream {
+            // Hashed rather than drawn in turn, so that the sample is the
+            // same whatever was rendered before it.
+            Some(stream) => to_unit(hash(&[stream, self.seed, self.index, dimension as u64])),
+            None => random(),
+        }
+    }
+
+    // Pairs of dimensions are cut into a grid with at least as many cells as
+    // the pixel has samples, which visit the cells in an order shuffled per
+    // pixel and pair. Samples past the last cell start over in a new order.
+    fn stratified(&self, dimension: u32) -> f64 {
+        let side = (self.samples.max(1) as f64).sqrt().ceil() as u64;
+        let cells = side * side;
+        let order = hash(&[self.seed, (dimension / 2) as u64, self.index / cells]);
+        let cell = permute((self.index % cells) as u32, cells as u32, order as u32) as u64;
+        let (x, y) = (cell % side, cell / side);
+        let jitter = self.uniform(dimension);
+        let stratum = if dimension.is_multiple_of(2) { x } else { y };
+        ((stratum as f64 + jitter) / side as f64).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for PixelSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match self.kind {
+            SamplerKind::Random => self.uniform(dimension),
+            SamplerKind::Stratified => self.stratified(dimension),
+            SamplerKind::Halton => halton(self.index, dimension, self.seed),
+            SamplerKind::Sobol => sobol(self.index as u32, dimension, self.seed),
+            SamplerKind::BlueNoise => blue_noise(self.pixel, self.index, dimension),
+        }
+    }
+}
+
+// A value in [0, 1) from the top 53 bits of a hash.
+pub fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Mixes a few values into a well-distributed 64-bit hash (splitmix64 steps).
+pub fn hash(values: &[u64]) -> u64 {
+    let mut h: u64 = 0x9e37_79b9_7f4a_7c15;
+    for &v in values {
+        h ^= v
+            .wrapping_add(0x9e37_79b9_7f4a_7c15)
+            .wrapping_add(h << 6)
+            .wrapping_add(h >> 2);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    h
+}
+
+// Element `index` of a pseudo-random permutation of 0..len picked by `seed`,
+// computed on its own without building the permutation (Kensler 2013).
+fn permute(mut index: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // A bijection of 0..=mask, applied until it lands within 0..len.
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < len {
+            break;
+        }
+    }
+    ((index as u64 + seed as u64) % len as u64) as u32
+}
+
+// Largest float below 1, so that samples never reach 1.0 exactly.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Point `index` of the Halton sequence in `dimension`. Dimensions past the
+// prime table reuse its bases with a different permutation. Each dimension's
+// digits go through a permutation drawn from `seed`, which decorrelates
+// pixels (and dimensions sharing a base) without breaking the stratification.
+pub fn halton(index: u64, dimension: u32, seed: u64) -> f64 {
+    let base = PRIMES[dimension as usize % PRIMES.len()];
+    let permutation_seed = hash(&[seed, dimension as u64]);
+
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut value = 0.0;
+    let mut index = index;
+    // Keep going past the last nonzero digit: permuted zeros are not zero.
+    while inv_base_m > f64::EPSILON {
+        let digit = index % base;
+        index /= base;
+        inv_base_m *= inv_base;
+        value += permute_digit(digit, base, permutation_seed) as f64 * inv_base_m;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+// Element `digit` of a pseudo-random permutation of 0..base, as a seeded
+// Fisher-Yates shuffle would produce.
+fn permute_digit(digit: u64, base: u64, seed: u64) -> u64 {
+    let mut permutation = [0u8; 131];
+    for (k, p) in permutation.iter_mut().enumerate().take(base as usize) {
+        *p = k as u8;
+    }
+    for k in (1..base as usize).rev() {
+        let r = (hash(&[seed, k as u64]) % (k as u64 + 1)) as usize;
+        permutation.swap(k, r);
+    }
+    permutation[digit as usize] as u64
+}
+
+// Direction numbers for the first four Sobol dimensions, built from the
+// primitive polynomials and initial values of Joe and Kuo.
+const SOBOL_MATRICES: [[u32; 32]; 4] = sobol_matrices();
+
+const fn sobol_matrices() -> [[u32; 32]; 4] {
+    // (degree s, coefficients a, initial m_1..m_s) per dimension after the first.
+    const PARAMS: [(usize, u32, [u32; 3]); 3] =
+        [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+    let mut matrices = [[0u32; 32]; 4];
+    let mut k = 0;
+    while k < 32 {
+        matrices[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+    let mut d = 0;
+    while d < 3 {
+        let (s, a, m) = PARAMS[d];
+        let v = &mut matrices[d + 1];
+        let mut k = 0;
+        while k < 32 {
+            if k < s {
+                v[k] = m[k] << (31 - k);
+            } else {
+                let mut value = v[k - s] ^ (v[k - s] >> s);
+                let mut l = 1;
+                while l < s {
+                    if (a >> (s - 1 - l)) & 1 == 1 {
+                        value ^= v[k - l];
+                    }
+                    l += 1;
+                }
+                v[k] = value;
+            }
+            k += 1;
+        }
+        d += 1;
+    }
+    matrices
+}
+
+fn sobol_raw(index: u32, dimension: usize) -> u32 {
+    let mut result = 0;
+    let mut index = index;
+    let mut k = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= SOBOL_MATRICES[dimension][k];
+        }
+        index >>= 1;
+        k += 1;
+    }
+    result
+}
+
+// Laine-Karras style hash that only lets bits affect higher bits, which makes
+// it an Owen scramble when applied to bit-reversed values (Burley 2020).
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Point `index` of an Owen-scrambled Sobol sequence in `dimension`, following
+// Burley's "Practical Hash-based Owen Scrambling": dimensions come in groups
+// of four, each group visiting the points in its own shuffled order, so any
+// number of dimensions can be drawn from the four-dimensional sequence.
+pub fn sobol(index: u32, dimension: u32, seed: u64) -> f64 {
+    let group_seed = hash(&[seed, (dimension / 4) as u64]) as u32;
+    let shuffled = nested_uniform_scramble(index, group_seed);
+    let x = sobol_raw(shuffled, (dimension % 4) as usize);
+    let x = nested_uniform_scramble(x, hash(&[seed, dimension as u64, 1]) as u32);
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// The plastic number, whose powers give the R2 sequence (the 2-D analogue of
+// the golden ratio sequence).
+const PLASTIC: f64 = 1.324_717_957_244_746;
+
+// Sample `index` of pixel `pixel` in `dimension`: the R2 sequence, pairing up
+// dimensions, rotated by the blue-noise mask. The first sample of every pixel
+// is the mask itself, and later samples keep its value spread across pixels.
+// Each dimension reads the tile at a different fixed offset so the
+// dimensions are not correlated with each other.
+pub fn blue_noise(pixel: (i32, i32), index: u64, dimension: u32) -> f64 {
+    let alpha = PLASTIC.powi(-(dimension as i32 % 2 + 1));
+    let offset = hash(&[dimension as u64]);
+    let x = pixel.0.wrapping_add(offset as i32 & 0xffff);
+    let y = pixel.1.wrapping_add((offset >> 16) as i32 & 0xffff);
+    let shift = bluenoise::mask().value(x, y);
+    ((shift + index as f64 * alpha) % 1.0).min(ONE_MINUS_EPSILON)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The biggest deviation from one point per cell when the points are
+    // binned into a grid of `cells_x` by `cells_y` cells.
+    fn worst_cell(points: &[(f64, f64)], cells_x: usize, cells_y: usize) -> usize {
+        let mut counts = vec![0usize; cells_x * cells_y];
+        for &(x, y) in points {
+            counts[(y * cells_y as f64) as usize * cells_x + (x * cells_x as f64) as usize] += 1;
+        }
+        counts.into_iter().map(|c| c.abs_diff(1)).max().unwrap()
+    }
+
+    #[test]
+    fn sobol_is_stratified() {
+        // The first 2^m points of a (0, 2)-sequence put exactly one point in
+        // every elementary interval, and Owen scrambling keeps that.
+        for dimension in [0, 2, 6] {
+            let points: Vec<(f64, f64)> = (0..64)
+                .map(|i| (sobol(i, dimension, 7), sobol(i, dimension + 1, 7)))
+                .collect();
+            assert_eq!(worst_cell(&points, 8, 8), 0);
+            assert_eq!(worst_cell(&points, 64, 1), 0);
+            assert_eq!(worst_cell(&points, 1, 64), 0);
+        }
+    }
+
+    #[test]
+    fn stratified_samples_fill_the_grid() {
+        let points = |samples: u64, dimension| -> Vec<(f64, f64)> {
+            (0..samples)
+                .map(|s| {
+                    let mut sampler =
+                        PixelSampler::start(SamplerKind::Stratified, 2, 5, s, samples, None);
+                    sampler.dimension = dimension;
+                    sampler.get_2d()
+                })
+                .collect()
+        };
+        for dimension in [0, 2, 4] {
+            assert_eq!(worst_cell(&points(16, dimension), 4, 4), 0);
+        }
+        // Ten samples take ten of the sixteen cells, none twice.
+        let mut cells: Vec<_> = points(10, 0)
+            .iter()
+            .map(|&(x, y)| ((x * 4.0) as u32, (y * 4.0) as u32))
+            .collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), 10);
+
+        let mut shuffled: Vec<u32> = (0..10).map(|i| permute(i, 10, 12345)).collect();
+        shuffled.sort();
+        assert_eq!(shuffled, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn halton_is_stratified() {
+        // 36 = 4 * 9 points of bases 2 and 3 fall one per cell of a 4x9 grid.
+        let points: Vec<(f64, f64)> = (0..36)
+            .map(|i| (halton(i, 0, 3), halton(i, 1, 3)))
+            .collect();
+        assert_eq!(worst_cell(&points, 4, 9), 0);
+    }
+
+    #[test]
+    fn seeded_random_samples_repeat() {
+        let draw = |i, j, s| {
+            let mut sampler = PixelSampler::start(SamplerKind::Random, i, j, s, 4, Some(11));
+            [sampler.get_1d(), sampler.get_1d(), sampler.get_1d()]
+        };
+        let first = draw(3, 4, 0);
+        // Unaffected by other pixels and samples drawn in between.
+        draw(5, 6, 1);
+        assert_eq!(draw(3, 4, 0), first);
+        assert_ne!(draw(3, 4, 1), first);
+        assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+    }
+
+    #[test]
+    fn parse_kinds() {
+        assert_eq!("sobol".parse::<SamplerKind>(), Ok(SamplerKind::Sobol));
+        assert!("poisson".parse::<SamplerKind>().is_err());
+        assert_eq!(
+            "stratified".parse::<SamplerKind>(),
+            Ok(SamplerKind::Stratified)
+        );
+        assert_eq!(SamplerKind::Halton.to_string(), "halton");
+        assert_eq!(
+            "blue-noise".parse::<SamplerKind>(),
+            Ok(SamplerKind::BlueNoise)
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::expr::{Expr, ExprInputs};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::mat4::Mat4;
+use crate::quat::Quat;
+use crate::ray::{Point3, Ray};
+use crate::transform::Transform;
+use crate::utility::{Interval, INFINITY, PI};
+use crate::vec3::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+
+// Scatters instances of prototype objects (grass, rocks, debris) over a target
+// surface. Candidate points are spread uniformly over a rectangle in the xz
+// plane and dropped straight down onto the target; a density expression keeps
+// each landed point with the probability it evaluates to there, and every
+// instance gets a random prototype, turn about the vertical and size. The
+// instances share their prototypes, so a meadow costs one blade of grass plus
+// a transform per instance.
+pub struct Scatter {
+    pub count: usize, // Candidate points; misses and density thin them out
+    pub seed: u64,
+    // Corners of the xz rectangle to scatter over; y is ignored. None covers
+    // the target's bounding box.
+    pub region: Option<(Point3, Point3)>,
+    // Chance of keeping a point, read from the x component and evaluated with
+    // the hit's u, v, p and normal. None keeps every point.
+    pub density: Option<Arc<Expr>>,
+    pub scale: (f64, f64), // Range of uniform scale factors
+    pub align: bool,       // Tilt instances to the surface normal instead of upright
+}
+
+impl Default for Scatter {
+    fn default() -> Self {
+        Self {
+            count: 100,
+            seed: 0,
+            region: None,
+            density: None,
+            scale: (1.0, 1.0),
+            align: false,
+        }
+    }
+}
+
+impl Scatter {
+    // The instances, each placing a prototype with its origin on the surface.
+    // The same seed, target and prototypes always give the same instances.
+    pub fn place(
+        &self,
+        target: &dyn Hittable,
+        prototypes: &[Arc<dyn Hittable>],
+    ) -> Result<HittableList, String> {
+        if prototypes.is_empty() {
+            return Err("scattering needs at least one prototype".to_string());
+        }
+        if !(self.scale.0 > 0.0 && self.scale.0 <= self.scale.1) {
+            return Err(format!(
+                "scale range {} to {} must be positive and increasing",
+                self.scale.0, self.scale.1
+            ));
+        }
+        crate::stage_span!("scatter", self.count);
+
+        let bbox = target.bounding_box();
+        let (min, max) = self.region.unwrap_or((
+            Point3::from(bbox.x.min, 0.0, bbox.z.min),
+            Point3::from(bbox.x.max, 0.0, bbox.z.max),
+        ));
+        let above = bbox.y.max + 1.0;
+        let up = Vec3::from(0.0, 1.0, 0.0);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut instances = HittableList::default();
+        for _ in 0..self.count {
+            // Draw every number up front so a rejected point doesn't shift
+            // the sequence for the points after it.
+            let (x, z): (f64, f64) = (rng.gen(), rng.gen());
+            let (keep, turn, size): (f64, f64, f64) = (rng.gen(), rng.gen(), rng.gen());
+            let prototype = &prototypes[rng.gen_range(0..prototypes.len())];
+
+            let origin = Point3::from(
+                min.x() + x * (max.x() - min.x()),
+                above,
+                min.z() + z * (max.z() - min.z()),
+            );
+            let r = Ray::from(origin, -up);
+            let Some(rec) = target.hit(&r, Interval::from(0.0, INFINITY)) else {
+                continue;
+            };
+            if let Some(density) = &self.density {
+                let inputs = ExprInputs {
+                    u: rec.u,
+                    v: rec.v,
+                    p: rec.p,
+                    normal: rec.normal,
+                };
+                if keep >= density.eval(&inputs).x() {
+                    continue;
+                }
+            }
+
+            let yaw = Quat::from_axis_angle(&up, 2.0 * PI * turn);
+            let rotation = if self.align {
+                Quat::from_rotation_arc(&up, &rec.geometric_normal) * yaw
+            } else {
+                yaw
+            };
+            let s = self.scale.0 + size * (self.scale.1 - self.scale.0);
+            let matrix = Mat4::from_trs(&rec.p, &rotation, &Vec3::from(s, s, s));
+            instances.add(Box::new(Transform::from(
+                Box::new(prototype.clone()),
+                matrix,
+            )?));
+        }
+        Ok(instances)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+
+    fn sphere(center: Point3, radius: f64) -> Sphere {
+        Sphere::from(
+            center,
+            radius,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )
+    }
+
+    #[test]
+    fn scatters_over_the_ground() {
+        let ground = sphere(Point3::from(0.0, -1000.0, 0.0), 1000.0);
+        let pebbles: [Arc<dyn Hittable>; 1] = [Arc::new(sphere(Point3::new(), 0.1))];
+        let scatter = Scatter {
+            count: 50,
+            seed: 7,
+            region: Some((Point3::from(-5.0, 0.0, -5.0), Point3::from(5.0, 0.0, 5.0))),
+            scale: (0.5, 2.0),
+            ..Default::default()
+        };
+        let instances = scatter.place(&ground, &pebbles).unwrap();
+        assert_eq!(instances.len(), 50);
+        let bbox = instances.bounding_box();
+        assert!(bbox.x.min >= -5.3 && bbox.x.max <= 5.3);
+        assert!(bbox.y.min > -0.3 && bbox.y.max < 0.3);
+
+        // Same seed, same meadow.
+        let again = scatter.place(&ground, &pebbles).unwrap();
+        approx::assert_relative_eq!(again.bounding_box().x.min, bbox.x.min);
+
+        let none = Scatter {
+            density: Some(Arc::new(Expr::parse("0.0").unwrap())),
+            ..scatter
+        };
+        assert!(none.place(&ground, &pebbles).unwrap().is_empty());
+        assert!(none.place(&ground, &[]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::ambient::Ambient;
+use crate::background::Background;
+use crate::camera::CameraBuilder;
+use crate::clip::{self, ClipPlane};
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+use crate::ray::Ray;
+use crate::utility::Interval;
+
+// Everything a render needs besides the camera doing the rendering: the
+// geometry, the lights, what rays see when they escape, and any cameras the
+// scene defines by name.
+#[derive(Default)]
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: HittableList, // Emitters the integrator may sample directly
+    // Point, directional and spot lights, which have no surface and are
+    // only seen through shadow rays.
+    pub punctual_lights: Vec<Light>,
+    pub background: Background,
+    pub ambient: Option<Ambient>, // Fill light added at every hit
+    // Cut away parts of the world for the camera's paths; lidar scans and
+    // ray queries still see all of it.
+    pub clip_planes: Vec<ClipPlane>,
+    cameras: Vec<(String, CameraBuilder)>,
+}
+
+impl Scene {
+    pub fn from(world: HittableList) -> Self {
+        Self {
+            world,
+            ..Self::default()
+        }
+    }
+
+    // The closest hit on the world along a camera path.
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        if self.clip_planes.is_empty() {
+            return self.world.hit(r, ray_t);
+        }
+        clip::hit(&self.world, &self.clip_planes, r, ray_t)
+    }
+
+    // Whether anything blocks a camera path's shadow ray within `ray_t`,
+    // seeing the world as `hit` does.
+    pub fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        if self.clip_planes.is_empty() {
+            return self.world.is_occluded(r, ray_t);
+        }
+        clip::is_occluded(&self.world, &self.clip_planes, r, ray_t)
+    }
+
+    // Like `hit`, but also returns the index of the world object hit, in
+    // order of `add`. Caps of clip planes belong to no object.
+    pub fn hit_object(&self, r: &Ray, ray_t: Interval) -> Option<(Option<usize>, HitRecord<'_>)> {
+        if self.clip_planes.is_empty() {
+            let (index, rec) = self.world.hit_object(r, ray_t)?;
+            return Some((Some(index), rec));
+        }
+        clip::hit_object(&self.world, &self.clip_planes, r, ray_t)
+    }
+
+    // Adds a named camera, replacing any camera of the same name.
+    pub fn add_camera(&mut self, name: &str, camera: CameraBuilder) {
+        match self.cameras.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = camera,
+            None => self.cameras.push((name.to_string(), camera)),
+        }
+    }
+
+    // A copy of the named camera's settings, ready to be built and rendered
+    // with.
+    pub fn camera(&self, name: &str) -> Option<CameraBuilder> {
+        self.cameras
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, camera)| camera.clone())
+    }
+
+    pub fn camera_names(&self) -> impl Iterator<Item = &str> {
+        self.cameras.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn named_cameras() {
+        let mut scene = Scene::default();
+        let mut top = CameraBuilder::new();
+        top.vfov = Some(40.0);
+        scene.add_camera("top", top);
+        scene.add_camera("side", CameraBuilder::new());
+        let mut wide = CameraBuilder::new();
+        wide.vfov = Some(90.0);
+        scene.add_camera("top", wide);
+
+        assert_eq!(scene.camera_names().collect::<Vec<_>>(), ["top", "side"]);
+        assert_eq!(scene.camera("top").unwrap().vfov, Some(90.0));
+        assert!(scene.camera("front").is_none());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::background::{Background, Sky};
+use crate::camera::CameraBuilder;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+use crate::material::Material;
+use crate::registry::{Param, Params, Registry};
+use crate::scene::Scene;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::Vec3;
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// Declarative scenes in JSON or RON, for scenes that need no loops or
+// expressions and for tools that write scenes out. A file lists camera
+// settings (the keys of `CameraBuilder::set`), an optional background, named
+// textures and materials and the objects, which are built from their
+// parameters by the factories of a `Registry`:
+//
+//     {
+//         "camera": { "look_from": [13, 2, 3], "vfov": 20 },
+//         "background": { "type": "sky", "sun_direction": [1, 1, 0] },
+//         "textures": {
+//             "tiles": { "type": "checker", "scale": 1, "even": [0.9, 0.9, 0.9], "odd": [0.1, 0.1, 0.1] }
+//         },
+//         "materials": {
+//             "ground": { "type": "lambertian", "texture": "tiles" }
+//         },
+//         "objects": [
+//             { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+//             { "type": "sphere", "center": [0, 1, 0], "radius": 1,
+//               "material": { "type": "dielectric", "ir": 1.5 } }
+//         ]
+//     }
+//
+// In RON, the same is a struct of those fields, with vectors as `(x, y, z)`
+// tuples or lists. A string parameter naming one of the textures or materials
+// is that texture or material; a map with a "type" is a material made in
+// place. Objects with
+// `"light": true`, normally spheres or quads of a diffuse light, are also
+// sampled directly as the scene's lights. Lights with no surface go in a
+// "lights" list of their own:
+//
+//     "lights": [
+//         { "type": "point", "position": [0, 5, 0], "intensity": [20, 20, 20] },
+//         { "type": "directional", "direction": [-1, -2, -1], "irradiance": [2, 2, 2] },
+//         { "type": "spot", "position": [0, 4, 2], "direction": [0, -1, -0.5],
+//           "intensity": [30, 30, 30], "angle": 25, "falloff": 10 }
+//     ]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Ron,
+}
+
+impl Format {
+    // The format of a file with this extension, if it is a scene file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "ron" => Some(Self::Ron),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    camera: BTreeMap<String, Value>,
+    #[serde(default)]
+    background: Option<Entry>,
+    #[serde(default)]
+    textures: BTreeMap<String, Entry>,
+    #[serde(default)]
+    materials: BTreeMap<String, Entry>,
+    objects: Vec<Entry>,
+    #[serde(default)]
+    lights: Vec<Entry>,
+}
+
+// An object, material or background: its "type" and parameters.
+type Entry = BTreeMap<String, Value>;
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Number(f64),
+    Vector([f64; 3]),
+    Text(String),
+    Map(Entry),
+}
+
+// Loads the scene file at `path`, applying its camera settings to `camera`.
+pub fn load(path: &Path, registry: &Registry, camera: &mut CameraBuilder) -> Result<Scene, Error> {
+    let failed = |message: String| Error::Scene {
+        path: path.to_path_buf(),
+        message,
+    };
+    let format = Format::from_path(path)
+        .ok_or_else(|| failed("not a .json or .ron scene file".to_string()))?;
+    let source = std::fs::read_to_string(path).map_err(|err| failed(err.to_string()))?;
+    parse(&source, format, registry, camera).map_err(failed)
+}
+
+pub fn parse(
+    source: &str,
+    format: Format,
+    registry: &Registry,
+    camera: &mut CameraBuilder,
+) -> Result<Scene, String> {
+    crate::stage_span!("parse_scene_file");
+    let file: SceneFile = match format {
+        Format::Json => serde_json::from_str(source).map_err(|err| err.to_string())?,
+        Format::Ron => ron::from_str(source).map_err(|err| err.to_string())?,
+    };
+
+    for (key, value) in &file.camera {
+        let value = match value {
+            Value::Bool(b) => b.to_string(),
+            Value::Number(x) => x.to_string(),
+            Value::Vector([x, y, z]) => format!("{},{},{}", x, y, z),
+            Value::Text(s) => s.clone(),
+            Value::Map(_) => return Err(format!("camera setting '{}' can't be a map", key)),
+        };
+        camera.set(key, &value)?;
+    }
+
+    let mut named = Named::default();
+    for (name, entry) in &file.textures {
+        let texture = to_params(registry, entry, &named)
+            .and_then(|(kind, params)| registry.create_texture(kind, &params))
+            .map_err(|err| format!("texture '{}': {}", name, err))?;
+        named.textures.insert(name.as_str(), texture);
+    }
+    for (name, entry) in &file.materials {
+        let material = make_material(registry, entry, &named)
+            .map_err(|err| format!("material '{}': {}", name, err))?;
+        named.materials.insert(name.as_str(), material);
+    }
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+    for (index, entry) in file.objects.iter().enumerate() {
+        let mut entry = entry.clone();
+        let light = match entry.remove("light") {
+            Some(Value::Bool(light)) => light,
+            Some(_) => return Err(format!("object {}: 'light' must be true or false", index)),
+            None => false,
+        };
+        let object = to_params(registry, &entry, &named)
+            .and_then(|(kind, params)| registry.create_hittable(kind, &params))
+            .map_err(|err| format!("object {}: {}", index, err))?;
+        if light {
+            let object: Arc<dyn Hittable> = Arc::from(object);
+            world.add(Box::new(object.clone()));
+            lights.add(Box::new(object));
+        } else {
+            world.add(object);
+        }
+    }
+
+    let mut scene = Scene::from(world);
+    scene.lights = lights;
+    for (index, entry) in file.lights.iter().enumerate() {
+        let light = to_light(entry).map_err(|err| format!("light {}: {}", index, err))?;
+        scene.punctual_lights.push(light);
+    }
+    if let Some(entry) = &file.background {
+        scene.background = to_background(entry).map_err(|err| format!("background: {}", err))?;
+    }
+    Ok(scene)
+}
+
+// The textures and materials of a file, by name, for parameters to refer to.
+#[derive(Default)]
+struct Named<'a> {
+    textures: BTreeMap<&'a str, Arc<dyn Texture>>,
+    materials: BTreeMap<&'a str, Material>,
+}
+
+fn make_material(registry: &Registry, entry: &Entry, named: &Named) -> Result<Material, String> {
+    let (kind, params) = to_params(registry, entry, named)?;
+    registry.create_material(kind, &params)
+}
+
+// The entry's type and its other values as factory parameters.
+fn to_params<'a>(
+    registry: &Registry,
+    entry: &'a Entry,
+    named: &Named,
+) -> Result<(&'a str, Params), String> {
+    let kind = entry_type(entry)?;
+    let mut params = Params::new();
+    for (key, value) in entry.iter().filter(|(key, _)| *key != "type") {
+        let param = match value {
+            Value::Bool(b) => Param::Bool(*b),
+            Value::Number(x) => Param::Float(*x),
+            Value::Vector([x, y, z]) => Param::Vec3(Vec3::from(*x, *y, *z)),
+            Value::Text(s) => match (
+                named.textures.get(s.as_str()),
+                named.materials.get(s.as_str()),
+            ) {
+                (Some(texture), _) => Param::Texture(texture.clone()),
+                (None, Some(material)) => Param::Material(material.clone()),
+                (None, None) => Param::Str(s.clone()),
+            },
+            Value::Map(inner) => Param::Material(
+                make_material(registry, inner, named)
+                    .map_err(|err| format!("'{}': {}", key, err))?,
+            ),
+        };
+        params.set(key, param);
+    }
+    Ok((kind, params))
+}
+
+fn entry_type(entry: &Entry) -> Result<&str, String> {
+    match entry.get("type") {
+        Some(Value::Text(kind)) => Ok(kind),
+        Some(_) => Err("'type' must be a string".to_string()),
+        None => Err("missing 'type'".to_string()),
+    }
+}
+
+fn vector(entry: &Entry, key: &str) -> Result<Vec3, String> {
+    match entry.get(key) {
+        Some(Value::Vector([x, y, z])) => Ok(Vec3::from(*x, *y, *z)),
+        Some(_) => Err(format!("'{}' must be a vector", key)),
+        None => Err(format!("missing '{}'", key)),
+    }
+}
+
+fn number_or(entry: &Entry, key: &str, default: f64) -> Result<f64, String> {
+    match entry.get(key) {
+        Some(Value::Number(x)) => Ok(*x),
+        Some(_) => Err(format!("'{}' must be a number", key)),
+        None => Ok(default),
+    }
+}
+
+fn to_light(entry: &Entry) -> Result<Light, String> {
+    let vector = |key: &str| vector(entry, key);
+    match entry_type(entry)? {
+        "point" => Ok(Light::point(
+            vector("position")?,
+            vector("intensity")?.into(),
+        )),
+        "directional" => Ok(Light::directional(
+            vector("direction")?,
+            vector("irradiance")?.into(),
+        )),
+        "spot" => Ok(Light::spot(
+            vector("position")?,
+            vector("direction")?,
+            vector("intensity")?.into(),
+            number_or(entry, "angle", 30.0)?,
+            number_or(entry, "falloff", 0.0)?,
+        )),
+        kind => Err(format!(
+            "unknown light type '{}', expected point, directional or spot",
+            kind
+        )),
+    }
+}
+
+fn to_background(entry: &Entry) -> Result<Background, String> {
+    let vector = |key: &str| vector(entry, key);
+    match entry_type(entry)? {
+        "solid" => Ok(Background::Solid(vector("color")?.into())),
+        "gradient" => Ok(Background::Gradient {
+            top: vector("top")?.into(),
+            bottom: vector("bottom")?.into(),
+        }),
+        "sky" => Ok(Background::Sky(Sky::from(vector("sun_direction")?))),
+        "environment" => match entry.get("expr") {
+            Some(Value::Text(source)) => {
+                Ok(Background::Environment(Arc::new(Expr::parse(source)?)))
+            }
+            _ => Err("missing 'expr' string".to_string()),
+        },
+        "hdri" => {
+            let Some(Value::Text(path)) = entry.get("path") else {
+                return Err("missing 'path' string".to_string());
+            };
+            let strength = number_or(entry, "strength", 1.0)?;
+            let image = ImageTexture::open_shared(Path::new(path))
+                .map_err(|err| format!("cannot load {}: {}", path, err))?;
+            Ok(Background::Image { image, strength })
+        }
+        kind => Err(format!(
+            "unknown background type '{}', expected solid, gradient, sky, environment or hdri",
+            kind
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ray::{Point3, Ray};
+    use crate::utility::{Interval, INFINITY};
+
+    #[test]
+    fn loads_json_and_ron() {
+        let json = r#"{
+            "camera": { "look_from": [0, 0, 5], "vfov": 30, "samples": 4 },
+            "background": { "type": "solid", "color": [0.1, 0.2, 0.3] },
+            "materials": { "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] } },
+            "objects": [
+                { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "red" },
+                { "type": "sphere", "center": [3, 0, 0], "radius": 1,
+                  "material": { "type": "metal", "albedo": [0.5, 0.5, 0.5] } }
+            ]
+        }"#;
+        let ron = r#"(
+            camera: { "look_from": (0, 0, 5), "vfov": 30, "samples": 4 },
+            background: Some({ "type": "solid", "color": (0.1, 0.2, 0.3) }),
+            materials: { "red": { "type": "lambertian", "albedo": (0.8, 0.1, 0.1) } },
+            objects: [
+                { "type": "sphere", "center": (0, 0, 0), "radius": 1, "material": "red" },
+                { "type": "sphere", "center": (3, 0, 0), "radius": 1,
+                  "material": { "type": "metal", "albedo": (0.5, 0.5, 0.5) } },
+            ],
+        )"#;
+        let registry = Registry::with_builtins();
+        for (source, format) in [(json, Format::Json), (ron, Format::Ron)] {
+            let mut camera = CameraBuilder::new();
+            let scene = parse(source, format, &registry, &mut camera).unwrap();
+            assert_eq!(camera.vfov, Some(30.0));
+            assert_eq!(camera.samples_per_pixel, Some(4));
+            assert_eq!(scene.world.len(), 2);
+            assert!(matches!(scene.background, Background::Solid(_)));
+
+            let r = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+            let rec = scene
+                .world
+                .hit(&r, Interval::from(0.001, INFINITY))
+                .unwrap();
+            approx::assert_relative_eq!(rec.mat.albedo(&rec).r(), 0.8);
+        }
+
+        let mut camera = CameraBuilder::new();
+        let lit = r#"{ "objects": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1, "light": true,
+              "material": { "type": "diffuse_light", "emit": [4, 4, 4] } }
+        ] }"#;
+        let scene = parse(lit, Format::Json, &registry, &mut camera).unwrap();
+        assert_eq!((scene.world.len(), scene.lights.len()), (1, 1));
+        let punctual = r#"{ "objects": [], "lights": [
+            { "type": "point", "position": [0, 5, 0], "intensity": [20, 20, 20] },
+            { "type": "spot", "position": [0, 4, 0], "direction": [0, -1, 0],
+              "intensity": [30, 30, 30], "angle": 25 }
+        ] }"#;
+        let scene = parse(punctual, Format::Json, &registry, &mut camera).unwrap();
+        assert_eq!(scene.punctual_lights.len(), 2);
+        let bad = r#"{ "objects": [], "lights": [{ "type": "area" }] }"#;
+        let err = parse(bad, Format::Json, &registry, &mut camera)
+            .err()
+            .unwrap();
+        assert!(err.contains("light 0: unknown light type 'area'"));
+        let unknown = r#"{ "objects": [{ "type": "cube" }] }"#;
+        let err = parse(unknown, Format::Json, &registry, &mut camera)
+            .err()
+            .unwrap();
+        assert!(err.contains("object 0: unknown object type 'cube'"));
+        let textured = r#"{
+            "textures": { "tiles": { "type": "checker", "scale": 1, "even": [1, 1, 1], "odd": [0, 0, 0] } },
+            "materials": { "floor": { "type": "lambertian", "texture": "tiles" } },
+            "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 0.5, "material": "floor" }]
+        }"#;
+        let scene = parse(textured, Format::Json, &registry, &mut camera).unwrap();
+        let r = Ray::from(Point3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = scene
+            .world
+            .hit(&r, Interval::from(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.mat.albedo(&rec).r(), 1.0);
+        let missing = r#"{ "textures": { "wood": { "type": "grain" } }, "objects": [] }"#;
+        let err = parse(missing, Format::Json, &registry, &mut camera)
+            .err()
+            .unwrap();
+        assert!(err.contains("texture 'wood': unknown texture type 'grain'"));
+        let typo = r#"{ "object": [] }"#;
+        assert!(parse(typo, Format::Json, &registry, &mut camera).is_err());
+        assert_eq!(Format::from_path(Path::new("a.ron")), Some(Format::Ron));
+        assert_eq!(Format::from_path(Path::new("a.rhai")), None);
+    }
+}
@@ -0,0 +1,1227 @@
+use crate::ambient::Ambient;
+use crate::background::{Background, Sky};
+use crate::camera::CameraBuilder;
+use crate::capsule::Capsule;
+use crate::clip::ClipPlane;
+use crate::cone::Cone;
+use crate::cuboid::Cuboid;
+use crate::cylinder::Cylinder;
+use crate::disk::Disk;
+use crate::expr::Expr;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+use crate::lod::Lod;
+use crate::lsystem::LSystem;
+use crate::masked::Masked;
+use crate::mat4::Mat4;
+use crate::material::{
+    Banded, Dieletric, DiffuseLight, Isotropic, Lambertian, Material, Metal, Procedural,
+};
+use crate::medium::ConstantMedium;
+use crate::mesh::Mesh;
+use crate::obj;
+use crate::ocean::{water, Ocean};
+use crate::plane::Plane;
+use crate::ply;
+use crate::quad::Quad;
+use crate::quat::Quat;
+use crate::ray::{Point3, ALL_SETS, DEFAULT_SET};
+use crate::registry::{Param, Params, Registry};
+use crate::scatter::Scatter;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::stl;
+use crate::texture::{CheckerTexture, ImageTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::utility::{random, random_in_range};
+use crate::vec3::*;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Scene scripts are plain Rhai programs evaluated once at load time.
+// Objects passed to `add()` end up in the returned world, so loops and
+// expressions can be used to place geometry procedurally, e.g.
+//
+//     for i in 0..20 {
+//         let t = i * 0.3;
+//         add(sphere(vec3(cos(t), 0.2, sin(t)), 0.2, lambertian(vec3(0.8, 0.3, 0.3))));
+//     }
+//
+// Types registered in a `Registry` are available through `add_object(name, #{...})`,
+// `material(name, #{...})` and `texture(name, #{...})`, with the map entries
+// passed as factory parameters.
+//
+// `set_camera(#{ look_from: vec3(13.0, 2.0, 3.0), vfov: 20.0 })` overrides camera
+// settings (see `CameraBuilder::set` for the keys). When rendering animations, the
+// script is evaluated once per frame with `frame` and `time` (in seconds) in
+// scope, so cameras and objects can be animated with ordinary expressions.
+//
+// `set_background(...)` takes `solid(color)`, `gradient(top, bottom)`,
+// `sky(sun_direction)`, `environment("expr")`, where the expression sees the
+// ray direction as `p` and its equirectangular coordinates as `u` and `v`, or
+// `hdri(path, strength)`, an equirectangular image (such as a Radiance .hdr
+// file) around the scene, its brightness scaled by `strength`.
+// `lambertian(texture)` takes its albedo from a texture: `solid_color(color)`,
+// `checker(scale, even, odd)` with colors or textures for the cubes, or
+// `image_texture(path)`, wrapped over the surface's texture coordinates.
+// `diffuse_light(color)` is a material that emits `color` instead of
+// scattering, so objects made of it light the scene. Adding a sphere, quad,
+// box or disk of it with `add_light(object)` also has diffuse surfaces aim
+// part of their rays at it, which clears up the noise of small lights.
+// `add_light` also takes lights with no surface: `point_light(position,
+// intensity)`, `directional_light(direction, irradiance)`, shining along
+// `direction` like the sun, and `spot_light(position, direction, intensity,
+// angle, falloff)`, full within `angle` degrees of `direction` and fading out
+// over `falloff` more. They light diffuse surfaces through shadow rays.
+// `set_ambient(color)` or `set_ambient(sky, ground)` adds a flat or
+// hemispherical fill light at every hit, independent of the background.
+//
+// `mesh(positions, indices, material)` builds a triangle mesh from an array of
+// vec3 positions and a flat array of indices, three per triangle, and
+// `mesh(positions, indices, normals, material)` one shaded smoothly with a
+// normal per vertex. `mesh.with_materials([m0, m1, ...], faces)` gives each
+// triangle the material at its index in `faces`. `triangle(a, b, c, material)`
+// is a single triangle, facing where a, b and c wind counterclockwise, and
+// `quad(q, u, v, material)` the parallelogram with corner q and edges u and v.
+// `cuboid(a, b, material)` is the axis-aligned box with opposite corners a and
+// b; `rotate_y` and `translate` turn and place it.
+// `plane(point, normal, material)` is the infinite plane through point facing
+// normal, such as a ground, and `disk(center, normal, radius, material)` a
+// round one, such as a round lamp. `cylinder(base, top, radius, material)` is
+// a capped cylinder around the line from base to top, `cone(base, apex,
+// radius, material)` a cone standing on a round base, and `capsule(a, b,
+// radius, material)` everything within radius of the segment from a to b.
+// `moving_sphere(center0, center1, radius, material)` moves from center0 to
+// center1 over ray times 0 to 1; with `shutter_close: 1.0` in `set_camera` it
+// is motion blurred along the way.
+// `constant_medium(boundary, density, color)` fills a convex object with fog
+// or smoke scattering `color` in every direction, the denser the more; a
+// material such as `isotropic(color)` can stand in for the color.
+// `translate(object, offset)`, `rotate_y(object, degrees)` and
+// `transform(object, translation, rotation, scale)` place an object; they and
+// `instance(object)` make instances, which share their geometry, so
+// `let rock = instance(load_obj(...))` can be placed many times over without
+// copying the mesh.
+// `load_obj(path, material)` loads a Wavefront OBJ model as a mesh, and
+// `load_obj(path, material, #{ name: m, ... })` gives the faces under each
+// `usemtl name` the material listed for it.
+// `tree(#{ axiom: "X", rules: #{ X: "F[+XL][-XL]F", F: "FF" }, iterations: 4 },
+// bark, leaves)` grows a mesh from an L-system (see `lsystem.rs` for the
+// symbols and the other keys).
+// `ocean(#{ size: 50.0, wind_speed: 8.0, time: time }, water())` builds a
+// patch of wind-driven waves centred on the origin (see `ocean.rs` for the
+// other keys); passing the script's `time` animates it.
+// With the `mmap` feature, `add_mapped_mesh(path, material)` adds a mesh file
+// written by `mapped::write_mesh`, read from disk as needed instead of loaded.
+//
+// `add_lod([fine, coarser, ...], [d1, ...])` adds levels of detail of one
+// asset; rays starting further than d1 from it see the second level, and so on.
+//
+// `add_scatter(target, [prototype, ...], #{ count: 500, seed: 1 })` scatters
+// instances of the prototypes (spheres or meshes, modelled standing on the
+// origin) over the target, which is not itself added. Other keys are
+// `scale_min`, `scale_max`, `region_min` and `region_max` (xz corners),
+// `align` to tilt instances to the surface, and `density`, an expression
+// giving the chance of keeping each point.
+//
+// `add_clip_plane(point, normal)` cuts away everything on the side `normal`
+// points to; `add_clip_plane(point, normal, material)` also caps the cut faces
+// of closed objects with `material`.
+pub fn load_scene(path: &Path) -> Result<HittableList, Box<EvalAltResult>> {
+    load_scene_with(path, Arc::new(Registry::with_builtins()))
+}
+
+pub fn load_scene_with(
+    path: &Path,
+    registry: Arc<Registry>,
+) -> Result<HittableList, Box<EvalAltResult>> {
+    let scene = evaluate(|engine| compile_file(engine, path), registry, 0, 0.0, None)?;
+    Ok(scene.world)
+}
+
+pub fn eval_scene(source: &str) -> Result<HittableList, Box<EvalAltResult>> {
+    eval_scene_with(source, Arc::new(Registry::with_builtins()))
+}
+
+pub fn eval_scene_with(
+    source: &str,
+    registry: Arc<Registry>,
+) -> Result<HittableList, Box<EvalAltResult>> {
+    let scene = evaluate(|engine| Ok(engine.compile(source)?), registry, 0, 0.0, None)?;
+    Ok(scene.world)
+}
+
+// The world and camera settings produced by one evaluation of a script.
+pub struct ScriptScene {
+    pub world: HittableList,
+    pub lights: HittableList,
+    pub punctual_lights: Vec<Light>,
+    pub camera: Vec<(String, String)>,
+    pub background: Option<Background>,
+    pub ambient: Option<Ambient>,
+    pub clip_planes: Vec<ClipPlane>,
+}
+
+impl ScriptScene {
+    pub fn into_scene(self) -> Scene {
+        let mut scene = Scene::from(self.world);
+        scene.lights = self.lights;
+        scene.punctual_lights = self.punctual_lights;
+        if let Some(background) = self.background {
+            scene.background = background;
+        }
+        scene.ambient = self.ambient;
+        scene.clip_planes = self.clip_planes;
+        scene
+    }
+
+    pub fn apply_camera(&self, camera: &mut CameraBuilder) -> Result<(), String> {
+        for (key, value) in &self.camera {
+            camera.set(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn load_frame(path: &Path, frame: i64, time: f64) -> Result<ScriptScene, Box<EvalAltResult>> {
+    evaluate(
+        |engine| compile_file(engine, path),
+        Arc::new(Registry::with_builtins()),
+        frame,
+        time,
+        None,
+    )
+}
+
+pub fn eval_frame(source: &str, frame: i64, time: f64) -> Result<ScriptScene, Box<EvalAltResult>> {
+    evaluate(
+        |engine| Ok(engine.compile(source)?),
+        Arc::new(Registry::with_builtins()),
+        frame,
+        time,
+        None,
+    )
+}
+
+// Limits on scripts from untrusted sources, such as the render server's
+// clients: how much work and memory evaluating one may take, and the
+// directory it may load models and images from, if any. Modules can't be
+// imported.
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    pub max_operations: u64,
+    pub max_call_levels: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+    pub max_string_size: usize,
+    pub assets: Option<PathBuf>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_operations: 10_000_000,
+            max_call_levels: 32,
+            max_array_size: 1_000_000,
+            max_map_size: 10_000,
+            max_string_size: 1 << 20,
+            assets: None,
+        }
+    }
+}
+
+impl Sandbox {
+    fn files(&self) -> Result<Files, String> {
+        match &self.assets {
+            Some(root) => root
+                .canonicalize()
+                .map(Files::Within)
+                .map_err(|err| format!("asset directory {}: {}", root.display(), err)),
+            None => Ok(Files::Denied),
+        }
+    }
+
+    fn limit(&self, engine: &mut Engine) {
+        engine
+            .set_max_operations(self.max_operations)
+            .set_max_call_levels(self.max_call_levels)
+            .set_max_array_size(self.max_array_size)
+            .set_max_map_size(self.max_map_size)
+            .set_max_string_size(self.max_string_size)
+            .set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    }
+}
+
+// Evaluates a script within the limits of `sandbox`.
+pub fn eval_frame_sandboxed(
+    source: &str,
+    frame: i64,
+    time: f64,
+    sandbox: &Sandbox,
+) -> Result<ScriptScene, Box<EvalAltResult>> {
+    // The registry's image texture opens files too.
+    let files = sandbox.files()?;
+    let mut registry = Registry::with_builtins();
+    registry.register_texture("image", move |params| {
+        let path = files.resolve(params.str("path")?)?;
+        let image = ImageTexture::open_shared(&path)
+            .map_err(|err| format!("cannot load {}: {}", path.display(), err))?;
+        Ok(image)
+    });
+    evaluate(
+        |engine| Ok(engine.compile(source)?),
+        Arc::new(registry),
+        frame,
+        time,
+        Some(sandbox),
+    )
+}
+
+// Where the paths a script gives lead.
+#[derive(Clone)]
+enum Files {
+    Any,
+    Within(PathBuf), // Canonical, so links out of it can be told apart
+    Denied,
+}
+
+impl Files {
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let root = match self {
+            Files::Any => return Ok(PathBuf::from(path)),
+            Files::Within(root) => root,
+            Files::Denied => return Err(format!("cannot load {}: files are disabled", path)),
+        };
+        let outside = || format!("cannot load {}: outside the asset directory", path);
+        let relative = Path::new(path);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(outside());
+        }
+        let full = root
+            .join(relative)
+            .canonicalize()
+            .map_err(|err| format!("cannot load {}: {}", path, err))?;
+        if !full.starts_with(root) {
+            return Err(outside());
+        }
+        Ok(full)
+    }
+}
+
+fn evaluate<F>(
+    compile: F,
+    registry: Arc<Registry>,
+    frame: i64,
+    time: f64,
+    sandbox: Option<&Sandbox>,
+) -> Result<ScriptScene, Box<EvalAltResult>>
+where
+    F: FnOnce(&Engine) -> Result<AST, Box<EvalAltResult>>,
+{
+    crate::stage_span!("load_scene", frame);
+    let outputs = Outputs::default();
+    let files = match sandbox {
+        Some(sandbox) => sandbox.files()?,
+        None => Files::Any,
+    };
+    let mut engine = build_engine(outputs.clone(), registry, &files);
+    if let Some(sandbox) = sandbox {
+        sandbox.limit(&mut engine);
+    }
+    let ast = compile(&engine)?;
+
+    let mut scope = Scope::new();
+    scope.push_constant("frame", frame);
+    scope.push_constant("time", time);
+    engine.run_ast_with_scope(&mut scope, &ast)?;
+
+    Ok(ScriptScene {
+        world: outputs.world.take(),
+        lights: outputs.lights.take(),
+        punctual_lights: outputs.punctual_lights.take(),
+        camera: outputs.camera.take(),
+        background: outputs.background.take(),
+        ambient: outputs.ambient.take(),
+        clip_planes: outputs.clip_planes.take(),
+    })
+}
+
+// What the script's calls add to the scene, shared with the functions
+// registered on its engine.
+#[derive(Clone, Default)]
+struct Outputs {
+    world: Rc<RefCell<HittableList>>,
+    lights: Rc<RefCell<HittableList>>,
+    punctual_lights: Rc<RefCell<Vec<Light>>>,
+    camera: Rc<RefCell<Vec<(String, String)>>>,
+    background: Rc<RefCell<Option<Background>>>,
+    ambient: Rc<RefCell<Option<Ambient>>>,
+    clip_planes: Rc<RefCell<Vec<ClipPlane>>>,
+}
+
+// Rhai's own `compile_file` isn't available on wasm32, so read the file here.
+fn compile_file(engine: &Engine, path: &Path) -> Result<AST, Box<EvalAltResult>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    Ok(engine.compile(source)?)
+}
+
+const MEDIUM_BOUNDARY: &str = "medium boundaries must be shapes or meshes";
+
+fn build_engine(outputs: Outputs, registry: Arc<Registry>, files: &Files) -> Engine {
+    let Outputs {
+        world,
+        lights,
+        punctual_lights,
+        camera,
+        background,
+        ambient,
+        clip_planes,
+    } = outputs;
+    let mut engine = Engine::new();
+
+    // Vectors, points and colors all share the Vec3 type in scripts; colors
+    // are converted when they reach a material or background.
+    engine
+        .register_type_with_name::<Vec3>("Vec3")
+        .register_fn("vec3", Vec3::from)
+        .register_get("x", |v: &mut Vec3| v.x())
+        .register_get("y", |v: &mut Vec3| v.y())
+        .register_get("z", |v: &mut Vec3| v.z())
+        .register_fn("+", |lhs: Vec3, rhs: Vec3| lhs + rhs)
+        .register_fn("-", |lhs: Vec3, rhs: Vec3| lhs - rhs)
+        .register_fn("-", |v: Vec3| -v)
+        .register_fn("*", |lhs: Vec3, rhs: Vec3| lhs * rhs)
+        .register_fn("*", |lhs: Vec3, rhs: f64| lhs * rhs)
+        .register_fn("*", |lhs: f64, rhs: Vec3| lhs * rhs)
+        .register_fn("/", |lhs: Vec3, rhs: f64| lhs / rhs)
+        .register_fn("length", |v: &mut Vec3| v.length())
+        .register_fn("dot", |lhs: Vec3, rhs: Vec3| dot(&lhs, &rhs))
+        .register_fn("cross", |lhs: Vec3, rhs: Vec3| cross(&lhs, &rhs))
+        .register_fn("unit_vector", unit_vector)
+        .register_fn("to_string", |v: &mut Vec3| format!("{:?}", v))
+        .register_fn("random_vec3", Vec3::random)
+        .register_fn("random_vec3", Vec3::random_in_range);
+
+    // Rotations, e.g. for orbiting cameras: `rotate(quat_axis_angle(axis, a), v)`
+    // or `slerp(q0, q1, t)` between keyframes. Angles are in radians.
+    engine
+        .register_type_with_name::<Quat>("Quat")
+        .register_fn("quat_axis_angle", |axis: Vec3, angle: f64| {
+            Quat::from_axis_angle(&axis, angle)
+        })
+        .register_fn("quat_euler", Quat::from_euler)
+        .register_fn("*", |lhs: Quat, rhs: Quat| lhs * rhs)
+        .register_fn("slerp", |a: Quat, b: Quat, t: f64| Quat::slerp(&a, &b, t))
+        .register_fn("rotate", |q: Quat, v: Vec3| q.rotate(&v));
+
+    // Instances: objects placed by a transform, sharing the geometry of the
+    // object they were made from. Angles of `rotate_y` are in degrees.
+    engine
+        .register_type_with_name::<Arc<dyn Hittable>>("Instance")
+        .register_fn("instance", to_instance)
+        .register_fn(
+            "translate",
+            |object: Dynamic, offset: Vec3| -> Result<Arc<dyn Hittable>, Box<EvalAltResult>> {
+                let object = Box::new(to_instance(object)?);
+                Ok(share(Transform::translate(object, offset)))
+            },
+        )
+        .register_fn(
+            "rotate_y",
+            |object: Dynamic, degrees: f64| -> Result<Arc<dyn Hittable>, Box<EvalAltResult>> {
+                let object = Box::new(to_instance(object)?);
+                Ok(share(Transform::rotate_y(object, degrees)))
+            },
+        )
+        .register_fn(
+            "transform",
+            |object: Dynamic,
+             translation: Vec3,
+             rotation: Quat,
+             scale: Vec3|
+             -> Result<Arc<dyn Hittable>, Box<EvalAltResult>> {
+                let object = Box::new(to_instance(object)?);
+                let matrix = Mat4::from_trs(&translation, &rotation, &scale);
+                Ok(share(Transform::from(object, matrix)?))
+            },
+        );
+
+    // Textures
+    engine
+        .register_type_with_name::<Arc<dyn Texture>>("Texture")
+        .register_fn("solid_color", |albedo: Vec3| -> Arc<dyn Texture> {
+            Arc::new(SolidColor::from(albedo.into()))
+        })
+        .register_fn(
+            "checker",
+            |scale: f64, even: Vec3, odd: Vec3| -> Arc<dyn Texture> {
+                Arc::new(CheckerTexture::from_colors(scale, even.into(), odd.into()))
+            },
+        )
+        .register_fn(
+            "checker",
+            |scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>| -> Arc<dyn Texture> {
+                Arc::new(CheckerTexture::from(scale, even, odd))
+            },
+        )
+        .register_fn("image_texture", {
+            let files = files.clone();
+            move |path: &str| -> Result<Arc<dyn Texture>, Box<EvalAltResult>> {
+                let image = ImageTexture::open_shared(&files.resolve(path)?)
+                    .map_err(|err| format!("cannot load {}: {}", path, err))?;
+                Ok(image)
+            }
+        });
+
+    // Materials
+    engine
+        .register_type_with_name::<Material>("Material")
+        .register_fn("lambertian", |albedo: Vec3| {
+            Material::Lambertian(Lambertian::from(albedo.into()))
+        })
+        .register_fn("lambertian", |texture: Arc<dyn Texture>| {
+            Material::Lambertian(Lambertian::textured(texture))
+        })
+        .register_fn("metal", |albedo: Vec3, fuzz: f64| {
+            Material::Metal(Metal::from(albedo.into(), fuzz))
+        })
+        .register_fn("dielectric", |ir: f64| {
+            Material::Dieletric(Dieletric::from(ir))
+        })
+        .register_fn("diffuse_light", |emit: Vec3| {
+            Material::DiffuseLight(DiffuseLight::from(emit.into()))
+        })
+        .register_fn("isotropic", |albedo: Vec3| {
+            Material::Isotropic(Isotropic::from(albedo.into()))
+        })
+        .register_fn("water", water)
+        .register_fn(
+            "procedural",
+            |albedo: &str| -> Result<Material, Box<EvalAltResult>> {
+                let expr = Expr::parse(albedo)?;
+                Ok(Material::Procedural(Procedural::from(expr)))
+            },
+        )
+        .register_fn("band", |base: Material, emission: f64| {
+            Material::Banded(Banded::from(base, emission, None))
+        })
+        .register_fn("band", |base: Material, emission: f64, reflectance: f64| {
+            Material::Banded(Banded::from(base, emission, Some(reflectance)))
+        });
+
+    // Geometry
+    engine
+        .register_type_with_name::<Sphere>("Sphere")
+        .register_fn(
+            "sphere",
+            |center: Point3, radius: f64, material: Material| {
+                Sphere::from(center, radius, material)
+            },
+        )
+        .register_fn(
+            "moving_sphere",
+            |center0: Point3, center1: Point3, radius: f64, material: Material| {
+                Sphere::moving(center0, center1, radius, material)
+            },
+        )
+        .register_fn(
+            "constant_medium",
+            |boundary: Dynamic,
+             density: f64,
+             albedo: Vec3|
+             -> Result<ConstantMedium, Box<EvalAltResult>> {
+                let boundary = to_hittable(boundary).ok_or(MEDIUM_BOUNDARY)?;
+                Ok(ConstantMedium::isotropic(
+                    Arc::from(boundary),
+                    density,
+                    albedo.into(),
+                ))
+            },
+        )
+        .register_fn(
+            "constant_medium",
+            |boundary: Dynamic,
+             density: f64,
+             phase: Material|
+             -> Result<ConstantMedium, Box<EvalAltResult>> {
+                let boundary = to_hittable(boundary).ok_or(MEDIUM_BOUNDARY)?;
+                Ok(ConstantMedium::from(Arc::from(boundary), density, phase))
+            },
+        )
+        .register_fn("quad", |q: Point3, u: Vec3, v: Vec3, material: Material| {
+            Quad::from(q, u, v, material)
+        })
+        .register_fn("cuboid", |a: Point3, b: Point3, material: Material| {
+            Cuboid::from(a, b, material)
+        })
+        .register_fn(
+            "plane",
+            |point: Point3, normal: Vec3, material: Material| Plane::from(point, normal, material),
+        )
+        .register_fn(
+            "disk",
+            |center: Point3, normal: Vec3, radius: f64, material: Material| {
+                Disk::from(center, normal, radius, material)
+            },
+        )
+        .register_fn(
+            "cylinder",
+            |base: Point3, top: Point3, radius: f64, material: Material| {
+                Cylinder::from(base, top, radius, material)
+            },
+        )
+        .register_fn(
+            "cone",
+            |base: Point3, apex: Point3, radius: f64, material: Material| {
+                Cone::from(base, apex, radius, material)
+            },
+        )
+        .register_fn(
+            "capsule",
+            |a: Point3, b: Point3, radius: f64, material: Material| {
+                Capsule::from(a, b, radius, material)
+            },
+        )
+        .register_fn(
+            "triangle",
+            |a: Point3, b: Point3, c: Point3, material: Material| Triangle::from(a, b, c, material),
+        )
+        .register_type_with_name::<Mesh>("Mesh")
+        .register_fn("mesh", to_mesh)
+        .register_fn(
+            "mesh",
+            |positions: Array,
+             indices: Array,
+             normals: Array,
+             material: Material|
+             -> Result<Mesh, Box<EvalAltResult>> {
+                let normals = to_vectors(normals, "normals")?;
+                Ok(to_mesh(positions, indices, material)?.with_normals(normals)?)
+            },
+        )
+        .register_fn("smooth", |mesh: Mesh| mesh.with_smooth_normals())
+        .register_fn(
+            "with_materials",
+            |mesh: Mesh, materials: Array, faces: Array| -> Result<Mesh, Box<EvalAltResult>> {
+                let materials: Vec<Material> = materials
+                    .into_iter()
+                    .map(|m| m.try_cast::<Material>())
+                    .collect::<Option<_>>()
+                    .ok_or("mesh materials must be materials")?;
+                let faces = to_indices(faces, "material indices")?;
+                Ok(mesh.with_face_materials(materials, faces)?)
+            },
+        )
+        .register_fn("load_obj", {
+            let files = files.clone();
+            move |path: &str, material: Material| -> Result<Mesh, Box<EvalAltResult>> {
+                Ok(obj::load(&files.resolve(path)?, material, &HashMap::new())
+                    .map_err(|err| err.to_string())?)
+            }
+        })
+        .register_fn("load_obj", {
+            let files = files.clone();
+            move |path: &str,
+                  material: Material,
+                  materials: Map|
+                  -> Result<Mesh, Box<EvalAltResult>> {
+                let materials = materials
+                    .into_iter()
+                    .map(|(name, m)| Some((name.to_string(), m.try_cast::<Material>()?)))
+                    .collect::<Option<_>>()
+                    .ok_or("OBJ materials must be a map of materials")?;
+                Ok(obj::load(&files.resolve(path)?, material, &materials)
+                    .map_err(|err| err.to_string())?)
+            }
+        })
+        .register_fn("load_ply", {
+            let files = files.clone();
+            move |path: &str, material: Material| -> Result<Mesh, Box<EvalAltResult>> {
+                Ok(ply::load(&files.resolve(path)?, material).map_err(|err| err.to_string())?)
+            }
+        })
+        .register_fn("load_stl", {
+            let files = files.clone();
+            move |path: &str, material: Material| -> Result<Mesh, Box<EvalAltResult>> {
+                Ok(stl::load(&files.resolve(path)?, material).map_err(|err| err.to_string())?)
+            }
+        })
+        .register_fn(
+            "ocean",
+            |options: Map, material: Material| -> Result<Mesh, Box<EvalAltResult>> {
+                let params = to_params(options)?;
+                let (ocean, time) = to_ocean(&params)?;
+                Ok(ocean.mesh(time, material)?)
+            },
+        )
+        .register_fn(
+            "tree",
+            |options: Map, bark: Material, leaves: Material| -> Result<Mesh, Box<EvalAltResult>> {
+                Ok(to_lsystem(options)?.build(bark, leaves)?)
+            },
+        );
+
+    // Random
+    engine
+        .register_fn("random", random)
+        .register_fn("random", random_in_range);
+
+    // Camera
+    engine.register_fn(
+        "set_camera",
+        move |settings: Map| -> Result<(), Box<EvalAltResult>> {
+            for (key, value) in settings {
+                let value = match to_param(&key, value)? {
+                    Param::Float(x) => x.to_string(),
+                    Param::Vec3(v) => format!("{},{},{}", v.x(), v.y(), v.z()),
+                    Param::Bool(b) => b.to_string(),
+                    Param::Str(s) => s,
+                    _ => {
+                        return Err(format!(
+                            "camera setting '{}' must be a number, bool, string or vec3",
+                            key
+                        )
+                        .into())
+                    }
+                };
+                camera.borrow_mut().push((key.to_string(), value));
+            }
+            Ok(())
+        },
+    );
+
+    // Background
+    engine
+        .register_type_with_name::<Background>("Background")
+        .register_fn("solid", |color: Vec3| Background::Solid(color.into()))
+        .register_fn("gradient", |top: Vec3, bottom: Vec3| Background::Gradient {
+            top: top.into(),
+            bottom: bottom.into(),
+        })
+        .register_fn("sky", |sun_direction: Vec3| {
+            Background::Sky(Sky::from(sun_direction))
+        })
+        .register_fn(
+            "environment",
+            |expr: &str| -> Result<Background, Box<EvalAltResult>> {
+                Ok(Background::Environment(Arc::new(Expr::parse(expr)?)))
+            },
+        )
+        .register_fn("hdri", {
+            let files = files.clone();
+            move |path: &str, strength: f64| -> Result<Background, Box<EvalAltResult>> {
+                let image = ImageTexture::open_shared(&files.resolve(path)?)
+                    .map_err(|err| format!("cannot load {}: {}", path, err))?;
+                Ok(Background::Image { image, strength })
+            }
+        })
+        .register_fn("set_background", move |value: Background| {
+            *background.borrow_mut() = Some(value);
+        });
+
+    // Ambient light
+    let flat = ambient.clone();
+    engine
+        .register_fn("set_ambient", move |color: Vec3| {
+            *flat.borrow_mut() = Some(Ambient::Flat(color.into()));
+        })
+        .register_fn("set_ambient", move |sky: Vec3, ground: Vec3| {
+            *ambient.borrow_mut() = Some(Ambient::Hemisphere {
+                sky: sky.into(),
+                ground: ground.into(),
+            });
+        });
+
+    // Clipping
+    let uncapped = clip_planes.clone();
+    engine
+        .register_fn("add_clip_plane", move |point: Vec3, normal: Vec3| {
+            uncapped
+                .borrow_mut()
+                .push(ClipPlane::from(point, normal, None));
+        })
+        .register_fn(
+            "add_clip_plane",
+            move |point: Vec3, normal: Vec3, cap: Material| {
+                clip_planes
+                    .borrow_mut()
+                    .push(ClipPlane::from(point, normal, Some(cap)));
+            },
+        );
+
+    // Registered plugin types
+    let materials = registry.clone();
+    engine.register_fn(
+        "material",
+        move |name: &str, params: Map| -> Result<Material, Box<EvalAltResult>> {
+            Ok(materials.create_material(name, &to_params(params)?)?)
+        },
+    );
+    let textures = registry.clone();
+    engine.register_fn(
+        "texture",
+        move |name: &str, params: Map| -> Result<Arc<dyn Texture>, Box<EvalAltResult>> {
+            Ok(textures.create_texture(name, &to_params(params)?)?)
+        },
+    );
+    let objects = world.clone();
+    engine.register_fn(
+        "add_object",
+        move |name: &str, params: Map| -> Result<(), Box<EvalAltResult>> {
+            let object = registry.create_hittable(name, &to_params(params)?)?;
+            objects.borrow_mut().add(object);
+            Ok(())
+        },
+    );
+
+    register_add::<Sphere>(&mut engine, &world);
+    register_add::<Triangle>(&mut engine, &world);
+    register_add::<Quad>(&mut engine, &world);
+    register_add::<Cuboid>(&mut engine, &world);
+    register_add::<Plane>(&mut engine, &world);
+    register_add::<Disk>(&mut engine, &world);
+    register_add::<Cylinder>(&mut engine, &world);
+    register_add::<Cone>(&mut engine, &world);
+    register_add::<Capsule>(&mut engine, &world);
+    register_add::<Mesh>(&mut engine, &world);
+    register_add::<ConstantMedium>(&mut engine, &world);
+    register_add::<Arc<dyn Hittable>>(&mut engine, &world);
+    #[cfg(feature = "mmap")]
+    {
+        let mapped = world.clone();
+        let files = files.clone();
+        engine.register_fn(
+            "add_mapped_mesh",
+            move |path: &str, material: Material| -> Result<(), Box<EvalAltResult>> {
+                let mesh = crate::mapped::MappedMesh::open(&files.resolve(path)?, material)
+                    .map_err(|err| format!("cannot map {}: {}", path, err))?;
+                mapped.borrow_mut().add(Box::new(mesh));
+                Ok(())
+            },
+        );
+    }
+    engine
+        .register_type_with_name::<Light>("Light")
+        .register_fn("point_light", |position: Vec3, intensity: Vec3| {
+            Light::point(position, intensity.into())
+        })
+        .register_fn("directional_light", |direction: Vec3, irradiance: Vec3| {
+            Light::directional(direction, irradiance.into())
+        })
+        .register_fn(
+            "spot_light",
+            |position: Vec3, direction: Vec3, intensity: Vec3, angle: f64, falloff: f64| {
+                Light::spot(position, direction, intensity.into(), angle, falloff)
+            },
+        );
+    let lit = world.clone();
+    engine.register_fn(
+        "add_light",
+        move |object: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            if object.is::<Light>() {
+                punctual_lights.borrow_mut().push(object.cast::<Light>());
+                return Ok(());
+            }
+            let sampled = object.is::<Sphere>()
+                || object.is::<Quad>()
+                || object.is::<Cuboid>()
+                || object.is::<Disk>()
+                || object.is::<Arc<dyn Hittable>>();
+            if !sampled {
+                return Err(
+                    "lights must be point, directional or spot lights, or spheres, quads, boxes, disks or instances of them".into(),
+                );
+            }
+            let light = to_instance(object)?;
+            lit.borrow_mut().add(Box::new(light.clone()));
+            lights.borrow_mut().add(Box::new(light));
+            Ok(())
+        },
+    );
+    let lods = world.clone();
+    engine.register_fn(
+        "add_lod",
+        move |levels: Array, distances: Array| -> Result<(), Box<EvalAltResult>> {
+            let levels = levels
+                .into_iter()
+                .map(to_hittable)
+                .collect::<Option<_>>()
+                .ok_or("levels of detail must be shapes or meshes")?;
+            let distances = distances
+                .into_iter()
+                .map(|d| d.as_float().or_else(|_| d.as_int().map(|i| i as f64)).ok())
+                .collect::<Option<_>>()
+                .ok_or("switch distances must be numbers")?;
+            lods.borrow_mut()
+                .add(Box::new(Lod::from(levels, distances)?));
+            Ok(())
+        },
+    );
+    let scattered = world.clone();
+    engine.register_fn(
+        "add_scatter",
+        move |target: Dynamic, prototypes: Array, options: Map| -> Result<(), Box<EvalAltResult>> {
+            let target = to_hittable(target).ok_or("scatter targets must be shapes or meshes")?;
+            let prototypes: Vec<Arc<dyn Hittable>> = prototypes
+                .into_iter()
+                .map(|p| to_hittable(p).map(Arc::from))
+                .collect::<Option<_>>()
+                .ok_or("scatter prototypes must be shapes or meshes")?;
+            let instances =
+                to_scatter(&to_params(options)?)?.place(target.as_ref(), &prototypes)?;
+            scattered.borrow_mut().add(Box::new(instances));
+            Ok(())
+        },
+    );
+    engine
+}
+
+// Registers `add(object)` and `add(object, #{ mask: 2, trace_set: 2 })`, which
+// puts the object in trace sets; either key defaults to the object being
+// unrestricted.
+fn register_add<T: Hittable + Clone + 'static>(
+    engine: &mut Engine,
+    world: &Rc<RefCell<HittableList>>,
+) {
+    let plain = world.clone();
+    engine.register_fn("add", move |object: T| {
+        plain.borrow_mut().add(Box::new(object));
+    });
+    let masked = world.clone();
+    engine.register_fn(
+        "add",
+        move |object: T, sets: Map| -> Result<(), Box<EvalAltResult>> {
+            let object = in_trace_sets(Box::new(object), &sets)?;
+            masked.borrow_mut().add(Box::new(object));
+            Ok(())
+        },
+    );
+}
+
+fn to_hittable(value: Dynamic) -> Option<Box<dyn Hittable>> {
+    if value.is::<Sphere>() {
+        return Some(Box::new(value.cast::<Sphere>()));
+    }
+    if value.is::<Triangle>() {
+        return Some(Box::new(value.cast::<Triangle>()));
+    }
+    if value.is::<Quad>() {
+        return Some(Box::new(value.cast::<Quad>()));
+    }
+    if value.is::<Cuboid>() {
+        return Some(Box::new(value.cast::<Cuboid>()));
+    }
+    if value.is::<Disk>() {
+        return Some(Box::new(value.cast::<Disk>()));
+    }
+    if value.is::<Cylinder>() {
+        return Some(Box::new(value.cast::<Cylinder>()));
+    }
+    if value.is::<Cone>() {
+        return Some(Box::new(value.cast::<Cone>()));
+    }
+    if value.is::<Capsule>() {
+        return Some(Box::new(value.cast::<Capsule>()));
+    }
+    if value.is::<Arc<dyn Hittable>>() {
+        return Some(Box::new(value.cast::<Arc<dyn Hittable>>()));
+    }
+    value
+        .try_cast::<Mesh>()
+        .map(|mesh| Box::new(mesh) as Box<dyn Hittable>)
+}
+
+fn share(object: impl Hittable + 'static) -> Arc<dyn Hittable> {
+    Arc::from(Box::new(object) as Box<dyn Hittable>)
+}
+
+// The object as an instance, sharing its geometry if it already is one.
+fn to_instance(value: Dynamic) -> Result<Arc<dyn Hittable>, Box<EvalAltResult>> {
+    if value.is::<Arc<dyn Hittable>>() {
+        return Ok(value.cast::<Arc<dyn Hittable>>());
+    }
+    let object = to_hittable(value).ok_or("instances must be of shapes or meshes")?;
+    Ok(Arc::from(object))
+}
+
+fn to_lsystem(mut options: Map) -> Result<LSystem, String> {
+    let mut rules = HashMap::new();
+    if let Some(map) = options.remove("rules") {
+        let map = map
+            .try_cast::<Map>()
+            .ok_or("L-system rules must be a map")?;
+        for (symbol, replacement) in map {
+            let mut chars = symbol.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(format!("L-system rule '{}' must be one symbol", symbol));
+            };
+            let replacement = replacement
+                .into_string()
+                .map_err(|_| format!("L-system rule '{}' must be a string", symbol))?;
+            rules.insert(c, replacement);
+        }
+    }
+    let params = to_params(options)?;
+    let mut tree = LSystem::from(params.str("axiom")?, rules);
+    tree.iterations = params
+        .float_or("iterations", tree.iterations as f64)?
+        .max(0.0) as u32;
+    tree.angle = params.float_or("angle", tree.angle)?;
+    tree.length = params.float_or("length", tree.length)?;
+    tree.radius = params.float_or("radius", tree.radius)?;
+    tree.taper = params.float_or("taper", tree.taper)?;
+    tree.leaf_size = params.float_or("leaf_size", tree.leaf_size)?;
+    tree.sides = params.float_or("sides", tree.sides as f64)?.max(0.0) as usize;
+    Ok(tree)
+}
+
+// The ocean and the time to show it at.
+fn to_ocean(params: &Params) -> Result<(Ocean, f64), String> {
+    let defaults = Ocean::default();
+    let ocean = Ocean {
+        resolution: params
+            .float_or("resolution", defaults.resolution as f64)?
+            .max(0.0) as usize,
+        size: params.float_or("size", defaults.size)?,
+        wind: match params.get("wind") {
+            None => defaults.wind,
+            Some(_) => params.vec3("wind")?,
+        },
+        wind_speed: params.float_or("wind_speed", defaults.wind_speed)?,
+        amplitude: params.float_or("amplitude", defaults.amplitude)?,
+        choppiness: params.float_or("choppiness", defaults.choppiness)?,
+        seed: params.float_or("seed", defaults.seed as f64)?.max(0.0) as u64,
+    };
+    Ok((ocean, params.float_or("time", 0.0)?))
+}
+
+fn to_scatter(params: &Params) -> Result<Scatter, String> {
+    let defaults = Scatter::default();
+    let region = match (params.get("region_min"), params.get("region_max")) {
+        (None, None) => None,
+        _ => Some((params.vec3("region_min")?, params.vec3("region_max")?)),
+    };
+    let density = match params.get("density") {
+        None => None,
+        Some(_) => Some(Arc::new(Expr::parse(params.str("density")?)?)),
+    };
+    let scale_min = params.float_or("scale_min", defaults.scale.0)?;
+    Ok(Scatter {
+        count: params.float_or("count", defaults.count as f64)?.max(0.0) as usize,
+        seed: params.float_or("seed", defaults.seed as f64)?.max(0.0) as u64,
+        region,
+        density,
+        scale: (scale_min, params.float_or("scale_max", scale_min)?),
+        align: match params.get("align") {
+            None => defaults.align,
+            Some(_) => params.bool("align")?,
+        },
+    })
+}
+
+fn in_trace_sets(object: Box<dyn Hittable>, sets: &Map) -> Result<Masked, Box<EvalAltResult>> {
+    let set = |key: &str, default: u32| -> Result<u32, Box<EvalAltResult>> {
+        match sets.get(key) {
+            None => Ok(default),
+            Some(value) => value
+                .as_int()
+                .ok()
+                .and_then(|x| u32::try_from(x).ok())
+                .ok_or_else(|| format!("'{}' must be a bitmask", key).into()),
+        }
+    };
+    Ok(Masked::from(
+        object,
+        set("mask", DEFAULT_SET)?,
+        set("trace_set", ALL_SETS)?,
+    ))
+}
+
+// A mesh from an array of vertex positions and a flat array of vertex
+// indices, three per triangle.
+fn to_mesh(
+    positions: Array,
+    indices: Array,
+    material: Material,
+) -> Result<Mesh, Box<EvalAltResult>> {
+    let positions = to_vectors(positions, "positions")?;
+    let indices = to_indices(indices, "indices")?;
+    if !indices.len().is_multiple_of(3) {
+        return Err("mesh indices must come in threes".into());
+    }
+    let triangles = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+    Ok(Mesh::from(positions, triangles, material)?)
+}
+
+fn to_indices(values: Array, what: &str) -> Result<Vec<usize>, Box<EvalAltResult>> {
+    values
+        .into_iter()
+        .map(|i| i.as_int().ok().and_then(|i| usize::try_from(i).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("mesh {} must be non-negative integers", what).into())
+}
+
+fn to_vectors(values: Array, what: &str) -> Result<Vec<Vec3>, Box<EvalAltResult>> {
+    values
+        .into_iter()
+        .map(|v| v.try_cast::<Vec3>())
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("mesh {} must be vec3s", what).into())
+}
+
+fn to_params(map: Map) -> Result<Params, String> {
+    let mut params = Params::new();
+    for (name, value) in map {
+        params.set(&name, to_param(&name, value)?);
+    }
+    Ok(params)
+}
+
+fn to_param(name: &str, value: Dynamic) -> Result<Param, String> {
+    if let Ok(x) = value.as_float() {
+        return Ok(Param::Float(x));
+    }
+    if let Ok(x) = value.as_int() {
+        return Ok(Param::Float(x as f64));
+    }
+    if let Ok(b) = value.as_bool() {
+        return Ok(Param::Bool(b));
+    }
+    if value.is_string() {
+        return Ok(Param::Str(value.into_string()?));
+    }
+    if let Some(v) = value.clone().try_cast::<Vec3>() {
+        return Ok(Param::Vec3(v));
+    }
+    if let Some(m) = value.clone().try_cast::<Material>() {
+        return Ok(Param::Material(m));
+    }
+    if let Some(t) = value.clone().try_cast::<Arc<dyn Texture>>() {
+        return Ok(Param::Texture(t));
+    }
+    Err(format!(
+        "parameter '{}' has unsupported type {}",
+        name,
+        value.type_name()
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::utility::{Interval, INFINITY};
+
+    #[test]
+    fn builds_worlds() {
+        let world = eval_scene(
+            r#"
+            let ground = checker(0.5, vec3(0.2, 0.3, 0.1), vec3(0.9, 0.9, 0.9));
+            add(sphere(vec3(0.0, -100.0, 0.0), 100.0, lambertian(ground)));
+            add(sphere(vec3(0.0, 1.0, 0.0), 1.0, dielectric(1.5)));
+            let mirror = metal(vec3(0.7, 0.6, 0.5), 0.1);
+            add(quad(vec3(-1.0, 0.0, -4.0), vec3(2.0, 0.0, 0.0), vec3(0.0, 2.0, 0.0), mirror));
+            let lamp = cuboid(vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0), diffuse_light(vec3(4.0, 4.0, 4.0)));
+            add(translate(lamp, vec3(5.0, 0.0, 0.0)));
+            "#,
+        )
+        .unwrap();
+        assert_eq!(world.len(), 4);
+
+        // Straight through the glass sphere's center, which hides the quad.
+        let r = Ray::from(Point3::from(0.0, 1.0, 5.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = world.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+
+        assert!(eval_scene("add(42);").is_err());
+        assert!(eval_scene("add(sphere(vec3(0.0, 0.0, 0.0), \"big\", dielectric(1.5)));").is_err());
+    }
+
+    #[test]
+    fn sets_the_camera_for_each_frame() {
+        let source = r#"
+            set_camera(#{ look_from: vec3(time, 2.0, 3.0), vfov: 20.0 + frame, samples: 16 });
+            add(sphere(vec3(0.0, 0.0, 0.0), 1.0, lambertian(vec3(0.5, 0.5, 0.5))));
+        "#;
+        let scene = eval_frame(source, 5, 0.25).unwrap();
+        assert_eq!(scene.world.len(), 1);
+        let mut camera = CameraBuilder::new();
+        scene.apply_camera(&mut camera).unwrap();
+        assert_eq!(camera.look_from, Some(Point3::from(0.25, 2.0, 3.0)));
+        assert_eq!(camera.vfov, Some(25.0));
+        assert_eq!(camera.samples_per_pixel, Some(16));
+
+        // Settings the camera doesn't have are reported when applied.
+        let scene = eval_frame("set_camera(#{ zoom: 2.0 });", 0, 0.0).unwrap();
+        assert!(scene.apply_camera(&mut CameraBuilder::new()).is_err());
+    }
+
+    #[test]
+    fn evaluates_example_scenes() {
+        let mut paths: Vec<_> = std::fs::read_dir("scenes")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            // Image textures need the `image` feature to load.
+            if !cfg!(feature = "image") && path.ends_with("textures.rhai") {
+                continue;
+            }
+            let scene = load_frame(&path, 0, 0.0)
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            assert!(!scene.world.is_empty(), "{}", path.display());
+            let mut camera = crate::preset::default_camera();
+            scene.apply_camera(&mut camera).unwrap();
+            assert!(camera.build().is_ok(), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn sandboxes_scripts() {
+        let load = "add(load_obj(\"models/gem.obj\", lambertian(vec3(0.5, 0.5, 0.5))));";
+        assert!(eval_frame(&load.replace("models", "scenes/models"), 0, 0.0).is_ok());
+        assert!(eval_frame_sandboxed(load, 0, 0.0, &Sandbox::default()).is_err());
+
+        let sandbox = Sandbox {
+            assets: Some(PathBuf::from("scenes")),
+            ..Sandbox::default()
+        };
+        let scene = eval_frame_sandboxed(load, 0, 0.0, &sandbox).unwrap();
+        assert_eq!(scene.world.len(), 1);
+        for path in ["../Cargo.toml", "/etc/hosts", "models/missing.obj"] {
+            let escape = load.replace("models/gem.obj", path);
+            assert!(
+                eval_frame_sandboxed(&escape, 0, 0.0, &sandbox).is_err(),
+                "{}",
+                path
+            );
+        }
+
+        for endless in [
+            "loop {}",
+            "fn f(n) { f(n + 1) } f(0);",
+            "let s = \"x\"; loop { s += s; }",
+        ] {
+            assert!(
+                eval_frame_sandboxed(endless, 0, 0.0, &sandbox).is_err(),
+                "{}",
+                endless
+            );
+        }
+        assert!(eval_frame_sandboxed("import \"scenes/tree\" as tree;", 0, 0.0, &sandbox).is_err());
+    }
+}
@@ -0,0 +1,611 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::{write_color, Color};
+use crate::script::{self, Sandbox};
+
+use log::{error, info};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// A small HTTP API for rendering scene scripts:
+//
+//   POST /render?width=400&samples=50    body: Rhai scene script  -> {"id": 1}
+//   GET  /jobs/1                          -> {"id": 1, "status": "rendering", "progress": 0.42}
+//   GET  /jobs/1/image                    -> PPM image once the job is done
+//
+// The script's `set_camera` settings are overridden by those in the query
+// string: width, aspect_ratio, samples, max_depth, vfov, look_from, look_at,
+// vup (as "x,y,z"), defocus_angle and focus_dist. Jobs render one at a time in
+// submission order.
+//
+// Scripts run in a `Sandbox`: they may only load files from `assets`, or none
+// without it. Scripts over `MAX_BODY` bytes are refused with 413, images over
+// `MAX_SIZE` pixels a side or `MAX_SAMPLES` samples a pixel with 400, and new
+// jobs while `MAX_QUEUED` wait with 503. Only the last `MAX_FINISHED` finished
+// jobs are kept; older ones are 404.
+pub fn serve(address: &str, assets: Option<&Path>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!("Listening on http://{}", listener.local_addr()?);
+
+    let sandbox = Sandbox {
+        assets: assets.map(Path::to_path_buf),
+        ..Sandbox::default()
+    };
+    let jobs: Jobs = Arc::new(Mutex::new(HashMap::new()));
+    let (queue, pending) = mpsc::channel();
+    let worker_jobs = jobs.clone();
+    thread::spawn(move || render_worker(worker_jobs, pending, &sandbox));
+
+    let state = Arc::new(Server {
+        jobs,
+        queue: Mutex::new(queue),
+        next_id: Mutex::new(1),
+    });
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let state = state.clone();
+        thread::spawn(move || {
+            if let Err(err) = state.handle(stream) {
+                error!("Connection failed: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+const MAX_BODY: usize = 1 << 20;
+const MAX_SIZE: i32 = 8192;
+const MAX_SAMPLES: i32 = 10_000;
+const MAX_QUEUED: usize = 64;
+const MAX_FINISHED: usize = 64;
+
+type Jobs = Arc<Mutex<HashMap<u64, Job>>>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Queued,
+    Rendering,
+    Done,
+    Failed,
+}
+
+struct Job {
+    status: Status,
+    progress: f64,
+    error: Option<String>,
+    image: Option<Vec<u8>>,
+}
+
+struct Submission {
+    id: u64,
+    query: HashMap<String, String>,
+    scene: String,
+}
+
+struct Server {
+    jobs: Jobs,
+    queue: Mutex<Sender<Submission>>,
+    next_id: Mutex<u64>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self::json(
+            status,
+            format!("{{\"error\": \"{}\"}}", json_escape(message)),
+        )
+    }
+}
+
+impl Server {
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let response = match read_request(&mut reader) {
+            Ok(request) => self.route(request),
+            Err(response) => response,
+        };
+
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        )?;
+        stream.write_all(&response.body)?;
+        stream.flush()
+    }
+
+    fn route(&self, request: Request) -> Response {
+        let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["render"]) => self.submit(&request),
+            ("GET", ["jobs", id]) => self.with_job(id, |id, job| {
+                let error = match &job.error {
+                    Some(err) => format!(", \"error\": \"{}\"", json_escape(err)),
+                    None => String::new(),
+                };
+                Response::json(
+                    "200 OK",
+                    format!(
+                        "{{\"id\": {}, \"status\": \"{}\", \"progress\": {:.4}{}}}",
+                        id,
+                        status_name(job.status),
+                        job.progress,
+                        error
+                    ),
+                )
+            }),
+            ("GET", ["jobs", id, "image"]) => self.with_job(id, |_, job| match &job.image {
+                Some(image) => Response {
+                    status: "200 OK",
+                    content_type: "image/x-portable-pixmap",
+                    body: image.clone(),
+                },
+                None => Response::error("409 Conflict", "image is not ready"),
+            }),
+            _ => Response::error("404 Not Found", "no such endpoint"),
+        }
+    }
+
+    fn submit(&self, request: &Request) -> Response {
+        // The script's camera is only known once it runs, so the query alone
+        // is checked here and the whole camera again before rendering.
+        if let Err(err) = camera_from_query(CameraBuilder::new(), &request.query) {
+            return Response::error("400 Bad Request", &err);
+        }
+        let Ok(scene) = String::from_utf8(request.body.clone()) else {
+            return Response::error("400 Bad Request", "scene script must be UTF-8");
+        };
+
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let queued = jobs.values().filter(|job| job.status == Status::Queued);
+            if queued.count() >= MAX_QUEUED {
+                return Response::error("503 Service Unavailable", "too many jobs are queued");
+            }
+            let mut next_id = self.next_id.lock().unwrap();
+            let id = *next_id;
+            *next_id += 1;
+            jobs.insert(
+                id,
+                Job {
+                    status: Status::Queued,
+                    progress: 0.0,
+                    error: None,
+                    image: None,
+                },
+            );
+            id
+        };
+        let submission = Submission {
+            id,
+            query: request.query.clone(),
+            scene,
+        };
+        if self.queue.lock().unwrap().send(submission).is_err() {
+            return Response::error("503 Service Unavailable", "render worker has stopped");
+        }
+
+        info!("Queued job {}", id);
+        Response::json("202 Accepted", format!("{{\"id\": {}}}", id))
+    }
+
+    fn with_job(&self, id: &str, f: impl FnOnce(u64, &Job) -> Response) -> Response {
+        let Ok(id) = id.parse::<u64>() else {
+            return Response::error("400 Bad Request", "invalid job id");
+        };
+        match self.jobs.lock().unwrap().get(&id) {
+            Some(job) => f(id, job),
+            None => Response::error("404 Not Found", "no such job"),
+        }
+    }
+}
+
+fn render_worker(jobs: Jobs, pending: Receiver<Submission>, sandbox: &Sandbox) {
+    for submission in pending {
+        let id = submission.id;
+        let update = |f: &dyn Fn(&mut Job)| {
+            if let Some(job) = jobs.lock().unwrap().get_mut(&id) {
+                f(job);
+            }
+        };
+
+        update(&|job| job.status = Status::Rendering);
+        // A job that panics fails alone; the worker goes on to the next.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            render_job(submission, sandbox, &|progress| {
+                update(&|job| job.progress = progress)
+            })
+        }))
+        .unwrap_or_else(|payload| {
+            Err(match payload.downcast_ref::<&str>() {
+                Some(message) => format!("render panicked: {}", message),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => format!("render panicked: {}", message),
+                    None => "render panicked".to_string(),
+                },
+            })
+        });
+        match result {
+            Ok(image) => update(&|job| {
+                job.status = Status::Done;
+                job.progress = 1.0;
+                job.image = Some(image.clone());
+            }),
+            Err(err) => update(&|job| {
+                job.status = Status::Failed;
+                job.error = Some(err.clone());
+            }),
+        }
+        evict_finished(&mut jobs.lock().unwrap());
+        info!("Finished job {}", id);
+    }
+}
+
+// Forgets the oldest finished jobs, images and all, beyond `MAX_FINISHED`.
+fn evict_finished(jobs: &mut HashMap<u64, Job>) {
+    let mut finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| matches!(job.status, Status::Done | Status::Failed))
+        .map(|(&id, _)| id)
+        .collect();
+    if finished.len() > MAX_FINISHED {
+        finished.sort_unstable();
+        for id in &finished[..finished.len() - MAX_FINISHED] {
+            jobs.remove(id);
+        }
+    }
+}
+
+fn render_job(
+    submission: Submission,
+    sandbox: &Sandbox,
+    progress: &dyn Fn(f64),
+) -> Result<Vec<u8>, String> {
+    let loaded = script::eval_frame_sandboxed(&submission.scene, 0, 0.0, sandbox)
+        .map_err(|err| err.to_string())?;
+    let mut camera = CameraBuilder::new();
+    loaded.apply_camera(&mut camera)?;
+    let camera = camera_from_query(camera, &submission.query)?;
+    let scene = loaded.into_scene();
+
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let samples = camera.samples_per_pixel();
+    let tonemap = camera.tonemap();
+
+    let mut image = Vec::new();
+    writeln!(image, "P3\n{} {}\n255", width, height).map_err(|err| err.to_string())?;
+    for j in 0..height {
+        for i in 0..width {
+            let pixel_color: Color = camera.sample_pixel(&scene, i, j);
+            write_color(&mut image, pixel_color, samples, tonemap)
+                .map_err(|err| err.to_string())?;
+        }
+        progress((j + 1) as f64 / height as f64);
+    }
+
+    Ok(image)
+}
+
+// `camera` with the settings in the query string, if it's within the limits.
+fn camera_from_query(
+    mut camera: CameraBuilder,
+    query: &HashMap<String, String>,
+) -> Result<Camera, String> {
+    for (key, value) in query {
+        camera.set(key, value)?;
+    }
+    let camera = camera.build()?;
+    if camera.image_width() > MAX_SIZE || camera.image_height() > MAX_SIZE {
+        return Err(format!(
+            "image is {}x{}, larger than {} pixels a side",
+            camera.image_width(),
+            camera.image_height(),
+            MAX_SIZE
+        ));
+    }
+    if camera.samples_per_pixel() > MAX_SAMPLES {
+        return Err(format!(
+            "{} samples per pixel, more than {}",
+            camera.samples_per_pixel(),
+            MAX_SAMPLES
+        ));
+    }
+    Ok(camera)
+}
+
+// The request read from `reader`, or the error response to send instead.
+fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let invalid = |message: &str| Response::error("400 Bad Request", message);
+    let failed = |err: io::Error| invalid(&err.to_string());
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(failed)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or(invalid("missing method"))?.to_string();
+    let target = parts.next().ok_or(invalid("missing path"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(failed)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY {
+        return Err(Response::error(
+            "413 Payload Too Large",
+            &format!("request body is over {} bytes", MAX_BODY),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(failed)?;
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        body,
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Queued => "queued",
+        Status::Rendering => "rendering",
+        Status::Done => "done",
+        Status::Failed => "failed",
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server() -> (Server, Receiver<Submission>) {
+        let (queue, pending) = mpsc::channel();
+        let server = Server {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            queue: Mutex::new(queue),
+            next_id: Mutex::new(1),
+        };
+        (server, pending)
+    }
+
+    fn request(raw: &str) -> Result<Request, Response> {
+        read_request(&mut io::Cursor::new(raw.as_bytes()))
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(&response.body).unwrap()
+    }
+
+    #[test]
+    fn decodes_query_strings() {
+        assert_eq!(percent_decode("look_from"), "look_from");
+        assert_eq!(percent_decode("13%2C2%2c3"), "13,2,3");
+        assert_eq!(percent_decode("a+b%20c"), "a b c");
+        assert_eq!(percent_decode("%E2%9C%93"), "\u{2713}");
+        // Broken escapes are kept as they are.
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn reads_requests() {
+        let raw = "POST /render?width=8&look_from=0%2C0%2C5 HTTP/1.1\r\n\
+                   Host: localhost\r\ncontent-length: 5\r\n\r\nhello";
+        let parsed = request(raw).ok().unwrap();
+        assert_eq!(
+            (parsed.method.as_str(), parsed.path.as_str()),
+            ("POST", "/render")
+        );
+        assert_eq!(parsed.query["look_from"], "0,0,5");
+        assert_eq!(parsed.body, b"hello");
+
+        let huge = format!(
+            "POST /render HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert_eq!(
+            request(&huge).err().unwrap().status,
+            "413 Payload Too Large"
+        );
+        let bad = "POST /render HTTP/1.1\r\nContent-Length: many\r\n\r\n";
+        assert_eq!(request(bad).err().unwrap().status, "400 Bad Request");
+        let short = "POST /render HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc";
+        assert_eq!(request(short).err().unwrap().status, "400 Bad Request");
+    }
+
+    #[test]
+    fn routes_requests() {
+        let (server, pending) = server();
+        let route = |raw: &str| server.route(request(raw).ok().unwrap());
+
+        let submitted =
+            route("POST /render?width=8&samples=2 HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}");
+        assert_eq!(submitted.status, "202 Accepted");
+        assert_eq!(body(&submitted), "{\"id\": 1}");
+        let queued = pending.try_recv().unwrap();
+        let camera = camera_from_query(CameraBuilder::new(), &queued.query).unwrap();
+        assert_eq!((queued.id, camera.image_width()), (1, 8));
+
+        let status = route("GET /jobs/1 HTTP/1.1\r\n\r\n");
+        assert_eq!(status.status, "200 OK");
+        assert!(body(&status).contains("\"status\": \"queued\""));
+        assert_eq!(
+            route("GET /jobs/1/image HTTP/1.1\r\n\r\n").status,
+            "409 Conflict"
+        );
+        assert_eq!(
+            route("GET /jobs/2 HTTP/1.1\r\n\r\n").status,
+            "404 Not Found"
+        );
+        assert_eq!(
+            route("GET /jobs/one HTTP/1.1\r\n\r\n").status,
+            "400 Bad Request"
+        );
+        assert_eq!(
+            route("DELETE /jobs/1 HTTP/1.1\r\n\r\n").status,
+            "404 Not Found"
+        );
+
+        // Oversized renders are refused before they are queued.
+        for query in [
+            "width=100000",
+            "width=100&aspect_ratio=0.001",
+            "samples=1000000",
+        ] {
+            let raw = format!("POST /render?{} HTTP/1.1\r\n\r\n", query);
+            assert_eq!(route(&raw).status, "400 Bad Request", "{}", query);
+        }
+        assert!(pending.try_recv().is_err());
+    }
+
+    #[test]
+    fn renders_with_the_script_camera() {
+        let scene = "set_camera(#{ width: 6, aspect_ratio: 1.0, samples: 1 });\n\
+                     add(sphere(vec3(0.0, 0.0, -1.0), 0.5, lambertian(vec3(0.5, 0.5, 0.5))));";
+        let render = |query: &[(&str, &str)]| {
+            let submission = Submission {
+                id: 1,
+                query: query
+                    .iter()
+                    .map(|&(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                scene: scene.to_string(),
+            };
+            render_job(submission, &Sandbox::default(), &|_| {})
+        };
+        assert!(render(&[]).unwrap().starts_with(b"P3\n6 6\n"));
+        // The query overrides the script, and the limits apply to both.
+        assert!(render(&[("width", "4")]).unwrap().starts_with(b"P3\n4 4\n"));
+        assert!(render(&[("aspect_ratio", "0.0001")]).is_err());
+
+        let submission = Submission {
+            id: 2,
+            query: HashMap::new(),
+            scene: "loop {}".to_string(),
+        };
+        assert!(render_job(submission, &Sandbox::default(), &|_| {}).is_err());
+    }
+
+    #[test]
+    fn keeps_the_latest_finished_jobs() {
+        let (server, pending) = server();
+        let route = |raw: &str| server.route(request(raw).ok().unwrap());
+        let submit = "POST /render?width=8 HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}";
+        for _ in 0..MAX_QUEUED {
+            assert_eq!(route(submit).status, "202 Accepted");
+        }
+        assert_eq!(route(submit).status, "503 Service Unavailable");
+
+        let mut jobs = server.jobs.lock().unwrap();
+        for id in 1000..1000 + MAX_FINISHED as u64 {
+            let done = Job {
+                status: Status::Queued,
+                progress: 1.0,
+                error: None,
+                image: Some(Vec::new()),
+            };
+            jobs.insert(id, done);
+        }
+        for job in jobs.values_mut() {
+            job.status = Status::Done;
+        }
+        jobs.get_mut(&1).unwrap().status = Status::Rendering;
+        evict_finished(&mut jobs);
+        assert_eq!(jobs.len(), MAX_FINISHED + 1);
+        assert!(jobs.contains_key(&1) && !jobs.contains_key(&2) && jobs.contains_key(&1000));
+        drop(jobs);
+        assert_eq!(pending.try_iter().count(), MAX_QUEUED);
+    }
+}
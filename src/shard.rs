@@ -0,0 +1,140 @@
+use crate::camera::Camera;
+use crate::framebuffer::FrameBuffer;
+use crate::scene::Scene;
+
+// One of `count` deterministic slices of a frame. Shards rendered separately
+// (e.g. as jobs on a scheduler) and saved as accumulation buffers combine
+// into the full frame with `FrameBuffer::merge`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    pub index: i32,
+    pub count: i32,
+    pub mode: ShardMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShardMode {
+    // Every shard renders the whole image with its share of the samples.
+    Samples,
+    // Every shard renders all samples for the scanlines j with j % count == index.
+    Rows,
+}
+
+impl Shard {
+    // Parses "i/N", with i counted from zero.
+    pub fn parse(s: &str, mode: ShardMode) -> Result<Self, String> {
+        let invalid = || format!("invalid shard '{}', expected i/N", s);
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: i32 = index.trim().parse().map_err(|_| invalid())?;
+        let count: i32 = count.trim().parse().map_err(|_| invalid())?;
+        if count < 1 || index < 0 || index >= count {
+            return Err(format!(
+                "shard index must be in 0..{}, got {}",
+                count, index
+            ));
+        }
+        Ok(Self { index, count, mode })
+    }
+
+    // Samples per pixel this shard takes out of `total`, spreading the
+    // remainder over the first shards.
+    pub fn samples(&self, total: i32) -> i32 {
+        match self.mode {
+            ShardMode::Samples => {
+                total / self.count
+                    + if self.index < total % self.count {
+                        1
+                    } else {
+                        0
+                    }
+            }
+            ShardMode::Rows => total,
+        }
+    }
+
+    // Index of this shard's first sample out of `total`, so that shards of
+    // the same pixels take disjoint sample indices.
+    pub fn first_sample(&self, total: i32) -> i32 {
+        match self.mode {
+            ShardMode::Samples => (0..self.index)
+                .map(|index| Shard { index, ..*self }.samples(total))
+                .sum(),
+            ShardMode::Rows => 0,
+        }
+    }
+
+    pub fn contains_row(&self, j: i32) -> bool {
+        match self.mode {
+            ShardMode::Samples => true,
+            ShardMode::Rows => j % self.count == self.index,
+        }
+    }
+
+    pub fn render(&self, camera: &Camera, scene: &Scene) -> FrameBuffer {
+        let width = camera.image_width();
+        let height = camera.image_height();
+        let samples = self.samples(camera.samples_per_pixel());
+        let first = self.first_sample(camera.samples_per_pixel());
+
+        let mut buffer = camera.frame_buffer();
+        for j in (0..height).filter(|&j| self.contains_row(j)) {
+            for i in 0..width {
+                let sum = camera.sample_pixel_from(scene, i, j, first, samples);
+                buffer.add_samples(i, j, sum, samples as u32);
+            }
+        }
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let shard = Shard::parse("1/4", ShardMode::Samples).unwrap();
+        assert_eq!((shard.index, shard.count), (1, 4));
+
+        assert!(Shard::parse("4/4", ShardMode::Samples).is_err());
+        assert!(Shard::parse("1", ShardMode::Samples).is_err());
+        assert!(Shard::parse("a/b", ShardMode::Rows).is_err());
+    }
+
+    #[test]
+    fn partitions_samples_and_rows() {
+        // Samples add up to the total across shards.
+        let total: i32 = (0..3)
+            .map(|index| {
+                let shard = Shard {
+                    index,
+                    count: 3,
+                    mode: ShardMode::Samples,
+                };
+                shard.samples(10)
+            })
+            .sum();
+        assert_eq!(total, 10);
+        let last = Shard {
+            index: 2,
+            count: 3,
+            mode: ShardMode::Samples,
+        };
+        assert_eq!(last.first_sample(10) + last.samples(10), 10);
+
+        // Every row belongs to exactly one shard.
+        for j in 0..9 {
+            let owners = (0..3)
+                .filter(|&index| {
+                    let shard = Shard {
+                        index,
+                        count: 3,
+                        mode: ShardMode::Rows,
+                    };
+                    shard.contains_row(j)
+                })
+                .count();
+            assert_eq!(owners, 1);
+        }
+    }
+}
@@ -1,28 +1,56 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
 use crate::material::Material;
-use crate::ray::{Point3, Ray};
-use crate::utility::Interval;
+use crate::onb::Onb;
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::sampler::Sampler;
+use crate::utility::{Interval, INFINITY, PI};
 use crate::vec3::*;
 
+// A sphere, optionally moving in a straight line for motion blur: its center
+// is `center` at ray time 0 and `center + velocity` at time 1.
+#[derive(Clone)]
 pub struct Sphere {
     center: Point3,
+    velocity: Vec3,
     radius: f64,
     material: Material,
+    bbox: Aabb,
 }
 
 impl Sphere {
     pub fn from(center: Point3, radius: f64, material: Material) -> Self {
+        let rvec = Vec3::from(radius, radius, radius);
         Self {
             center,
+            velocity: Vec3::new(),
             radius,
             material,
+            bbox: Aabb::from_points(center - rvec, center + rvec),
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let oc = r.origin() - self.center;
+    // A sphere moving from `center0` at ray time 0 to `center1` at time 1.
+    pub fn moving(center0: Point3, center1: Point3, radius: f64, material: Material) -> Self {
+        let rvec = Vec3::from(radius, radius, radius);
+        Self {
+            velocity: center1 - center0,
+            bbox: Aabb::union(
+                &Aabb::from_points(center0 - rvec, center0 + rvec),
+                &Aabb::from_points(center1 - rvec, center1 + rvec),
+            ),
+            ..Self::from(center0, radius, material)
+        }
+    }
+
+    fn center_at(&self, time: f64) -> Point3 {
+        self.center + time * self.velocity
+    }
+
+    // The nearest root of the ray-sphere equation within `ray_t`.
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<f64> {
+        let oc = r.origin() - self.center_at(r.time());
         let a = r.direction().length_squared();
         let half_b = dot(&r.direction(), &oc);
         let c = oc.length_squared() - self.radius.powi(2);
@@ -43,19 +71,221 @@ impl Hittable for Sphere {
                 return None;
             }
         }
+        Some(root)
+    }
+
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        // p: a given point on the sphere of radius one, centered at the origin.
+        // u: returned value [0,1] of angle around the Y axis from X=-1.
+        // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+        let theta = f64::acos(-p.y());
+        let phi = f64::atan2(-p.z(), p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn get_sphere_dndu_dndv(n: &Vec3) -> (Vec3, Vec3) {
+        // Derivatives of the unit normal n(u, v) for the mapping above, where
+        // n = (-cos(phi) sin(theta), -cos(theta), sin(phi) sin(theta)).
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        let dndu = 2.0 * PI * Vec3::from(n.z(), 0.0, -n.x());
+        if sin_theta < 1e-8 {
+            // At the poles u is degenerate; any direction in the tangent plane will do.
+            return (Vec3::from(2.0 * PI, 0.0, 0.0), Vec3::from(0.0, 0.0, PI));
+        }
+        let dndv = PI
+            * Vec3::from(
+                -n.x() * n.y() / sin_theta,
+                sin_theta,
+                -n.z() * n.y() / sin_theta,
+            );
+        (dndu, dndv)
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let root = self.intersect(r, &ray_t)?;
 
         let t = root;
         let p = r.at(t);
-        let outward_normal = (p - self.center) / self.radius;
+        let outward_normal = (p - self.center_at(r.time())) / self.radius;
+        let (u, v) = Self::get_sphere_uv(&outward_normal);
+        let (dndu, dndv) = Self::get_sphere_dndu_dndv(&outward_normal);
         let mut rec: HitRecord = HitRecord {
             p: r.at(root),
             t: root,
+            u,
+            v,
+            dpdu: self.radius * dndu,
+            dpdv: self.radius * dndv,
+            dndu,
+            dndv,
             normal: outward_normal,
+            geometric_normal: outward_normal,
             front_face: false,
             mat: &self.material,
+            trace_set: ALL_SETS,
         };
         rec.set_face_normal(r, &outward_normal);
 
         Some(rec)
     }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        (p - self.center).length_squared() < self.radius * self.radius
+    }
+
+    // Sampling as a light draws directions uniformly within the cone the
+    // sphere covers from `origin`; moving spheres are sampled where they are
+    // at time 0.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::from(origin, direction);
+        if self
+            .intersect(&r, &Interval::from(0.001, INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+        let distance_squared = (self.center - origin).length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared)
+            .max(0.0)
+            .sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared)
+            .max(0.0)
+            .sqrt();
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        let local = Vec3::from(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::from(direction).transform(local)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        // The inverse of `get_sphere_uv`.
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        let n = Vec3::from(
+            -phi.cos() * theta.sin(),
+            -theta.cos(),
+            phi.sin() * theta.sin(),
+        );
+        Some((self.center + self.radius * n, n))
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("sphere", std::mem::size_of::<Self>());
+        info.add_material(&self.material);
+    }
+
+    #[cfg(feature = "gpu")]
+    fn upload(&self, scene: &mut crate::gpu::GpuScene) -> Result<(), String> {
+        scene.add_sphere(self.center, self.velocity, self.radius, &self.material)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn tangents_follow_uv() {
+        let sphere = Sphere::from(
+            Point3::new(),
+            2.0,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        );
+        let r = Ray::from(Point3::from(3.0, 1.0, -4.0), Vec3::from(-3.0, -1.0, 4.5));
+        let rec = sphere.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+
+        // Tangents are perpendicular to the normal and match a finite
+        // difference of the uv mapping.
+        assert!(dot(&rec.dpdu, &rec.normal).abs() < 1e-9);
+        assert!(dot(&rec.dpdv, &rec.normal).abs() < 1e-9);
+        let h = 1e-6;
+        let n = (rec.p - sphere.center) / sphere.radius;
+        let (u, v) = Sphere::get_sphere_uv(&(n + h * rec.dndu));
+        assert!((u - rec.u - h).abs() < 1e-9);
+        assert!((v - rec.v).abs() < 1e-9);
+        let (u, v) = Sphere::get_sphere_uv(&(n + h * rec.dndv));
+        assert!((u - rec.u).abs() < 1e-9);
+        assert!((v - rec.v - h).abs() < 1e-9);
+    }
+
+    #[test]
+    fn uv_is_measured_around_the_center() {
+        let center = Point3::from(1.0, 2.0, 3.0);
+        let sphere = Sphere::from(
+            center,
+            2.0,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        );
+        let uv = |from: Vec3| {
+            let r = Ray::from(center + 5.0 * from, -from);
+            let rec = sphere.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+            (rec.u, rec.v)
+        };
+        assert_eq!(uv(Vec3::from(1.0, 0.0, 0.0)), (0.5, 0.5));
+        assert_eq!(uv(Vec3::from(0.0, 0.0, 1.0)), (0.25, 0.5));
+        assert_eq!(uv(Vec3::from(0.0, 0.0, -1.0)), (0.75, 0.5));
+        assert_eq!(uv(Vec3::from(0.0, 1.0, 0.0)).1, 1.0);
+        assert_eq!(uv(Vec3::from(0.0, -1.0, 0.0)).1, 0.0);
+    }
+
+    #[test]
+    fn moves_with_ray_time() {
+        let sphere = Sphere::moving(
+            Point3::new(),
+            Point3::from(2.0, 0.0, 0.0),
+            0.5,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        );
+        let r = |time| {
+            Ray::from(Point3::from(2.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0)).with_time(time)
+        };
+        let ray_t = Interval::from(0.001, INFINITY);
+        assert!(sphere.hit(&r(0.0), ray_t).is_none());
+        let rec = sphere.hit(&r(1.0), ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 4.5);
+        approx::assert_relative_eq!(rec.normal.z(), 1.0);
+        assert!(sphere.hit(&r(0.8), ray_t).is_some());
+        approx::assert_relative_eq!(sphere.bounding_box().x.min, -0.5, epsilon = 1e-3);
+        approx::assert_relative_eq!(sphere.bounding_box().x.max, 2.5, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn occlusion() {
+        let sphere = Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        );
+        let r = Ray::from(Point3::from(0.0, 0.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        assert!(sphere.is_occluded(&r, Interval::from(0.001, INFINITY)));
+        // A shadow ray towards a light in front of the sphere is unblocked.
+        assert!(!sphere.is_occluded(&r, Interval::from(0.001, 3.5)));
+        let r = Ray::from(Point3::from(0.0, 2.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        assert!(!sphere.is_occluded(&r, Interval::from(0.001, INFINITY)));
+    }
 }
@@ -0,0 +1,239 @@
+use std::cell::Cell;
+use std::ops;
+use std::time::Duration;
+
+// Counters bumped by the integrator on the thread tracing the rays; tiled
+// renders add their workers' counts to the thread that started them. They
+// only ever grow; measure a render by taking the difference of two readings.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counters {
+    // Camera paths started, one primary ray each.
+    pub paths: u64,
+    // Every ray traced along camera paths, primary ones included.
+    pub path_rays: u64,
+    pub shadow_rays: u64,
+    pub bvh_node_visits: u64,
+}
+
+impl Counters {
+    pub fn primary_rays(&self) -> u64 {
+        self.paths
+    }
+
+    pub fn secondary_rays(&self) -> u64 {
+        self.path_rays - self.paths
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.path_rays + self.shadow_rays
+    }
+
+    // Scattering events per camera path.
+    pub fn average_bounces(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+        self.secondary_rays() as f64 / self.paths as f64
+    }
+}
+
+impl ops::Sub for Counters {
+    type Output = Self;
+    fn sub(self, earlier: Self) -> Self::Output {
+        Self {
+            paths: self.paths - earlier.paths,
+            path_rays: self.path_rays - earlier.path_rays,
+            shadow_rays: self.shadow_rays - earlier.shadow_rays,
+            bvh_node_visits: self.bvh_node_visits - earlier.bvh_node_visits,
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = const {
+        Cell::new(Counters {
+            paths: 0,
+            path_rays: 0,
+            shadow_rays: 0,
+            bvh_node_visits: 0,
+        })
+    };
+}
+
+fn bump(update: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        update(&mut value);
+        counters.set(value);
+    });
+}
+
+pub fn count_path() {
+    bump(|c| c.paths += 1);
+}
+
+pub fn count_ray() {
+    bump(|c| c.path_rays += 1);
+}
+
+pub fn count_shadow_ray() {
+    bump(|c| c.shadow_rays += 1);
+}
+
+pub fn count_bvh_node_visit() {
+    bump(|c| c.bvh_node_visits += 1);
+}
+
+// Adds counts made on another thread, such as a render worker's, to this
+// thread's.
+pub fn add(counts: Counters) {
+    bump(|c| {
+        c.paths += counts.paths;
+        c.path_rays += counts.path_rays;
+        c.shadow_rays += counts.shadow_rays;
+        c.bvh_node_visits += counts.bvh_node_visits;
+    });
+}
+
+// The counters of this thread so far.
+pub fn counters() -> Counters {
+    COUNTERS.with(|counters| counters.get())
+}
+
+// Rays traced on this thread so far.
+pub fn rays_traced() -> u64 {
+    counters().total_rays()
+}
+
+// Where the time of a render went, and what it traced.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub counters: Counters,
+    pub samples: u64,
+    // Loading the scene and building acceleration structures, filled in by
+    // whoever did that.
+    pub build: Duration,
+    pub trace: Duration,
+    pub write: Duration,
+    // Peak resident memory of the process in bytes, where the platform reports it.
+    pub peak_memory: Option<u64>,
+}
+
+impl RenderStats {
+    pub fn total_time(&self) -> Duration {
+        self.build + self.trace + self.write
+    }
+
+    pub fn samples_per_sec(&self) -> f64 {
+        per_second(self.samples, self.trace)
+    }
+
+    pub fn rays_per_sec(&self) -> f64 {
+        per_second(self.counters.total_rays(), self.trace)
+    }
+
+    // Human-readable summary, one figure per line.
+    pub fn report(&self) -> String {
+        let c = &self.counters;
+        let mut lines = vec![
+            format!(
+                "Rays:          {} ({} primary, {} secondary, {} shadow)",
+                c.total_rays(),
+                c.primary_rays(),
+                c.secondary_rays(),
+                c.shadow_rays
+            ),
+            format!("Avg bounces:   {:.2}", c.average_bounces()),
+            format!("BVH visits:    {}", c.bvh_node_visits),
+            format!(
+                "Time:          {:.2?} (build {:.2?}, trace {:.2?}, write {:.2?})",
+                self.total_time(),
+                self.build,
+                self.trace,
+                self.write
+            ),
+            format!(
+                "Rate:          {:.0} samples/s, {:.0} rays/s",
+                self.samples_per_sec(),
+                self.rays_per_sec()
+            ),
+        ];
+        if let Some(bytes) = self.peak_memory {
+            lines.push(format!(
+                "Peak memory:   {:.1} MiB",
+                bytes as f64 / (1024.0 * 1024.0)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        format!(
+            "{{\"rays\": {{\"total\": {}, \"primary\": {}, \"secondary\": {}, \"shadow\": {}}}, \"average_bounces\": {:.3}, \"bvh_node_visits\": {}, \"samples\": {}, \"time\": {{\"build\": {:.3}, \"trace\": {:.3}, \"write\": {:.3}}}, \"samples_per_sec\": {:.0}, \"rays_per_sec\": {:.0}, \"peak_memory\": {}}}",
+            c.total_rays(),
+            c.primary_rays(),
+            c.secondary_rays(),
+            c.shadow_rays,
+            c.average_bounces(),
+            c.bvh_node_visits,
+            self.samples,
+            self.build.as_secs_f64(),
+            self.trace.as_secs_f64(),
+            self.write.as_secs_f64(),
+            self.samples_per_sec(),
+            self.rays_per_sec(),
+            self.peak_memory
+                .map_or("null".to_string(), |bytes| bytes.to_string())
+        )
+    }
+}
+
+fn per_second(count: u64, time: Duration) -> f64 {
+    let seconds = time.as_secs_f64();
+    if seconds > 0.0 {
+        count as f64 / seconds
+    } else {
+        0.0
+    }
+}
+
+// Peak resident set size (VmHWM) on Linux.
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counters_accumulate() {
+        let before = counters();
+        for _ in 0..3 {
+            count_path();
+            count_ray();
+            count_ray();
+        }
+        count_shadow_ray();
+        let counted = counters() - before;
+        assert_eq!(counted.primary_rays(), 3);
+        assert_eq!(counted.secondary_rays(), 3);
+        assert_eq!(counted.total_rays(), 7);
+        assert_eq!(counted.average_bounces(), 1.0);
+
+        let stats = RenderStats {
+            counters: counted,
+            samples: 3,
+            trace: Duration::from_secs(1),
+            ..Default::default()
+        };
+        assert_eq!(stats.rays_per_sec(), 7.0);
+        assert!(stats
+            .to_json()
+            .starts_with("{\"rays\": {\"total\": 7, \"primary\": 3,"));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::assets::Cache;
+use crate::error::Error;
+use crate::material::Material;
+use crate::mesh::{self, Mesh};
+use crate::ray::Point3;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// Loads STL models, binary or ASCII, into a `Mesh`. STL lists every triangle
+// with its own corners, so corners at the same position are merged into one
+// vertex. The facet normals are left out: the mesh is shaded flat from its
+// triangles.
+//
+// Loaded models are cached until their file changes, so scenes rebuilt for
+// each frame or edit only copy them.
+pub fn load(path: &Path, material: Material) -> Result<Mesh, Error> {
+    static MODELS: Cache<Mesh> = Cache::new();
+    let model = MODELS.get(path, |path| {
+        crate::stage_span!("load_stl");
+        let failed = |message: String| Error::Scene {
+            path: path.to_path_buf(),
+            message,
+        };
+        let data = std::fs::read(path).map_err(|err| failed(err.to_string()))?;
+        parse(&data, mesh::stand_in()).map_err(failed)
+    })?;
+    Ok(Mesh::clone(&model)
+        .with_materials(vec![material])
+        .expect("models are parsed in one material"))
+}
+
+pub fn parse(data: &[u8], material: Material) -> Result<Mesh, String> {
+    let corners = if is_binary(data) {
+        binary_corners(data)
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| "not an STL file".to_string())?;
+        ascii_corners(text)?
+    };
+    if corners.is_empty() {
+        return Err("model has no facets".to_string());
+    }
+
+    let mut positions = Vec::new();
+    let mut vertices = HashMap::new();
+    let indices: Vec<usize> = corners
+        .iter()
+        .map(|p| {
+            let key = [p.x(), p.y(), p.z()].map(f64::to_bits);
+            *vertices.entry(key).or_insert_with(|| {
+                positions.push(*p);
+                positions.len() - 1
+            })
+        })
+        .collect();
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    Mesh::from(positions, triangles, material)
+}
+
+// Binary files begin with an 80-byte header and a count of triangles, 50
+// bytes each. The header of some binary files starts with "solid" like
+// ASCII ones, so the size decides.
+fn is_binary(data: &[u8]) -> bool {
+    let Some(count) = data.get(80..84) else {
+        return false;
+    };
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    data.len() == 84 + 50 * count || !data.starts_with(b"solid")
+}
+
+fn binary_corners(data: &[u8]) -> Vec<Point3> {
+    data[84..]
+        .chunks_exact(50)
+        .flat_map(|facet| {
+            let float = |at: usize| f32::from_le_bytes(facet[at..at + 4].try_into().unwrap());
+            // The normal comes first, then the three corners.
+            (1..4).map(move |k| {
+                let at = 12 * k;
+                Vec3::from(float(at) as f64, float(at + 4) as f64, float(at + 8) as f64)
+            })
+        })
+        .collect()
+}
+
+fn ascii_corners(text: &str) -> Result<Vec<Point3>, String> {
+    let mut corners = Vec::new();
+    let mut facet = 0;
+    for (number, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("facet") => facet = 0,
+            Some("vertex") => {
+                let values = words
+                    .map(|w| w.parse::<f64>().map_err(|_| format!("bad number '{}'", w)))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| format!("line {}: {}", number + 1, err))?;
+                if values.len() != 3 {
+                    return Err(format!("line {}: vertices need 3 numbers", number + 1));
+                }
+                corners.push(Vec3::from(values[0], values[1], values[2]));
+                facet += 1;
+            }
+            Some("endfacet") if facet != 3 => {
+                return Err(format!("line {}: facets need 3 vertices", number + 1));
+            }
+            _ => {}
+        }
+    }
+    Ok(corners)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::utility::{Interval, INFINITY};
+
+    fn gray() -> Material {
+        Material::Lambertian(Lambertian::from(Color::gray(0.5)))
+    }
+
+    fn hits(mesh: &Mesh, x: f64, y: f64) -> bool {
+        let r = Ray::from(Point3::from(x, y, 1.0), Vec3::from(0.0, 0.0, -1.0));
+        mesh.hit(&r, Interval::from(0.001, INFINITY)).is_some()
+    }
+
+    #[test]
+    fn parses_ascii_and_binary() {
+        let ascii = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+        let mesh = parse(ascii.as_bytes(), gray()).unwrap();
+        assert!(hits(&mesh, 0.25, 0.75));
+        assert!(hits(&mesh, 0.75, 0.25));
+        assert!(!hits(&mesh, 1.5, 0.5));
+
+        // A binary header may start with "solid" too.
+        let mut binary = b"solid but binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend(1u32.to_le_bytes());
+        for value in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(value.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+        let mesh = parse(&binary, gray()).unwrap();
+        assert!(hits(&mesh, 0.25, 0.25));
+        assert!(!hits(&mesh, 0.75, 0.75));
+
+        let broken = ascii.replace("vertex 1 1 0\n    endloop", "endloop");
+        assert!(parse(broken.as_bytes(), gray()).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::aov::{Aovs, Surface};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::control::RenderControl;
+use crate::error::Error;
+use crate::framebuffer::FrameBuffer;
+use crate::progress;
+use crate::scene::Scene;
+use crate::vec3::*;
+
+use std::io::Write;
+
+// Largest relative depth difference at which a reprojected pixel still
+// counts as the same surface.
+const DEPTH_TOLERANCE: f64 = 0.05;
+// Smallest cosine between the normals of the same surface in both frames.
+const NORMAL_TOLERANCE: f64 = 0.9;
+
+// Temporal accumulation for animations: every frame is blended with the
+// previous frame's result, reprojected through the motion vectors, so noise
+// averages out across frames instead of flickering. Pixels whose surface was
+// hidden or out of view in the previous frame keep their own value.
+pub struct Temporal {
+    // Weight of the reprojected previous frame, from 0 (off) towards 1.
+    blend: f64,
+    history: Option<History>,
+}
+
+struct History {
+    camera: Camera,
+    aovs: Aovs,
+    image: Vec<Color>,
+}
+
+impl Temporal {
+    pub fn from(blend: f64) -> Self {
+        Self {
+            blend: blend.clamp(0.0, 1.0),
+            history: None,
+        }
+    }
+
+    // Renders the frame and blends in the reprojected history.
+    pub fn render(&mut self, camera: &Camera, scene: &Scene) -> FrameBuffer {
+        let current = camera.render_buffer(scene, &RenderControl::new(), &mut progress::log);
+        let aovs = Aovs::render(camera, scene);
+        let (width, height) = (current.width(), current.height());
+        let motion = self
+            .history
+            .as_ref()
+            .map(|history| aovs.motion_vectors(&history.camera));
+
+        crate::stage_span!("reproject");
+        let mut output = FrameBuffer::new(width, height);
+        output.set_tonemap(current.tonemap());
+        let mut image = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            for i in 0..width {
+                let count = current.sample_count(i, j).max(1);
+                let mut color = current.pixel_sum(i, j) / count as f64;
+                if current.is_invalid(i, j) {
+                    output.mark_invalid(i, j);
+                } else if let (Some(history), Some(motion), Some(surface)) =
+                    (&self.history, &motion, aovs.get(i, j))
+                {
+                    if let Some((dx, dy)) = motion[(j * width + i) as usize] {
+                        let (x, y) = (i as f64 - dx, j as f64 - dy);
+                        if let Some(previous) = history.fetch(x, y, surface) {
+                            color = (1.0 - self.blend) * color + self.blend * previous;
+                        }
+                    }
+                }
+                output.add_samples(i, j, color * count as f64, count);
+                image.push(color);
+            }
+        }
+        output.complete_pass();
+
+        self.history = Some(History {
+            camera: camera.clone(),
+            aovs,
+            image,
+        });
+        output
+    }
+
+    pub fn render_to(
+        &mut self,
+        camera: &Camera,
+        scene: &Scene,
+        out: &mut impl Write,
+    ) -> Result<(), Error> {
+        Ok(self.render(camera, scene).write_ppm(out)?)
+    }
+}
+
+impl History {
+    // Bilinearly interpolated previous color at continuous pixel coordinates
+    // (x, y), using only the neighbours that saw the same surface.
+    fn fetch(&self, x: f64, y: f64, surface: &Surface) -> Option<Color> {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let center = self.camera.look_from();
+        let expected_depth = distance(&center, &surface.position);
+
+        let mut sum = Color::new();
+        let mut total = 0.0;
+        for (di, dj, weight) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let (i, j) = (x0 as i32 + di, y0 as i32 + dj);
+            let Some(previous) = self.aovs.get(i, j) else {
+                continue;
+            };
+            let same_depth =
+                (previous.depth - expected_depth).abs() <= DEPTH_TOLERANCE * expected_depth;
+            let same_facing = dot(&previous.normal, &surface.normal) >= NORMAL_TOLERANCE;
+            if weight > 0.0 && same_depth && same_facing {
+                sum += weight * self.image[(j * self.aovs.width() + i) as usize];
+                total += weight;
+            }
+        }
+        (total > 0.0).then(|| sum / total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::background::Background;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::ray::Point3;
+    use crate::sphere::Sphere;
+
+    // A diffuse sphere under a white sky, which every pixel sees without noise.
+    fn scene(center: Point3, albedo: f64) -> Scene {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            center,
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(albedo))),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        scene
+    }
+
+    #[test]
+    fn blends_reprojected_history() {
+        let camera = CameraBuilder {
+            image_width: Some(8),
+            samples_per_pixel: Some(1),
+            look_from: Some(Point3::from(0.0, 0.0, 3.0)),
+            // Narrow enough that the spheres cover the whole center pixel.
+            vfov: Some(40.0),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut temporal = Temporal::from(0.25);
+        let mean = |buffer: &FrameBuffer, i, j| buffer.pixel_sum(i, j).g();
+
+        let first = temporal.render(&camera, &scene(Point3::new(), 0.2));
+        approx::assert_relative_eq!(mean(&first, 4, 4), 0.2);
+
+        // Same surface: a quarter of the previous value.
+        let second = temporal.render(&camera, &scene(Point3::new(), 0.6));
+        approx::assert_relative_eq!(mean(&second, 4, 4), 0.5);
+        approx::assert_relative_eq!(mean(&second, 0, 0), 1.0);
+
+        // The surface moved away: the history is rejected.
+        let far = Point3::from(0.0, 0.0, -1.0);
+        let third = temporal.render(&camera, &scene(far, 0.6));
+        approx::assert_relative_eq!(mean(&third, 4, 4), 0.6);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::camera::Camera;
+use crate::scene::Scene;
+
+use log::info;
+use std::io::{self, Write};
+use std::net::TcpStream;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:14158";
+
+// Client for the IPC protocol of the tev image viewer
+// (https://github.com/Tom94/tev). Every packet is a little-endian u32 total
+// length, a u8 packet type and the payload; strings are null-terminated.
+pub struct TevClient {
+    stream: TcpStream,
+}
+
+const CLOSE_IMAGE: u8 = 2;
+const UPDATE_IMAGE: u8 = 3;
+const CREATE_IMAGE: u8 = 4;
+
+impl TevClient {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+
+    pub fn create_image(
+        &mut self,
+        name: &str,
+        width: i32,
+        height: i32,
+        channels: &[&str],
+    ) -> io::Result<()> {
+        let mut packet = Packet::new(CREATE_IMAGE);
+        packet.bool(true);
+        packet.string(name);
+        packet.i32(width);
+        packet.i32(height);
+        packet.i32(channels.len() as i32);
+        for channel in channels {
+            packet.string(channel);
+        }
+        self.send(packet)
+    }
+
+    // Sends a single channel of the rectangle at (x, y), row-major.
+    pub fn update_image(
+        &mut self,
+        name: &str,
+        channel: &str,
+        (x, y): (i32, i32),
+        (width, height): (i32, i32),
+        data: &[f32],
+    ) -> io::Result<()> {
+        let mut packet = Packet::new(UPDATE_IMAGE);
+        packet.bool(false);
+        packet.string(name);
+        packet.string(channel);
+        packet.i32(x);
+        packet.i32(y);
+        packet.i32(width);
+        packet.i32(height);
+        for value in data {
+            packet.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        self.send(packet)
+    }
+
+    pub fn close_image(&mut self, name: &str) -> io::Result<()> {
+        let mut packet = Packet::new(CLOSE_IMAGE);
+        packet.string(name);
+        self.send(packet)
+    }
+
+    fn send(&mut self, packet: Packet) -> io::Result<()> {
+        let length = packet.bytes.len() as u32 + 4;
+        self.stream.write_all(&length.to_le_bytes())?;
+        self.stream.write_all(&packet.bytes)
+    }
+}
+
+struct Packet {
+    bytes: Vec<u8>,
+}
+
+impl Packet {
+    fn new(packet_type: u8) -> Self {
+        Self {
+            bytes: vec![packet_type],
+        }
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.bytes.extend_from_slice(value.as_bytes());
+        self.bytes.push(0);
+    }
+}
+
+// Renders the image in strips of `TILE_ROWS` scanlines, streaming each
+// finished strip to tev as linear (not gamma corrected) RGB.
+pub fn render_to_tev(
+    camera: &Camera,
+    scene: &Scene,
+    client: &mut TevClient,
+    name: &str,
+) -> io::Result<()> {
+    const TILE_ROWS: i32 = 16;
+
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let scale = 1.0 / camera.samples_per_pixel() as f64;
+    client.create_image(name, width, height, &["R", "G", "B"])?;
+
+    for y in (0..height).step_by(TILE_ROWS as usize) {
+        let rows = TILE_ROWS.min(height - y);
+        let mut channels = [Vec::new(), Vec::new(), Vec::new()];
+        for j in y..y + rows {
+            for i in 0..width {
+                let color = camera.sample_pixel(scene, i, j) * scale;
+                for (k, channel) in channels.iter_mut().enumerate() {
+                    channel.push(color[k] as f32);
+                }
+            }
+        }
+
+        for (channel, data) in ["R", "G", "B"].iter().zip(channels.iter()) {
+            client.update_image(name, channel, (0, y), (width, rows), data)?;
+        }
+        info!("Sent scanlines {}..{} to tev", y, y + rows);
+    }
+
+    Ok(())
+}
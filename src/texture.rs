@@ -0,0 +1,186 @@
+use crate::assets::Cache;
+use crate::color::Color;
+use crate::ray::Point3;
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// A color that varies over a surface, looked up by the hit's texture
+// coordinates (u, v) or its position p. Textures are shared between the
+// materials and threads that use them, hence `Send + Sync`.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+// The same color everywhere.
+#[derive(Clone, Copy)]
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn from(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// A 3D checkerboard of cubes `scale` wide, alternating between two textures.
+// Being spatial, it needs no texture coordinates and doesn't stretch towards
+// the poles of a sphere.
+#[derive(Clone)]
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn from(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::from(
+            scale,
+            Arc::new(SolidColor::from(even)),
+            Arc::new(SolidColor::from(odd)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = |c: f64| (self.inv_scale * c).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// An image wrapped over the surface's texture coordinates, u running left to
+// right and v bottom to top, with the nearest pixel to (u, v) looked up.
+// Pixels are linear colors; images loaded from files are decoded from sRGB,
+// except floating-point ones such as Radiance .hdr files, which are linear
+// already and may go well above 1.
+#[derive(Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>, // Row by row from the top
+}
+
+impl ImageTexture {
+    pub fn from(width: usize, height: usize, pixels: Vec<Color>) -> Result<Self, String> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(format!(
+                "{} pixels for a {}x{} image",
+                pixels.len(),
+                width,
+                height
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // The image at `path`, decoded once and shared by every scene built
+    // while the file is unchanged.
+    pub fn open_shared(path: &Path) -> io::Result<Arc<Self>> {
+        static IMAGES: Cache<ImageTexture> = Cache::new();
+        IMAGES.get(path, Self::open)
+    }
+
+    #[cfg(feature = "image")]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        crate::stage_span!("load_texture");
+        let image = image::open(path).map_err(|err| io::Error::other(err.to_string()))?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = match image {
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => image
+                .into_rgb32f()
+                .pixels()
+                .map(|p| {
+                    let [r, g, b] = p.0.map(|c| c as f64);
+                    Color::from(r, g, b)
+                })
+                .collect(),
+            _ => image
+                .into_rgb8()
+                .pixels()
+                .map(|p| {
+                    let [r, g, b] = p.0.map(|c| c as f64 / 255.0);
+                    Color::from_srgb(r, g, b)
+                })
+                .collect(),
+        };
+        Self::from(width, height, pixels).map_err(io::Error::other)
+    }
+
+    #[cfg(not(feature = "image"))]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Err(io::Error::other(format!(
+            "rebuild with `--features image` to load textures like {}",
+            path.display()
+        )))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn looks_up_checkers_and_pixels() {
+        let checker = CheckerTexture::from_colors(0.5, Color::gray(1.0), Color::gray(0.0));
+        let at = |x, y, z| checker.value(0.0, 0.0, &Point3::from(x, y, z)).r();
+        approx::assert_relative_eq!(at(0.1, 0.1, 0.1), 1.0);
+        approx::assert_relative_eq!(at(0.6, 0.1, 0.1), 0.0);
+        approx::assert_relative_eq!(at(-0.1, 0.1, 0.1), 0.0);
+        approx::assert_relative_eq!(at(0.6, -0.1, 0.1), 1.0);
+
+        // Red top left, green top right, blue bottom left, white bottom right.
+        let image = ImageTexture::from(
+            2,
+            2,
+            vec![
+                Color::from(1.0, 0.0, 0.0),
+                Color::from(0.0, 1.0, 0.0),
+                Color::from(0.0, 0.0, 1.0),
+                Color::gray(1.0),
+            ],
+        )
+        .unwrap();
+        let p = Point3::new();
+        approx::assert_relative_eq!(image.value(0.2, 0.9, &p).r(), 1.0);
+        approx::assert_relative_eq!(image.value(1.0, 1.0, &p).g(), 1.0);
+        approx::assert_relative_eq!(image.value(0.0, 0.0, &p).b(), 1.0);
+        approx::assert_relative_eq!(image.value(2.0, -1.0, &p).r(), 1.0);
+        assert!(ImageTexture::from(2, 2, vec![p.into()]).is_err());
+    }
+}
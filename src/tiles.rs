@@ -0,0 +1,150 @@
+use crate::color::Color;
+use crate::progress::Tile;
+use crate::stats::{self, Counters};
+use crate::utility::Instant;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Renders an image as tiles shared out over a pool of threads. Every worker
+// takes the next tile nobody has started, so threads that get cheap tiles,
+// such as open sky, simply take more of them and all run out of work at about
+// the same time. Tiles come back to the calling thread, which puts them in
+// place and reports progress, so callers see the events of a serial render.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TileEvent {
+    Started(Tile),
+    // The summed samples of the tile's pixels, row by row.
+    Finished {
+        tile: Tile,
+        pixels: Vec<Color>,
+        elapsed: Duration,
+    },
+}
+
+enum Message {
+    Started(Tile),
+    Finished(TileEvent, Counters),
+}
+
+// The threads to render on when the camera doesn't say: one per core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Traces `tiles` in order on `threads` threads, handing each worker's tiles
+// to `trace`, which returns None to stop that worker (e.g. when the render is
+// cancelled). With one thread, everything happens on the calling thread. The
+// rays the workers trace are added to the calling thread's counters.
+pub fn render<T>(tiles: &[Tile], threads: usize, trace: T, on_event: &mut dyn FnMut(TileEvent))
+where
+    T: Fn(Tile) -> Option<Vec<Color>> + Sync,
+{
+    if threads <= 1 {
+        for &tile in tiles {
+            on_event(TileEvent::Started(tile));
+            let time_start = Instant::now();
+            let Some(pixels) = trace(tile) else {
+                return;
+            };
+            on_event(TileEvent::Finished {
+                tile,
+                pixels,
+                elapsed: time_start.elapsed(),
+            });
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads.min(tiles.len()) {
+            let sender = sender.clone();
+            let (next, trace) = (&next, &trace);
+            scope.spawn(move || loop {
+                let Some(&tile) = tiles.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                    return;
+                };
+                if sender.send(Message::Started(tile)).is_err() {
+                    return;
+                }
+                let (time_start, counters) = (Instant::now(), stats::counters());
+                let Some(pixels) = trace(tile) else {
+                    return;
+                };
+                let finished = TileEvent::Finished {
+                    tile,
+                    pixels,
+                    elapsed: time_start.elapsed(),
+                };
+                if sender
+                    .send(Message::Finished(finished, stats::counters() - counters))
+                    .is_err()
+                {
+                    return;
+                }
+            });
+        }
+        // The workers hold the only senders left, so this ends with them.
+        drop(sender);
+        for message in receiver {
+            match message {
+                Message::Started(tile) => on_event(TileEvent::Started(tile)),
+                Message::Finished(finished, counters) => {
+                    stats::add(counters);
+                    on_event(finished);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_tile_comes_back() {
+        let tiles = Tile::grid(10, 7, 4);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[5],
+            Tile {
+                x0: 8,
+                y0: 4,
+                x1: 10,
+                y1: 7
+            }
+        );
+
+        for threads in [1, 3] {
+            let mut finished = Vec::new();
+            let trace = |tile: Tile| {
+                stats::count_ray();
+                Some(vec![Color::gray(tile.x0 as f64); tile.pixels() as usize])
+            };
+            let rays = stats::counters().path_rays;
+            render(&tiles, threads, trace, &mut |event| {
+                if let TileEvent::Finished { tile, pixels, .. } = event {
+                    assert_eq!(pixels.len() as u64, tile.pixels());
+                    assert_eq!(pixels[0].r(), tile.x0 as f64);
+                    finished.push(tile);
+                }
+            });
+            finished.sort_by_key(|tile| (tile.y0, tile.x0));
+            assert_eq!(finished, tiles);
+            assert_eq!(stats::counters().path_rays - rays, 6);
+        }
+
+        // Workers stop when told to.
+        let mut started = 0;
+        render(&tiles, 2, |_| None, &mut |event| {
+            assert!(matches!(event, TileEvent::Started(_)));
+            started += 1;
+        });
+        assert!(started <= 2);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::aov::{Aovs, Surface};
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::hittable::Hittable;
+use crate::progress::{self, Tile, Tracker};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::utility::{Interval, INFINITY};
+use crate::vec3::*;
+
+// Cel shading: surfaces are lit by a single directional light, with the
+// diffuse term quantized into flat bands and cast shadows in the darkest
+// one, and outlines are drawn where the AOVs jump, at silhouettes (depth)
+// and creases (normals). Materials only contribute their albedo.
+#[derive(Clone, Copy, Debug)]
+pub struct Toon {
+    pub bands: u32,
+    // Towards the light; by default the sun of a sky background, or from
+    // above and behind the viewer's right shoulder otherwise.
+    pub light: Option<Vec3>,
+    pub ambient: f64, // Brightness of the darkest band, relative to the albedo
+    pub outline: Color,
+    // Neighbours whose depths differ by more than this fraction, or whose
+    // normals are further apart than this cosine, are separated by a line.
+    pub depth_threshold: f64,
+    pub normal_threshold: f64,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Self {
+            bands: 3,
+            light: None,
+            ambient: 0.3,
+            outline: Color::new(),
+            depth_threshold: 0.1,
+            normal_threshold: 0.7,
+        }
+    }
+}
+
+impl Toon {
+    pub fn from(bands: u32) -> Self {
+        Self {
+            bands,
+            ..Self::default()
+        }
+    }
+
+    // Renders the scene through `camera`, antialiasing the shading with the
+    // camera's samples per pixel; outlines come from one ray per pixel.
+    pub fn render(&self, camera: &Camera, scene: &Scene) -> FrameBuffer {
+        let width = camera.image_width();
+        let height = camera.image_height();
+        let samples = camera.samples_per_pixel();
+        crate::stage_span!("toon", width, height, samples);
+        let light = unit_vector(self.light.unwrap_or(match &scene.background {
+            Background::Sky(sky) => sky.sun_direction,
+            _ => camera_relative_light(camera),
+        }));
+        let aovs = Aovs::render(camera, scene);
+        let overridden = camera.mode().material();
+
+        let mut on_progress = progress::log;
+        let mut progress = Tracker::start(width, height, samples, &mut on_progress);
+        let mut buffer = FrameBuffer::new(width, height);
+        for j in 0..height {
+            let tile = Tile::row(j, width);
+            progress.tile_started(tile);
+            for i in 0..width {
+                if self.is_outline(&aovs, i, j) {
+                    buffer.add_samples(i, j, self.outline * samples as f64, samples as u32);
+                    continue;
+                }
+                let mut sum = Color::new();
+                for s in 0..samples {
+                    let mut sampler = camera.pixel_sampler(i, j, s as u64);
+                    let r = camera.get_ray(i, j, &mut sampler);
+                    sum += match scene.hit(&r, Interval::from(0.001, INFINITY)) {
+                        Some(rec) => {
+                            let albedo = overridden.as_ref().unwrap_or(rec.mat).albedo(&rec);
+                            let lit = dot(&rec.normal, &light).max(0.0);
+                            let shadow = Ray::from(rec.p, light);
+                            let shadowed = lit > 0.0
+                                && scene
+                                    .world
+                                    .is_occluded(&shadow, Interval::from(0.001, INFINITY));
+                            albedo * self.shade(if shadowed { 0.0 } else { lit })
+                        }
+                        None => scene.background.value(&r),
+                    };
+                }
+                buffer.add_samples(i, j, sum, samples as u32);
+            }
+            progress.tile_finished(tile, tile.pixels() * samples as u64);
+        }
+        progress.finish();
+        for _ in 0..samples {
+            buffer.complete_pass();
+        }
+        buffer
+    }
+
+    // Brightness for a cosine to the light of `lit`, snapped to the top of
+    // its band: `bands` flat steps from `ambient` up to 1.
+    fn shade(&self, lit: f64) -> f64 {
+        let bands = self.bands.max(1) as f64;
+        let step = (lit * bands).ceil() / bands;
+        self.ambient + (1.0 - self.ambient) * step
+    }
+
+    // Whether pixel (i, j) lies on a line: it sees a surface and differs from
+    // a neighbour that is background, further away or turned another way.
+    // Lines are drawn on the nearer side only, so they stay one pixel wide.
+    fn is_outline(&self, aovs: &Aovs, i: i32, j: i32) -> bool {
+        let Some(surface) = aovs.get(i, j) else {
+            return false;
+        };
+        let inside = |i, j| i >= 0 && j >= 0 && i < aovs.width() && j < aovs.height();
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .iter()
+            .filter(|(di, dj)| inside(i + di, j + dj))
+            .any(|(di, dj)| match aovs.get(i + di, j + dj) {
+                None => true,
+                Some(other) => self.separated(surface, other),
+            })
+    }
+
+    fn separated(&self, near: &Surface, other: &Surface) -> bool {
+        let deeper = other.depth - near.depth > self.depth_threshold * near.depth;
+        let crease =
+            dot(&near.normal, &other.normal) < self.normal_threshold && near.depth <= other.depth;
+        deeper || crease
+    }
+}
+
+fn camera_relative_light(camera: &Camera) -> Vec3 {
+    let backward = unit_vector(camera.look_from() - camera.look_at());
+    let up = camera.vup();
+    let right = unit_vector(cross(&up, &backward));
+    right + 2.0 * up + backward
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::ray::Point3;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn quantizes_shading_and_outlines_silhouettes() {
+        let toon = Toon::from(2);
+        approx::assert_relative_eq!(toon.shade(0.0), 0.3);
+        approx::assert_relative_eq!(toon.shade(0.2), 0.65);
+        approx::assert_relative_eq!(toon.shade(0.5), 0.65);
+        approx::assert_relative_eq!(toon.shade(0.9), 1.0);
+
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        )));
+        let mut scene = Scene::from(world);
+        scene.background = Background::Solid(Color::gray(1.0));
+        let camera = CameraBuilder {
+            image_width: Some(21),
+            samples_per_pixel: Some(1),
+            look_from: Some(Point3::from(0.0, 0.0, 3.0)),
+            vfov: Some(60.0),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let toon = Toon {
+            light: Some(Vec3::from(0.0, 0.0, 1.0)),
+            ..Toon::from(2)
+        };
+        let image = toon.render(&camera, &scene);
+        let pixel = |i, j| image.pixel_sum(i, j);
+
+        // Facing the light, the center is in the top band; the background is
+        // untouched; the sphere's rim along the middle row is outlined.
+        approx::assert_relative_eq!(pixel(10, 10).g(), 0.5);
+        approx::assert_relative_eq!(pixel(0, 0).g(), 1.0);
+        let row: Vec<f64> = (0..21).map(|i| pixel(i, 10).g()).collect();
+        let first = row.iter().position(|&g| g < 1.0).unwrap();
+        assert_eq!(row[first], 0.0);
+        assert!(row[first + 1] > 0.0);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::hittable::Hittable;
+use crate::ray::{Point3, Ray};
+use crate::scene::Scene;
+use crate::stats;
+use crate::utility::{Interval, INFINITY};
+use crate::vec3::Vec3;
+
+// Single-ray queries against a scene, for picking and visibility tests in
+// applications that embed the geometry kernel rather than render images.
+//
+//     let ray = Ray::from(eye, direction);
+//     if let Some(hit) = trace(&scene, &ray) {
+//         println!("object {} at distance {}", hit.object, hit.t);
+//     }
+//
+// Only geometry is involved; materials, lights and the background are ignored.
+
+// The closest intersection found by `trace`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceHit {
+    pub t: f64,       // Ray parameter; the distance when the direction has unit length
+    pub p: Point3,    // Hit point in world space
+    pub normal: Vec3, // Unit normal, facing against the ray
+    pub u: f64,       // Surface texture coordinates
+    pub v: f64,
+    pub front_face: bool, // Whether the ray hit the outside of the surface
+    pub object: usize,    // Index of the object in the scene's world, in order of `add`
+}
+
+// The closest hit along the ray in front of its origin, if any.
+pub fn trace(scene: &Scene, ray: &Ray) -> Option<TraceHit> {
+    trace_within(scene, ray, Interval::from(0.0, INFINITY))
+}
+
+// The closest hit with a ray parameter strictly inside `ray_t`.
+pub fn trace_within(scene: &Scene, ray: &Ray, ray_t: Interval) -> Option<TraceHit> {
+    let (object, rec) = scene.world.hit_object(ray, ray_t)?;
+    Some(TraceHit {
+        t: rec.t,
+        p: rec.p,
+        normal: rec.normal,
+        u: rec.u,
+        v: rec.v,
+        front_face: rec.front_face,
+        object,
+    })
+}
+
+// Whether anything lies between the two points, e.g. for line-of-sight tests.
+pub fn is_visible(scene: &Scene, from: Point3, to: Point3) -> bool {
+    // The ray spans the segment for t in [0, 1]; keep clear of both ends.
+    let ray = Ray::from(from, to - from);
+    stats::count_shadow_ray();
+    !scene
+        .world
+        .is_occluded(&ray, Interval::from(1e-6, 1.0 - 1e-6))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn trace_reports_closest_object() {
+        let material = Material::Lambertian(Lambertian::from(Color::new()));
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, 0.0, -10.0),
+            1.0,
+            material.clone(),
+        )));
+        world.add(Box::new(Sphere::from(
+            Point3::from(0.0, 0.0, -5.0),
+            1.0,
+            material,
+        )));
+        let scene = Scene::from(world);
+
+        let ray = Ray::from(Point3::new(), Vec3::from(0.0, 0.0, -1.0));
+        let hit = trace(&scene, &ray).unwrap();
+        assert_eq!(hit.object, 1);
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.normal, Vec3::from(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+
+        let up = Ray::from(Point3::new(), Vec3::from(0.0, 1.0, 0.0));
+        assert!(trace(&scene, &up).is_none());
+
+        assert!(!is_visible(
+            &scene,
+            Point3::new(),
+            Point3::from(0.0, 0.0, -20.0)
+        ));
+        assert!(is_visible(
+            &scene,
+            Point3::new(),
+            Point3::from(0.0, 0.0, -3.0)
+        ));
+    }
+}
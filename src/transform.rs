@@ -0,0 +1,223 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::quat::Quat;
+use crate::ray::{Point3, Ray};
+use crate::sampler::Sampler;
+use crate::utility::{degrees_to_radians, Interval};
+use crate::vec3::*;
+
+// Places an object in the world through an affine matrix. Rays are moved into
+// object space for intersection and the hit is moved back, so `t` is shared
+// between the two spaces. To place one piece of geometry several times, wrap
+// an `Arc<dyn Hittable>` of it in each transform.
+pub struct Transform {
+    object: Box<dyn Hittable>,
+    to_world: Mat4,
+    to_object: Mat4,
+    // Inverse transpose of `to_world`, for normals.
+    normal_matrix: Mat4,
+    bbox: Aabb,
+}
+
+impl Transform {
+    pub fn from(object: Box<dyn Hittable>, matrix: Mat4) -> Result<Self, String> {
+        let to_object = matrix
+            .inverse()
+            .ok_or("transform matrix is not invertible")?;
+
+        // Bound the transformed corners of the object's box.
+        let object_box = object.bounding_box();
+        let mut bbox = Aabb::EMPTY;
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                let interval = object_box.axis_interval(axis);
+                if corner & (1 << axis) == 0 {
+                    interval.min
+                } else {
+                    interval.max
+                }
+            };
+            let p = matrix.transform_point(&Point3::from(pick(0), pick(1), pick(2)));
+            bbox = Aabb::union(&bbox, &Aabb::from_points(p, p));
+        }
+
+        Ok(Self {
+            object,
+            to_world: matrix,
+            to_object,
+            normal_matrix: to_object.transpose(),
+            bbox,
+        })
+    }
+
+    // The object moved by `offset`.
+    pub fn translate(object: Box<dyn Hittable>, offset: Vec3) -> Self {
+        Self::from(object, Mat4::translation(&offset)).expect("translations are invertible")
+    }
+
+    // The object turned by `degrees` about the y axis, counterclockwise seen
+    // from above.
+    pub fn rotate_y(object: Box<dyn Hittable>, degrees: f64) -> Self {
+        let axis = Vec3::from(0.0, 1.0, 0.0);
+        let rotation = Quat::from_axis_angle(&axis, degrees_to_radians(degrees));
+        Self::from(object, Mat4::rotation(&rotation)).expect("rotations are invertible")
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.to_world
+    }
+
+    fn object_ray(&self, r: &Ray) -> Ray {
+        Ray::from(
+            self.to_object.transform_point(&r.origin()),
+            self.to_object.transform_vector(&r.direction()),
+        )
+        .with_mask(r.mask())
+        .with_time(r.time())
+        .with_eye(self.to_object.transform_point(&r.eye()))
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut rec = self.object.hit(&self.object_ray(r), ray_t)?;
+        rec.p = self.to_world.transform_point(&rec.p);
+        rec.normal = unit_vector(self.normal_matrix.transform_vector(&rec.normal));
+        rec.geometric_normal =
+            unit_vector(self.normal_matrix.transform_vector(&rec.geometric_normal));
+        rec.dpdu = self.to_world.transform_vector(&rec.dpdu);
+        rec.dpdv = self.to_world.transform_vector(&rec.dpdv);
+        rec.dndu = self.normal_matrix.transform_vector(&rec.dndu);
+        rec.dndv = self.normal_matrix.transform_vector(&rec.dndv);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.object.is_occluded(&self.object_ray(r), ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn mask(&self) -> u32 {
+        self.object.mask()
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.object.contains(self.to_object.transform_point(&p))
+    }
+
+    // Exact for rigid and uniformly scaled transforms, which keep solid
+    // angles; other scales skew the density.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(
+            self.to_object.transform_point(&origin),
+            self.to_object.transform_vector(&direction),
+        )
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let local = self
+            .object
+            .random(self.to_object.transform_point(&origin), sampler);
+        self.to_world.transform_vector(&local)
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (p, n) = self.object.surface_at(u, v)?;
+        Some((
+            self.to_world.transform_point(&p),
+            unit_vector(self.normal_matrix.transform_vector(&n)),
+        ))
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.object.materials_mut()
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.instances += 1;
+        info.memory += std::mem::size_of::<Self>();
+        self.object.describe(info);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::utility::{INFINITY, PI};
+    use approx::assert_relative_eq;
+    use std::sync::Arc;
+
+    fn unit_sphere() -> Box<dyn Hittable> {
+        Box::new(Sphere::from(
+            Point3::new(),
+            1.0,
+            Material::Lambertian(Lambertian::from(Color::new())),
+        ))
+    }
+
+    #[test]
+    fn scaled_sphere() {
+        // An ellipsoid with semi-axes 2, 1, 1 rotated a quarter turn about z,
+        // then moved up by 5.
+        let matrix = Mat4::from_trs(
+            &Vec3::from(0.0, 5.0, 0.0),
+            &Quat::from_axis_angle(&Vec3::from(0.0, 0.0, 1.0), PI / 2.0),
+            &Vec3::from(2.0, 1.0, 1.0),
+        );
+        let ellipsoid = Transform::from(unit_sphere(), matrix).unwrap();
+
+        let bbox = ellipsoid.bounding_box();
+        assert_relative_eq!(bbox.y.min, 3.0, epsilon = 1e-3);
+        assert_relative_eq!(bbox.y.max, 7.0, epsilon = 1e-3);
+        assert_relative_eq!(bbox.x.max, 1.0, epsilon = 1e-3);
+
+        let r = Ray::from(Point3::from(0.0, 10.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        let rec = ellipsoid.hit(&r, Interval::from(0.001, INFINITY)).unwrap();
+        assert_relative_eq!(rec.t, 3.0, epsilon = 1e-9);
+        assert_relative_eq!(rec.p.y(), 7.0, epsilon = 1e-9);
+        assert_relative_eq!(rec.normal.y(), 1.0, epsilon = 1e-9);
+        assert!(rec.front_face);
+
+        let r = Ray::from(Point3::from(1.5, 10.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        assert!(!ellipsoid.is_occluded(&r, Interval::from(0.001, INFINITY)));
+        assert!(Transform::from(unit_sphere(), Mat4::scale(&Vec3::new())).is_err());
+    }
+
+    #[test]
+    fn translated_and_turned_instances() {
+        // One unit square in the xy plane, facing +z, placed twice.
+        let square: Arc<dyn Hittable> = Arc::new(Quad::from(
+            Point3::new(),
+            Vec3::from(1.0, 0.0, 0.0),
+            Vec3::from(0.0, 1.0, 0.0),
+            Material::Lambertian(Lambertian::from(Color::new())),
+        ));
+        let moved = Transform::translate(Box::new(square.clone()), Vec3::from(5.0, 0.0, 0.0));
+        let turned = Transform::rotate_y(Box::new(square), 90.0);
+        let ray_t = Interval::from(0.001, INFINITY);
+
+        let down_z = Ray::from(Point3::from(5.5, 0.5, 3.0), Vec3::from(0.0, 0.0, -1.0));
+        let rec = moved.hit(&down_z, ray_t).unwrap();
+        assert_relative_eq!(rec.p.x(), 5.5, epsilon = 1e-9);
+        assert_relative_eq!(rec.u, 0.5, epsilon = 1e-9);
+
+        // A quarter turn takes +x to -z and the normal from +z to +x.
+        let down_x = Ray::from(Point3::from(3.0, 0.5, -0.5), Vec3::from(-1.0, 0.0, 0.0));
+        let rec = turned.hit(&down_x, ray_t).unwrap();
+        assert_relative_eq!(rec.t, 3.0, epsilon = 1e-9);
+        assert_relative_eq!(rec.normal.x(), 1.0, epsilon = 1e-9);
+        assert!(rec.front_face);
+        assert_relative_eq!(turned.bounding_box().z.min, -1.0, epsilon = 1e-3);
+        assert!(turned.hit(&down_z, ray_t).is_none());
+    }
+}
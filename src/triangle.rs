@@ -0,0 +1,111 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::info::SceneInfo;
+use crate::material::Material;
+use crate::mesh::{intersect_triangle, TriangleHit};
+use crate::ray::{Point3, Ray, ALL_SETS};
+use crate::utility::Interval;
+use crate::vec3::*;
+
+// A single flat triangle, for the odd triangle in a scene; anything with more
+// than a few belongs in a `Mesh`, which shares vertices and has a BVH. Its
+// texture coordinates are the barycentric weights of b and c, and it faces
+// the side from which a, b and c wind counterclockwise.
+#[derive(Clone)]
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normal: Vec3,
+    material: Material,
+}
+
+impl Triangle {
+    pub fn from(a: Point3, b: Point3, c: Point3, material: Material) -> Self {
+        Self {
+            vertices: [a, b, c],
+            normal: unit_vector(cross(&(b - a), &(c - a))),
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let TriangleHit { t, b1, b2 } = intersect_triangle(self.vertices, r, &ray_t)?;
+        let [a, b, c] = self.vertices;
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: self.normal,
+            geometric_normal: self.normal,
+            t,
+            u: b1,
+            v: b2,
+            dpdu: b - a,
+            dpdv: c - a,
+            dndu: Vec3::new(),
+            dndv: Vec3::new(),
+            front_face: false,
+            mat: &self.material,
+            trace_set: ALL_SETS,
+        };
+        rec.set_face_normal(r, &self.normal);
+        Some(rec)
+    }
+
+    fn is_occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        intersect_triangle(self.vertices, r, &ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        Aabb::union(&Aabb::from_points(a, b), &Aabb::from_points(c, c))
+    }
+
+    fn surface_at(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        if u + v > 1.0 {
+            return None;
+        }
+        let [a, b, c] = self.vertices;
+        Some((a + u * (b - a) + v * (c - a), self.normal))
+    }
+
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![&mut self.material]
+    }
+
+    fn describe(&self, info: &mut SceneInfo) {
+        info.add_primitive("triangle", std::mem::size_of::<Self>());
+        info.triangles += 1;
+        info.add_material(&self.material);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::utility::INFINITY;
+
+    #[test]
+    fn hits_inside_the_edges() {
+        let triangle = Triangle::from(
+            Point3::new(),
+            Point3::from(2.0, 0.0, 0.0),
+            Point3::from(0.0, 2.0, 0.0),
+            Material::Lambertian(Lambertian::from(Color::gray(0.5))),
+        );
+        let ray_t = Interval::from(0.001, INFINITY);
+        let down = |x, y| Ray::from(Point3::from(x, y, 3.0), Vec3::from(0.0, 0.0, -1.0));
+
+        let rec = triangle.hit(&down(0.5, 0.5), ray_t).unwrap();
+        approx::assert_relative_eq!(rec.t, 3.0);
+        approx::assert_relative_eq!(rec.u, 0.25);
+        assert!(rec.front_face && rec.normal.z() > 0.0);
+        let (p, _) = triangle.surface_at(rec.u, rec.v).unwrap();
+        approx::assert_relative_eq!(p.x(), 0.5);
+
+        assert!(triangle.hit(&down(1.5, 1.5), ray_t).is_none());
+        assert!(triangle.is_occluded(&down(0.1, 1.8), ray_t));
+        approx::assert_relative_eq!(triangle.bounding_box().y.max, 2.0, epsilon = 1e-3);
+    }
+}
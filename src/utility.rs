@@ -1,8 +1,17 @@
 use rand::prelude::*;
+use std::cell::RefCell;
+use std::ops;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// std's clock panics on wasm32-unknown-unknown; web-time reads the browser's.
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub use web_time::Instant;
 
 // Constants
 pub const INFINITY: f64 = f64::INFINITY;
-const PI: f64 = std::f64::consts::PI;
+pub const PI: f64 = std::f64::consts::PI;
 
 // Utility Functions
 pub fn degrees_to_radians(degrees: f64) -> f64 {
@@ -10,30 +19,96 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
 }
 
 // Random
+//
+// Every thread draws from its own generator. By default these are seeded from
+// system entropy; after `seed`, the calling thread restarts the sequence for
+// that seed and threads created later get their own streams derived from it,
+// so scenes built at random on one thread are reproducible. Camera samples
+// don't draw from these: a camera with a seed of its own draws them from that
+// seed, the pixel and the sample index alone (see `PixelSampler`).
+static SEEDED: AtomicBool = AtomicBool::new(false);
+static SEED: AtomicU64 = AtomicU64::new(0);
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(new_stream());
+}
+
+fn new_stream() -> StdRng {
+    if !SEEDED.load(Ordering::Relaxed) {
+        return StdRng::from_entropy();
+    }
+    let stream = NEXT_STREAM.fetch_add(1, Ordering::Relaxed);
+    stream_rng(SEED.load(Ordering::Relaxed), stream)
+}
+
+fn stream_rng(seed: u64, stream: u64) -> StdRng {
+    StdRng::seed_from_u64(seed ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+pub fn seed(seed: u64) {
+    SEED.store(seed, Ordering::Relaxed);
+    NEXT_STREAM.store(1, Ordering::Relaxed);
+    SEEDED.store(true, Ordering::Relaxed);
+    RNG.with(|rng| *rng.borrow_mut() = stream_rng(seed, 0));
+}
+
 pub fn random() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen()
+    RNG.with(|rng| rng.borrow_mut().gen())
 }
 
 pub fn random_in_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
 
 // Interval
+#[derive(Clone, Copy, Debug)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
 }
 
 impl Interval {
-    pub fn from(_min: f64, _max: f64) -> Self {
+    pub const EMPTY: Interval = Interval::from(INFINITY, -INFINITY);
+    pub const UNIVERSE: Interval = Interval::from(-INFINITY, INFINITY);
+
+    pub const fn from(_min: f64, _max: f64) -> Self {
         Self {
             min: _min,
             max: _max,
         }
     }
 
+    // The smallest interval enclosing both intervals.
+    pub fn union(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    // The overlap of both intervals, empty if they are disjoint.
+    pub fn intersect(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: a.min.max(b.min),
+            max: a.max.min(b.max),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max < self.min
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    // Widens the interval by `delta` in total, half on each side.
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        Self::from(self.min - padding, self.max + padding)
+    }
+
     pub fn contains(&self, x: f64) -> bool {
         self.min <= x && x <= self.max
     }
@@ -54,11 +129,59 @@ impl Interval {
     }
 }
 
-pub const EMPTY: Interval = Interval {
-    min: INFINITY,
-    max: -INFINITY,
-};
-pub const UNIVERSE: Interval = Interval {
-    min: -INFINITY,
-    max: INFINITY,
-};
+impl ops::Add<f64> for Interval {
+    type Output = Self;
+    fn add(self, displacement: f64) -> Self::Output {
+        Self::from(self.min + displacement, self.max + displacement)
+    }
+}
+
+impl ops::Add<Interval> for f64 {
+    type Output = Interval;
+    fn add(self, ival: Interval) -> Self::Output {
+        ival + self
+    }
+}
+
+impl ops::Sub<f64> for Interval {
+    type Output = Self;
+    fn sub(self, displacement: f64) -> Self::Output {
+        self + -displacement
+    }
+}
+
+pub const EMPTY: Interval = Interval::EMPTY;
+pub const UNIVERSE: Interval = Interval::UNIVERSE;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_sequences_repeat() {
+        seed(7);
+        let first: Vec<f64> = (0..4).map(|_| random()).collect();
+        let thread = std::thread::spawn(random).join().unwrap();
+        seed(7);
+        let second: Vec<f64> = (0..4).map(|_| random()).collect();
+        assert_eq!(first, second);
+        assert!(!first.contains(&thread));
+    }
+
+    #[test]
+    fn interval_algebra() {
+        let a = Interval::from(0.0, 2.0);
+        let b = Interval::from(1.0, 5.0);
+        let overlap = Interval::intersect(&a, &b);
+        assert_eq!((overlap.min, overlap.max), (1.0, 2.0));
+        assert!(Interval::intersect(&a, &(b + 3.0)).is_empty());
+        assert!(Interval::EMPTY.is_empty() && !Interval::UNIVERSE.is_empty());
+
+        let moved = 1.5 + a;
+        assert_eq!((moved.min, moved.max), (1.5, 3.5));
+        let moved = a - 1.0;
+        assert_eq!((moved.min, moved.max), (-1.0, 1.0));
+        assert_eq!(Interval::union(&a, &Interval::EMPTY).size(), 2.0);
+        assert_eq!(a.expand(1.0).size(), 3.0);
+    }
+}
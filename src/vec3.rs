@@ -1,6 +1,6 @@
 use std::ops;
 
-use crate::utility::{random, random_in_range};
+use crate::utility::{random, random_in_range, PI};
 
 #[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct Vec3 {
@@ -8,6 +8,8 @@ pub struct Vec3 {
 }
 
 impl Vec3 {
+    pub const ZERO: Vec3 = Vec3 { e: [0.0; 3] };
+
     pub fn new() -> Self {
         Self { e: [0.0; 3] }
     }
@@ -42,6 +44,47 @@ impl Vec3 {
         self.length_squared().sqrt()
     }
 
+    // Component-wise operations
+    pub fn is_finite(&self) -> bool {
+        self.e.iter().all(|x| x.is_finite())
+    }
+
+    pub fn abs(&self) -> Self {
+        self.map(f64::abs)
+    }
+
+    pub fn min(&self, other: &Vec3) -> Self {
+        Self::from(
+            self.e[0].min(other.e[0]),
+            self.e[1].min(other.e[1]),
+            self.e[2].min(other.e[2]),
+        )
+    }
+
+    pub fn max(&self, other: &Vec3) -> Self {
+        Self::from(
+            self.e[0].max(other.e[0]),
+            self.e[1].max(other.e[1]),
+            self.e[2].max(other.e[2]),
+        )
+    }
+
+    pub fn clamp(&self, min: f64, max: f64) -> Self {
+        self.map(|x| x.clamp(min, max))
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self::from(f(self.e[0]), f(self.e[1]), f(self.e[2]))
+    }
+
+    pub fn min_component(&self) -> f64 {
+        self.e[0].min(self.e[1]).min(self.e[2])
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.e[0].max(self.e[1]).max(self.e[2])
+    }
+
     pub fn random() -> Self {
         Self::from(random(), random(), random())
     }
@@ -74,6 +117,34 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
+// Linear interpolation: `a` at t = 0, `b` at t = 1.
+pub fn lerp(a: &Vec3, b: &Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * *a + t * *b
+}
+
+pub fn distance(a: &Vec3, b: &Vec3) -> f64 {
+    (*a - *b).length()
+}
+
+pub fn distance_squared(a: &Vec3, b: &Vec3) -> f64 {
+    (*a - *b).length_squared()
+}
+
+// Mirror reflection of `v` about the surface normal `n`.
+pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
+    (*v) - 2.0 * dot(v, n) * (*n)
+}
+
+// Refraction of the unit vector `uv` through a surface with unit normal `n`
+// facing against it, by Snell's law with the ratio of refractive indices.
+pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
+    let cos_theta = f64::min(dot(&-(*uv), n), 1.0);
+    let r_out_perp = etai_over_etat * (*uv + cos_theta * *n);
+    let r_out_parallel = -f64::sqrt(f64::abs(1.0 - r_out_perp.length_squared())) * *n;
+
+    r_out_parallel + r_out_perp
+}
+
 pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
     let on_unit_sphere = random_unit_vector();
 
@@ -106,6 +177,29 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
+// Maps a point of the unit square to a uniformly distributed unit vector.
+pub fn sample_unit_vector((u, v): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::from(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Maps a point of the unit square onto the unit disk (Shirley and Chiu's
+// concentric mapping), keeping stratified samples stratified.
+pub fn concentric_disk(u: f64, v: f64) -> Vec3 {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::from(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
 impl ops::Add for Vec3 {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
@@ -257,7 +351,6 @@ mod test {
         assert_eq!(point[2], 3.0);
 
         // Test mutable indexer
-        let mut point = point;
         point[0] = 0.0;
         point[1] = 3.0;
         point[2] = 4.0;
@@ -275,4 +368,24 @@ mod test {
         assert_eq!(dot(&u, &v), 10.0);
         assert_eq!(cross(&u, &v), Vec3::new())
     }
+
+    #[test]
+    fn component_helpers() {
+        let a = Vec3::from(-1.0, 2.0, 5.0);
+        let b = Vec3::from(3.0, -2.0, 5.0);
+
+        assert_eq!(a.abs(), Vec3::from(1.0, 2.0, 5.0));
+        assert_eq!(a.min(&b), Vec3::from(-1.0, -2.0, 5.0));
+        assert_eq!(a.max(&b), Vec3::from(3.0, 2.0, 5.0));
+        assert_eq!(a.clamp(0.0, 3.0), Vec3::from(0.0, 2.0, 3.0));
+        assert_eq!((a.min_component(), a.max_component()), (-1.0, 5.0));
+        assert_eq!(lerp(&a, &b, 0.5), Vec3::from(1.0, 0.0, 5.0));
+        assert_eq!(distance(&a, &b), f64::sqrt(32.0));
+
+        let n = Vec3::from(0.0, 1.0, 0.0);
+        let v = unit_vector(Vec3::from(1.0, -1.0, 0.0));
+        assert_eq!(reflect(&v, &n), unit_vector(Vec3::from(1.0, 1.0, 0.0)));
+        // Equal indices pass straight through.
+        approx::assert_relative_eq!(dot(&refract(&v, &n, 1.0), &v), 1.0);
+    }
 }
@@ -0,0 +1,132 @@
+use log::info;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// Encoder settings. Codecs default from the output extension: H.264 for .mp4
+// and .mov, VP9 for .webm.
+pub struct VideoSettings {
+    pub fps: f64,
+    pub bitrate: Option<String>, // e.g. "8M", passed to ffmpeg's -b:v
+    pub codec: Option<String>,
+    pub ffmpeg: PathBuf,
+}
+
+impl VideoSettings {
+    pub fn new(fps: f64) -> Self {
+        Self {
+            fps,
+            bitrate: None,
+            codec: None,
+            ffmpeg: PathBuf::from("ffmpeg"),
+        }
+    }
+
+    fn codec_for(&self, path: &Path) -> &str {
+        if let Some(codec) = &self.codec {
+            return codec;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("webm") => "libvpx-vp9",
+            _ => "libx264",
+        }
+    }
+
+    fn args(&self, path: &Path) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "-y",
+            "-loglevel",
+            "error",
+            "-f",
+            "image2pipe",
+            "-c:v",
+            "ppm",
+            "-framerate",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        args.push(self.fps.to_string());
+        args.extend(
+            [
+                "-i",
+                "-",
+                "-c:v",
+                self.codec_for(path),
+                "-pix_fmt",
+                "yuv420p",
+            ]
+            .map(String::from),
+        );
+        if let Some(bitrate) = &self.bitrate {
+            args.push("-b:v".to_string());
+            args.push(bitrate.clone());
+        }
+        args.push(path.display().to_string());
+        args
+    }
+}
+
+// An ffmpeg process that encodes PPM frames written to it into a video file.
+pub struct VideoEncoder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    path: PathBuf,
+    frames: usize,
+}
+
+impl VideoEncoder {
+    pub fn spawn(path: &Path, settings: &VideoSettings) -> io::Result<Self> {
+        let mut child = Command::new(&settings.ffmpeg)
+            .args(settings.args(path))
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("failed to run {}: {}", settings.ffmpeg.display(), err),
+                )
+            })?;
+        let stdin = child.stdin.take();
+        Ok(Self {
+            child,
+            stdin,
+            path: path.to_path_buf(),
+            frames: 0,
+        })
+    }
+
+    // Stream the next frame, a complete PPM image, into the encoder.
+    pub fn frame(&mut self) -> &mut impl Write {
+        self.frames += 1;
+        self.stdin.as_mut().expect("encoder already finished")
+    }
+
+    // Closes the pipe and waits for ffmpeg to write the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg exited with {}", status)));
+        }
+        info!("Encoded {} frames to {}", self.frames, self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codec_from_extension() {
+        let mut settings = VideoSettings::new(30.0);
+        assert_eq!(settings.codec_for(Path::new("out.mp4")), "libx264");
+        assert_eq!(settings.codec_for(Path::new("out.webm")), "libvpx-vp9");
+        settings.bitrate = Some("4M".to_string());
+        let args = settings.args(Path::new("out.mp4"));
+        assert!(args.windows(2).any(|w| w == ["-b:v", "4M"]));
+        assert!(args.windows(2).any(|w| w == ["-framerate", "30"]));
+        assert_eq!(args.last().unwrap(), "out.mp4");
+    }
+}
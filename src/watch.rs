@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// Polls the modification times of a scene file and the assets it references.
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    interval: Duration,
+}
+
+impl Watcher {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let mut watcher = Self {
+            files: Vec::new(),
+            interval: Duration::from_millis(250),
+        };
+        for path in paths {
+            watcher.watch(path);
+        }
+        watcher
+    }
+
+    pub fn watch(&mut self, path: &Path) {
+        if self.files.iter().all(|(p, _)| p != path) {
+            self.files.push((path.to_path_buf(), modified(path)));
+        }
+    }
+
+    // Returns true if any watched file changed since the last poll.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, stamp) in self.files.iter_mut() {
+            let current = modified(path);
+            if current != *stamp {
+                *stamp = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn wait_for_change(&mut self) {
+        loop {
+            thread::sleep(self.interval);
+            if self.poll() {
+                // Editors often save in several steps, let the writes settle.
+                thread::sleep(self.interval);
+                self.poll();
+                return;
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
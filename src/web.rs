@@ -0,0 +1,80 @@
+use crate::camera::{Camera, CameraBuilder};
+use crate::framebuffer::FrameBuffer;
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+use crate::script;
+
+use wasm_bindgen::prelude::*;
+
+// Progressive renderer for the browser demo in web/ (see the README). The page
+// calls `render_pass` from requestAnimationFrame, one sample per pixel at a
+// time, and draws `pixels` into a canvas, so the tab stays responsive without
+// needing threads.
+#[wasm_bindgen]
+pub struct WebRenderer {
+    camera: Camera,
+    scene: Scene,
+    buffer: FrameBuffer,
+}
+
+#[wasm_bindgen]
+impl WebRenderer {
+    // Evaluates a scene script; its `set_camera` settings apply, then
+    // `width` (if nonzero) overrides the image width.
+    #[wasm_bindgen(constructor)]
+    pub fn new(script: &str, width: i32) -> Result<WebRenderer, JsError> {
+        let loaded =
+            script::eval_frame(script, 0, 0.0).map_err(|err| JsError::new(&err.to_string()))?;
+        let mut settings = CameraBuilder::new();
+        loaded
+            .apply_camera(&mut settings)
+            .map_err(|err| JsError::new(&err))?;
+        if width > 0 {
+            settings.image_width = Some(width);
+        }
+        settings.sampler.get_or_insert(SamplerKind::BlueNoise);
+        let camera = settings.build().map_err(|err| JsError::new(&err))?;
+        let buffer = camera.frame_buffer();
+        Ok(Self {
+            camera,
+            scene: loaded.into_scene(),
+            buffer,
+        })
+    }
+
+    pub fn width(&self) -> i32 {
+        self.buffer.width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.buffer.height()
+    }
+
+    // Completed passes, i.e. samples per pixel so far.
+    pub fn passes(&self) -> i32 {
+        self.buffer.samples()
+    }
+
+    pub fn target_passes(&self) -> i32 {
+        self.camera.samples_per_pixel()
+    }
+
+    // Adds one sample to every pixel, returning false once the target sample
+    // count has been reached.
+    pub fn render_pass(&mut self) -> bool {
+        if self.passes() >= self.target_passes() {
+            return false;
+        }
+        self.camera.render_pass(&self.scene, &mut self.buffer);
+        true
+    }
+
+    // The current image as RGBA bytes, row-major, for `ImageData`.
+    pub fn pixels(&self) -> Vec<u8> {
+        self.buffer
+            .to_rgb_u32()
+            .iter()
+            .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8, 255])
+            .collect()
+    }
+}
@@ -0,0 +1,51 @@
+// Built by `wasm-pack build --target web --features web`, which writes ../pkg.
+import init, { WebRenderer } from "../pkg/render.js";
+
+const canvas = document.getElementById("image");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+const script = document.getElementById("script");
+const width = document.getElementById("width");
+
+let renderer = null;
+let started = 0;
+
+function start() {
+  renderer?.free();
+  renderer = null;
+  try {
+    renderer = new WebRenderer(script.value, Number(width.value));
+  } catch (err) {
+    status.textContent = String(err);
+    return;
+  }
+  canvas.width = renderer.width();
+  canvas.height = renderer.height();
+  started = performance.now();
+  requestAnimationFrame(frame);
+}
+
+// One pass per animation frame keeps the page responsive.
+function frame() {
+  if (!renderer) {
+    return;
+  }
+  const more = renderer.render_pass();
+  const pixels = new Uint8ClampedArray(renderer.pixels());
+  context.putImageData(new ImageData(pixels, renderer.width(), renderer.height()), 0, 0);
+  const seconds = ((performance.now() - started) / 1000).toFixed(1);
+  status.textContent = `${renderer.passes()} / ${renderer.target_passes()} spp, ${seconds}s`;
+  if (more) {
+    requestAnimationFrame(frame);
+  }
+}
+
+document.getElementById("render").addEventListener("click", start);
+script.addEventListener("keydown", (event) => {
+  if (event.key === "Enter" && event.ctrlKey) {
+    start();
+  }
+});
+
+await init();
+start();